@@ -0,0 +1,79 @@
+//! Attestation latency of the sample evidence under concurrent load, with and without the
+//! compiled policy cache of each enabled policy engine.
+//!
+//! Run by `cargo bench -p attestation-service --bench policy_cache`.
+
+use attestation_service::{PolicyEngineType, Service};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use serde_json::json;
+use sha2::{Digest, Sha384};
+use std::sync::Arc;
+
+// The concurrent attestations per iteration.
+const CONCURRENCY: usize = 16;
+const CACHE_SIZES: [usize; 2] = [0, attestation_service::DEFAULT_POLICY_CACHE_SIZE];
+
+fn sample_evidence() -> String {
+    let nonce = "1234567890";
+    let pubkey = json!({
+        "algorithm": "",
+        "pubkey-length": "",
+        "pubkey": "hduabci29e0asdadans0212nsj0e3n"
+    })
+    .to_string();
+    let mut hasher = Sha384::new();
+    hasher.update(nonce);
+    hasher.update(&pubkey);
+    let tee_evidence = json!({
+        "is_debuggable": false,
+        "cpusvn": 1,
+        "svn": 1,
+        "report_data": base64::encode(hasher.finalize())
+    })
+    .to_string();
+    json!({
+        "nonce": nonce,
+        "tee": "sample",
+        "tee-pubkey": pubkey,
+        "tee-evidence": tee_evidence
+    })
+    .to_string()
+}
+
+fn attestation(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let evidence = Arc::new(sample_evidence());
+    let mut group = c.benchmark_group("attestation");
+    group.throughput(Throughput::Elements(CONCURRENCY as u64));
+
+    for engine in PolicyEngineType::enabled() {
+        for cache_size in CACHE_SIZES {
+            let service = Arc::new(
+                Service::builder()
+                    .policy_engine(engine.to_engine_with_cache_size(cache_size).unwrap())
+                    .build(),
+            );
+            let id = BenchmarkId::new(engine.as_ref(), format!("cache-{}", cache_size));
+            group.bench_function(id, |b| {
+                b.to_async(&runtime).iter(|| async {
+                    let tasks: Vec<_> = (0..CONCURRENCY)
+                        .map(|_| {
+                            let service = service.clone();
+                            let evidence = evidence.clone();
+                            tokio::spawn(async move {
+                                service.attestation(&evidence, None, None).await.unwrap()
+                            })
+                        })
+                        .collect();
+                    for task in tasks {
+                        task.await.unwrap();
+                    }
+                })
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, attestation);
+criterion_main!(benches);
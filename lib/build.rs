@@ -1,13 +1,18 @@
 use std::process::{exit, Command};
 
 fn real_main() -> Result<(), String> {
+    // Only the OPA policy engine needs the Go toolchain.
+    if std::env::var("CARGO_FEATURE_OPA_ENGINE").is_err() {
+        return Ok(());
+    }
+
     let out_dir = std::env::var("OUT_DIR").unwrap();
     println!("cargo:rerun-if-changed={}", out_dir);
     println!("cargo:rustc-link-search=native={}", out_dir);
     println!("cargo:rustc-link-lib=static=opa");
     let opa_dir = "./src/core/policy_engine/opa".to_string();
     let opa = Command::new("go")
-        .args(&[
+        .args([
             "build",
             "-o",
             &format!("{}/libopa.a", out_dir),
@@ -0,0 +1,263 @@
+//! Append-only audit log of the management changes and the attestation decisions.
+//!
+//! The log is a JSON lines file, each record carries the hash of its predecessor and its own
+//! hash over its content and that one, so editing, dropping or reordering the records breaks the
+//! chain, which is detected by [`verify`].
+
+use crate::Error;
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+
+/// The `prev_hash` of the first record.
+pub const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// The hex encoded SHA-256 of `data`, which identifies the contents in the records.
+pub fn sha256(data: &[u8]) -> String {
+    hex::encode(Sha256::digest(data))
+}
+
+/// The audited event.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Event {
+    /// A change made by the management API.
+    Management {
+        /// The authenticated client, `anonymous` if the management API isn't authenticated.
+        principal: String,
+        /// The endpoint, e.g. `set_policy`.
+        action: String,
+        tenant: String,
+        /// The TEE of the policy or reference data, `None` for the TEE-agnostic ones.
+        #[serde(default)]
+        tee: Option<String>,
+        /// The hash of the replaced or deleted content, `None` if there was none.
+        #[serde(default)]
+        old_sha256: Option<String>,
+        /// The hash of the new content, `None` if it's deleted.
+        #[serde(default)]
+        new_sha256: Option<String>,
+    },
+    /// A decision of the attestation.
+    Attestation {
+        tenant: String,
+        tee: String,
+        nonce: String,
+        /// The hash of the TCB claims, empty if the evidence failed to be verified.
+        claims_sha256: String,
+        /// The hash of the policy which made the decision.
+        policy_sha256: String,
+        allow: bool,
+    },
+}
+
+/// A record of the audit log.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Record {
+    /// The sequence number, which starts from 0.
+    pub seq: u64,
+    /// The time (seconds since UNIX epoch).
+    pub time: u64,
+    #[serde(flatten)]
+    pub event: Event,
+    pub prev_hash: String,
+    pub hash: String,
+}
+
+impl Record {
+    // Hash the canonical JSON of the record without its `hash`, whose object keys are sorted.
+    fn digest(&self) -> Result<String> {
+        let mut value = serde_json::to_value(self)?;
+        if let Some(object) = value.as_object_mut() {
+            object.remove("hash");
+        }
+        Ok(sha256(&serde_json::to_vec(&value)?))
+    }
+}
+
+// The sequence number and the hash of the next record's predecessor.
+#[derive(Debug)]
+struct Head {
+    seq: u64,
+    hash: String,
+}
+
+/// The writer of the audit log.
+#[derive(Debug)]
+pub struct AuditLog {
+    file: PathBuf,
+    // Serialize the appends, so the chain isn't forked.
+    head: Mutex<Head>,
+}
+
+impl AuditLog {
+    /// Open the audit log, the records are appended to the existing ones.
+    pub async fn open(file: PathBuf) -> Result<Self> {
+        let error = || Error::Storage(format!("Read audit log {:?} failed.", file));
+        let head = match file.exists() {
+            true => {
+                let content = tokio::fs::read_to_string(&file).await.with_context(error)?;
+                match content.lines().rfind(|line| !line.is_empty()) {
+                    Some(line) => {
+                        let last: Record = serde_json::from_str(line).with_context(error)?;
+                        Head {
+                            seq: last.seq + 1,
+                            hash: last.hash,
+                        }
+                    }
+                    None => Head {
+                        seq: 0,
+                        hash: GENESIS_HASH.to_string(),
+                    },
+                }
+            }
+            false => Head {
+                seq: 0,
+                hash: GENESIS_HASH.to_string(),
+            },
+        };
+        Ok(Self {
+            file,
+            head: Mutex::new(head),
+        })
+    }
+
+    /// Append the event, which is synced to the disk before returning its record.
+    pub async fn append(&self, event: Event) -> Result<Record> {
+        let error = || Error::Storage(format!("Write audit log {:?} failed.", self.file));
+        let mut head = self.head.lock().await;
+        let mut record = Record {
+            seq: head.seq,
+            time: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+            event,
+            prev_hash: head.hash.clone(),
+            hash: String::new(),
+        };
+        record.hash = record.digest()?;
+        let mut line = serde_json::to_vec(&record)?;
+        line.push(b'\n');
+
+        if let Some(dir) = self.file.parent() {
+            tokio::fs::create_dir_all(dir).await.with_context(error)?;
+        }
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.file)
+            .await
+            .with_context(error)?;
+        file.write_all(&line).await.with_context(error)?;
+        file.sync_data().await.with_context(error)?;
+
+        head.seq += 1;
+        head.hash = record.hash.clone();
+        Ok(record)
+    }
+}
+
+/// Verify the hash chain of the audit log's content, returns the number of its records.
+pub fn verify(content: &str) -> Result<u64> {
+    let mut prev_hash = GENESIS_HASH.to_string();
+    let mut seq = 0;
+    for (i, line) in content.lines().enumerate() {
+        if line.is_empty() {
+            continue;
+        }
+        let record: Record = serde_json::from_str(line)
+            .with_context(|| format!("Line {}: malformed record", i + 1))?;
+        if record.seq != seq {
+            bail!(
+                "Line {}: expected record {}, got {}",
+                i + 1,
+                seq,
+                record.seq
+            );
+        }
+        if record.prev_hash != prev_hash {
+            bail!("Line {}: record {} breaks the chain", i + 1, record.seq);
+        }
+        if record.digest()? != record.hash {
+            bail!("Line {}: record {} is tampered", i + 1, record.seq);
+        }
+        prev_hash = record.hash;
+        seq += 1;
+    }
+    Ok(seq)
+}
+
+/// Verify the audit log file like [`verify`].
+pub fn verify_file(path: &Path) -> Result<u64> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Read audit log {:?} failed.", path))?;
+    verify(&content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(i: u64) -> Event {
+        Event::Management {
+            principal: "admin".to_string(),
+            action: "set_policy".to_string(),
+            tenant: "default".to_string(),
+            tee: None,
+            old_sha256: None,
+            new_sha256: Some(sha256(&i.to_be_bytes())),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_audit_log() {
+        let file = std::env::temp_dir().join(format!("audit-{}.log", std::process::id()));
+        let log = AuditLog::open(file.clone()).await.unwrap();
+        for i in 0..3 {
+            let record = log.append(event(i)).await.unwrap();
+            assert_eq!(record.seq, i);
+        }
+        let attestation = Event::Attestation {
+            tenant: "default".to_string(),
+            tee: "sample".to_string(),
+            nonce: "nonce".to_string(),
+            claims_sha256: sha256(b"{}"),
+            policy_sha256: sha256(b"package policy"),
+            allow: true,
+        };
+        log.append(attestation.clone()).await.unwrap();
+
+        // The reopened log continues the chain.
+        let log = AuditLog::open(file.clone()).await.unwrap();
+        let record = log.append(event(4)).await.unwrap();
+        assert_eq!(record.seq, 4);
+        assert_eq!(verify_file(&file).unwrap(), 5);
+
+        let content = std::fs::read_to_string(&file).unwrap();
+        let record: Record = serde_json::from_str(content.lines().nth(3).unwrap()).unwrap();
+        assert_eq!(record.event, attestation);
+
+        // Tamper a record.
+        let tampered = content.replacen("\"allow\":true", "\"allow\":false", 1);
+        let e = verify(&tampered).unwrap_err();
+        assert!(e.to_string().contains("record 3 is tampered"), "{}", e);
+
+        // Drop a record.
+        let mut lines: Vec<&str> = content.lines().collect();
+        lines.remove(1);
+        assert!(verify(&lines.join("\n")).is_err());
+
+        // Re-hash a tampered record, which breaks its successor's link.
+        let mut record: Record = serde_json::from_str(lines[0]).unwrap();
+        record.event = event(100);
+        record.hash = record.digest().unwrap();
+        let mut lines: Vec<String> = content.lines().map(str::to_string).collect();
+        lines[0] = serde_json::to_string(&record).unwrap();
+        let e = verify(&lines.join("\n")).unwrap_err();
+        assert!(e.to_string().contains("record 1 breaks the chain"), "{}", e);
+
+        std::fs::remove_file(file).unwrap();
+    }
+}
@@ -1,17 +1,27 @@
-use anyhow::{Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
 extern crate serde;
 use self::serde::{Deserialize, Serialize};
-use crate::core::policy_engine::opa;
-use crate::default_policy;
-use crate::*;
+use crate::core::policy_engine::{PolicyDecision, PolicyEngine, Violation};
+use crate::metrics::{Metrics, Outcome, UNKNOWN_TEE};
+use crate::nonce::{Challenge, Nonces};
+use crate::rvps::Rvps;
+use crate::{Error, TEE};
 
 pub mod policy_engine;
 pub mod proxy;
+pub mod results;
 pub mod verifier;
-use serde_json::Value;
+use serde_json::{json, Value};
 use std::collections::HashMap;
-use verifier::Verifier;
-use verifier::*;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Instant;
+use verifier::sample::{self, Sample};
+use verifier::{Verifier, VerifierRegistry};
+
+// The nonce and the public key bound by the sample evidence of the self-test.
+const SELF_TEST_NONCE: &str = "self-test";
+const SELF_TEST_PUBKEY: &str = "self-test";
 
 #[macro_export]
 macro_rules! default_policy {
@@ -34,84 +44,291 @@ pub struct Evidence {
 pub struct AttestationResults {
     pub tee: String,
     pub allow: bool,
+    /// The claims mismatching the reference, which explain why the evidence isn't allowed.
+    #[serde(default)]
+    pub violations: Vec<Violation>,
     pub verifier_output: String,
+    /// The machine-readable reason of the verifier's failure, see [`crate::Error::reason`].
+    #[serde(default)]
+    pub verifier_error_reason: Option<String>,
     pub policy_engine_output: String,
     pub tcb: String,
 }
 
 pub type TeeEvidenceParsedClaim = serde_json::Value;
 
-impl TEE {
-    fn to_verifier(&self) -> Result<Box<dyn Verifier + Send + Sync>> {
-        match self {
-            TEE::SAMPLE => {
-                Ok(Box::new(sample::Sample::default()) as Box<dyn Verifier + Send + Sync>)
-            }
-            _ => Err(anyhow!("TEE is not supported!")),
+/// The customized policy and reference data. The TEE's built-in default policy and the
+/// reference values from RVPS are used when they are absent.
+#[derive(Debug, Default, Clone)]
+pub struct PolicyData {
+    pub policy: Option<String>,
+    pub reference_data: Option<String>,
+}
+
+/// The customized policies and reference data of the TEEs, the ones matching `Evidence.tee`
+/// are selected during the attestation.
+#[derive(Debug, Default, Clone)]
+pub struct TeePolicies {
+    /// The TEE-agnostic policy and reference data, which apply to the TEEs without their own.
+    pub common: PolicyData,
+    /// The policy and reference data of each TEE, keyed by the lowercase TEE name.
+    pub tees: HashMap<String, PolicyData>,
+}
+
+impl From<PolicyData> for TeePolicies {
+    fn from(common: PolicyData) -> Self {
+        Self {
+            common,
+            tees: HashMap::new(),
+        }
+    }
+}
+
+impl TeePolicies {
+    /// Select the policy and reference data of TEE `tee`, each of them falls back to the
+    /// TEE-agnostic one separately.
+    pub fn select(&self, tee: &str) -> PolicyData {
+        let own = self.tees.get(&tee.to_ascii_lowercase());
+        PolicyData {
+            policy: own
+                .and_then(|data| data.policy.clone())
+                .or_else(|| self.common.policy.clone()),
+            reference_data: own
+                .and_then(|data| data.reference_data.clone())
+                .or_else(|| self.common.reference_data.clone()),
         }
     }
 }
 
-#[derive(Debug, Default)]
-pub struct Attestation {}
+/// The built-in default policy of TEE `tee`. The TEEs without their own, e.g. the sample TEE
+/// and the customized ones, share the TEE-agnostic default policy.
+pub fn tee_default_policy(tee: &str) -> &'static str {
+    match TEE::from_str(tee) {
+        Ok(TEE::TDX) => std::include_str!("./policy_engine/tdx_default_policy.rego"),
+        Ok(TEE::SGX) => std::include_str!("./policy_engine/sgx_default_policy.rego"),
+        Ok(TEE::SEVSNP) => std::include_str!("./policy_engine/sevsnp_default_policy.rego"),
+        _ => std::include_str!(default_policy!()),
+    }
+}
+
+#[derive(Debug)]
+pub struct Attestation {
+    verifiers: VerifierRegistry,
+    rvps: Option<Arc<Rvps>>,
+    policy_engine: Box<dyn PolicyEngine>,
+    nonces: Option<Arc<Nonces>>,
+    metrics: Option<Arc<Metrics>>,
+}
 
 impl Attestation {
-    pub async fn evaluate(
+    pub fn new(
+        verifiers: VerifierRegistry,
+        rvps: Option<Arc<Rvps>>,
+        policy_engine: Box<dyn PolicyEngine>,
+        nonces: Option<Arc<Nonces>>,
+        metrics: Option<Arc<Metrics>>,
+    ) -> Self {
+        Self {
+            verifiers,
+            rvps,
+            policy_engine,
+            nonces,
+            metrics,
+        }
+    }
+
+    // Count the attestation of `tee` by its outcome, along with its latency since `start`.
+    fn record(&self, tee: &str, outcome: Outcome, start: Instant) {
+        if let Some(metrics) = &self.metrics {
+            metrics.attestation(tee, outcome, start.elapsed());
+        }
+    }
+
+    /// Update the size of the policy engine's cache in the metrics.
+    pub fn record_policy_cache(&self) {
+        if let Some(metrics) = &self.metrics {
+            metrics.policy_cache(self.policy_engine.cached());
+        }
+    }
+
+    /// Attest the built-in sample evidence by the sample verifier and the TEE-agnostic default
+    /// policy, which should allow it against the matching reference data and deny it against
+    /// the mismatching one. The metrics aren't recorded.
+    pub async fn self_test(&self) -> Result<()> {
+        let evidence = sample::evidence(SELF_TEST_NONCE, SELF_TEST_PUBKEY, 1, 1);
+        let claims = Sample::default()
+            .evaluate(&evidence)
+            .await
+            .context("The sample verifier rejects the sample evidence.")?;
+        let input = serde_json::to_string(&claims)?;
+        let policy = tee_default_policy(&evidence.tee);
+        for (reference, expected) in [(json!(1), true), (json!([2, 3]), false)] {
+            let reference = json!({ "reference": { "svn": reference } }).to_string();
+            let output = self.policy_engine.evaluate(policy, &reference, &input)?;
+            let decision = PolicyDecision::from_output(&output)?;
+            if decision.allow != expected {
+                bail!(
+                    "The default policy decides allow = {} for the sample evidence against {}.",
+                    decision.allow,
+                    reference
+                );
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn challenge(&self) -> Result<Challenge> {
+        match &self.nonces {
+            Some(nonces) => nonces.issue().await,
+            None => Err(anyhow!("The nonces aren't enabled")),
+        }
+    }
+
+    pub fn policy_engine(&self) -> &dyn PolicyEngine {
+        self.policy_engine.as_ref()
+    }
+
+    pub async fn evaluate(&self, evidence: &str, policies: &TeePolicies) -> Result<String> {
+        let (_, attestation_results) = self.evaluate_results(evidence, policies).await?;
+        Ok(serde_json::to_string(&attestation_results)?)
+    }
+
+    /// Evaluate the evidence with the policy and reference data of its TEE, returns the parsed
+    /// evidence along with its attestation results.
+    pub async fn evaluate_results(
         &self,
         evidence: &str,
-        policy: Option<String>,
-        reference_data: Option<String>,
-    ) -> Result<String> {
-        let evidence =
-            serde_json::from_str::<Evidence>(evidence).context("Deserialize Evidence failed.")?;
-        let verifier = TEE::from_str(&evidence.tee)?.to_verifier()?;
-
-        let claims_from_tee_evidence = match verifier.evaluate(&evidence).await {
+        policies: &TeePolicies,
+    ) -> Result<(Evidence, AttestationResults)> {
+        let start = Instant::now();
+        let evidence = serde_json::from_str::<Evidence>(evidence).context(
+            Error::MalformedEvidence("Deserialize Evidence failed.".to_string()),
+        );
+        let evidence = match evidence {
+            Ok(evidence) => evidence,
+            Err(e) => {
+                self.record(UNKNOWN_TEE, Outcome::VerifierError, start);
+                return Err(e);
+            }
+        };
+        let verifier = match self.verifiers.get(&evidence.tee) {
+            Ok(verifier) => verifier,
+            Err(e) => {
+                self.record(UNKNOWN_TEE, Outcome::VerifierError, start);
+                return Err(e);
+            }
+        };
+        // The TEE is registered, so the labels are bounded.
+        let tee = evidence.tee.to_ascii_lowercase();
+
+        let verifier_start = Instant::now();
+        let claims = verifier.evaluate(&evidence).await;
+        if let Some(metrics) = &self.metrics {
+            metrics.verifier(&tee, verifier_start.elapsed());
+        }
+        let claims_from_tee_evidence = match claims {
             Ok(claims) => claims,
             Err(e) => {
-                let attestation_results = serde_json::to_string(&AttestationResults {
+                self.record(&tee, Outcome::VerifierError, start);
+                let attestation_results = AttestationResults {
                     tee: evidence.tee.clone(),
                     allow: false,
+                    violations: Vec::new(),
                     verifier_output: format!("Verifier evaluate failed: {:?}", e),
+                    verifier_error_reason: Error::find(&e).map(|e| e.reason().to_string()),
                     policy_engine_output: String::default(),
                     tcb: String::default(),
-                })?;
-                return Ok(attestation_results);
+                };
+                return Ok((evidence, attestation_results));
             }
         };
+        // The nonce is consumed once the verifier proves the evidence is bound to it, so the
+        // malformed or forged evidence can't burn an issued challenge.
+        if let Some(nonces) = &self.nonces {
+            if let Err(e) = nonces.consume(&evidence.nonce).await {
+                self.record(&tee, Outcome::VerifierError, start);
+                return Err(e);
+            }
+        }
 
-        let opa_input_data = serde_json::to_string(&claims_from_tee_evidence)?;
+        let attestation_results = match self
+            .evaluate_claims(&evidence.tee, &claims_from_tee_evidence, policies)
+            .await
+        {
+            Ok(attestation_results) => attestation_results,
+            Err(e) => {
+                self.record(&tee, Outcome::PolicyError, start);
+                return Err(e);
+            }
+        };
+        let outcome = match attestation_results.allow {
+            true => Outcome::Allow,
+            false => Outcome::Deny,
+        };
+        self.record(&tee, outcome, start);
+        Ok((evidence, attestation_results))
+    }
 
-        let opa_policy = policy.unwrap_or_else(|| std::include_str!(default_policy!()).to_string());
+    /// Evaluate the TCB claims which were parsed from TEE `tee`'s evidence with the policy and
+    /// reference data of the TEE, e.g. to re-evaluate them after the policy is updated.
+    pub async fn evaluate_claims(
+        &self,
+        tee: &str,
+        claims_from_tee_evidence: &TeeEvidenceParsedClaim,
+        policies: &TeePolicies,
+    ) -> Result<AttestationResults> {
+        let opa_input_data = serde_json::to_string(claims_from_tee_evidence)?;
+
+        let PolicyData {
+            policy,
+            reference_data,
+        } = policies.select(tee);
+        let opa_policy = policy.unwrap_or_else(|| tee_default_policy(tee).to_string());
         let opa_reference_data = match reference_data {
             Some(data) => data,
             None => {
-                let mut data: HashMap<String, Vec<String>> = HashMap::new();
-                let claims_map: HashMap<String, serde_json::Value> =
+                // The reference values from RVPS, the other claims are not restricted.
+                let mut data = match &self.rvps {
+                    Some(rvps) => rvps.reference(tee).await?,
+                    None => serde_json::Map::new(),
+                };
+                let claims_map: serde_json::Map<String, Value> =
                     serde_json::from_value(claims_from_tee_evidence.clone())?;
                 for key in claims_map.keys() {
-                    data.insert(key.to_string(), Vec::new());
+                    data.entry(key.to_string())
+                        .or_insert_with(|| Value::Array(Vec::new()));
                 }
                 serde_json::json!({ "reference": data }).to_string()
             }
         };
-        // TODO: Update the reference data with RVPS.
 
-        let opa_output = opa::evaluate(opa_policy, opa_reference_data, opa_input_data)?;
-        let v_opa_output: Value = serde_json::from_str(&opa_output)?;
+        let policy_start = Instant::now();
+        let opa_output =
+            self.policy_engine
+                .evaluate(&opa_policy, &opa_reference_data, &opa_input_data);
+        if let Some(metrics) = &self.metrics {
+            metrics.policy(&tee.to_ascii_lowercase(), policy_start.elapsed());
+        }
+        self.record_policy_cache();
+        let opa_output = opa_output?;
+        let decision = PolicyDecision::from_output(&opa_output)?;
 
         let attestation_results = AttestationResults {
-            tee: evidence.tee.clone(),
-            allow: v_opa_output["allow"].as_bool().unwrap_or(false),
+            tee: tee.to_string(),
+            allow: decision.allow,
+            violations: decision.violations,
             verifier_output: String::default(),
+            verifier_error_reason: None,
             policy_engine_output: opa_output,
-            tcb: serde_json::to_string(&claims_from_tee_evidence)?,
+            tcb: opa_input_data,
         };
 
-        let results = serde_json::to_string(&attestation_results)?;
-
         debug!("Attestation Results: {:?}", &attestation_results);
-        Ok(results)
+        Ok(attestation_results)
+    }
+
+    pub fn tees(&self) -> Vec<String> {
+        self.verifiers.tees()
     }
 
     pub fn default_policy(&self) -> Result<String> {
@@ -1 +1,310 @@
+use crate::Error;
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+#[cfg(feature = "opa-engine")]
 pub mod opa;
+#[cfg(feature = "regorus-engine")]
+pub mod regorus;
+
+/// The default capacity of the compiled policy cache.
+pub const DEFAULT_POLICY_CACHE_SIZE: usize = 32;
+
+/// The Rego module of the helpers shared by the default policies (`package lib`), which every
+/// engine compiles along with the policy.
+pub const POLICY_LIB: &str = include_str!("lib.rego");
+
+/// The policy engine evaluates the TCB claims parsed from the TEE evidence.
+pub trait PolicyEngine: std::fmt::Debug + Send + Sync {
+    /// Evaluate the `input` claims with the Rego `policy` and the `data` document, all of them
+    /// are JSON (the policy aside). Returns the JSON serialized `data.policy` document, whose
+    /// `allow` is the decision.
+    fn evaluate(&self, policy: &str, data: &str, input: &str) -> Result<String>;
+
+    /// Drop the compiled `policy` from the cache, e.g. when it's replaced.
+    fn invalidate(&self, policy: &str);
+
+    /// The number of the compiled policies in the cache.
+    fn cached(&self) -> usize {
+        0
+    }
+}
+
+/// A claim mismatching the reference, reported by the `violations` rule of the policy.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Violation {
+    /// The key of the claim in the policy input.
+    pub claim: String,
+    /// The claim's value parsed from the evidence.
+    pub observed: Value,
+    /// The reference value(s) the claim is expected to match.
+    pub expected: Value,
+}
+
+/// The decision made by the policy, i.e. the `allow` and `violations` of the `data.policy`
+/// document. The policies without the `violations` rule have no violations reported.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct PolicyDecision {
+    pub allow: bool,
+    pub violations: Vec<Violation>,
+}
+
+impl PolicyDecision {
+    /// Parse the decision from the output of `PolicyEngine::evaluate()`.
+    pub fn from_output(output: &str) -> Result<Self> {
+        let output: Value = serde_json::from_str(output).context(Error::PolicyEval(
+            "Deserialize policy output failed.".to_string(),
+        ))?;
+        let mut violations: Vec<Violation> = match output.get("violations") {
+            Some(violations) => serde_json::from_value(violations.clone()).context(
+                Error::PolicyEval("Deserialize policy violations failed.".to_string()),
+            )?,
+            None => Vec::new(),
+        };
+        violations.sort_by(|a, b| a.claim.cmp(&b.claim));
+        Ok(Self {
+            allow: output["allow"].as_bool().unwrap_or(false),
+            violations,
+        })
+    }
+}
+
+/// The built-in policy engines, each of them is enabled by its cargo feature:
+/// - OPA: Open Policy Agent linked via cgo (`opa-engine`), which needs the Go toolchain to build.
+/// - REGORUS: The pure Rust Rego interpreter (`regorus-engine`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumString, AsRefStr)]
+#[strum(ascii_case_insensitive, serialize_all = "lowercase")]
+pub enum PolicyEngineType {
+    OPA,
+    REGORUS,
+}
+
+impl Default for PolicyEngineType {
+    /// OPA if it's enabled, otherwise Regorus.
+    fn default() -> Self {
+        if cfg!(feature = "opa-engine") {
+            PolicyEngineType::OPA
+        } else {
+            PolicyEngineType::REGORUS
+        }
+    }
+}
+
+impl PolicyEngineType {
+    /// Create the policy engine, it fails if the engine isn't enabled.
+    pub fn to_engine(self) -> Result<Box<dyn PolicyEngine>> {
+        self.to_engine_with_cache_size(DEFAULT_POLICY_CACHE_SIZE)
+    }
+
+    /// Create the policy engine which caches at most `cache_size` compiled policies, 0 disables
+    /// the cache.
+    pub fn to_engine_with_cache_size(self, cache_size: usize) -> Result<Box<dyn PolicyEngine>> {
+        match self {
+            #[cfg(feature = "opa-engine")]
+            PolicyEngineType::OPA => Ok(Box::new(opa::Opa::new(cache_size))),
+            #[cfg(feature = "regorus-engine")]
+            PolicyEngineType::REGORUS => Ok(Box::new(regorus::Regorus::new(cache_size))),
+            #[allow(unreachable_patterns)]
+            engine => Err(anyhow!(
+                "Policy engine {} isn't enabled by its cargo feature",
+                engine.as_ref()
+            )),
+        }
+    }
+
+    /// The enabled policy engines.
+    pub fn enabled() -> Vec<PolicyEngineType> {
+        let mut engines = Vec::new();
+        if cfg!(feature = "opa-engine") {
+            engines.push(PolicyEngineType::OPA);
+        }
+        if cfg!(feature = "regorus-engine") {
+            engines.push(PolicyEngineType::REGORUS);
+        }
+        engines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    fn dummy_reference(ver: u64) -> String {
+        json!({
+            "reference": {
+                "productId": ver,
+                "svn": ver
+            }
+        })
+        .to_string()
+    }
+
+    fn dummy_input(product_id: u64, svn: u64) -> String {
+        json!({
+            "productId": product_id,
+            "svn": svn
+        })
+        .to_string()
+    }
+
+    // Run the test against every enabled policy engine.
+    fn engines() -> Vec<Box<dyn PolicyEngine>> {
+        PolicyEngineType::enabled()
+            .into_iter()
+            .map(|engine| engine.to_engine().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_evaluate() {
+        let policy = std::include_str!("default_policy.rego");
+
+        for engine in engines() {
+            let res = engine.evaluate(policy, &dummy_reference(5), &dummy_input(5, 5));
+            assert!(res.is_ok(), "{:?} evaluate() should be success", engine);
+            let v: Value = serde_json::from_str(&res.unwrap()).unwrap();
+            assert!(v["allow"] == true, "allow should be true");
+
+            let res = engine.evaluate(policy, &dummy_reference(5), &dummy_input(0, 0));
+            assert!(res.is_ok(), "{:?} evaluate() should be success", engine);
+            let v: Value = serde_json::from_str(&res.unwrap()).unwrap();
+            assert!(v["allow"] == false, "allow should be false");
+        }
+    }
+
+    #[test]
+    fn test_evaluate_violations() {
+        let policy = std::include_str!("default_policy.rego");
+        let reference = json!({ "reference": { "productId": [1, 2], "svn": 5 } }).to_string();
+
+        for engine in engines() {
+            let output = engine
+                .evaluate(policy, &reference, &dummy_input(3, 5))
+                .unwrap();
+            let decision = PolicyDecision::from_output(&output).unwrap();
+            assert!(!decision.allow, "allow should be false");
+            assert_eq!(
+                decision.violations,
+                vec![Violation {
+                    claim: "productId".to_string(),
+                    observed: json!(3),
+                    expected: json!([1, 2]),
+                }],
+                "{:?} should report the mismatched claim",
+                engine
+            );
+
+            let output = engine
+                .evaluate(policy, &reference, &dummy_input(2, 5))
+                .unwrap();
+            let decision = PolicyDecision::from_output(&output).unwrap();
+            assert!(decision.allow, "allow should be true");
+            assert!(decision.violations.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_evaluate_policy_lib() {
+        // The customized policy calls the library of the default policies.
+        let policy = "package policy\n\
+            allow {\n data.lib.claims_match(input)\n}\n\
+            violations[v] {\n v := data.lib.mismatches(input)[_]\n}";
+
+        for engine in engines() {
+            let output = engine
+                .evaluate(policy, &dummy_reference(5), &dummy_input(5, 5))
+                .unwrap();
+            let v: Value = serde_json::from_str(&output).unwrap();
+            assert_eq!(
+                v,
+                json!({ "allow": true, "violations": [] }),
+                "{:?}",
+                engine
+            );
+
+            let output = engine
+                .evaluate(policy, &dummy_reference(5), &dummy_input(5, 4))
+                .unwrap();
+            let decision = PolicyDecision::from_output(&output).unwrap();
+            assert!(!decision.allow, "allow should be false");
+            assert_eq!(decision.violations[0].claim, "svn");
+        }
+    }
+
+    #[test]
+    fn test_policy_decision_without_violations() {
+        let decision = PolicyDecision::from_output(r#"{"allow": true}"#).unwrap();
+        assert_eq!(
+            decision,
+            PolicyDecision {
+                allow: true,
+                violations: Vec::new(),
+            }
+        );
+        assert!(PolicyDecision::from_output(r#"{"violations": 1}"#).is_err());
+    }
+
+    #[test]
+    fn test_evaluate_tee_default_policy() {
+        let policy = std::include_str!("tdx_default_policy.rego");
+        let reference = json!({ "reference": { "mrtd": ["aa"] } }).to_string();
+
+        for engine in engines() {
+            let input = json!({ "mrtd": "aa", "td_attributes": "0000001000000000" });
+            let res = engine.evaluate(policy, &reference, &input.to_string());
+            let v: Value = serde_json::from_str(&res.unwrap()).unwrap();
+            assert!(v["allow"] == true, "allow should be true");
+
+            // The debug TD
+            let input = json!({ "mrtd": "aa", "td_attributes": "0100001000000000" });
+            let res = engine.evaluate(policy, &reference, &input.to_string());
+            let decision = PolicyDecision::from_output(&res.unwrap()).unwrap();
+            assert!(!decision.allow, "allow should be false");
+            assert_eq!(decision.violations[0].claim, "td_attributes");
+        }
+
+        // The debug enclave, whose `debug` claim is reported as well.
+        let policy = std::include_str!("sgx_default_policy.rego");
+        for engine in engines() {
+            let input = json!({ "mrtd": "aa", "debug": true });
+            let output = engine.evaluate(policy, &reference, &input.to_string());
+            let decision = PolicyDecision::from_output(&output.unwrap()).unwrap();
+            assert!(!decision.allow, "allow should be false");
+            assert_eq!(
+                decision.violations,
+                vec![Violation {
+                    claim: "debug".to_string(),
+                    observed: json!(true),
+                    expected: json!(false),
+                }]
+            );
+        }
+    }
+
+    #[test]
+    fn test_evaluate_illegal_policy() {
+        for engine in engines() {
+            let res = engine.evaluate("package policy\nallow {", "{}", "{}");
+            assert!(
+                res.is_err(),
+                "{:?} should reject the illegal policy",
+                engine
+            );
+            assert_eq!(
+                Error::find(&res.unwrap_err()).map(Error::reason),
+                Some("POLICY_COMPILE")
+            );
+        }
+    }
+
+    #[test]
+    fn test_policy_engine_type() {
+        assert_eq!(
+            "Regorus".parse::<PolicyEngineType>().unwrap(),
+            PolicyEngineType::REGORUS
+        );
+        assert!(PolicyEngineType::enabled().contains(&PolicyEngineType::default()));
+    }
+}
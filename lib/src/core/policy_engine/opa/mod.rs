@@ -1,4 +1,6 @@
-use anyhow::{anyhow, Result};
+use super::{PolicyEngine, POLICY_LIB};
+use crate::Error;
+use anyhow::Result;
 use std::ffi::CStr;
 use std::os::raw::c_char;
 
@@ -6,6 +8,10 @@ use std::os::raw::c_char;
 #[link(name = "opa")]
 extern "C" {
     pub fn evaluateGo(policy: GoString, data: GoString, input: GoString) -> *mut c_char;
+    pub fn setCacheCapacityGo(capacity: i64);
+    pub fn setLibraryGo(lib: GoString);
+    pub fn invalidateGo(policy: GoString);
+    pub fn cacheLenGo() -> i64;
 }
 
 /// String structure passed into cgo
@@ -16,68 +22,63 @@ pub struct GoString {
     pub n: isize,
 }
 
-pub fn evaluate(policy: String, reference: String, input: String) -> Result<String> {
-    let policy_go = GoString {
-        p: policy.as_ptr() as *const i8,
-        n: policy.len() as isize,
-    };
-
-    let reference_go = GoString {
-        p: reference.as_ptr() as *const i8,
-        n: reference.len() as isize,
-    };
+impl GoString {
+    fn new(s: &str) -> Self {
+        Self {
+            p: s.as_ptr() as *const c_char,
+            n: s.len() as isize,
+        }
+    }
+}
 
-    let input_go = GoString {
-        p: input.as_ptr() as *const i8,
-        n: input.len() as isize,
-    };
+/// The Open Policy Agent linked via cgo. The queries of the policies prepared against their
+/// reference data are cached in the Go runtime, whose capacity is shared by all the instances.
+#[derive(Debug)]
+pub struct Opa {}
 
-    // Call the function exported by cgo and process the returned decision
-    let decision_buf: *mut c_char = unsafe { evaluateGo(policy_go, reference_go, input_go) };
-    let decision_str: &CStr = unsafe { CStr::from_ptr(decision_buf) };
-    let res = decision_str.to_str()?.to_string();
-    debug!("Evaluated: {}", res);
-    if res.starts_with("Error::") {
-        return Err(anyhow!(res));
+impl Opa {
+    pub fn new(cache_size: usize) -> Self {
+        unsafe {
+            setLibraryGo(GoString::new(POLICY_LIB));
+            setCacheCapacityGo(cache_size as i64);
+        }
+        Self {}
     }
-    Ok(res)
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use serde_json::{json, Value};
-
-    fn dummy_reference(ver: u64) -> String {
-        json!({
-            "reference": {
-                "productId": ver,
-                "svn": ver
-            }
-        })
-        .to_string()
+impl PolicyEngine for Opa {
+    fn evaluate(&self, policy: &str, data: &str, input: &str) -> Result<String> {
+        evaluate(policy, data, input)
     }
 
-    fn dummy_input(product_id: u64, svn: u64) -> String {
-        json!({
-            "productId": product_id,
-            "svn": svn
-        })
-        .to_string()
+    fn invalidate(&self, policy: &str) {
+        unsafe { invalidateGo(GoString::new(policy)) };
     }
 
-    #[test]
-    fn test_evaluate() {
-        let policy = std::include_str!("../default_policy.rego").to_string();
+    fn cached(&self) -> usize {
+        unsafe { cacheLenGo() as usize }
+    }
+}
 
-        let res = evaluate(policy.clone(), dummy_reference(5), dummy_input(5, 5));
-        assert!(res.is_ok(), "OPA execution() should be success");
-        let v: Value = serde_json::from_str(&res.unwrap()).unwrap();
-        assert!(v["allow"] == true, "allow should be true");
+pub fn evaluate(policy: &str, reference: &str, input: &str) -> Result<String> {
+    let policy_go = GoString::new(policy);
+    let reference_go = GoString::new(reference);
+    let input_go = GoString::new(input);
 
-        let res = evaluate(policy.clone(), dummy_reference(5), dummy_input(0, 0));
-        assert!(res.is_ok(), "OPA execution() should be success");
-        let v: Value = serde_json::from_str(&res.unwrap()).unwrap();
-        assert!(v["allow"] == false, "allow should be false");
+    // Call the function exported by cgo and process the returned decision
+    let decision_buf: *mut c_char = unsafe { evaluateGo(policy_go, reference_go, input_go) };
+    let decision_str: &CStr = unsafe { CStr::from_ptr(decision_buf) };
+    let res = decision_str.to_str().map(|res| res.to_string());
+    // The decision is allocated by `C.CString` with malloc.
+    unsafe { libc::free(decision_buf as *mut libc::c_void) };
+    let res = res?;
+    debug!("Evaluated: {}", res);
+    // The compile errors are prefixed by "CompileError::", the other ones by "Error::".
+    if res.starts_with("CompileError::") {
+        return Err(Error::PolicyCompile(res).into());
     }
+    if res.starts_with("Error::") {
+        return Err(Error::PolicyEval(res).into());
+    }
+    Ok(res)
 }
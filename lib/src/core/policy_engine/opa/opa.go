@@ -3,13 +3,140 @@ package main
 import "C"
 
 import (
+	"container/list"
 	"context"
+	"crypto/sha256"
 	"encoding/json"
+	"sync"
 
+	"github.com/open-policy-agent/opa/ast"
 	"github.com/open-policy-agent/opa/rego"
 	"github.com/open-policy-agent/opa/storage/inmem"
 )
 
+// The prepared query cached in the LRU, keyed by the SHA-256 of the policy text and of the
+// reference data, which is bound to the query's store when it's prepared.
+type cacheKey struct {
+	policy [sha256.Size]byte
+	data   [sha256.Size]byte
+}
+
+type preparedPolicy struct {
+	key   cacheKey
+	query rego.PreparedEvalQuery
+}
+
+var (
+	// The library module compiled along with each policy, which is set by the Rust side.
+	library       string
+	cacheLock     sync.Mutex
+	cacheCapacity = 32
+	cacheList     = list.New()
+	cacheEntries  = make(map[cacheKey]*list.Element)
+)
+
+// Evict the least recently used queries beyond the capacity, the caller holds cacheLock.
+func evict() {
+	for cacheList.Len() > cacheCapacity {
+		oldest := cacheList.Back()
+		cacheList.Remove(oldest)
+		delete(cacheEntries, oldest.Value.(*preparedPolicy).key)
+	}
+}
+
+// Compile the policy module and prepare its query against the reference data, or reuse the
+// cached one of the same policy text and reference data.
+func prepare(ctx context.Context, policy string, data string) (rego.PreparedEvalQuery, error) {
+	key := cacheKey{sha256.Sum256([]byte(policy)), sha256.Sum256([]byte(data))}
+	cacheLock.Lock()
+	if elem, ok := cacheEntries[key]; ok {
+		cacheList.MoveToFront(elem)
+		cacheLock.Unlock()
+		return elem.Value.(*preparedPolicy).query, nil
+	}
+	cacheLock.Unlock()
+
+	dataMap := make(map[string]interface{})
+	if err := json.Unmarshal([]byte(data), &dataMap); err != nil {
+		return rego.PreparedEvalQuery{}, err
+	}
+	cacheLock.Lock()
+	lib := library
+	cacheLock.Unlock()
+	compiler, err := ast.CompileModules(map[string]string{"lib.rego": lib, "policy.rego": policy})
+	if err != nil {
+		return rego.PreparedEvalQuery{}, &compileError{err}
+	}
+	// The in-memory store of the reference data is only read by the evaluations.
+	query, err := rego.New(
+		rego.Query("input;data.policy"),
+		rego.Compiler(compiler),
+		rego.Store(inmem.NewFromObject(dataMap)),
+	).PrepareForEval(ctx)
+	if err != nil {
+		return rego.PreparedEvalQuery{}, &compileError{err}
+	}
+
+	cacheLock.Lock()
+	defer cacheLock.Unlock()
+	if _, ok := cacheEntries[key]; !ok && cacheCapacity > 0 {
+		cacheEntries[key] = cacheList.PushFront(&preparedPolicy{key, query})
+		evict()
+	}
+	return query, nil
+}
+
+// The failure to compile the policy, unlike the malformed reference data.
+type compileError struct {
+	err error
+}
+
+func (e *compileError) Error() string {
+	return e.err.Error()
+}
+
+//export setCacheCapacityGo
+func setCacheCapacityGo(capacity int) {
+	cacheLock.Lock()
+	defer cacheLock.Unlock()
+	cacheCapacity = capacity
+	evict()
+}
+
+//export setLibraryGo
+func setLibraryGo(lib string) {
+	// Copy the string, whose memory is owned by the caller.
+	copied := string([]byte(lib))
+	cacheLock.Lock()
+	defer cacheLock.Unlock()
+	if library != copied {
+		library = copied
+		cacheList.Init()
+		cacheEntries = make(map[cacheKey]*list.Element)
+	}
+}
+
+//export cacheLenGo
+func cacheLenGo() int {
+	cacheLock.Lock()
+	defer cacheLock.Unlock()
+	return cacheList.Len()
+}
+
+//export invalidateGo
+func invalidateGo(policy string) {
+	key := sha256.Sum256([]byte(policy))
+	cacheLock.Lock()
+	defer cacheLock.Unlock()
+	// Drop the queries of the policy prepared against any reference data.
+	for entryKey, elem := range cacheEntries {
+		if entryKey.policy == key {
+			cacheList.Remove(elem)
+			delete(cacheEntries, entryKey)
+		}
+	}
+}
+
 //export evaluateGo
 func evaluateGo(policy string, data string, input string) *C.char {
 	// Deserialize the message in json format
@@ -19,25 +146,11 @@ func evaluateGo(policy string, data string, input string) *C.char {
 		return C.CString("Error:: " + err.Error())
 	}
 
-	data_map := make(map[string]interface{})
-	err2 := json.Unmarshal([]byte(data), &data_map)
-	if err2 != nil {
-		return C.CString("Error:: " + err.Error())
-	}
-	// Manually create the storage layer. inmem.NewFromObject returns an
-	// in-memory store containing the supplied data.
-	store := inmem.NewFromObject(data_map)
-
-	// Construct a Rego object that can be prepared or evaluated.
-	r := rego.New(
-		rego.Query("input;data.policy"),
-		rego.Module("policy.rego", policy),
-		rego.Store(store),
-	)
-
-	// Create a prepared query that can be evaluated.
 	ctx := context.Background()
-	query, err := r.PrepareForEval(ctx)
+	query, err := prepare(ctx, policy, data)
+	if _, ok := err.(*compileError); ok {
+		return C.CString("CompileError:: " + err.Error())
+	}
 	if err != nil {
 		return C.CString("Error:: " + err.Error())
 	}
@@ -0,0 +1,158 @@
+use super::{PolicyEngine, POLICY_LIB};
+use crate::Error;
+use anyhow::Result;
+use lru::LruCache;
+use regorus::{Engine, Value};
+use sha2::{Digest, Sha256};
+use std::num::NonZeroUsize;
+use std::sync::Mutex;
+
+/// The pure Rust Rego interpreter, which doesn't need the Go toolchain. The engines with the
+/// parsed policies are cached in a LRU keyed by the SHA-256 of the policy text.
+#[derive(Debug)]
+pub struct Regorus {
+    cache: Option<Mutex<LruCache<[u8; 32], Engine>>>,
+}
+
+fn key(policy: &str) -> [u8; 32] {
+    Sha256::digest(policy.as_bytes()).into()
+}
+
+fn add_policy(engine: &mut Engine, policy: &str) -> Result<()> {
+    engine
+        .add_policy("lib.rego".to_string(), POLICY_LIB.to_string())
+        .map_err(|e| Error::PolicyCompile(e.to_string()))?;
+    engine
+        .add_policy("policy.rego".to_string(), policy.to_string())
+        .map_err(|e| Error::PolicyCompile(e.to_string()))?;
+    // The rules are only analyzed by the first evaluation, evaluate a clone against the empty
+    // data and input to report the unsafe variables and alike as compile errors.
+    engine
+        .clone()
+        .eval_query("data.policy".to_string(), false)
+        .map_err(|e| Error::PolicyCompile(e.to_string()))?;
+    Ok(())
+}
+
+impl Regorus {
+    pub fn new(cache_size: usize) -> Self {
+        Self {
+            cache: NonZeroUsize::new(cache_size).map(|size| Mutex::new(LruCache::new(size))),
+        }
+    }
+
+    // Get the engine with the parsed policy, it's cloned from the cached one if any.
+    fn engine(&self, policy: &str) -> Result<Engine> {
+        let cache = match &self.cache {
+            Some(cache) => cache,
+            None => {
+                let mut engine = Engine::new();
+                add_policy(&mut engine, policy)?;
+                return Ok(engine);
+            }
+        };
+
+        let key = key(policy);
+        if let Some(engine) = cache.lock().unwrap().get(&key) {
+            return Ok(engine.clone());
+        }
+        let mut engine = Engine::new();
+        add_policy(&mut engine, policy)?;
+        cache.lock().unwrap().put(key, engine.clone());
+        Ok(engine)
+    }
+}
+
+impl PolicyEngine for Regorus {
+    fn evaluate(&self, policy: &str, data: &str, input: &str) -> Result<String> {
+        let mut engine = self.engine(policy)?;
+        let eval_error = |e: anyhow::Error| Error::PolicyEval(e.to_string());
+        engine
+            .add_data(Value::from_json_str(data).map_err(eval_error)?)
+            .map_err(eval_error)?;
+        engine.set_input(Value::from_json_str(input).map_err(eval_error)?);
+
+        let results = engine
+            .eval_query("data.policy".to_string(), false)
+            .map_err(eval_error)?;
+        let decision = results
+            .result
+            .first()
+            .and_then(|result| result.expressions.first())
+            .ok_or_else(|| Error::PolicyEval("The policy isn't defined".to_string()))?;
+        let res = serde_json::to_string(&decision.value)?;
+        debug!("Evaluated: {}", res);
+        Ok(res)
+    }
+
+    fn invalidate(&self, policy: &str) {
+        if let Some(cache) = &self.cache {
+            cache.lock().unwrap().pop(&key(policy));
+        }
+    }
+
+    fn cached(&self) -> usize {
+        self.cache
+            .as_ref()
+            .map_or(0, |cache| cache.lock().unwrap().len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    const POLICY: &str =
+        "package policy\ndefault allow = false\nallow {\n input.svn >= data.svn\n}";
+
+    fn cached(engine: &Regorus, policy: &str) -> bool {
+        engine
+            .cache
+            .as_ref()
+            .unwrap()
+            .lock()
+            .unwrap()
+            .contains(&key(policy))
+    }
+
+    #[test]
+    fn test_evaluate_package() {
+        let res = Regorus::new(1).evaluate(POLICY, r#"{"svn": 1}"#, r#"{"svn": 2}"#);
+        let v: Value = serde_json::from_str(&res.unwrap()).unwrap();
+        assert_eq!(v, json!({ "allow": true }));
+
+        // The data isn't a JSON object.
+        let res = Regorus::new(1).evaluate(POLICY, "[]", r#"{"svn": 2}"#);
+        assert!(res.is_err(), "evaluate should fail");
+    }
+
+    #[test]
+    fn test_policy_cache() {
+        let engine = Regorus::new(1);
+        let other = POLICY.replace(">=", ">");
+
+        let res = engine.evaluate(POLICY, r#"{"svn": 1}"#, r#"{"svn": 1}"#);
+        assert_eq!(res.unwrap(), r#"{"allow":true}"#);
+        assert!(cached(&engine, POLICY));
+        assert_eq!(engine.cached(), 1);
+        // The cached engine doesn't keep the data and input of the last evaluation.
+        let res = engine.evaluate(POLICY, r#"{"svn": 2}"#, r#"{"svn": 1}"#);
+        assert_eq!(res.unwrap(), r#"{"allow":false}"#);
+
+        // The least recently used policy is evicted.
+        let res = engine.evaluate(&other, r#"{"svn": 1}"#, r#"{"svn": 1}"#);
+        assert_eq!(res.unwrap(), r#"{"allow":false}"#);
+        assert!(cached(&engine, &other));
+        assert!(!cached(&engine, POLICY));
+
+        engine.invalidate(&other);
+        assert!(!cached(&engine, &other));
+
+        // The illegal policy isn't cached.
+        assert!(engine
+            .evaluate("package policy\nallow {", "{}", "{}")
+            .is_err());
+        assert!(engine.cache.as_ref().unwrap().lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,30 @@
+# Attestation Service Default Policy of SEV-SNP
+#
+# It's adopted when no custom policy is provided for the SEV-SNP evidence. Besides matching
+# each claim with the reference like the TEE-agnostic default policy does, it rejects
+# the guests whose policy allows debugging.
+#
+# The reference data is the same as the one of the TEE-agnostic default policy
+# (`default_policy.rego`), please refer it for the details.
+package policy
+
+default allow = false
+
+allow {
+	# Reject the guests which allow the hypervisor to debug them.
+	input.policy_debug_allowed == false
+
+	data.lib.claims_match(input)
+}
+
+# `violations`: The claims mismatching the reference (`data.lib.mismatches`), e.g.
+#
+#	{"claim": "svn", "observed": 1, "expected": [2, 3]}
+violations[violation] {
+	violation := data.lib.mismatches(input)[_]
+}
+
+violations[violation] {
+	not input.policy_debug_allowed == false
+	violation := {"claim": "policy_debug_allowed", "observed": object.get(input, "policy_debug_allowed", null), "expected": false}
+}
@@ -0,0 +1,30 @@
+# Attestation Service Default Policy of SGX
+#
+# It's adopted when no custom policy is provided for the SGX evidence. Besides matching
+# each claim with the reference like the TEE-agnostic default policy does, it rejects
+# the debug enclaves.
+#
+# The reference data is the same as the one of the TEE-agnostic default policy
+# (`default_policy.rego`), please refer it for the details.
+package policy
+
+default allow = false
+
+allow {
+	# Reject the debug enclaves, whose secrets can be inspected by the host.
+	input.debug == false
+
+	data.lib.claims_match(input)
+}
+
+# `violations`: The claims mismatching the reference (`data.lib.mismatches`), e.g.
+#
+#	{"claim": "svn", "observed": 1, "expected": [2, 3]}
+violations[violation] {
+	violation := data.lib.mismatches(input)[_]
+}
+
+violations[violation] {
+	not input.debug == false
+	violation := {"claim": "debug", "observed": object.get(input, "debug", null), "expected": false}
+}
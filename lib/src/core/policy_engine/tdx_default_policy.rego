@@ -0,0 +1,36 @@
+# Attestation Service Default Policy of TDX
+#
+# It's adopted when no custom policy is provided for the TDX evidence. Besides matching
+# each claim with the reference like the TEE-agnostic default policy does, it rejects
+# the debug TDs.
+#
+# The reference data is the same as the one of the TEE-agnostic default policy
+# (`default_policy.rego`), please refer it for the details.
+package policy
+
+default allow = false
+
+allow {
+	not td_debug
+
+	data.lib.claims_match(input)
+}
+
+# TD_ATTRIBUTES.DEBUG is the bit 0 of the first byte, i.e. the TD is debuggable when the
+# second hex digit of `td_attributes` is odd.
+td_debug {
+	odd_digits := {"1", "3", "5", "7", "9", "b", "d", "f"}
+	odd_digits[substring(input.td_attributes, 1, 1)]
+}
+
+# `violations`: The claims mismatching the reference (`data.lib.mismatches`), e.g.
+#
+#	{"claim": "svn", "observed": 1, "expected": [2, 3]}
+violations[violation] {
+	violation := data.lib.mismatches(input)[_]
+}
+
+violations[violation] {
+	td_debug
+	violation := {"claim": "td_attributes", "observed": input.td_attributes, "expected": "TD_ATTRIBUTES.DEBUG unset"}
+}
@@ -1,3 +1,4 @@
+#[allow(dead_code)]
 #[derive(Debug)]
 pub struct Proxies {
     // Fixme: add some sub-proxies
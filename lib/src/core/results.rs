@@ -0,0 +1,129 @@
+use super::policy_engine::PolicyDecision;
+use super::AttestationResults;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// The schema version of [`TypedAttestationResults`].
+pub const ATTESTATION_RESULTS_VERSION: &str = "1.0";
+
+/// The typed attestation results, the counterpart of the `attestation.AttestationResults`
+/// protobuf message. Unlike [`AttestationResults`], the claims and the decision are structured
+/// rather than nested JSON strings, and the serde representation is stable within the schema
+/// `version`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TypedAttestationResults {
+    pub version: String,
+    pub tee: String,
+    pub allow: bool,
+    /// The TCB claims parsed from the evidence, sorted by the claim key.
+    pub claims: BTreeMap<String, Value>,
+    /// The policy decision, absent if the evidence failed to be verified.
+    pub decision: Option<PolicyDecision>,
+    /// Why the evidence failed to be verified.
+    pub verifier_error: Option<String>,
+    /// The machine-readable reason of `verifier_error`, see [`crate::Error::reason`].
+    #[serde(default)]
+    pub verifier_error_reason: Option<String>,
+}
+
+impl TryFrom<&AttestationResults> for TypedAttestationResults {
+    type Error = anyhow::Error;
+
+    fn try_from(results: &AttestationResults) -> Result<Self> {
+        let claims = match results.tcb.is_empty() {
+            true => BTreeMap::new(),
+            false => {
+                serde_json::from_str(&results.tcb).context("Deserialize TCB claims failed.")?
+            }
+        };
+        let (decision, verifier_error) = match results.verifier_output.is_empty() {
+            true => (
+                Some(PolicyDecision {
+                    allow: results.allow,
+                    violations: results.violations.clone(),
+                }),
+                None,
+            ),
+            false => (None, Some(results.verifier_output.clone())),
+        };
+        Ok(Self {
+            version: ATTESTATION_RESULTS_VERSION.to_string(),
+            tee: results.tee.clone(),
+            allow: results.allow,
+            claims,
+            decision,
+            verifier_error,
+            verifier_error_reason: results.verifier_error_reason.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::policy_engine::Violation;
+    use serde_json::json;
+
+    fn results() -> AttestationResults {
+        AttestationResults {
+            tee: "sample".to_string(),
+            allow: false,
+            violations: vec![Violation {
+                claim: "svn".to_string(),
+                observed: json!(1),
+                expected: json!([2, 3]),
+            }],
+            verifier_output: String::default(),
+            verifier_error_reason: None,
+            policy_engine_output: json!({ "allow": false }).to_string(),
+            tcb: json!({ "svn": 1, "cpusvn": 1 }).to_string(),
+        }
+    }
+
+    #[test]
+    fn test_typed_results() {
+        let typed = TypedAttestationResults::try_from(&results()).unwrap();
+        // The serde representation is part of the schema.
+        assert_eq!(
+            serde_json::to_value(&typed).unwrap(),
+            json!({
+                "version": "1.0",
+                "tee": "sample",
+                "allow": false,
+                "claims": { "cpusvn": 1, "svn": 1 },
+                "decision": {
+                    "allow": false,
+                    "violations": [{ "claim": "svn", "observed": 1, "expected": [2, 3] }]
+                },
+                "verifier_error": null,
+                "verifier_error_reason": null
+            })
+        );
+        let deserialized: TypedAttestationResults =
+            serde_json::from_str(&serde_json::to_string(&typed).unwrap()).unwrap();
+        assert_eq!(deserialized, typed);
+    }
+
+    #[test]
+    fn test_typed_results_verifier_error() {
+        let mut results = results();
+        results.violations.clear();
+        results.verifier_output = "Verifier evaluate failed".to_string();
+        results.verifier_error_reason = Some("SIGNATURE_INVALID".to_string());
+        results.policy_engine_output.clear();
+        results.tcb.clear();
+        let typed = TypedAttestationResults::try_from(&results).unwrap();
+        assert!(typed.claims.is_empty());
+        assert!(typed.decision.is_none());
+        assert_eq!(
+            typed.verifier_error,
+            Some("Verifier evaluate failed".to_string())
+        );
+        assert_eq!(
+            typed.verifier_error_reason,
+            Some("SIGNATURE_INVALID".to_string())
+        );
+    }
+}
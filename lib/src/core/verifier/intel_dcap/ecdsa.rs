@@ -0,0 +1,274 @@
+use super::{Reader, SGX_REPORT_BODY_SIZE, SGX_REPORT_DATA_OFFSET};
+use crate::Error;
+use anyhow::{anyhow, Context, Result};
+use p256::ecdsa::signature::Verifier as _;
+use p256::ecdsa::{Signature, VerifyingKey};
+use sha2::{Digest, Sha256};
+use x509_parser::certificate::X509Certificate;
+use x509_parser::pem::{parse_x509_pem, Pem};
+
+// Certification data types defined by the Intel DCAP quote format.
+const CERT_DATA_TYPE_PCK_CERT_CHAIN: u16 = 5;
+const CERT_DATA_TYPE_QE_REPORT: u16 = 6;
+
+// The hex encoded SHA-256 of the DER SubjectPublicKeyInfo of the Intel SGX Root CA, which is
+// published at https://certificates.trustedservices.intel.com/Intel_SGX_Provisioning_Certification_RootCA.pem,
+// e.g. `openssl x509 -pubkey -noout -in root_ca.pem | openssl pkey -pubin -outform DER | sha256sum`.
+// The root CA installed at the configured path is only trusted if it matches.
+#[cfg(not(test))]
+const PINNED_ROOT_CAS: &[&str] = &[];
+// The root CAs of the test data.
+#[cfg(test)]
+const PINNED_ROOT_CAS: &[&str] = &[
+    "dccab889d5547bca7ec34762acd8272e356085e9f44a436156d793edfa8d769a",
+    "027d50526b5fdd9316535be660e0d6bc5b388735fb56213a36972640dbca6403",
+    "6cc3f644590687450a0212446ea747b3326b9ae24e4f206443f2796609422535",
+];
+
+/// The ECDSA-256-with-P-256 quote signature data.
+#[derive(Debug)]
+pub struct EcdsaSigData<'a> {
+    pub quote_signature: [u8; 64],
+    // Raw (x, y) coordinates of the attestation key.
+    pub attestation_key: [u8; 64],
+    // Report of the Quoting Enclave, signed by the PCK.
+    pub qe_report: &'a [u8],
+    pub qe_report_signature: [u8; 64],
+    pub qe_auth_data: &'a [u8],
+    // PEM encoded PCK leaf, intermediate and root certificates.
+    pub pck_cert_chain: &'a [u8],
+}
+
+impl<'a> EcdsaSigData<'a> {
+    /// Parse the signature data of a v3 quote, where the QE report directly
+    /// follows the attestation key.
+    pub fn parse_v3(sig_data: &'a [u8]) -> Result<Self> {
+        let mut reader = Reader::new(sig_data);
+        let quote_signature = reader.array()?;
+        let attestation_key = reader.array()?;
+        Self::parse_qe_report(quote_signature, attestation_key, &mut reader)
+    }
+
+    /// Parse the signature data of a v4 quote, where the QE report is wrapped
+    /// in a certification data structure.
+    pub fn parse_v4(sig_data: &'a [u8]) -> Result<Self> {
+        let mut reader = Reader::new(sig_data);
+        let quote_signature = reader.array()?;
+        let attestation_key = reader.array()?;
+
+        let (cert_type, cert_data) = certification_data(&mut reader)?;
+        if cert_type != CERT_DATA_TYPE_QE_REPORT {
+            return Err(anyhow!(
+                "Unsupported certification data type: {}",
+                cert_type
+            ));
+        }
+
+        Self::parse_qe_report(
+            quote_signature,
+            attestation_key,
+            &mut Reader::new(cert_data),
+        )
+    }
+
+    fn parse_qe_report(
+        quote_signature: [u8; 64],
+        attestation_key: [u8; 64],
+        reader: &mut Reader<'a>,
+    ) -> Result<Self> {
+        let qe_report = reader.take(SGX_REPORT_BODY_SIZE)?;
+        let qe_report_signature = reader.array()?;
+        let qe_auth_data_len = reader.u16()? as usize;
+        let qe_auth_data = reader.take(qe_auth_data_len)?;
+
+        let (cert_type, pck_cert_chain) = certification_data(reader)?;
+        if cert_type != CERT_DATA_TYPE_PCK_CERT_CHAIN {
+            return Err(anyhow!(
+                "Unsupported QE certification data type: {}",
+                cert_type
+            ));
+        }
+
+        Ok(Self {
+            quote_signature,
+            attestation_key,
+            qe_report,
+            qe_report_signature,
+            qe_auth_data,
+            pck_cert_chain,
+        })
+    }
+
+    /// Verify the quote signature, the QE report signature, the binding of the
+    /// attestation key to the QE report and the PCK certificate chain.
+    pub fn verify(&self, signed_data: &[u8], root_ca: &[u8]) -> Result<()> {
+        // The attestation key signs the quote header and body.
+        let attestation_key =
+            VerifyingKey::from_sec1_bytes(&[&[0x04], self.attestation_key.as_slice()].concat())
+                .context("Parse attestation key failed.")?;
+        let signature = Signature::from_slice(&self.quote_signature)?;
+        attestation_key
+            .verify(signed_data, &signature)
+            .map_err(|_| {
+                Error::SignatureInvalid("Quote signature verification failed!".to_string())
+            })?;
+
+        // The PCK signs the QE report.
+        let pck_key = verify_pck_cert_chain(self.pck_cert_chain, root_ca)?;
+        let signature = Signature::from_slice(&self.qe_report_signature)?;
+        pck_key.verify(self.qe_report, &signature).map_err(|_| {
+            Error::SignatureInvalid("QE report signature verification failed!".to_string())
+        })?;
+
+        // The QE report data binds the attestation key:
+        // SHA256(attestation key || QE authentication data) || 32 zero bytes.
+        let mut hasher = Sha256::new();
+        hasher.update(self.attestation_key);
+        hasher.update(self.qe_auth_data);
+        let hash = hasher.finalize();
+        let report_data = &self.qe_report[SGX_REPORT_DATA_OFFSET..];
+        if report_data[..32] != hash[..] || report_data[32..].iter().any(|b| *b != 0) {
+            return Err(Error::SignatureInvalid(
+                "Attestation key is not bound to the QE report!".to_string(),
+            )
+            .into());
+        }
+
+        Ok(())
+    }
+}
+
+fn certification_data<'a>(reader: &mut Reader<'a>) -> Result<(u16, &'a [u8])> {
+    let cert_type = reader.u16()?;
+    let cert_data_len = reader.u32()? as usize;
+    let cert_data = reader
+        .take(cert_data_len)
+        .context("Certification data is truncated")?;
+    Ok((cert_type, cert_data))
+}
+
+/// Verify the PCK certificate chain up to the trusted root CA and return the
+/// PCK leaf's public key.
+pub fn verify_pck_cert_chain(chain: &[u8], root_ca: &[u8]) -> Result<VerifyingKey> {
+    // The chain may be NUL terminated.
+    let end = chain.iter().rposition(|b| *b != 0).map_or(0, |i| i + 1);
+    let pems = Pem::iter_from_buffer(&chain[..end])
+        .collect::<Result<Vec<_>, _>>()
+        .context(Error::MalformedEvidence(
+            "Parse PCK certificate chain failed.".to_string(),
+        ))?;
+    let mut certs = pems
+        .iter()
+        .map(|pem| pem.parse_x509())
+        .collect::<Result<Vec<_>, _>>()
+        .context(Error::MalformedEvidence(
+            "Parse PCK certificate failed.".to_string(),
+        ))?;
+
+    let (_, root_pem) = parse_x509_pem(root_ca).context("Parse root CA failed.")?;
+    let root = root_pem
+        .parse_x509()
+        .context("Parse root CA certificate failed.")?;
+    let digest = hex::encode(Sha256::digest(root.public_key().raw));
+    if !PINNED_ROOT_CAS.contains(&digest.as_str()) {
+        return Err(Error::SignatureInvalid(format!(
+            "{} is not the pinned Intel SGX root!",
+            root.subject()
+        ))
+        .into());
+    }
+
+    // The root embedded in the quote must be the trusted one, the chain is
+    // then anchored on the trusted root's public key.
+    if let (Some(last), Some(last_pem)) = (certs.last(), pems.last()) {
+        if last.subject() == last.issuer() {
+            if last_pem.contents != root_pem.contents {
+                return Err(Error::SignatureInvalid(
+                    "PCK certificate chain root is not trusted!".to_string(),
+                )
+                .into());
+            }
+            certs.pop();
+        }
+    }
+    if certs.is_empty() {
+        return Err(Error::SignatureInvalid("PCK certificate chain is empty!".to_string()).into());
+    }
+
+    // The intermediates must be CAs, unlike the PCK leaf.
+    for cert in certs.iter().skip(1) {
+        let ca = cert
+            .basic_constraints()
+            .ok()
+            .flatten()
+            .is_some_and(|constraints| constraints.value.ca);
+        if !ca {
+            return Err(Error::SignatureInvalid(format!(
+                "Certificate \"{}\" is not a CA!",
+                cert.subject()
+            ))
+            .into());
+        }
+    }
+
+    let issuers = certs.iter().skip(1).chain(std::iter::once(&root));
+    for (cert, issuer) in certs.iter().zip(issuers) {
+        check_validity(cert)?;
+        cert.verify_signature(Some(issuer.public_key()))
+            .map_err(|e| {
+                Error::SignatureInvalid(format!(
+                    "Certificate \"{}\" verification failed: {}",
+                    cert.subject(),
+                    e
+                ))
+            })?;
+    }
+    check_validity(&root)?;
+
+    VerifyingKey::from_sec1_bytes(&certs[0].public_key().subject_public_key.data)
+        .context("Parse PCK public key failed.")
+}
+
+fn check_validity(cert: &X509Certificate) -> Result<()> {
+    if !cert.validity().is_valid() {
+        return Err(Error::SignatureInvalid(format!(
+            "Certificate \"{}\" is expired!",
+            cert.subject()
+        ))
+        .into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::verifier::test_util::test_data;
+
+    fn verify(chain: &str, root_ca: &str) -> Result<VerifyingKey> {
+        let chain = std::fs::read(test_data("intel_dcap", chain)).unwrap();
+        let root_ca = std::fs::read(test_data("intel_dcap", root_ca)).unwrap();
+        verify_pck_cert_chain(&chain, &root_ca)
+    }
+
+    #[test]
+    fn test_intermediate_not_ca() {
+        let e = verify("not_ca_chain.pem", "root_ca.pem").unwrap_err();
+        assert!(
+            e.to_string().contains("is not a CA"),
+            "Non-CA intermediate should fail: {}",
+            e
+        );
+    }
+
+    #[test]
+    fn test_forged_root() {
+        // A self-signed root named after the genuine one, whose key isn't pinned.
+        let e = verify("forged_chain.pem", "forged_root_ca.pem").unwrap_err();
+        assert!(
+            e.to_string().contains("is not the pinned Intel SGX root"),
+            "Forged root should fail: {}",
+            e
+        );
+    }
+}
@@ -0,0 +1,70 @@
+//! Common pieces of the Intel DCAP (ECDSA) quotes shared by the SGX and TDX verifiers.
+
+use anyhow::{anyhow, Result};
+use std::path::PathBuf;
+
+pub mod ecdsa;
+
+/// Default path of the Intel SGX Provisioning Certification Root CA (PEM).
+pub const DEFAULT_INTEL_ROOT_CA_PATH: &str =
+    "/etc/attestation-service/Intel_SGX_Provisioning_Certification_RootCA.pem";
+/// Environment variable overriding the Intel root CA path.
+pub const INTEL_ROOT_CA_PATH_ENV: &str = "INTEL_ROOT_CA_PATH";
+
+pub const ATTESTATION_KEY_TYPE_ECDSA_P256: u16 = 2;
+
+pub const SGX_REPORT_BODY_SIZE: usize = 384;
+// Offset of `report_data` in the SGX report body.
+pub const SGX_REPORT_DATA_OFFSET: usize = 320;
+
+/// The configured Intel root CA path.
+pub fn intel_root_ca_path() -> PathBuf {
+    let path = std::env::var(INTEL_ROOT_CA_PATH_ENV)
+        .unwrap_or_else(|_| DEFAULT_INTEL_ROOT_CA_PATH.to_string());
+    PathBuf::from(path)
+}
+
+/// Little-endian cursor over a binary quote.
+pub struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(buf: &'a [u8]) -> Self {
+        Self { buf, pos: 0 }
+    }
+
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    pub fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|end| *end <= self.buf.len())
+            .ok_or_else(|| anyhow!("Quote is truncated at offset {}", self.pos))?;
+        let bytes = &self.buf[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    pub fn array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let mut array = [0u8; N];
+        array.copy_from_slice(self.take(N)?);
+        Ok(array)
+    }
+
+    pub fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_le_bytes(self.array()?))
+    }
+
+    pub fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    pub fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+}
@@ -1,8 +1,28 @@
 use super::{Evidence, TeeEvidenceParsedClaim};
+use crate::Error;
+use crate::TEE;
 use anyhow::Result;
 use async_trait::async_trait;
+use std::collections::HashMap;
 
+#[cfg(any(feature = "tdx-verifier", feature = "sgx-verifier"))]
+mod intel_dcap;
 pub mod sample;
+#[cfg(feature = "sgx-verifier")]
+pub mod sgx;
+#[cfg(feature = "snp-verifier")]
+pub mod snp;
+#[cfg(feature = "tdx-verifier")]
+pub mod tdx;
+#[cfg(all(
+    test,
+    any(
+        feature = "tdx-verifier",
+        feature = "sgx-verifier",
+        feature = "snp-verifier"
+    )
+))]
+mod test_util;
 
 #[async_trait]
 pub trait Verifier {
@@ -11,3 +31,159 @@ pub trait Verifier {
     /// The policy engine of AS will carry out the verification of TCB status.
     async fn evaluate(&self, evidence: &Evidence) -> Result<TeeEvidenceParsedClaim>;
 }
+
+/// Verify that the report data of a hardware TEE's quote binds the evidence, i.e. it starts with
+/// SHA384(nonce || tee-pubkey).
+#[cfg(any(
+    feature = "tdx-verifier",
+    feature = "sgx-verifier",
+    feature = "snp-verifier"
+))]
+fn verify_report_data(evidence: &Evidence, report_data: &[u8]) -> Result<()> {
+    use sha2::{Digest, Sha384};
+
+    let mut hasher = Sha384::new();
+    hasher.update(&evidence.nonce);
+    hasher.update(&evidence.tee_pubkey);
+    if !report_data.starts_with(&hasher.finalize()) {
+        return Err(
+            Error::ReportDataMismatch("Report data verification failed!".to_string()).into(),
+        );
+    }
+    Ok(())
+}
+
+/// The verifiers keyed by TEE name, the name is matched case insensitively
+/// against `Evidence.tee`.
+#[derive(Default)]
+pub struct VerifierRegistry {
+    verifiers: HashMap<String, Box<dyn Verifier + Send + Sync>>,
+}
+
+impl std::fmt::Debug for VerifierRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("VerifierRegistry")
+            .field("tees", &self.tees())
+            .finish()
+    }
+}
+
+impl VerifierRegistry {
+    /// Create an empty registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a registry with the built-in verifiers enabled by cargo features.
+    pub fn builtin() -> Self {
+        let mut registry = Self::new();
+        registry.register(TEE::SAMPLE.as_ref(), Box::new(sample::Sample::default()));
+        #[cfg(feature = "tdx-verifier")]
+        registry.register(TEE::TDX.as_ref(), Box::new(tdx::Tdx::default()));
+        #[cfg(feature = "sgx-verifier")]
+        registry.register(TEE::SGX.as_ref(), Box::new(sgx::Sgx::default()));
+        #[cfg(feature = "snp-verifier")]
+        registry.register(TEE::SEVSNP.as_ref(), Box::new(snp::Snp::default()));
+        registry
+    }
+
+    /// Register the verifier of TEE `name`, the previous verifier of the same
+    /// TEE is replaced and returned.
+    pub fn register(
+        &mut self,
+        name: &str,
+        verifier: Box<dyn Verifier + Send + Sync>,
+    ) -> Option<Box<dyn Verifier + Send + Sync>> {
+        self.verifiers.insert(name.to_ascii_lowercase(), verifier)
+    }
+
+    /// Get the verifier of TEE `name`.
+    pub fn get(&self, name: &str) -> Result<&(dyn Verifier + Send + Sync)> {
+        self.verifiers
+            .get(&name.to_ascii_lowercase())
+            .map(|verifier| verifier.as_ref())
+            .ok_or_else(|| Error::UnsupportedTee(format!("TEE {} is not supported!", name)).into())
+    }
+
+    /// Keep only the verifiers of the TEEs `names`, it fails if any of them isn't registered.
+    pub fn retain(&mut self, names: &[String]) -> Result<()> {
+        let names: Vec<String> = names.iter().map(|name| name.to_ascii_lowercase()).collect();
+        if let Some(name) = names
+            .iter()
+            .find(|name| !self.verifiers.contains_key(*name))
+        {
+            return Err(Error::UnsupportedTee(format!("TEE {} is not supported!", name)).into());
+        }
+        self.verifiers.retain(|name, _| names.contains(name));
+        Ok(())
+    }
+
+    /// The names of the registered TEEs.
+    pub fn tees(&self) -> Vec<String> {
+        let mut tees: Vec<String> = self.verifiers.keys().cloned().collect();
+        tees.sort();
+        tees
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[derive(Debug, Default)]
+    struct Dummy {}
+
+    #[async_trait]
+    impl Verifier for Dummy {
+        async fn evaluate(&self, _evidence: &Evidence) -> Result<TeeEvidenceParsedClaim> {
+            Ok(json!({ "dummy": true }))
+        }
+    }
+
+    #[test]
+    fn test_builtin() {
+        let registry = VerifierRegistry::builtin();
+        assert!(registry.get("sample").is_ok());
+        assert!(registry.get("SAMPLE").is_ok());
+        #[cfg(feature = "tdx-verifier")]
+        assert!(registry.get("tdx").is_ok());
+        #[cfg(feature = "sgx-verifier")]
+        assert!(registry.get("sgx").is_ok());
+        #[cfg(feature = "snp-verifier")]
+        assert!(registry.get("sevsnp").is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_register() {
+        let mut registry = VerifierRegistry::new();
+        assert!(registry.get("dummy").is_err(), "Empty registry");
+
+        assert!(registry.register("Dummy", Box::new(Dummy {})).is_none());
+        assert_eq!(registry.tees(), vec!["dummy".to_string()]);
+        let evidence = Evidence {
+            nonce: String::default(),
+            tee: "dummy".to_string(),
+            tee_pubkey: String::default(),
+            tee_evidence: String::default(),
+        };
+        let claims = registry.get("DUMMY").unwrap().evaluate(&evidence).await;
+        assert_eq!(claims.unwrap(), json!({ "dummy": true }));
+
+        assert!(
+            registry.register("dummy", Box::new(Dummy {})).is_some(),
+            "The previous verifier should be replaced"
+        );
+    }
+
+    #[test]
+    fn test_retain() {
+        let mut registry = VerifierRegistry::new();
+        registry.register("dummy", Box::new(Dummy {}));
+        registry.register("other", Box::new(Dummy {}));
+        assert!(registry.retain(&["unknown".to_string()]).is_err());
+        assert_eq!(registry.tees().len(), 2);
+        registry.retain(&["Dummy".to_string()]).unwrap();
+        assert_eq!(registry.tees(), vec!["dummy".to_string()]);
+    }
+}
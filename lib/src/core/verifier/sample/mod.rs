@@ -1,9 +1,9 @@
-use anyhow::{anyhow, Context, Result};
+use crate::Error;
+use anyhow::{Context, Result};
 extern crate serde;
 use self::serde::{Deserialize, Serialize};
 use super::*;
 use async_trait::async_trait;
-use base64;
 use serde_json::json;
 use sha2::{Digest, Sha384};
 
@@ -20,6 +20,26 @@ struct Quote {
 #[derive(Debug, Default)]
 pub struct Sample {}
 
+/// The built-in sample evidence of the TCB `cpusvn` and `svn`, whose report data binds `nonce`
+/// and `tee_pubkey`.
+pub fn evidence(nonce: &str, tee_pubkey: &str, cpusvn: u64, svn: u64) -> Evidence {
+    let mut hasher = Sha384::new();
+    hasher.update(nonce);
+    hasher.update(tee_pubkey);
+    let quote = Quote {
+        is_debuggable: false,
+        cpusvn,
+        svn,
+        report_data: base64::encode(hasher.finalize()),
+    };
+    Evidence {
+        nonce: nonce.to_string(),
+        tee: TEE::SAMPLE.as_ref().to_string(),
+        tee_pubkey: tee_pubkey.to_string(),
+        tee_evidence: json!(quote).to_string(),
+    }
+}
+
 #[async_trait]
 impl Verifier for Sample {
     async fn evaluate(&self, evidence: &Evidence) -> Result<TeeEvidenceParsedClaim> {
@@ -27,8 +47,9 @@ impl Verifier for Sample {
             .await
             .context("Evidence's identity verification error.")?;
 
-        let quote = serde_json::from_str::<Quote>(&evidence.tee_evidence)
-            .context("Deserialize Quote failed.")?;
+        let quote = serde_json::from_str::<Quote>(&evidence.tee_evidence).context(
+            Error::MalformedEvidence("Deserialize Quote failed.".to_string()),
+        )?;
         debug!("Quote<sample>: {:?}", &quote);
 
         tcb_status(&quote)
@@ -46,6 +67,7 @@ fn tcb_status(quote: &Quote) -> Result<TeeEvidenceParsedClaim> {
     Ok(claims_map as TeeEvidenceParsedClaim)
 }
 
+#[allow(dead_code)]
 #[derive(Serialize, Deserialize, Debug)]
 struct Ehd {
     nonce: String,
@@ -54,14 +76,17 @@ struct Ehd {
 
 async fn verify(evidence: &Evidence) -> Result<()> {
     // Emulate the EHD (report data).
-    let quote = serde_json::from_str::<Quote>(&evidence.tee_evidence)
-        .context("Deserialize quote failed.")?;
+    let quote = serde_json::from_str::<Quote>(&evidence.tee_evidence).context(
+        Error::MalformedEvidence("Deserialize quote failed.".to_string()),
+    )?;
     let mut hasher = Sha384::new();
     hasher.update(&evidence.nonce);
     hasher.update(&evidence.tee_pubkey);
     let hash = hasher.finalize();
     if quote.report_data != base64::encode(hash) {
-        return Err(anyhow!("Report data verification failed!"));
+        return Err(
+            Error::ReportDataMismatch("Report data verification failed!".to_string()).into(),
+        );
     }
 
     // Verify the TEE Hardware signature. (Null for sample TEE)
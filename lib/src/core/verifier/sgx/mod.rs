@@ -0,0 +1,167 @@
+use crate::Error;
+use anyhow::{Context, Result};
+extern crate serde;
+use self::serde::{Deserialize, Serialize};
+use super::*;
+use async_trait::async_trait;
+use quote::{parse_quote, Quote, SGX_FLAGS_DEBUG, SGX_FLAGS_MODE64BIT};
+use serde_json::json;
+use std::path::PathBuf;
+
+mod quote;
+
+#[derive(Serialize, Deserialize, Debug)]
+struct SgxEvidence {
+    // Base64 encoded SGX ECDSA quote.
+    quote: String,
+}
+
+#[derive(Debug)]
+pub struct Sgx {
+    // The trusted root of the PCK certificate chain.
+    root_ca_path: PathBuf,
+}
+
+impl Default for Sgx {
+    fn default() -> Self {
+        Self::new(intel_dcap::intel_root_ca_path())
+    }
+}
+
+impl Sgx {
+    pub fn new(root_ca_path: PathBuf) -> Self {
+        Self { root_ca_path }
+    }
+}
+
+#[async_trait]
+impl Verifier for Sgx {
+    async fn evaluate(&self, evidence: &Evidence) -> Result<TeeEvidenceParsedClaim> {
+        let sgx_evidence = serde_json::from_str::<SgxEvidence>(&evidence.tee_evidence).context(
+            Error::MalformedEvidence("Deserialize SGX Evidence failed.".to_string()),
+        )?;
+        let quote_bin = base64::decode(&sgx_evidence.quote).context(Error::MalformedEvidence(
+            "Base64 decode SGX quote failed.".to_string(),
+        ))?;
+        let quote = parse_quote(&quote_bin).context(Error::MalformedEvidence(
+            "Parse SGX quote failed.".to_string(),
+        ))?;
+        debug!("Quote<sgx>: {:?}", &quote);
+
+        let root_ca = tokio::fs::read(&self.root_ca_path)
+            .await
+            .with_context(|| format!("Read Intel root CA {:?} failed.", &self.root_ca_path))?;
+
+        verify(evidence, &quote, &root_ca).context("Evidence's identity verification error.")?;
+
+        tcb_status(&quote)
+    }
+}
+
+// Dump the TCB status from the quote as flat key value pairs, so that the
+// default policy can match each of them against the reference data.
+fn tcb_status(quote: &Quote) -> Result<TeeEvidenceParsedClaim> {
+    let body = &quote.body;
+    let claims_map = json!({
+        "mrenclave": hex::encode(body.mr_enclave),
+        "mrsigner": hex::encode(body.mr_signer),
+        "isv_prod_id": body.isv_prod_id,
+        "isv_svn": body.isv_svn,
+        "debug": body.attributes_flags & SGX_FLAGS_DEBUG != 0,
+        "mode64": body.attributes_flags & SGX_FLAGS_MODE64BIT != 0,
+        "cpusvn": hex::encode(body.cpu_svn),
+    });
+
+    Ok(claims_map as TeeEvidenceParsedClaim)
+}
+
+fn verify(evidence: &Evidence, quote: &Quote, root_ca: &[u8]) -> Result<()> {
+    // Verify the report data: SHA384(nonce || tee-pubkey).
+    verify_report_data(evidence, &quote.body.report_data)?;
+
+    // Verify the TEE Hardware signature.
+    quote.signature.verify(quote.signed_data, root_ca)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::verifier::test_util::{
+        assert_report_data_mismatch, evidence, tampered_evidence, test_data,
+    };
+
+    const TEE: &str = "sgx";
+
+    #[tokio::test]
+    async fn test_evaluate() {
+        let sgx = Sgx::new(test_data(TEE, "root_ca.pem"));
+        let res = sgx.evaluate(&evidence(TEE, "evidence.json")).await;
+        assert!(res.is_ok(), "SGX evaluate should success: {:?}", res);
+        let claims = res.unwrap();
+        assert_eq!(claims["mrenclave"], json!("aa".repeat(32)));
+        assert_eq!(claims["mrsigner"], json!("bb".repeat(32)));
+        assert_eq!(claims["isv_prod_id"], json!(1));
+        assert_eq!(claims["isv_svn"], json!(2));
+        assert_eq!(claims["debug"], json!(false));
+        assert_eq!(claims["mode64"], json!(true));
+        assert_eq!(claims["cpusvn"], json!("0f0f0204ff8000000000000000000000"));
+    }
+
+    #[tokio::test]
+    async fn test_evaluate_untrusted_root_ca() {
+        let sgx = Sgx::new(test_data(TEE, "other_root_ca.pem"));
+        let res = sgx.evaluate(&evidence(TEE, "evidence.json")).await;
+        assert!(res.is_err(), "Untrusted root CA should fail");
+    }
+
+    #[tokio::test]
+    async fn test_evaluate_report_data_mismatch() {
+        let sgx = Sgx::new(test_data(TEE, "root_ca.pem"));
+        assert_report_data_mismatch(&sgx, || evidence(TEE, "evidence.json")).await;
+    }
+
+    #[tokio::test]
+    async fn test_evaluate_tampered_quote() {
+        let sgx = Sgx::new(test_data(TEE, "root_ca.pem"));
+        // Flip a byte of MRENCLAVE.
+        let res = sgx
+            .evaluate(&tampered_evidence(
+                evidence(TEE, "evidence.json"),
+                "quote",
+                48 + 64,
+            ))
+            .await;
+        assert!(res.is_err(), "Tampered quote should fail");
+    }
+
+    #[tokio::test]
+    async fn test_evaluate_tampered_qe_report() {
+        let sgx = Sgx::new(test_data(TEE, "root_ca.pem"));
+        // Flip a byte of the QE report, after the quote signature and attestation key.
+        let res = sgx
+            .evaluate(&tampered_evidence(
+                evidence(TEE, "evidence.json"),
+                "quote",
+                48 + 384 + 4 + 128,
+            ))
+            .await;
+        assert!(res.is_err(), "Tampered QE report should fail");
+    }
+
+    #[test]
+    fn test_claims_match_default_policy_input() {
+        // Every claim should be a scalar, so that `every k, v in input` can
+        // compare it with the reference data.
+        let quote_bin = base64::decode(
+            serde_json::from_str::<SgxEvidence>(&evidence(TEE, "evidence.json").tee_evidence)
+                .unwrap()
+                .quote,
+        )
+        .unwrap();
+        let quote = parse_quote(&quote_bin).unwrap();
+        let claims = tcb_status(&quote).unwrap();
+        for (_, v) in claims.as_object().unwrap() {
+            assert!(!v.is_object() && !v.is_array());
+        }
+    }
+}
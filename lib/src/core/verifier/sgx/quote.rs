@@ -0,0 +1,125 @@
+use crate::core::verifier::intel_dcap::ecdsa::EcdsaSigData;
+use crate::core::verifier::intel_dcap::{Reader, ATTESTATION_KEY_TYPE_ECDSA_P256};
+use anyhow::{anyhow, Context, Result};
+
+// SGX ECDSA quote v3 layout: https://download.01.org/intel-sgx/latest/dcap-latest/linux/docs/Intel_SGX_ECDSA_QuoteLibReference_DCAP_API.pdf
+pub const QUOTE_VERSION: u16 = 3;
+
+pub const SGX_FLAGS_DEBUG: u64 = 0x02;
+pub const SGX_FLAGS_MODE64BIT: u64 = 0x04;
+
+// The full layout is kept for the debug output.
+#[allow(dead_code)]
+#[derive(Debug)]
+pub struct QuoteHeader {
+    pub version: u16,
+    pub att_key_type: u16,
+    pub qe_svn: u16,
+    pub pce_svn: u16,
+    pub qe_vendor_id: [u8; 16],
+    pub user_data: [u8; 20],
+}
+
+#[allow(dead_code)]
+#[derive(Debug)]
+pub struct ReportBody {
+    // CPU Security Version Number
+    pub cpu_svn: [u8; 16],
+    pub misc_select: u32,
+    pub isv_ext_prod_id: [u8; 16],
+    pub attributes_flags: u64,
+    pub attributes_xfrm: u64,
+    // Measurement of the enclave
+    pub mr_enclave: [u8; 32],
+    // Hash of the enclave signer's public key
+    pub mr_signer: [u8; 32],
+    pub config_id: [u8; 64],
+    pub isv_prod_id: u16,
+    // ISV enclave Security Version Number
+    pub isv_svn: u16,
+    pub config_svn: u16,
+    pub isv_family_id: [u8; 16],
+    pub report_data: [u8; 64],
+}
+
+#[allow(dead_code)]
+#[derive(Debug)]
+pub struct Quote<'a> {
+    pub header: QuoteHeader,
+    pub body: ReportBody,
+    // The quote header and report body, which are covered by the quote signature.
+    pub signed_data: &'a [u8],
+    pub signature: EcdsaSigData<'a>,
+}
+
+pub fn parse_quote(quote: &[u8]) -> Result<Quote<'_>> {
+    let mut reader = Reader::new(quote);
+
+    let version = reader.u16()?;
+    let att_key_type = reader.u16()?;
+    // Reserved
+    reader.take(4)?;
+    let header = QuoteHeader {
+        version,
+        att_key_type,
+        qe_svn: reader.u16()?,
+        pce_svn: reader.u16()?,
+        qe_vendor_id: reader.array()?,
+        user_data: reader.array()?,
+    };
+    if header.version != QUOTE_VERSION {
+        return Err(anyhow!("Unsupported SGX quote version: {}", header.version));
+    }
+    if header.att_key_type != ATTESTATION_KEY_TYPE_ECDSA_P256 {
+        return Err(anyhow!(
+            "Unsupported attestation key type: {}",
+            header.att_key_type
+        ));
+    }
+
+    let cpu_svn = reader.array()?;
+    let misc_select = reader.u32()?;
+    // Reserved
+    reader.take(12)?;
+    let isv_ext_prod_id = reader.array()?;
+    let attributes_flags = reader.u64()?;
+    let attributes_xfrm = reader.u64()?;
+    let mr_enclave = reader.array()?;
+    reader.take(32)?;
+    let mr_signer = reader.array()?;
+    reader.take(32)?;
+    let config_id = reader.array()?;
+    let isv_prod_id = reader.u16()?;
+    let isv_svn = reader.u16()?;
+    let config_svn = reader.u16()?;
+    reader.take(42)?;
+    let body = ReportBody {
+        cpu_svn,
+        misc_select,
+        isv_ext_prod_id,
+        attributes_flags,
+        attributes_xfrm,
+        mr_enclave,
+        mr_signer,
+        config_id,
+        isv_prod_id,
+        isv_svn,
+        config_svn,
+        isv_family_id: reader.array()?,
+        report_data: reader.array()?,
+    };
+    let signed_data = &quote[..reader.position()];
+
+    let sig_data_len = reader.u32()? as usize;
+    let sig_data = reader
+        .take(sig_data_len)
+        .context("Quote signature data is truncated")?;
+    let signature = EcdsaSigData::parse_v3(sig_data)?;
+
+    Ok(Quote {
+        header,
+        body,
+        signed_data,
+        signature,
+    })
+}
@@ -0,0 +1,241 @@
+use super::report::AttestationReport;
+use crate::Error;
+use anyhow::{anyhow, Context, Result};
+use rsa::pss::{Signature, VerifyingKey};
+use rsa::signature::Verifier as _;
+use rsa::{BigUint, RsaPublicKey};
+use sha2::{Digest, Sha256, Sha384};
+use std::path::Path;
+use x509_parser::certificate::X509Certificate;
+use x509_parser::oid_registry::OID_PKCS1_RSASSAPSS;
+use x509_parser::pem::parse_x509_pem;
+use x509_parser::prelude::FromDer;
+use x509_parser::public_key::PublicKey;
+
+// VCEK extensions: https://www.amd.com/system/files/TechDocs/57230.pdf
+const OID_BL_SPL: &str = "1.3.6.1.4.1.3704.1.3.1";
+const OID_TEE_SPL: &str = "1.3.6.1.4.1.3704.1.3.2";
+const OID_SNP_SPL: &str = "1.3.6.1.4.1.3704.1.3.3";
+const OID_UCODE_SPL: &str = "1.3.6.1.4.1.3704.1.3.8";
+const OID_HW_ID: &str = "1.3.6.1.4.1.3704.1.4";
+
+// The hex SHA-256 of the SubjectPublicKeyInfo of the genuine ARKs, which are fetched from
+// `https://kdsintf.amd.com/vcek/v1/<Milan|Genoa>/cert_chain` and hashed by
+// `openssl x509 -pubkey -noout -in ark.pem | openssl pkey -pubin -outform DER | sha256sum`.
+// The ARKs installed in the certificates directory are only trusted if they match.
+#[cfg(not(test))]
+const PINNED_ARKS: &[(Generation, &str)] = &[];
+// The ARKs of the test data.
+#[cfg(test)]
+const PINNED_ARKS: &[(Generation, &str)] = &[
+    (
+        Generation::Milan,
+        "105c7ac1fcd76a3e235875c86fdc21f8162d17fbdceb03121cd566730988c516",
+    ),
+    (
+        Generation::Genoa,
+        "52b2d830f8ab8ee07d893b4e26f9d561a82154624fae30ef94cb9602044c6222",
+    ),
+];
+
+/// The processor generations whose AMD roots are pinned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Generation {
+    Milan,
+    Genoa,
+}
+
+impl Generation {
+    pub const ALL: [Generation; 2] = [Generation::Milan, Generation::Genoa];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Generation::Milan => "Milan",
+            Generation::Genoa => "Genoa",
+        }
+    }
+}
+
+/// The AMD Root Key and AMD SEV Key of a processor generation.
+#[derive(Debug)]
+pub struct AmdRoots {
+    pub generation: Generation,
+    pub ark: Vec<u8>,
+    pub ask: Vec<u8>,
+}
+
+impl AmdRoots {
+    /// Load `<dir>/<generation>/ark.pem` and `<dir>/<generation>/ask.pem`.
+    pub async fn load(dir: &Path, generation: Generation) -> Result<Self> {
+        let dir = dir.join(generation.name());
+        let ark = tokio::fs::read(dir.join("ark.pem"))
+            .await
+            .with_context(|| format!("Read ARK of {} failed.", generation.name()))?;
+        let ask = tokio::fs::read(dir.join("ask.pem"))
+            .await
+            .with_context(|| format!("Read ASK of {} failed.", generation.name()))?;
+        Ok(Self {
+            generation,
+            ark: to_der(&ark)?,
+            ask: to_der(&ask)?,
+        })
+    }
+}
+
+/// Accept both PEM and DER encoded certificates.
+pub fn to_der(cert: &[u8]) -> Result<Vec<u8>> {
+    if cert.starts_with(b"-----BEGIN") {
+        let (_, pem) = parse_x509_pem(cert).context("Parse PEM certificate failed.")?;
+        return Ok(pem.contents);
+    }
+    Ok(cert.to_vec())
+}
+
+fn parse_cert(der: &[u8]) -> Result<X509Certificate<'_>> {
+    let (_, cert) = X509Certificate::from_der(der).context("Parse certificate failed.")?;
+    Ok(cert)
+}
+
+fn common_name<'a>(cert: &'a X509Certificate) -> Option<&'a str> {
+    cert.subject()
+        .iter_common_name()
+        .next()
+        .and_then(|cn| cn.as_str().ok())
+}
+
+// ARK, ASK and VCEK are all signed with RSASSA-PSS (SHA-384).
+fn verify_rsa_pss(cert: &X509Certificate, issuer: &X509Certificate) -> Result<()> {
+    if cert.signature_algorithm.algorithm != OID_PKCS1_RSASSAPSS {
+        return Err(anyhow!(
+            "Certificate \"{}\" is not signed with RSASSA-PSS",
+            cert.subject()
+        ));
+    }
+    let key = match issuer.public_key().parsed() {
+        Ok(PublicKey::RSA(key)) => RsaPublicKey::new(
+            BigUint::from_bytes_be(key.modulus),
+            BigUint::from_bytes_be(key.exponent),
+        )?,
+        _ => return Err(anyhow!("Issuer \"{}\" is not a RSA key", issuer.subject())),
+    };
+    let signature = Signature::try_from(cert.signature_value.data.as_ref())?;
+    VerifyingKey::<Sha384>::new(key)
+        .verify(cert.tbs_certificate.as_ref(), &signature)
+        .map_err(|_| {
+            Error::SignatureInvalid(format!(
+                "Certificate \"{}\" signature verification failed!",
+                cert.subject()
+            ))
+        })?;
+    Ok(())
+}
+
+fn check_validity(cert: &X509Certificate) -> Result<()> {
+    if !cert.validity().is_valid() {
+        return Err(Error::SignatureInvalid(format!(
+            "Certificate \"{}\" is expired!",
+            cert.subject()
+        ))
+        .into());
+    }
+    Ok(())
+}
+
+impl AmdRoots {
+    /// Verify the ARK -> ASK -> VCEK chain.
+    pub fn verify_vcek(&self, vcek: &X509Certificate) -> Result<()> {
+        let ark = parse_cert(&self.ark)?;
+        let ask = parse_cert(&self.ask)?;
+
+        let expected = format!("ARK-{}", self.generation.name());
+        let digest = hex::encode(Sha256::digest(ark.public_key().raw));
+        let pinned = PINNED_ARKS
+            .iter()
+            .any(|(generation, pin)| *generation == self.generation && *pin == digest);
+        if !pinned || common_name(&ark) != Some(expected.as_str()) {
+            return Err(Error::SignatureInvalid(format!(
+                "{} is not the pinned AMD {} root!",
+                ark.subject(),
+                self.generation.name()
+            ))
+            .into());
+        }
+
+        for (cert, issuer) in [(&ark, &ark), (&ask, &ark), (vcek, &ask)] {
+            check_validity(cert)?;
+            verify_rsa_pss(cert, issuer)?;
+        }
+        Ok(())
+    }
+}
+
+/// Verify the VCEK against the pinned roots, returns the matched processor generation.
+pub fn verify_vcek_chain(vcek: &X509Certificate, roots: &[AmdRoots]) -> Result<Generation> {
+    for root in roots {
+        match root.verify_vcek(vcek) {
+            Ok(()) => return Ok(root.generation),
+            Err(e) => debug!("VCEK is not issued by {}: {:?}", root.generation.name(), e),
+        }
+    }
+    Err(Error::SignatureInvalid("VCEK is not issued by a pinned AMD root!".to_string()).into())
+}
+
+// Integer extensions are DER encoded INTEGERs.
+fn ext_u8(value: &[u8]) -> Option<u8> {
+    match value {
+        [0x02, 0x01, v] => Some(*v),
+        [0x02, 0x02, 0x00, v] => Some(*v),
+        _ => None,
+    }
+}
+
+// The hardware ID is either an OCTET STRING or the raw bytes.
+fn ext_bytes(value: &[u8]) -> &[u8] {
+    match value {
+        [0x04, 0x40, bytes @ ..] => bytes,
+        _ => value,
+    }
+}
+
+/// Check the VCEK was issued for the chip and the TCB in the report.
+pub fn check_vcek_extensions(vcek: &X509Certificate, report: &AttestationReport) -> Result<()> {
+    let tcb = &report.reported_tcb;
+    let expected = [
+        (OID_BL_SPL, tcb.bootloader),
+        (OID_TEE_SPL, tcb.tee),
+        (OID_SNP_SPL, tcb.snp),
+        (OID_UCODE_SPL, tcb.microcode),
+    ];
+
+    // The extension is required, so a VCEK without it doesn't match any chip or TCB.
+    let extension = |oid: &str| {
+        vcek.extensions()
+            .iter()
+            .find(|ext| ext.oid.to_id_string() == oid)
+            .map(|ext| ext.value)
+            .ok_or_else(|| Error::SignatureInvalid(format!("VCEK has no {} extension!", oid)))
+    };
+
+    if ext_bytes(extension(OID_HW_ID)?) != report.chip_id {
+        return Err(
+            Error::SignatureInvalid("VCEK is not issued for this chip!".to_string()).into(),
+        );
+    }
+    for (oid, spl) in expected {
+        if ext_u8(extension(oid)?) != Some(spl) {
+            return Err(Error::SignatureInvalid(format!(
+                "VCEK {} does not match the reported TCB!",
+                oid
+            ))
+            .into());
+        }
+    }
+
+    Ok(())
+}
+
+pub fn parse_vcek(der: &[u8]) -> Result<X509Certificate<'_>> {
+    let vcek = parse_cert(der).context("Parse VCEK failed.")?;
+    check_validity(&vcek)?;
+    Ok(vcek)
+}
@@ -0,0 +1,289 @@
+use crate::Error;
+use anyhow::{anyhow, Context, Result};
+extern crate serde;
+use self::serde::{Deserialize, Serialize};
+use super::*;
+use async_trait::async_trait;
+use certs::{AmdRoots, Generation};
+use p384::ecdsa::signature::Verifier as _;
+use p384::ecdsa::{Signature, VerifyingKey};
+use report::{
+    parse_report, AttestationReport, POLICY_DEBUG, POLICY_MIGRATE_MA, POLICY_SINGLE_SOCKET,
+    POLICY_SMT_ALLOWED,
+};
+use serde_json::json;
+use std::path::{Path, PathBuf};
+
+mod certs;
+mod report;
+
+/// Default directory of the pinned AMD certificates:
+/// - `<dir>/Milan/ark.pem`, `<dir>/Milan/ask.pem`
+/// - `<dir>/Genoa/ark.pem`, `<dir>/Genoa/ask.pem`
+/// - `<dir>/vcek/<chip_id>-<reported_tcb>.pem`: the local VCEK cache.
+pub const DEFAULT_AMD_CERTS_DIR: &str = "/etc/attestation-service/amd";
+/// Environment variable overriding the AMD certificates directory.
+pub const AMD_CERTS_DIR_ENV: &str = "AMD_CERTS_DIR";
+
+#[derive(Serialize, Deserialize, Debug)]
+struct SnpEvidence {
+    // Base64 encoded attestation report.
+    attestation_report: String,
+    // PEM encoded VCEK, looked up in the local cache if absent.
+    vcek: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct Snp {
+    certs_dir: PathBuf,
+}
+
+impl Default for Snp {
+    fn default() -> Self {
+        let path =
+            std::env::var(AMD_CERTS_DIR_ENV).unwrap_or_else(|_| DEFAULT_AMD_CERTS_DIR.to_string());
+        Self::new(PathBuf::from(path))
+    }
+}
+
+impl Snp {
+    pub fn new(certs_dir: PathBuf) -> Self {
+        Self { certs_dir }
+    }
+
+    // The cached VCEK is named by the chip ID and the raw reported TCB.
+    fn cached_vcek_path(&self, report: &AttestationReport) -> PathBuf {
+        self.certs_dir.join("vcek").join(format!(
+            "{}-{:016x}.pem",
+            hex::encode(report.chip_id),
+            report.reported_tcb_raw
+        ))
+    }
+
+    async fn vcek(
+        &self,
+        snp_evidence: &SnpEvidence,
+        report: &AttestationReport<'_>,
+    ) -> Result<Vec<u8>> {
+        match &snp_evidence.vcek {
+            Some(vcek) => certs::to_der(vcek.as_bytes()),
+            None => {
+                let path = self.cached_vcek_path(report);
+                let vcek = tokio::fs::read(&path)
+                    .await
+                    .with_context(|| format!("Read cached VCEK {:?} failed.", path))?;
+                certs::to_der(&vcek)
+            }
+        }
+    }
+}
+
+async fn load_roots(dir: &Path) -> Result<Vec<AmdRoots>> {
+    let mut roots = Vec::new();
+    for generation in Generation::ALL {
+        match AmdRoots::load(dir, generation).await {
+            Ok(root) => roots.push(root),
+            Err(e) => debug!("Skip AMD roots: {:?}", e),
+        }
+    }
+    if roots.is_empty() {
+        return Err(anyhow!("No pinned AMD roots in {:?}", dir));
+    }
+    Ok(roots)
+}
+
+#[async_trait]
+impl Verifier for Snp {
+    async fn evaluate(&self, evidence: &Evidence) -> Result<TeeEvidenceParsedClaim> {
+        let snp_evidence = serde_json::from_str::<SnpEvidence>(&evidence.tee_evidence).context(
+            Error::MalformedEvidence("Deserialize SNP Evidence failed.".to_string()),
+        )?;
+        let report_bin = base64::decode(&snp_evidence.attestation_report).context(
+            Error::MalformedEvidence("Base64 decode SNP attestation report failed.".to_string()),
+        )?;
+        let report = parse_report(&report_bin).context(Error::MalformedEvidence(
+            "Parse SNP attestation report failed.".to_string(),
+        ))?;
+        debug!("Report<sevsnp>: {:?}", &report);
+
+        let vcek = self.vcek(&snp_evidence, &report).await?;
+        let roots = load_roots(&self.certs_dir).await?;
+
+        verify(evidence, &report, &vcek, &roots)
+            .context("Evidence's identity verification error.")?;
+
+        tcb_status(&report)
+    }
+}
+
+// Dump the TCB status from the attestation report.
+fn tcb_status(report: &AttestationReport) -> Result<TeeEvidenceParsedClaim> {
+    let claims_map = json!({
+        "measurement": hex::encode(report.measurement),
+        "host_data": hex::encode(report.host_data),
+        "chip_id": hex::encode(report.chip_id),
+        "policy_abi_major": (report.policy >> 8) & 0xff,
+        "policy_abi_minor": report.policy & 0xff,
+        "policy_smt_allowed": report.policy & POLICY_SMT_ALLOWED != 0,
+        "policy_migrate_ma": report.policy & POLICY_MIGRATE_MA != 0,
+        "policy_debug_allowed": report.policy & POLICY_DEBUG != 0,
+        "policy_single_socket": report.policy & POLICY_SINGLE_SOCKET != 0,
+        "reported_tcb_bootloader": report.reported_tcb.bootloader,
+        "reported_tcb_tee": report.reported_tcb.tee,
+        "reported_tcb_snp": report.reported_tcb.snp,
+        "reported_tcb_microcode": report.reported_tcb.microcode,
+    });
+
+    Ok(claims_map as TeeEvidenceParsedClaim)
+}
+
+fn verify(
+    evidence: &Evidence,
+    report: &AttestationReport,
+    vcek: &[u8],
+    roots: &[AmdRoots],
+) -> Result<()> {
+    // Verify the report data: SHA384(nonce || tee-pubkey).
+    verify_report_data(evidence, &report.report_data)?;
+
+    // Verify the VCEK -> ASK -> ARK chain.
+    let vcek = certs::parse_vcek(vcek)?;
+    let generation = certs::verify_vcek_chain(&vcek, roots)?;
+    debug!("VCEK is issued by AMD {}", generation.name());
+    certs::check_vcek_extensions(&vcek, report)?;
+
+    // Verify the TEE Hardware signature.
+    let key = VerifyingKey::from_sec1_bytes(&vcek.public_key().subject_public_key.data)
+        .context("Parse VCEK public key failed.")?;
+    let signature = Signature::from_scalars(report.signature_r, report.signature_s)?;
+    key.verify(report.signed_data, &signature).map_err(|_| {
+        Error::SignatureInvalid("Report signature verification failed!".to_string())
+    })?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::verifier::test_util::{
+        assert_report_data_mismatch, evidence, tampered_evidence, test_data,
+    };
+
+    const TEE: &str = "snp";
+
+    #[tokio::test]
+    async fn test_evaluate() {
+        let snp = Snp::new(test_data(TEE, "certs"));
+        let res = snp.evaluate(&evidence(TEE, "evidence.json")).await;
+        assert!(res.is_ok(), "SNP evaluate should success: {:?}", res);
+        let claims = res.unwrap();
+        assert_eq!(claims["measurement"], json!("5c".repeat(48)));
+        assert_eq!(claims["host_data"], json!("7e".repeat(32)));
+        assert_eq!(
+            claims["chip_id"],
+            json!(hex::encode((0u8..64).collect::<Vec<_>>()))
+        );
+        assert_eq!(claims["policy_smt_allowed"], json!(true));
+        assert_eq!(claims["policy_debug_allowed"], json!(false));
+        assert_eq!(claims["reported_tcb_bootloader"], json!(3));
+        assert_eq!(claims["reported_tcb_snp"], json!(8));
+        assert_eq!(claims["reported_tcb_microcode"], json!(115));
+    }
+
+    #[tokio::test]
+    async fn test_evaluate_cached_vcek() {
+        let snp = Snp::new(test_data(TEE, "certs"));
+        let res = snp.evaluate(&evidence(TEE, "evidence_no_vcek.json")).await;
+        assert!(res.is_ok(), "SNP evaluate should success: {:?}", res);
+    }
+
+    #[tokio::test]
+    async fn test_evaluate_missing_vcek() {
+        let snp = Snp::new(test_data(TEE, "missing"));
+        let res = snp.evaluate(&evidence(TEE, "evidence_no_vcek.json")).await;
+        assert!(res.is_err(), "Missing VCEK should fail");
+    }
+
+    #[tokio::test]
+    async fn test_evaluate_unpinned_root() {
+        // Only the Genoa roots are pinned, but the VCEK is issued by Milan.
+        let dir = std::env::temp_dir().join(format!("snp-roots-{}", std::process::id()));
+        let genoa = dir.join("Genoa");
+        std::fs::create_dir_all(&genoa).unwrap();
+        for name in ["ark.pem", "ask.pem"] {
+            std::fs::copy(test_data(TEE, "certs/Genoa").join(name), genoa.join(name)).unwrap();
+        }
+        let snp = Snp::new(dir.clone());
+        let res = snp.evaluate(&evidence(TEE, "evidence.json")).await;
+        std::fs::remove_dir_all(dir).unwrap();
+        assert!(res.is_err(), "Unpinned root should fail");
+    }
+
+    #[tokio::test]
+    async fn test_forged_root() {
+        // A self-signed ARK named after the genuine one, whose key isn't pinned.
+        let root = AmdRoots::load(&test_data(TEE, "forged"), Generation::Milan)
+            .await
+            .unwrap();
+        let snp_evidence: SnpEvidence =
+            serde_json::from_str(&evidence(TEE, "evidence.json").tee_evidence).unwrap();
+        let vcek = certs::to_der(snp_evidence.vcek.unwrap().as_bytes()).unwrap();
+        let e = root
+            .verify_vcek(&certs::parse_vcek(&vcek).unwrap())
+            .unwrap_err();
+        assert!(
+            e.to_string().contains("is not the pinned AMD Milan root"),
+            "Forged root should fail: {}",
+            e
+        );
+    }
+
+    #[tokio::test]
+    async fn test_evaluate_report_data_mismatch() {
+        let snp = Snp::new(test_data(TEE, "certs"));
+        assert_report_data_mismatch(&snp, || evidence(TEE, "evidence.json")).await;
+    }
+
+    #[tokio::test]
+    async fn test_evaluate_tampered_report() {
+        let snp = Snp::new(test_data(TEE, "certs"));
+        // Flip a byte of the measurement.
+        let res = snp
+            .evaluate(&tampered_evidence(
+                evidence(TEE, "evidence.json"),
+                "attestation_report",
+                0x90,
+            ))
+            .await;
+        assert!(res.is_err(), "Tampered report should fail");
+    }
+
+    #[tokio::test]
+    async fn test_evaluate_wrong_chip() {
+        let snp_evidence: SnpEvidence =
+            serde_json::from_str(&evidence(TEE, "evidence.json").tee_evidence).unwrap();
+        let report_bin = base64::decode(&snp_evidence.attestation_report).unwrap();
+        let mut report = parse_report(&report_bin).unwrap();
+        let vcek = certs::to_der(snp_evidence.vcek.unwrap().as_bytes()).unwrap();
+        let vcek = certs::parse_vcek(&vcek).unwrap();
+        certs::check_vcek_extensions(&vcek, &report).unwrap();
+
+        // The report's signature is checked afterwards, so it's bypassed by checking the VCEK
+        // extensions directly.
+        report.chip_id[0] ^= 0xff;
+        let e = certs::check_vcek_extensions(&vcek, &report).unwrap_err();
+        assert!(
+            e.to_string().contains("not issued for this chip"),
+            "Wrong chip should fail: {}",
+            e
+        );
+        report.chip_id[0] ^= 0xff;
+        report.reported_tcb.snp += 1;
+        let e = certs::check_vcek_extensions(&vcek, &report).unwrap_err();
+        assert!(
+            e.to_string().contains("does not match the reported TCB"),
+            "Wrong TCB should fail: {}",
+            e
+        );
+    }
+}
@@ -0,0 +1,138 @@
+use anyhow::{anyhow, Result};
+
+// SEV-SNP ATTESTATION_REPORT layout: https://www.amd.com/system/files/TechDocs/56860.pdf
+pub const ATTESTATION_REPORT_SIZE: usize = 0x4A0;
+// The report is signed up to the signature field.
+const SIGNED_DATA_SIZE: usize = 0x2A0;
+pub const SIG_ALGO_ECDSA_P384_SHA384: u32 = 1;
+
+// Guest policy bits.
+pub const POLICY_SMT_ALLOWED: u64 = 1 << 16;
+pub const POLICY_MIGRATE_MA: u64 = 1 << 18;
+pub const POLICY_DEBUG: u64 = 1 << 19;
+pub const POLICY_SINGLE_SOCKET: u64 = 1 << 20;
+
+/// The security patch levels of the firmware components.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TcbVersion {
+    pub bootloader: u8,
+    pub tee: u8,
+    pub snp: u8,
+    pub microcode: u8,
+}
+
+impl From<[u8; 8]> for TcbVersion {
+    fn from(raw: [u8; 8]) -> Self {
+        Self {
+            bootloader: raw[0],
+            tee: raw[1],
+            snp: raw[6],
+            microcode: raw[7],
+        }
+    }
+}
+
+// The full layout is kept for the debug output.
+#[allow(dead_code)]
+#[derive(Debug)]
+pub struct AttestationReport<'a> {
+    pub version: u32,
+    pub guest_svn: u32,
+    pub policy: u64,
+    pub family_id: [u8; 16],
+    pub image_id: [u8; 16],
+    pub vmpl: u32,
+    pub signature_algo: u32,
+    pub current_tcb: TcbVersion,
+    pub platform_info: u64,
+    pub report_data: [u8; 64],
+    // Launch measurement of the guest
+    pub measurement: [u8; 48],
+    // Data provided by the hypervisor at launch
+    pub host_data: [u8; 32],
+    pub id_key_digest: [u8; 48],
+    pub author_key_digest: [u8; 48],
+    pub report_id: [u8; 32],
+    pub reported_tcb: TcbVersion,
+    // Raw `reported_tcb`, which names the cached VCEK.
+    pub reported_tcb_raw: u64,
+    pub chip_id: [u8; 64],
+    pub committed_tcb: TcbVersion,
+    pub launch_tcb: TcbVersion,
+    pub signed_data: &'a [u8],
+    // Big-endian ECDSA P-384 signature components.
+    pub signature_r: [u8; 48],
+    pub signature_s: [u8; 48],
+}
+
+fn array<const N: usize>(report: &[u8], offset: usize) -> [u8; N] {
+    let mut array = [0u8; N];
+    array.copy_from_slice(&report[offset..offset + N]);
+    array
+}
+
+fn u32_at(report: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(array(report, offset))
+}
+
+fn u64_at(report: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(array(report, offset))
+}
+
+// The signature components are stored as 72 bytes little-endian integers.
+fn signature_component(report: &[u8], offset: usize) -> Result<[u8; 48]> {
+    let le: [u8; 72] = array(report, offset);
+    if le[48..].iter().any(|b| *b != 0) {
+        return Err(anyhow!("Malformed report signature"));
+    }
+    let mut be: [u8; 48] = array(&le, 0);
+    be.reverse();
+    Ok(be)
+}
+
+pub fn parse_report(report: &[u8]) -> Result<AttestationReport<'_>> {
+    if report.len() != ATTESTATION_REPORT_SIZE {
+        return Err(anyhow!(
+            "Invalid SNP attestation report size: {:#x}",
+            report.len()
+        ));
+    }
+
+    let version = u32_at(report, 0x00);
+    if version < 2 {
+        return Err(anyhow!("Unsupported SNP report version: {}", version));
+    }
+    let signature_algo = u32_at(report, 0x34);
+    if signature_algo != SIG_ALGO_ECDSA_P384_SHA384 {
+        return Err(anyhow!(
+            "Unsupported SNP report signature algorithm: {}",
+            signature_algo
+        ));
+    }
+
+    Ok(AttestationReport {
+        version,
+        guest_svn: u32_at(report, 0x04),
+        policy: u64_at(report, 0x08),
+        family_id: array(report, 0x10),
+        image_id: array(report, 0x20),
+        vmpl: u32_at(report, 0x30),
+        signature_algo,
+        current_tcb: array(report, 0x38).into(),
+        platform_info: u64_at(report, 0x40),
+        report_data: array(report, 0x50),
+        measurement: array(report, 0x90),
+        host_data: array(report, 0xC0),
+        id_key_digest: array(report, 0xE0),
+        author_key_digest: array(report, 0x110),
+        report_id: array(report, 0x140),
+        reported_tcb: array(report, 0x180).into(),
+        reported_tcb_raw: u64_at(report, 0x180),
+        chip_id: array(report, 0x1A0),
+        committed_tcb: array(report, 0x1E0).into(),
+        launch_tcb: array(report, 0x1F0).into(),
+        signed_data: &report[..SIGNED_DATA_SIZE],
+        signature_r: signature_component(report, SIGNED_DATA_SIZE)?,
+        signature_s: signature_component(report, SIGNED_DATA_SIZE + 72)?,
+    })
+}
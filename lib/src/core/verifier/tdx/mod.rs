@@ -0,0 +1,140 @@
+use crate::Error;
+use anyhow::{Context, Result};
+extern crate serde;
+use self::serde::{Deserialize, Serialize};
+use super::*;
+use async_trait::async_trait;
+use quote::{parse_quote, Quote};
+use serde_json::json;
+use std::path::PathBuf;
+
+mod quote;
+
+#[derive(Serialize, Deserialize, Debug)]
+struct TdxEvidence {
+    // Base64 encoded TD quote.
+    quote: String,
+}
+
+#[derive(Debug)]
+pub struct Tdx {
+    // The trusted root of the PCK certificate chain.
+    root_ca_path: PathBuf,
+}
+
+impl Default for Tdx {
+    fn default() -> Self {
+        Self::new(intel_dcap::intel_root_ca_path())
+    }
+}
+
+impl Tdx {
+    pub fn new(root_ca_path: PathBuf) -> Self {
+        Self { root_ca_path }
+    }
+}
+
+#[async_trait]
+impl Verifier for Tdx {
+    async fn evaluate(&self, evidence: &Evidence) -> Result<TeeEvidenceParsedClaim> {
+        let tdx_evidence = serde_json::from_str::<TdxEvidence>(&evidence.tee_evidence).context(
+            Error::MalformedEvidence("Deserialize TDX Evidence failed.".to_string()),
+        )?;
+        let quote_bin = base64::decode(&tdx_evidence.quote).context(Error::MalformedEvidence(
+            "Base64 decode TDX quote failed.".to_string(),
+        ))?;
+        let quote = parse_quote(&quote_bin).context(Error::MalformedEvidence(
+            "Parse TDX quote failed.".to_string(),
+        ))?;
+        debug!("Quote<tdx>: {:?}", &quote);
+
+        let root_ca = tokio::fs::read(&self.root_ca_path)
+            .await
+            .with_context(|| format!("Read Intel root CA {:?} failed.", &self.root_ca_path))?;
+
+        verify(evidence, &quote, &root_ca).context("Evidence's identity verification error.")?;
+
+        tcb_status(&quote)
+    }
+}
+
+// Dump the TCB status from the quote.
+fn tcb_status(quote: &Quote) -> Result<TeeEvidenceParsedClaim> {
+    let body = &quote.body;
+    let claims_map = json!({
+        "mrtd": hex::encode(body.mr_td),
+        "rtmr0": hex::encode(body.rtmr[0]),
+        "rtmr1": hex::encode(body.rtmr[1]),
+        "rtmr2": hex::encode(body.rtmr[2]),
+        "rtmr3": hex::encode(body.rtmr[3]),
+        "td_attributes": hex::encode(body.td_attributes),
+        "tcb_svn": hex::encode(body.tee_tcb_svn),
+    });
+
+    Ok(claims_map as TeeEvidenceParsedClaim)
+}
+
+fn verify(evidence: &Evidence, quote: &Quote, root_ca: &[u8]) -> Result<()> {
+    // Verify the report data: SHA384(nonce || tee-pubkey).
+    verify_report_data(evidence, &quote.body.report_data)?;
+
+    // Verify the TEE Hardware signature.
+    quote.signature.verify(quote.signed_data, root_ca)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::verifier::test_util::{
+        assert_report_data_mismatch, evidence, tampered_evidence, test_data,
+    };
+
+    const TEE: &str = "tdx";
+
+    #[tokio::test]
+    async fn test_evaluate() {
+        let tdx = Tdx::new(test_data(TEE, "root_ca.pem"));
+        let res = tdx.evaluate(&evidence(TEE, "evidence.json")).await;
+        assert!(res.is_ok(), "TDX evaluate should success: {:?}", res);
+        let claims = res.unwrap();
+        assert_eq!(claims["mrtd"], json!("11".repeat(48)));
+        assert_eq!(claims["rtmr0"], json!("20".repeat(48)));
+        assert_eq!(claims["rtmr3"], json!("23".repeat(48)));
+        assert_eq!(claims["td_attributes"], json!("0000001000000000"));
+        assert_eq!(claims["tcb_svn"], json!("03010000000000000000000000000000"));
+    }
+
+    #[tokio::test]
+    async fn test_evaluate_untrusted_root_ca() {
+        let tdx = Tdx::new(test_data(TEE, "other_root_ca.pem"));
+        let res = tdx.evaluate(&evidence(TEE, "evidence.json")).await;
+        assert!(res.is_err(), "Untrusted root CA should fail");
+    }
+
+    #[tokio::test]
+    async fn test_evaluate_missing_root_ca() {
+        let tdx = Tdx::new(test_data(TEE, "missing.pem"));
+        let res = tdx.evaluate(&evidence(TEE, "evidence.json")).await;
+        assert!(res.is_err(), "Missing root CA should fail");
+    }
+
+    #[tokio::test]
+    async fn test_evaluate_report_data_mismatch() {
+        let tdx = Tdx::new(test_data(TEE, "root_ca.pem"));
+        assert_report_data_mismatch(&tdx, || evidence(TEE, "evidence.json")).await;
+    }
+
+    #[tokio::test]
+    async fn test_evaluate_tampered_quote() {
+        let tdx = Tdx::new(test_data(TEE, "root_ca.pem"));
+        // Flip a byte of MRTD.
+        let res = tdx
+            .evaluate(&tampered_evidence(
+                evidence(TEE, "evidence.json"),
+                "quote",
+                48 + 136,
+            ))
+            .await;
+        assert!(res.is_err(), "Tampered quote should fail");
+    }
+}
@@ -0,0 +1,115 @@
+use crate::core::verifier::intel_dcap::ecdsa::EcdsaSigData;
+use crate::core::verifier::intel_dcap::{Reader, ATTESTATION_KEY_TYPE_ECDSA_P256};
+use anyhow::{anyhow, Context, Result};
+
+// TDX quote v4 layout: https://download.01.org/intel-sgx/latest/dcap-latest/linux/docs/Intel_TDX_DCAP_Quoting_Library_API.pdf
+pub const QUOTE_VERSION: u16 = 4;
+pub const TEE_TYPE_TDX: u32 = 0x81;
+
+const QUOTE_HEADER_SIZE: usize = 48;
+const TD_QUOTE_BODY_SIZE: usize = 584;
+
+// The full layout is kept for the debug output.
+#[allow(dead_code)]
+#[derive(Debug)]
+pub struct QuoteHeader {
+    pub version: u16,
+    pub att_key_type: u16,
+    pub tee_type: u32,
+    pub qe_vendor_id: [u8; 16],
+    pub user_data: [u8; 20],
+}
+
+#[allow(dead_code)]
+#[derive(Debug)]
+pub struct TdQuoteBody {
+    // TEE TCB Security Version Number
+    pub tee_tcb_svn: [u8; 16],
+    pub mr_seam: [u8; 48],
+    pub mrsigner_seam: [u8; 48],
+    pub seam_attributes: [u8; 8],
+    pub td_attributes: [u8; 8],
+    pub xfam: [u8; 8],
+    // Measurement of the initial contents of the TD
+    pub mr_td: [u8; 48],
+    pub mr_config_id: [u8; 48],
+    pub mr_owner: [u8; 48],
+    pub mr_owner_config: [u8; 48],
+    // Runtime extendable measurement registers
+    pub rtmr: [[u8; 48]; 4],
+    pub report_data: [u8; 64],
+}
+
+#[allow(dead_code)]
+#[derive(Debug)]
+pub struct Quote<'a> {
+    pub header: QuoteHeader,
+    pub body: TdQuoteBody,
+    // The quote header and body, which are covered by the quote signature.
+    pub signed_data: &'a [u8],
+    pub signature: EcdsaSigData<'a>,
+}
+
+pub fn parse_quote(quote: &[u8]) -> Result<Quote<'_>> {
+    let mut reader = Reader::new(quote);
+
+    let version = reader.u16()?;
+    let att_key_type = reader.u16()?;
+    let tee_type = reader.u32()?;
+    // Reserved
+    reader.take(4)?;
+    let header = QuoteHeader {
+        version,
+        att_key_type,
+        tee_type,
+        qe_vendor_id: reader.array()?,
+        user_data: reader.array()?,
+    };
+    if header.version != QUOTE_VERSION {
+        return Err(anyhow!("Unsupported TDX quote version: {}", header.version));
+    }
+    if header.att_key_type != ATTESTATION_KEY_TYPE_ECDSA_P256 {
+        return Err(anyhow!(
+            "Unsupported attestation key type: {}",
+            header.att_key_type
+        ));
+    }
+    if header.tee_type != TEE_TYPE_TDX {
+        return Err(anyhow!("Not a TDX quote, TEE type: {:#x}", header.tee_type));
+    }
+
+    let body = TdQuoteBody {
+        tee_tcb_svn: reader.array()?,
+        mr_seam: reader.array()?,
+        mrsigner_seam: reader.array()?,
+        seam_attributes: reader.array()?,
+        td_attributes: reader.array()?,
+        xfam: reader.array()?,
+        mr_td: reader.array()?,
+        mr_config_id: reader.array()?,
+        mr_owner: reader.array()?,
+        mr_owner_config: reader.array()?,
+        rtmr: [
+            reader.array()?,
+            reader.array()?,
+            reader.array()?,
+            reader.array()?,
+        ],
+        report_data: reader.array()?,
+    };
+    debug_assert_eq!(reader.position(), QUOTE_HEADER_SIZE + TD_QUOTE_BODY_SIZE);
+    let signed_data = &quote[..reader.position()];
+
+    let sig_data_len = reader.u32()? as usize;
+    let sig_data = reader
+        .take(sig_data_len)
+        .context("Quote signature data is truncated")?;
+    let signature = EcdsaSigData::parse_v4(sig_data)?;
+
+    Ok(Quote {
+        header,
+        body,
+        signed_data,
+        signature,
+    })
+}
@@ -0,0 +1,48 @@
+//! The fixtures shared by the tests of the hardware TEE verifiers.
+
+use super::{Evidence, Verifier};
+use crate::Error;
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+
+/// The file `name` of TEE `tee`'s test data.
+pub fn test_data(tee: &str, name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("test_data")
+        .join(tee)
+        .join(name)
+}
+
+/// The evidence in the file `name` of TEE `tee`'s test data.
+pub fn evidence(tee: &str, name: &str) -> Evidence {
+    let evidence = std::fs::read_to_string(test_data(tee, name)).unwrap();
+    serde_json::from_str(&evidence).unwrap()
+}
+
+/// The evidence whose base64 encoded `field` of the TEE evidence has the byte at `offset`
+/// flipped.
+pub fn tampered_evidence(mut evidence: Evidence, field: &str, offset: usize) -> Evidence {
+    let mut tee_evidence: Value = serde_json::from_str(&evidence.tee_evidence).unwrap();
+    let mut binary = base64::decode(tee_evidence[field].as_str().unwrap()).unwrap();
+    binary[offset] ^= 0xff;
+    tee_evidence[field] = Value::String(base64::encode(binary));
+    evidence.tee_evidence = tee_evidence.to_string();
+    evidence
+}
+
+/// Assert that the verifier rejects the `evidence` once it's bound to another nonce or TEE
+/// public key, whose report data mismatches.
+pub async fn assert_report_data_mismatch(verifier: &dyn Verifier, evidence: impl Fn() -> Evidence) {
+    let mut replayed = evidence();
+    replayed.nonce = "replayed nonce".to_string();
+    let mut substituted = evidence();
+    substituted.tee_pubkey = "another public key".to_string();
+    for evidence in [replayed, substituted] {
+        let e = verifier.evaluate(&evidence).await.unwrap_err();
+        assert!(
+            matches!(Error::find(&e), Some(Error::ReportDataMismatch(_))),
+            "Mismatched report data should fail: {:?}",
+            e
+        );
+    }
+}
@@ -0,0 +1,84 @@
+use thiserror::Error as ThisError;
+
+/// The typed errors of the attestation service, which callers can react to without parsing
+/// the messages.
+///
+/// The APIs return `anyhow::Error`, whose root cause or context is one of them when it's
+/// classified, see [`Error::find`].
+#[derive(ThisError, Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// The Evidence or the TEE evidence in it can't be parsed.
+    #[error("{0}")]
+    MalformedEvidence(String),
+    /// No verifier is registered for `Evidence.tee`.
+    #[error("{0}")]
+    UnsupportedTee(String),
+    /// The signature of the TEE evidence, or its certificate chain, is invalid.
+    #[error("{0}")]
+    SignatureInvalid(String),
+    /// The report data of the TEE evidence isn't the hash of the nonce and the TEE public key.
+    #[error("{0}")]
+    ReportDataMismatch(String),
+    /// The nonce wasn't issued, has expired or was already consumed.
+    #[error("{0}")]
+    NonceInvalid(String),
+    /// The policy can't be compiled.
+    #[error("{0}")]
+    PolicyCompile(String),
+    /// The policy failed to be evaluated.
+    #[error("{0}")]
+    PolicyEval(String),
+    /// The policies, reference values or nonces failed to be read or written.
+    #[error("{0}")]
+    Storage(String),
+}
+
+impl Error {
+    /// The machine-readable reason, which is stable across the releases.
+    pub fn reason(&self) -> &'static str {
+        match self {
+            Error::MalformedEvidence(_) => "MALFORMED_EVIDENCE",
+            Error::UnsupportedTee(_) => "UNSUPPORTED_TEE",
+            Error::SignatureInvalid(_) => "SIGNATURE_INVALID",
+            Error::ReportDataMismatch(_) => "REPORT_DATA_MISMATCH",
+            Error::NonceInvalid(_) => "NONCE_INVALID",
+            Error::PolicyCompile(_) => "POLICY_COMPILE",
+            Error::PolicyEval(_) => "POLICY_EVAL",
+            Error::Storage(_) => "STORAGE",
+        }
+    }
+
+    /// Find the typed error in the chain of `err`, the outermost one wins.
+    pub fn find(err: &anyhow::Error) -> Option<&Error> {
+        // The typed contexts are only visible to `anyhow::Error::downcast_ref`, while the typed
+        // sources of the std errors are only visible through the chain.
+        err.downcast_ref::<Error>()
+            .or_else(|| err.chain().find_map(|cause| cause.downcast_ref::<Error>()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::{Context, Result};
+
+    #[test]
+    fn test_find() {
+        let res: Result<()> = Err(Error::ReportDataMismatch("mismatch".to_string()).into());
+        let err = res.context("Verify evidence failed.").unwrap_err();
+        assert_eq!(
+            Error::find(&err),
+            Some(&Error::ReportDataMismatch("mismatch".to_string()))
+        );
+        assert_eq!(Error::find(&err).unwrap().reason(), "REPORT_DATA_MISMATCH");
+
+        // The typed context classifies the untyped cause.
+        let res: Result<()> = Err(anyhow::anyhow!("EOF"));
+        let err = res
+            .context(Error::MalformedEvidence("Parse quote failed.".to_string()))
+            .unwrap_err();
+        assert_eq!(Error::find(&err).unwrap().reason(), "MALFORMED_EVIDENCE");
+
+        assert!(Error::find(&anyhow::anyhow!("unclassified")).is_none());
+    }
+}
@@ -1,6 +1,20 @@
-use crate::core::policy_engine::opa;
-use anyhow::{anyhow, Result};
-use std::str::FromStr;
+use crate::metrics::Metrics;
+use crate::nonce::{Challenge, Nonces};
+use crate::rvps::Rvps;
+use anyhow::{Context, Result};
+use futures::stream::{self, StreamExt};
+use std::sync::Arc;
+
+pub use crate::core::policy_engine::{
+    PolicyDecision, PolicyEngine, PolicyEngineType, Violation, DEFAULT_POLICY_CACHE_SIZE,
+};
+pub use crate::core::results::{TypedAttestationResults, ATTESTATION_RESULTS_VERSION};
+pub use crate::core::verifier::{Verifier, VerifierRegistry};
+pub use crate::core::{
+    AttestationResults, Evidence, PolicyData, TeeEvidenceParsedClaim, TeePolicies,
+};
+pub use crate::error::Error;
+pub use crate::token::{TokenAlgorithm, TokenSigner};
 
 #[macro_use]
 extern crate log;
@@ -9,15 +23,29 @@ extern crate strum;
 #[macro_use]
 extern crate strum_macros;
 
+pub mod audit;
 mod core;
+mod error;
+pub mod metrics;
+pub mod nonce;
+pub mod rvps;
+pub mod token;
+
+#[cfg(not(any(feature = "opa-engine", feature = "regorus-engine")))]
+compile_error!("At least one of the `opa-engine` and `regorus-engine` features must be enabled");
+
+/// The default number of the evidences attested concurrently by [`Service::attestation_batch`].
+pub const DEFAULT_BATCH_PARALLELISM: usize = 16;
 
 /// The supported TEE types:
 /// - TDX: TDX TEE.
 /// - SGX: SGX TEE.
 /// - SEVSNP: SEV-SNP TEE.
 /// - SAMPLE: A dummy TEE that used to test/demo the attestation service functionalities.
-#[derive(Debug, EnumString)]
-#[strum(ascii_case_insensitive)]
+///
+/// The lowercase variant name is the `Evidence.tee` the built-in verifier is registered for.
+#[derive(Debug, EnumString, AsRefStr)]
+#[strum(ascii_case_insensitive, serialize_all = "lowercase")]
 pub enum TEE {
     TDX,
     SGX,
@@ -36,15 +64,132 @@ impl Default for Service {
     }
 }
 
+/// Build an attestation service's instance with customized verifiers.
+#[derive(Debug)]
+pub struct ServiceBuilder {
+    verifiers: VerifierRegistry,
+    rvps: Option<Arc<Rvps>>,
+    policy_engine: Option<Box<dyn PolicyEngine>>,
+    nonces: Option<Arc<Nonces>>,
+    metrics: Option<Arc<Metrics>>,
+}
+
+impl ServiceBuilder {
+    /// Register the verifier of TEE `name`, which replaces the built-in one of the same TEE.
+    pub fn register_verifier(
+        mut self,
+        name: &str,
+        verifier: Box<dyn Verifier + Send + Sync>,
+    ) -> Self {
+        self.verifiers.register(name, verifier);
+        self
+    }
+
+    /// Keep only the verifiers of `tees`, e.g. to disable the built-in ones which aren't deployed.
+    /// It fails if any of them isn't registered.
+    pub fn enable_verifiers(mut self, tees: &[String]) -> Result<Self> {
+        self.verifiers.retain(tees)?;
+        Ok(self)
+    }
+
+    /// Assemble the reference data from RVPS when the attestation doesn't designate it.
+    pub fn rvps(mut self, rvps: Arc<Rvps>) -> Self {
+        self.rvps = Some(rvps);
+        self
+    }
+
+    /// Evaluate the TCB claims by `policy_engine` instead of the default one, see
+    /// [`PolicyEngineType::default`].
+    pub fn policy_engine(mut self, policy_engine: Box<dyn PolicyEngine>) -> Self {
+        self.policy_engine = Some(policy_engine);
+        self
+    }
+
+    /// Issue the nonces by [`Service::challenge`], the Evidence whose nonce wasn't issued by it,
+    /// has expired or was already consumed is rejected.
+    pub fn nonces(mut self, nonces: Arc<Nonces>) -> Self {
+        self.nonces = Some(nonces);
+        self
+    }
+
+    /// Record the attestations' outcomes and latencies, and the policy engine's cache size.
+    pub fn metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    pub fn build(self) -> Service {
+        let policy_engine = self.policy_engine.unwrap_or_else(|| {
+            PolicyEngineType::default()
+                .to_engine()
+                .expect("The default policy engine is enabled")
+        });
+        Service {
+            attestation: core::Attestation::new(
+                self.verifiers,
+                self.rvps,
+                policy_engine,
+                self.nonces,
+                self.metrics,
+            ),
+        }
+    }
+}
+
 impl Service {
-    /// Create a new attestation service's instance.
+    /// Create a new attestation service's instance with the built-in verifiers.
     pub fn new() -> Self {
-        Self {
-            attestation: core::Attestation::default(),
+        Self::builder().build()
+    }
+
+    /// Create a builder initialized with the built-in verifiers.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use anyhow::Result;
+    /// use async_trait::async_trait;
+    /// use attestation_service::{Evidence, Service, TeeEvidenceParsedClaim, Verifier};
+    /// use serde_json::json;
+    ///
+    /// struct Vendor {}
+    ///
+    /// #[async_trait]
+    /// impl Verifier for Vendor {
+    ///     async fn evaluate(&self, _evidence: &Evidence) -> Result<TeeEvidenceParsedClaim> {
+    ///         Ok(json!({ "svn": 1 }))
+    ///     }
+    /// }
+    ///
+    /// let service = Service::builder()
+    ///     .register_verifier("vendor", Box::new(Vendor {}))
+    ///     .build();
+    /// assert!(service.tees().contains(&"vendor".to_string()));
+    /// ```
+    pub fn builder() -> ServiceBuilder {
+        ServiceBuilder {
+            verifiers: VerifierRegistry::builtin(),
+            rvps: None,
+            policy_engine: None,
+            nonces: None,
+            metrics: None,
         }
     }
 
-    /// Attest the received Evidence by the attestation service instance.
+    /// The TEEs supported by this attestation service's instance.
+    pub fn tees(&self) -> Vec<String> {
+        self.attestation.tees()
+    }
+
+    /// Issue a nonce as the challenge of the attestation, which the Evidence should carry as its
+    /// `nonce` and bind to its report data. It fails if the nonces aren't enabled by
+    /// [`ServiceBuilder::nonces`].
+    pub async fn challenge(&self) -> Result<Challenge> {
+        self.attestation.challenge().await
+    }
+
+    /// Attest the received Evidence by the attestation service instance, the `policy` and
+    /// `reference_data` apply to whichever TEE the Evidence comes from.
     ///
     /// # Examples
     ///
@@ -115,11 +260,123 @@ impl Service {
         policy: Option<String>,
         reference_data: Option<String>,
     ) -> Result<String> {
+        let policies = TeePolicies::from(PolicyData {
+            policy,
+            reference_data,
+        });
+        self.attestation_with_policies(evidence, &policies).await
+    }
+
+    /// Attest the received Evidence with the policy and reference data of its TEE, which are
+    /// selected from `policies` by `Evidence.tee`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use attestation_service::{PolicyData, Service, TeePolicies};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let service = Service::new();
+    ///
+    ///     let mut policies = TeePolicies::default();
+    ///     policies.tees.insert(
+    ///         "tdx".to_string(),
+    ///         PolicyData {
+    ///             policy: Some("package policy\ndefault allow = false".to_string()),
+    ///             reference_data: None,
+    ///         },
+    ///     );
+    ///     assert!(policies.select("sample").policy.is_none());
+    ///
+    ///     // Not a valid evidence.
+    ///     let res = service.attestation_with_policies("{}", &policies).await;
+    ///     assert!(res.is_err());
+    /// }
+    /// ```
+    pub async fn attestation_with_policies(
+        &self,
+        evidence: &str,
+        policies: &TeePolicies,
+    ) -> Result<String> {
+        self.attestation.evaluate(evidence, policies).await
+    }
+
+    /// Attest the evidence like [`Service::attestation_with_policies`], returns the parsed Evidence
+    /// along with the unserialized attestation results, e.g. to convert them to
+    /// [`TypedAttestationResults`] or issue them as a JWT by [`TokenSigner::issue`].
+    pub async fn attestation_results_with_policies(
+        &self,
+        evidence: &str,
+        policies: &TeePolicies,
+    ) -> Result<(Evidence, AttestationResults)> {
+        self.attestation.evaluate_results(evidence, policies).await
+    }
+
+    /// Evaluate the TCB claims of an attested evidence again, which are the `tcb` of its
+    /// [`AttestationResults`], e.g. to find out whether the evidence still passes after the
+    /// policy or the reference data is updated. The evidence isn't verified again, so its nonce
+    /// isn't consumed.
+    pub async fn evaluate_claims(
+        &self,
+        tee: &str,
+        claims: &TeeEvidenceParsedClaim,
+        policies: &TeePolicies,
+    ) -> Result<AttestationResults> {
         self.attestation
-            .evaluate(evidence, policy, reference_data)
+            .evaluate_claims(tee, claims, policies)
             .await
     }
 
+    /// Attest the evidences like [`Service::attestation_results_with_policies`], at most
+    /// `parallelism` of them concurrently. The `policies` are shared by all the evidences, the
+    /// results are in the order of `evidences` and a failed evidence doesn't fail the others.
+    pub async fn attestation_batch(
+        &self,
+        evidences: &[String],
+        policies: &TeePolicies,
+        parallelism: usize,
+    ) -> Vec<Result<(Evidence, AttestationResults)>> {
+        // Map the indexes rather than the references, otherwise the future isn't `Send` due to
+        // rust-lang/rust#64552.
+        stream::iter(0..evidences.len())
+            .map(|i| self.attestation_results_with_policies(&evidences[i], policies))
+            .buffered(parallelism.max(1))
+            .collect()
+            .await
+    }
+
+    /// Attest the evidence like [`Service::attestation`], but the attestation results are issued
+    /// as a JWT signed by `signer`.
+    pub async fn attestation_token(
+        &self,
+        evidence: &str,
+        policy: Option<String>,
+        reference_data: Option<String>,
+        signer: &TokenSigner,
+    ) -> Result<String> {
+        let policies = TeePolicies::from(PolicyData {
+            policy,
+            reference_data,
+        });
+        self.attestation_token_with_policies(evidence, &policies, signer)
+            .await
+    }
+
+    /// Attest the evidence like [`Service::attestation_with_policies`], but the attestation
+    /// results are issued as a JWT signed by `signer`.
+    pub async fn attestation_token_with_policies(
+        &self,
+        evidence: &str,
+        policies: &TeePolicies,
+        signer: &TokenSigner,
+    ) -> Result<String> {
+        let (evidence, results) = self
+            .attestation_results_with_policies(evidence, policies)
+            .await?;
+        signer.issue(&evidence, &results)
+    }
+
     /// Get the specific TEE's Open Policy Agent default policy file.
     ///
     /// # Examples
@@ -138,7 +395,57 @@ impl Service {
         self.attestation.default_policy()
     }
 
-    /// Evaluate the input data, policy file, and reference data by the OPA policy engine.
+    /// Get the TEE `tee`'s built-in default policy, which is adopted when no policy is provided
+    /// for its Evidence.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use attestation_service::{Service, TEE};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let service = Service::new();
+    ///     let res = service.tee_default_policy(TEE::TDX.as_ref()).await;
+    ///     assert!(res.unwrap().contains("td_debug"));
+    /// }
+    /// ```
+    pub async fn tee_default_policy(&self, tee: &str) -> Result<String> {
+        Ok(core::tee_default_policy(tee).to_string())
+    }
+
+    /// Drop the compiled `policy` from the policy engine's cache, which should be called once
+    /// the policy is replaced or deleted.
+    pub fn invalidate_policy(&self, policy: &str) {
+        self.attestation.policy_engine().invalidate(policy);
+        self.attestation.record_policy_cache();
+    }
+
+    /// Check the policy's syntax by evaluating it with the empty reference data and input.
+    pub fn check_policy(&self, policy: &str) -> Result<()> {
+        self.attestation
+            .policy_engine()
+            .evaluate(policy, "{}", "{}")
+            .map(|_| ())
+    }
+
+    /// Check that the service is ready to attest: the built-in default policies of the enabled
+    /// TEEs and the TEE-agnostic one compile, and the built-in sample evidence is attested as
+    /// expected.
+    pub async fn self_test(&self) -> Result<()> {
+        for tee in self.tees() {
+            self.check_policy(core::tee_default_policy(&tee))
+                .with_context(|| format!("The default policy of TEE {} doesn't compile.", tee))?;
+        }
+        self.check_policy(&self.default_policy().await?)
+            .context("The default policy doesn't compile.")?;
+        self.attestation
+            .self_test()
+            .await
+            .context("Attest the sample evidence failed.")
+    }
+
+    /// Evaluate the input data, policy file, and reference data by the policy engine.
     ///
     /// # Examples
     ///
@@ -176,21 +483,37 @@ impl Service {
         reference_content: String,
         input_content: String,
     ) -> Result<String> {
-        opa::evaluate(policy_content, reference_content, input_content)
+        self.attestation.policy_engine().evaluate(
+            &policy_content,
+            &reference_content,
+            &input_content,
+        )
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::Service;
-    use base64;
+    use super::{
+        nonce, Error, Evidence, Nonces, PolicyData, Service, TeeEvidenceParsedClaim, TeePolicies,
+        Verifier,
+    };
+    use crate::metrics::Metrics;
+    use crate::rvps::{MemoryStore, Rvps, MESSAGE_VERSION};
+    use anyhow::{anyhow, Result};
+    use async_trait::async_trait;
     use serde_json::{json, Value};
     use sha2::{Digest, Sha384};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
 
     const NONCE: &str = "1234567890";
     const PUBLIC_KEY: &str = "hduabci29e0asdadans0212nsj0e3n";
 
     fn sample_evidence() -> String {
+        sample_evidence_with_nonce(NONCE)
+    }
+
+    fn sample_evidence_with_nonce(nonce: &str) -> String {
         let pubkey = json!({
             "algorithm": "".to_string(),
             "pubkey-length": "".to_string(),
@@ -198,7 +521,7 @@ mod tests {
         })
         .to_string();
         let mut hasher = Sha384::new();
-        hasher.update(NONCE);
+        hasher.update(nonce);
         hasher.update(&pubkey);
         let hash = hasher.finalize();
         let tee_evidence = json!({
@@ -209,7 +532,7 @@ mod tests {
         })
         .to_string();
         json!({
-            "nonce": NONCE.to_owned(),
+            "nonce": nonce,
             "tee": "sample".to_string(),
             "tee-pubkey": pubkey,
             "tee-evidence": tee_evidence
@@ -285,6 +608,33 @@ allow {
         .unwrap()
     }
 
+    #[tokio::test]
+    async fn test_attestation_rvps() {
+        let rvps = Arc::new(
+            Rvps::new(Box::new(MemoryStore::default()), Vec::new()).allow_unsigned_sample(),
+        );
+        let service = Service::builder().rvps(rvps.clone()).build();
+        let res = service.attestation(&sample_evidence(), None, None).await;
+        let v: Value = serde_json::from_str(&res.unwrap()).unwrap();
+        assert_eq!(v["allow"], json!(true));
+
+        // The evidence's svn isn't in the registered reference values.
+        let message = json!({
+            "version": MESSAGE_VERSION,
+            "type": "sample",
+            "tee": "sample",
+            "payload": base64::encode(json!({ "svn": [2, 3] }).to_string())
+        });
+        rvps.register(&message.to_string()).await.unwrap();
+        let res = service.attestation(&sample_evidence(), None, None).await;
+        let v: Value = serde_json::from_str(&res.unwrap()).unwrap();
+        assert_eq!(v["allow"], json!(false));
+        assert_eq!(
+            v["violations"],
+            json!([{ "claim": "svn", "observed": 1, "expected": [2, 3] }])
+        );
+    }
+
     #[tokio::test]
     async fn test_default_policy() {
         let service = Service::new();
@@ -296,10 +646,278 @@ allow {
         );
     }
 
+    #[tokio::test]
+    async fn test_tee_default_policy() {
+        let service = Service::new();
+        let policy = service.tee_default_policy("SGX").await.unwrap();
+        assert_eq!(
+            policy,
+            std::include_str!("./core/policy_engine/sgx_default_policy.rego")
+        );
+        let policy = service.tee_default_policy("sample").await.unwrap();
+        assert_eq!(policy, service.default_policy().await.unwrap());
+    }
+
+    #[test]
+    fn test_tee_policies_select() {
+        let mut policies = TeePolicies::from(PolicyData {
+            policy: Some("common policy".to_string()),
+            reference_data: Some("common reference data".to_string()),
+        });
+        policies.tees.insert(
+            "tdx".to_string(),
+            PolicyData {
+                policy: Some("tdx policy".to_string()),
+                reference_data: None,
+            },
+        );
+
+        let tdx = policies.select("TDX");
+        assert_eq!(tdx.policy.as_deref(), Some("tdx policy"));
+        assert_eq!(tdx.reference_data.as_deref(), Some("common reference data"));
+        let sgx = policies.select("sgx");
+        assert_eq!(sgx.policy.as_deref(), Some("common policy"));
+        assert!(TeePolicies::default().select("sgx").policy.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_attestation_nonce() {
+        let nonces = Arc::new(Nonces::new(
+            Box::new(nonce::MemoryStore::default()),
+            nonce::DEFAULT_NONCE_TTL,
+        ));
+        let service = Service::builder().nonces(nonces).build();
+        let challenge = service.challenge().await.unwrap();
+        let evidence = sample_evidence_with_nonce(&challenge.nonce);
+        // The evidence failing the verifier doesn't consume the nonce.
+        let forged = evidence.replace(PUBLIC_KEY, "another public key");
+        let res = service.attestation(&forged, None, None).await;
+        let v: Value = serde_json::from_str(&res.unwrap()).unwrap();
+        assert_eq!(v["allow"], json!(false));
+        let res = service.attestation(&evidence, None, None).await;
+        let v: Value = serde_json::from_str(&res.unwrap()).unwrap();
+        assert_eq!(v["allow"], json!(true));
+
+        // The replayed evidence.
+        let res = service.attestation(&evidence, None, None).await;
+        assert!(res.unwrap_err().to_string().contains("already consumed"));
+        // The nonce chosen by the client.
+        let res = service.attestation(&sample_evidence(), None, None).await;
+        assert!(res.unwrap_err().to_string().contains("never issued"));
+
+        assert!(Service::new().challenge().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_attestation_tee_policies() {
+        let service = Service::new();
+        let deny = "package policy\ndefault allow = false".to_string();
+        let mut policies = TeePolicies::default();
+        policies.tees.insert(
+            "tdx".to_string(),
+            PolicyData {
+                policy: Some(deny.clone()),
+                reference_data: None,
+            },
+        );
+        let res = service
+            .attestation_with_policies(&sample_evidence(), &policies)
+            .await;
+        let v: Value = serde_json::from_str(&res.unwrap()).unwrap();
+        assert_eq!(v["allow"], json!(true));
+
+        policies.tees.insert(
+            "sample".to_string(),
+            PolicyData {
+                policy: Some(deny),
+                reference_data: None,
+            },
+        );
+        let res = service
+            .attestation_with_policies(&sample_evidence(), &policies)
+            .await;
+        let v: Value = serde_json::from_str(&res.unwrap()).unwrap();
+        assert_eq!(v["allow"], json!(false));
+    }
+
     #[tokio::test]
     async fn test_opa_test() {
         let service = Service::new();
         let res = service.opa_test(sample_policy(), sample_reference(1), sample_input(1));
         assert!(res.is_ok(), "opa test should success");
     }
+
+    struct Rejecting {}
+
+    #[async_trait]
+    impl Verifier for Rejecting {
+        async fn evaluate(&self, _evidence: &Evidence) -> Result<TeeEvidenceParsedClaim> {
+            Err(anyhow!("rejected"))
+        }
+    }
+
+    // Track the evidences evaluated concurrently.
+    #[derive(Default)]
+    struct Counting {
+        running: AtomicUsize,
+        max: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl Verifier for Arc<Counting> {
+        async fn evaluate(&self, _evidence: &Evidence) -> Result<TeeEvidenceParsedClaim> {
+            let running = self.running.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max.fetch_max(running, Ordering::SeqCst);
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+            self.running.fetch_sub(1, Ordering::SeqCst);
+            Err(anyhow!("counted"))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_evaluate_claims() {
+        let service = Service::new();
+        let (_, results) = service
+            .attestation_results_with_policies(&sample_evidence(), &TeePolicies::default())
+            .await
+            .unwrap();
+        assert!(results.allow);
+
+        // The claims are evaluated again with the updated reference data.
+        let claims: Value = serde_json::from_str(&results.tcb).unwrap();
+        let policies = TeePolicies::from(PolicyData {
+            policy: None,
+            reference_data: Some(sample_reference(2)),
+        });
+        let reevaluated = service
+            .evaluate_claims("sample", &claims, &policies)
+            .await
+            .unwrap();
+        assert!(!reevaluated.allow);
+        assert_eq!(reevaluated.tcb, results.tcb);
+        let violations: Vec<&str> = reevaluated
+            .violations
+            .iter()
+            .map(|violation| violation.claim.as_str())
+            .collect();
+        assert_eq!(violations, vec!["cpusvn", "svn"]);
+    }
+
+    #[tokio::test]
+    async fn test_attestation_batch() {
+        let counting = Arc::new(Counting::default());
+        let service = Service::builder()
+            .register_verifier("sample", Box::new(counting.clone()))
+            .build();
+        let mut evidences = vec![sample_evidence(); 8];
+        evidences[3] = "{}".to_string();
+        let results = service
+            .attestation_batch(&evidences, &TeePolicies::default(), 3)
+            .await;
+
+        assert_eq!(results.len(), 8);
+        for (i, res) in results.iter().enumerate() {
+            match i {
+                3 => assert_eq!(
+                    Error::find(res.as_ref().unwrap_err()).map(Error::reason),
+                    Some("MALFORMED_EVIDENCE")
+                ),
+                _ => assert!(res.as_ref().unwrap().1.verifier_output.contains("counted")),
+            }
+        }
+        let max = counting.max.load(Ordering::SeqCst);
+        assert!(
+            max > 1 && max <= 3,
+            "{} evidences evaluated concurrently",
+            max
+        );
+    }
+
+    #[tokio::test]
+    async fn test_attestation_report_data_mismatch() {
+        let service = Service::new();
+        let evidence = sample_evidence().replace(NONCE, "another nonce");
+        let (_, results) = service
+            .attestation_results_with_policies(&evidence, &TeePolicies::default())
+            .await
+            .unwrap();
+        assert!(!results.allow);
+        assert_eq!(
+            results.verifier_error_reason,
+            Some("REPORT_DATA_MISMATCH".to_string())
+        );
+
+        let res = service.attestation("{}", None, None).await;
+        assert_eq!(
+            Error::find(&res.unwrap_err()).map(Error::reason),
+            Some("MALFORMED_EVIDENCE")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_attestation_metrics() {
+        let metrics = Arc::new(Metrics::new().unwrap());
+        let service = Service::builder().metrics(metrics.clone()).build();
+        let evidence = sample_evidence();
+        for ver in [1, 5] {
+            let res = service
+                .attestation(&evidence, None, Some(sample_reference(ver)))
+                .await;
+            assert!(res.is_ok());
+        }
+        let res = service
+            .attestation(&evidence, Some("package policy\nallow {".to_string()), None)
+            .await;
+        assert!(res.is_err());
+        let mismatched = evidence.replace(NONCE, "another nonce");
+        let unsupported = evidence.replace(r#""tee":"sample""#, r#""tee":"vendor""#);
+        for evidence in [mismatched, unsupported] {
+            let _ = service.attestation(&evidence, None, None).await;
+        }
+
+        let text = metrics.encode().unwrap();
+        for outcome in [
+            r#"{outcome="allow",tee="sample"} 1"#,
+            r#"{outcome="deny",tee="sample"} 1"#,
+            r#"{outcome="policy_error",tee="sample"} 1"#,
+            r#"{outcome="verifier_error",tee="sample"} 1"#,
+            r#"{outcome="verifier_error",tee="unknown"} 1"#,
+        ] {
+            assert!(text.contains(outcome), "{} should be counted", outcome);
+        }
+        assert!(text.contains(r#"attestation_verifier_duration_seconds_count{tee="sample"} 4"#));
+        assert!(text.contains(r#"attestation_policy_duration_seconds_count{tee="sample"} 3"#));
+    }
+
+    #[tokio::test]
+    async fn test_self_test() {
+        let metrics = Arc::new(Metrics::new().unwrap());
+        let service = Service::builder().metrics(metrics.clone()).build();
+        service.self_test().await.unwrap();
+        // The self-test isn't counted as an attestation.
+        let text = metrics.encode().unwrap();
+        assert!(!text.contains("attestation_attestations_total{"));
+    }
+
+    #[tokio::test]
+    async fn test_register_verifier() {
+        let service = Service::builder()
+            .register_verifier("sample", Box::new(Rejecting {}))
+            .build();
+        let res = service.attestation(&sample_evidence(), None, None).await;
+        assert!(res.is_ok(), "attestation should success");
+        let v: Value = serde_json::from_str(&res.unwrap()).unwrap();
+        assert_eq!(v["allow"], json!(false));
+        assert!(v["verifier_output"].as_str().unwrap().contains("rejected"));
+
+        let evidence = sample_evidence().replace("\"sample\"", "\"unknown\"");
+        let res = service.attestation(&evidence, None, None).await;
+        assert!(res.is_err(), "Unknown TEE should fail");
+        assert_eq!(
+            Error::find(&res.unwrap_err()),
+            Some(&Error::UnsupportedTee(
+                "TEE unknown is not supported!".to_string()
+            ))
+        );
+    }
 }
@@ -0,0 +1,139 @@
+//! The Prometheus metrics of the attestations, e.g. served by the attestation server.
+
+use anyhow::Result;
+use prometheus::{
+    HistogramOpts, HistogramVec, IntCounterVec, IntGauge, Opts, Registry, TextEncoder,
+};
+use std::time::Duration;
+
+/// The `tee` label of the evidence whose TEE isn't supported, which keeps the labels bounded.
+pub const UNKNOWN_TEE: &str = "unknown";
+
+/// The outcome of an attestation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, AsRefStr)]
+#[strum(serialize_all = "snake_case")]
+pub enum Outcome {
+    /// The policy allows the evidence.
+    Allow,
+    /// The policy denies the evidence.
+    Deny,
+    /// The evidence is malformed, its TEE isn't supported, its nonce is invalid or the verifier
+    /// rejects it.
+    VerifierError,
+    /// The reference data can't be assembled, or the policy fails to be compiled or evaluated.
+    PolicyError,
+}
+
+/// The metrics in a registry, which the callers can register their own metrics to.
+#[derive(Debug, Clone)]
+pub struct Metrics {
+    registry: Registry,
+    attestations: IntCounterVec,
+    attestation_duration: HistogramVec,
+    verifier_duration: HistogramVec,
+    policy_duration: HistogramVec,
+    policy_cache: IntGauge,
+}
+
+impl Metrics {
+    pub fn new() -> Result<Self> {
+        let registry = Registry::new();
+        let attestations = IntCounterVec::new(
+            Opts::new(
+                "attestation_attestations_total",
+                "The attestations by TEE and outcome.",
+            ),
+            &["tee", "outcome"],
+        )?;
+        let attestation_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "attestation_duration_seconds",
+                "The end-to-end latency of the attestations by TEE.",
+            ),
+            &["tee"],
+        )?;
+        let verifier_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "attestation_verifier_duration_seconds",
+                "The latency of the verifiers by TEE.",
+            ),
+            &["tee"],
+        )?;
+        let policy_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "attestation_policy_duration_seconds",
+                "The latency of the policy engine by TEE.",
+            ),
+            &["tee"],
+        )?;
+        let policy_cache = IntGauge::new(
+            "attestation_policy_cache_entries",
+            "The compiled policies cached by the policy engine.",
+        )?;
+        registry.register(Box::new(attestations.clone()))?;
+        registry.register(Box::new(attestation_duration.clone()))?;
+        registry.register(Box::new(verifier_duration.clone()))?;
+        registry.register(Box::new(policy_duration.clone()))?;
+        registry.register(Box::new(policy_cache.clone()))?;
+        Ok(Self {
+            registry,
+            attestations,
+            attestation_duration,
+            verifier_duration,
+            policy_duration,
+            policy_cache,
+        })
+    }
+
+    pub fn registry(&self) -> &Registry {
+        &self.registry
+    }
+
+    /// The metrics of the registry in the Prometheus text format.
+    pub fn encode(&self) -> Result<String> {
+        Ok(TextEncoder::new().encode_to_string(&self.registry.gather())?)
+    }
+
+    /// Count the attestation of `tee` by its outcome, along with its end-to-end latency.
+    pub fn attestation(&self, tee: &str, outcome: Outcome, duration: Duration) {
+        self.attestations
+            .with_label_values(&[tee, outcome.as_ref()])
+            .inc();
+        self.attestation_duration
+            .with_label_values(&[tee])
+            .observe(duration.as_secs_f64());
+    }
+
+    pub fn verifier(&self, tee: &str, duration: Duration) {
+        self.verifier_duration
+            .with_label_values(&[tee])
+            .observe(duration.as_secs_f64());
+    }
+
+    pub fn policy(&self, tee: &str, duration: Duration) {
+        self.policy_duration
+            .with_label_values(&[tee])
+            .observe(duration.as_secs_f64());
+    }
+
+    pub fn policy_cache(&self, size: usize) {
+        self.policy_cache.set(size as i64);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode() {
+        let metrics = Metrics::new().unwrap();
+        metrics.attestation("tdx", Outcome::VerifierError, Duration::from_millis(5));
+        metrics.policy_cache(3);
+        let text = metrics.encode().unwrap();
+        assert!(text
+            .contains(r#"attestation_attestations_total{outcome="verifier_error",tee="tdx"} 1"#));
+        assert!(text.contains(r#"attestation_duration_seconds_count{tee="tdx"} 1"#));
+        assert!(text.contains("attestation_policy_cache_entries 3"));
+    }
+}
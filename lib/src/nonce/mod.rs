@@ -0,0 +1,140 @@
+//! Nonce issuance and replay protection.
+//!
+//! The nonces are issued by the attestation service as the challenges of the attestation, each
+//! of them is valid for a TTL and can be consumed by only one Evidence. The Evidence whose
+//! nonce was never issued, has expired or was already consumed is rejected.
+
+use crate::Error;
+use anyhow::Result;
+use rand_core::{OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::Mutex;
+
+pub mod store;
+
+pub use store::{LocalJsonStore, MemoryStore, Store};
+
+/// The default TTL of the issued nonces.
+pub const DEFAULT_NONCE_TTL: Duration = Duration::from_secs(300);
+
+// The random bytes of a nonce.
+const NONCE_LEN: usize = 32;
+
+/// The state of an issued nonce.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct NonceEntry {
+    /// Expiration time (seconds since UNIX epoch).
+    pub expiration: u64,
+    pub consumed: bool,
+}
+
+impl NonceEntry {
+    pub fn expired(&self, now: u64) -> bool {
+        self.expiration <= now
+    }
+}
+
+/// The nonce issued as the challenge of the attestation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Challenge {
+    /// The base64 encoded random nonce.
+    pub nonce: String,
+    /// Expiration time (seconds since UNIX epoch).
+    pub expiration: u64,
+}
+
+fn now() -> Result<u64> {
+    Ok(SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs())
+}
+
+pub struct Nonces {
+    store: Box<dyn Store>,
+    ttl: Duration,
+    // Serialize the check-and-consume of the nonces.
+    lock: Mutex<()>,
+}
+
+impl std::fmt::Debug for Nonces {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Nonces").field("ttl", &self.ttl).finish()
+    }
+}
+
+impl Nonces {
+    /// Create the nonces whose entries are kept in `store`, each of them expires after `ttl`.
+    pub fn new(store: Box<dyn Store>, ttl: Duration) -> Self {
+        Self {
+            store,
+            ttl,
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// Issue a new nonce, the expired ones are purged meanwhile.
+    pub async fn issue(&self) -> Result<Challenge> {
+        let now = now()?;
+        self.store.purge(now).await?;
+
+        let mut nonce = [0u8; NONCE_LEN];
+        OsRng.fill_bytes(&mut nonce);
+        let challenge = Challenge {
+            nonce: base64::encode(nonce),
+            expiration: now + self.ttl.as_secs(),
+        };
+        let entry = NonceEntry {
+            expiration: challenge.expiration,
+            consumed: false,
+        };
+        self.store.set(challenge.nonce.clone(), entry).await?;
+        Ok(challenge)
+    }
+
+    /// Consume the nonce, it fails if the nonce was never issued, has expired or was already
+    /// consumed.
+    pub async fn consume(&self, nonce: &str) -> Result<()> {
+        let _guard = self.lock.lock().await;
+        let mut entry = self
+            .store
+            .get(nonce)
+            .await?
+            .ok_or_else(|| Error::NonceInvalid("The nonce was never issued".to_string()))?;
+        if entry.consumed {
+            return Err(Error::NonceInvalid("The nonce was already consumed".to_string()).into());
+        }
+        if entry.expired(now()?) {
+            return Err(Error::NonceInvalid("The nonce has expired".to_string()).into());
+        }
+        entry.consumed = true;
+        self.store.set(nonce.to_string(), entry).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_consume() {
+        let nonces = Nonces::new(Box::new(MemoryStore::default()), DEFAULT_NONCE_TTL);
+        let challenge = nonces.issue().await.unwrap();
+        assert_eq!(base64::decode(&challenge.nonce).unwrap().len(), NONCE_LEN);
+        assert_ne!(nonces.issue().await.unwrap(), challenge);
+
+        assert!(nonces.consume(&challenge.nonce).await.is_ok());
+        let res = nonces.consume(&challenge.nonce).await;
+        assert!(res.unwrap_err().to_string().contains("already consumed"));
+        let res = nonces.consume("never issued").await;
+        assert!(res.unwrap_err().to_string().contains("never issued"));
+    }
+
+    #[tokio::test]
+    async fn test_consume_expired() {
+        let nonces = Nonces::new(Box::new(MemoryStore::default()), Duration::from_secs(1));
+        let challenge = nonces.issue().await.unwrap();
+        tokio::time::sleep(Duration::from_millis(2100)).await;
+        let res = nonces.consume(&challenge.nonce).await;
+        assert!(res.unwrap_err().to_string().contains("expired"));
+    }
+}
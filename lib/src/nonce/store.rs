@@ -0,0 +1,159 @@
+use super::NonceEntry;
+use crate::Error;
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use tokio::sync::{Mutex, RwLock};
+
+/// The store of the issued nonces.
+#[async_trait]
+pub trait Store: Send + Sync {
+    /// Store the nonce's entry, returns the replaced one.
+    async fn set(&self, nonce: String, entry: NonceEntry) -> Result<Option<NonceEntry>>;
+
+    async fn get(&self, nonce: &str) -> Result<Option<NonceEntry>>;
+
+    /// Drop the nonces expired at `now` (seconds since UNIX epoch).
+    async fn purge(&self, now: u64) -> Result<()>;
+}
+
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    entries: RwLock<HashMap<String, NonceEntry>>,
+}
+
+#[async_trait]
+impl Store for MemoryStore {
+    async fn set(&self, nonce: String, entry: NonceEntry) -> Result<Option<NonceEntry>> {
+        Ok(self.entries.write().await.insert(nonce, entry))
+    }
+
+    async fn get(&self, nonce: &str) -> Result<Option<NonceEntry>> {
+        Ok(self.entries.read().await.get(nonce).copied())
+    }
+
+    async fn purge(&self, now: u64) -> Result<()> {
+        self.entries
+            .write()
+            .await
+            .retain(|_, entry| !entry.expired(now));
+        Ok(())
+    }
+}
+
+/// Persist the nonces in a local JSON file, so they survive the restarts.
+#[derive(Debug)]
+pub struct LocalJsonStore {
+    file: PathBuf,
+    // Serialize the read-modify-write of the file.
+    lock: Mutex<()>,
+}
+
+impl LocalJsonStore {
+    pub fn new(file: PathBuf) -> Self {
+        Self {
+            file,
+            lock: Mutex::new(()),
+        }
+    }
+
+    async fn load(&self) -> Result<HashMap<String, NonceEntry>> {
+        if !self.file.exists() {
+            return Ok(HashMap::new());
+        }
+        let content = tokio::fs::read(&self.file)
+            .await
+            .with_context(|| Error::Storage(format!("Read nonces {:?} failed.", self.file)))?;
+        serde_json::from_slice(&content)
+            .with_context(|| Error::Storage(format!("Deserialize nonces {:?} failed.", self.file)))
+    }
+
+    // Write to a temporary file then rename it, the file is never partially written.
+    async fn save(&self, entries: &HashMap<String, NonceEntry>) -> Result<()> {
+        let error = || Error::Storage(format!("Write nonces {:?} failed.", self.file));
+        if let Some(dir) = self.file.parent() {
+            tokio::fs::create_dir_all(dir).await.with_context(error)?;
+        }
+        let tmp = self.file.with_extension("tmp");
+        tokio::fs::write(&tmp, serde_json::to_vec(entries)?)
+            .await
+            .with_context(error)?;
+        tokio::fs::rename(&tmp, &self.file)
+            .await
+            .with_context(error)?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Store for LocalJsonStore {
+    async fn set(&self, nonce: String, entry: NonceEntry) -> Result<Option<NonceEntry>> {
+        let _guard = self.lock.lock().await;
+        let mut entries = self.load().await?;
+        let old = entries.insert(nonce, entry);
+        self.save(&entries).await?;
+        Ok(old)
+    }
+
+    async fn get(&self, nonce: &str) -> Result<Option<NonceEntry>> {
+        let _guard = self.lock.lock().await;
+        Ok(self.load().await?.remove(nonce))
+    }
+
+    async fn purge(&self, now: u64) -> Result<()> {
+        let _guard = self.lock.lock().await;
+        let mut entries = self.load().await?;
+        let len = entries.len();
+        entries.retain(|_, entry| !entry.expired(now));
+        if entries.len() != len {
+            self.save(&entries).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(expiration: u64, consumed: bool) -> NonceEntry {
+        NonceEntry {
+            expiration,
+            consumed,
+        }
+    }
+
+    async fn check_store(store: &dyn Store) {
+        assert!(store
+            .set("a".to_string(), entry(10, false))
+            .await
+            .unwrap()
+            .is_none());
+        store.set("b".to_string(), entry(20, false)).await.unwrap();
+        let old = store.set("b".to_string(), entry(20, true)).await.unwrap();
+        assert_eq!(old, Some(entry(20, false)));
+
+        store.purge(10).await.unwrap();
+        assert!(store.get("a").await.unwrap().is_none());
+        assert_eq!(store.get("b").await.unwrap(), Some(entry(20, true)));
+    }
+
+    #[tokio::test]
+    async fn test_memory_store() {
+        check_store(&MemoryStore::default()).await;
+    }
+
+    #[tokio::test]
+    async fn test_local_json_store() {
+        let dir = std::env::temp_dir().join(format!("nonce-store-{}", std::process::id()));
+        let file = dir.join("nonces.json");
+        check_store(&LocalJsonStore::new(file.clone())).await;
+
+        // Reload from the file.
+        let store = LocalJsonStore::new(file);
+        let stored = store.get("b").await;
+        std::fs::remove_dir_all(dir).unwrap();
+        assert_eq!(stored.unwrap(), Some(entry(20, true)));
+    }
+}
@@ -0,0 +1,323 @@
+use super::{ExtractedValues, Extractor};
+use anyhow::{anyhow, Context, Result};
+use p256::ecdsa::signature::Verifier as _;
+use p256::ecdsa::{Signature, VerifyingKey};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+/// in-toto link metadata: https://github.com/in-toto/docs/blob/master/in-toto-spec.md#44-file-formats-namekeyid-prefixlink
+pub const LINK_PROVENANCE_TYPE: &str = "in-toto-link";
+/// in-toto statement (e.g. SLSA provenance) in a DSSE envelope: https://github.com/in-toto/attestation
+pub const STATEMENT_PROVENANCE_TYPE: &str = "in-toto-statement";
+
+const DSSE_PAYLOAD_TYPE: &str = "application/vnd.in-toto+json";
+const STATEMENT_TYPE_PREFIX: &str = "https://in-toto.io/Statement/";
+
+// The signatures are ECDSA P-256 (SHA-256) in DER.
+fn verify_signatures(trusted_keys: &[VerifyingKey], data: &[u8], sigs: &[Vec<u8>]) -> Result<()> {
+    if trusted_keys.is_empty() {
+        return Err(anyhow!("No trusted key to verify the provenance"));
+    }
+    let verified = sigs.iter().any(|sig| match Signature::from_der(sig) {
+        Ok(sig) => trusted_keys
+            .iter()
+            .any(|key| key.verify(data, &sig).is_ok()),
+        Err(_) => false,
+    });
+    if !verified {
+        return Err(anyhow!("Provenance signature verification failed!"));
+    }
+    Ok(())
+}
+
+// The digests of an artifact, e.g. `{ "sha384": "..." }`.
+fn digest_values(digests: &BTreeMap<String, String>) -> Vec<Value> {
+    digests
+        .values()
+        .map(|digest| Value::String(digest.to_ascii_lowercase()))
+        .collect()
+}
+
+#[derive(Deserialize)]
+struct LinkSignature {
+    // Hex encoded.
+    sig: String,
+}
+
+#[derive(Deserialize)]
+struct SignedLink {
+    signed: Value,
+    signatures: Vec<LinkSignature>,
+}
+
+#[derive(Deserialize)]
+struct LinkBody {
+    #[serde(rename = "_type")]
+    link_type: String,
+    products: HashMap<String, BTreeMap<String, String>>,
+}
+
+/// Serialize the JSON value in the canonical form signed by in-toto: sorted keys and no
+/// whitespace.
+pub fn canonical_json(value: &Value) -> String {
+    match value {
+        Value::Object(map) => {
+            let mut entries: Vec<(&String, &Value)> = map.iter().collect();
+            entries.sort_by(|a, b| a.0.cmp(b.0));
+            let entries: Vec<String> = entries
+                .into_iter()
+                .map(|(k, v)| format!("{}:{}", Value::String(k.clone()), canonical_json(v)))
+                .collect();
+            format!("{{{}}}", entries.join(","))
+        }
+        Value::Array(values) => {
+            let values: Vec<String> = values.iter().map(canonical_json).collect();
+            format!("[{}]", values.join(","))
+        }
+        _ => value.to_string(),
+    }
+}
+
+/// The products of the signed link are the reference values.
+#[derive(Debug)]
+pub struct Link {
+    trusted_keys: Arc<Vec<VerifyingKey>>,
+}
+
+impl Link {
+    pub fn new(trusted_keys: Arc<Vec<VerifyingKey>>) -> Self {
+        Self { trusted_keys }
+    }
+}
+
+impl Extractor for Link {
+    fn verify_and_extract(&self, provenance: &[u8]) -> Result<ExtractedValues> {
+        let link: SignedLink =
+            serde_json::from_slice(provenance).context("Deserialize in-toto link failed.")?;
+        let sigs = link
+            .signatures
+            .iter()
+            .map(|s| hex::decode(&s.sig))
+            .collect::<Result<Vec<_>, _>>()
+            .context("Hex decode link signature failed.")?;
+        verify_signatures(
+            &self.trusted_keys,
+            canonical_json(&link.signed).as_bytes(),
+            &sigs,
+        )?;
+
+        let body: LinkBody =
+            serde_json::from_value(link.signed).context("Deserialize link body failed.")?;
+        if body.link_type != "link" {
+            return Err(anyhow!(
+                "Unexpected in-toto metadata type {}",
+                body.link_type
+            ));
+        }
+        Ok(body
+            .products
+            .iter()
+            .map(|(name, digests)| (name.clone(), digest_values(digests)))
+            .collect())
+    }
+}
+
+#[derive(Deserialize)]
+struct EnvelopeSignature {
+    // Base64 encoded.
+    sig: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Envelope {
+    payload_type: String,
+    // Base64 encoded statement.
+    payload: String,
+    signatures: Vec<EnvelopeSignature>,
+}
+
+#[derive(Deserialize)]
+struct Subject {
+    name: String,
+    digest: BTreeMap<String, String>,
+}
+
+#[derive(Deserialize)]
+struct StatementBody {
+    #[serde(rename = "_type")]
+    statement_type: String,
+    subject: Vec<Subject>,
+}
+
+/// DSSE pre-authentication encoding.
+pub fn pae(payload_type: &str, payload: &[u8]) -> Vec<u8> {
+    let mut pae = format!(
+        "DSSEv1 {} {} {} ",
+        payload_type.len(),
+        payload_type,
+        payload.len()
+    )
+    .into_bytes();
+    pae.extend_from_slice(payload);
+    pae
+}
+
+/// The subjects of the signed statement are the reference values.
+#[derive(Debug)]
+pub struct Statement {
+    trusted_keys: Arc<Vec<VerifyingKey>>,
+}
+
+impl Statement {
+    pub fn new(trusted_keys: Arc<Vec<VerifyingKey>>) -> Self {
+        Self { trusted_keys }
+    }
+}
+
+impl Extractor for Statement {
+    fn verify_and_extract(&self, provenance: &[u8]) -> Result<ExtractedValues> {
+        let envelope: Envelope =
+            serde_json::from_slice(provenance).context("Deserialize DSSE envelope failed.")?;
+        if envelope.payload_type != DSSE_PAYLOAD_TYPE {
+            return Err(anyhow!("Unexpected payload type {}", envelope.payload_type));
+        }
+        let payload = base64::decode(&envelope.payload).context("Base64 decode payload failed.")?;
+        let sigs = envelope
+            .signatures
+            .iter()
+            .map(|s| base64::decode(&s.sig))
+            .collect::<Result<Vec<_>, _>>()
+            .context("Base64 decode DSSE signature failed.")?;
+        verify_signatures(
+            &self.trusted_keys,
+            &pae(&envelope.payload_type, &payload),
+            &sigs,
+        )?;
+
+        let statement: StatementBody =
+            serde_json::from_slice(&payload).context("Deserialize in-toto statement failed.")?;
+        if !statement.statement_type.starts_with(STATEMENT_TYPE_PREFIX) {
+            return Err(anyhow!(
+                "Unexpected in-toto statement type {}",
+                statement.statement_type
+            ));
+        }
+        let mut values = ExtractedValues::new();
+        for subject in statement.subject {
+            values
+                .entry(subject.name)
+                .or_default()
+                .extend(digest_values(&subject.digest));
+        }
+        Ok(values)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use p256::ecdsa::signature::Signer;
+    use p256::ecdsa::SigningKey;
+    use rand_core::OsRng;
+    use serde_json::json;
+
+    fn sign(key: &SigningKey, data: &[u8]) -> Vec<u8> {
+        let sig: Signature = key.sign(data);
+        sig.to_der().as_bytes().to_vec()
+    }
+
+    fn link(key: &SigningKey) -> Value {
+        let signed = json!({
+            "_type": "link",
+            "name": "build-kernel",
+            "materials": {},
+            "products": {
+                "kernel": { "sha384": "AABB" },
+                "initrd": { "sha256": "ccdd" }
+            },
+            "byproducts": {},
+            "environment": {},
+            "command": []
+        });
+        let sig = sign(key, canonical_json(&signed).as_bytes());
+        json!({
+            "signed": signed,
+            "signatures": [{ "keyid": "test", "sig": hex::encode(sig) }]
+        })
+    }
+
+    fn envelope(key: &SigningKey, statement: &Value) -> Value {
+        let payload = statement.to_string();
+        let sig = sign(key, &pae(DSSE_PAYLOAD_TYPE, payload.as_bytes()));
+        json!({
+            "payloadType": DSSE_PAYLOAD_TYPE,
+            "payload": base64::encode(payload),
+            "signatures": [{ "keyid": "test", "sig": base64::encode(sig) }]
+        })
+    }
+
+    fn slsa_statement() -> Value {
+        json!({
+            "_type": "https://in-toto.io/Statement/v0.1",
+            "subject": [
+                { "name": "mrtd", "digest": { "sha384": "11" } },
+                { "name": "mrtd", "digest": { "sha384": "22" } }
+            ],
+            "predicateType": "https://slsa.dev/provenance/v0.2",
+            "predicate": {}
+        })
+    }
+
+    #[test]
+    fn test_canonical_json() {
+        let value = json!({ "b": [1, { "d": "x", "c": null }], "a": true });
+        assert_eq!(
+            canonical_json(&value),
+            r#"{"a":true,"b":[1,{"c":null,"d":"x"}]}"#
+        );
+    }
+
+    #[test]
+    fn test_link() {
+        let key = SigningKey::random(&mut OsRng);
+        let extractor = Link::new(Arc::new(vec![*key.verifying_key()]));
+        let provenance = link(&key).to_string();
+        let values = extractor.verify_and_extract(provenance.as_bytes()).unwrap();
+        assert_eq!(values["kernel"], vec![json!("aabb")]);
+        assert_eq!(values["initrd"], vec![json!("ccdd")]);
+
+        // Tampered products.
+        let mut tampered = link(&key);
+        tampered["signed"]["products"]["kernel"]["sha384"] = json!("eeff");
+        let res = extractor.verify_and_extract(tampered.to_string().as_bytes());
+        assert!(res.is_err(), "Tampered link should fail");
+
+        // Signed by an untrusted key.
+        let other = SigningKey::random(&mut OsRng);
+        let res = extractor.verify_and_extract(link(&other).to_string().as_bytes());
+        assert!(res.is_err(), "Untrusted key should fail");
+    }
+
+    #[test]
+    fn test_statement() {
+        let key = SigningKey::random(&mut OsRng);
+        let extractor = Statement::new(Arc::new(vec![*key.verifying_key()]));
+        let provenance = envelope(&key, &slsa_statement()).to_string();
+        let values = extractor.verify_and_extract(provenance.as_bytes()).unwrap();
+        assert_eq!(values["mrtd"], vec![json!("11"), json!("22")]);
+
+        let mut statement = slsa_statement();
+        statement["_type"] = json!("https://example.com/Statement");
+        let provenance = envelope(&key, &statement).to_string();
+        let res = extractor.verify_and_extract(provenance.as_bytes());
+        assert!(res.is_err(), "Unknown statement type should fail");
+
+        let no_keys = Statement::new(Arc::new(Vec::new()));
+        let provenance = envelope(&key, &slsa_statement()).to_string();
+        let res = no_keys.verify_and_extract(provenance.as_bytes());
+        assert!(res.is_err(), "No trusted key should fail");
+    }
+}
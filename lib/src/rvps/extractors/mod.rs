@@ -0,0 +1,60 @@
+use anyhow::{anyhow, Result};
+use p256::ecdsa::VerifyingKey;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+pub mod in_toto;
+pub mod sample;
+
+/// The reference values extracted from a provenance, keyed by the claim's name.
+pub type ExtractedValues = HashMap<String, Vec<Value>>;
+
+pub trait Extractor {
+    /// Verify the provenance's signature, then extract the reference values from it.
+    fn verify_and_extract(&self, provenance: &[u8]) -> Result<ExtractedValues>;
+}
+
+/// The extractors keyed by provenance type.
+pub struct Extractors {
+    extractors: HashMap<String, Box<dyn Extractor + Send + Sync>>,
+}
+
+impl std::fmt::Debug for Extractors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut types: Vec<&String> = self.extractors.keys().collect();
+        types.sort();
+        f.debug_struct("Extractors").field("types", &types).finish()
+    }
+}
+
+impl Extractors {
+    pub fn new(trusted_keys: Vec<VerifyingKey>) -> Self {
+        let trusted_keys = Arc::new(trusted_keys);
+        let mut extractors: HashMap<String, Box<dyn Extractor + Send + Sync>> = HashMap::new();
+        extractors.insert(
+            in_toto::LINK_PROVENANCE_TYPE.to_string(),
+            Box::new(in_toto::Link::new(trusted_keys.clone())),
+        );
+        extractors.insert(
+            in_toto::STATEMENT_PROVENANCE_TYPE.to_string(),
+            Box::new(in_toto::Statement::new(trusted_keys)),
+        );
+        Self { extractors }
+    }
+
+    /// Accept the unsigned `sample` provenances.
+    pub fn allow_unsigned_sample(&mut self) {
+        self.extractors.insert(
+            sample::PROVENANCE_TYPE.to_string(),
+            Box::new(sample::Sample::default()),
+        );
+    }
+
+    pub fn get(&self, provenance_type: &str) -> Result<&(dyn Extractor + Send + Sync)> {
+        self.extractors
+            .get(provenance_type)
+            .map(|extractor| extractor.as_ref())
+            .ok_or_else(|| anyhow!("Provenance type {} is not supported!", provenance_type))
+    }
+}
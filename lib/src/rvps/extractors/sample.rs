@@ -0,0 +1,15 @@
+use super::{ExtractedValues, Extractor};
+use anyhow::{Context, Result};
+
+pub const PROVENANCE_TYPE: &str = "sample";
+
+/// A dummy provenance used to test/demo RVPS: the unsigned reference values in JSON, e.g.
+/// `{ "svn": [1, 2] }`.
+#[derive(Debug, Default)]
+pub struct Sample {}
+
+impl Extractor for Sample {
+    fn verify_and_extract(&self, provenance: &[u8]) -> Result<ExtractedValues> {
+        serde_json::from_slice(provenance).context("Deserialize sample provenance failed.")
+    }
+}
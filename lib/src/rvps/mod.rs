@@ -0,0 +1,255 @@
+//! Reference Value Provider Service (RVPS).
+//!
+//! RVPS ingests the provenance messages of the software running in the TEEs, verifies and
+//! extracts their reference values, then stores them with expiry in a local store. The
+//! reference values of a TEE are assembled as the `data.reference` document of the policy.
+
+use anyhow::{anyhow, Context, Result};
+use extractors::Extractors;
+use p256::ecdsa::VerifyingKey;
+use p256::pkcs8::DecodePublicKey;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub mod extractors;
+pub mod store;
+
+pub use store::{LocalJsonStore, MemoryStore, Store};
+
+/// The version of the provenance message.
+pub const MESSAGE_VERSION: &str = "0.1.0";
+
+/// The provenance message registered to RVPS.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Message {
+    pub version: String,
+    /// The provenance type, i.e. the name of its extractor.
+    #[serde(rename = "type")]
+    pub provenance_type: String,
+    /// The TEE whose `data.reference` the reference values belong to.
+    pub tee: String,
+    /// Base64 encoded provenance.
+    pub payload: String,
+    /// Expiration time of the reference values (seconds since UNIX epoch), never expire if absent.
+    pub expiration: Option<u64>,
+}
+
+/// The reference value of a TEE claim.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ReferenceValue {
+    pub version: String,
+    pub tee: String,
+    /// The claim's name, i.e. the key of `data.reference`.
+    pub name: String,
+    /// The accepted values of the claim.
+    pub values: Vec<Value>,
+    pub expiration: Option<u64>,
+}
+
+impl ReferenceValue {
+    /// The key in the store.
+    pub fn key(&self) -> String {
+        format!("{}/{}", self.tee.to_ascii_lowercase(), self.name)
+    }
+
+    pub fn expired(&self, now: u64) -> bool {
+        self.expiration.is_some_and(|expiration| expiration <= now)
+    }
+}
+
+fn now() -> Result<u64> {
+    Ok(SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs())
+}
+
+pub struct Rvps {
+    store: Box<dyn Store>,
+    extractors: Extractors,
+}
+
+impl std::fmt::Debug for Rvps {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Rvps")
+            .field("extractors", &self.extractors)
+            .finish()
+    }
+}
+
+impl Rvps {
+    /// Create a RVPS, the signed provenances are verified by the `trusted_keys`.
+    pub fn new(store: Box<dyn Store>, trusted_keys: Vec<VerifyingKey>) -> Self {
+        Self {
+            store,
+            extractors: Extractors::new(trusted_keys),
+        }
+    }
+
+    /// Accept the unsigned `sample` provenances to test/demo RVPS. Anyone reaching the
+    /// registration endpoint can forge them, so they're rejected by default.
+    pub fn allow_unsigned_sample(mut self) -> Self {
+        self.extractors.allow_unsigned_sample();
+        self
+    }
+
+    /// Load the trusted keys from PEM encoded P-256 public key files.
+    pub fn load_trusted_keys(paths: &[&Path]) -> Result<Vec<VerifyingKey>> {
+        paths
+            .iter()
+            .map(|path| {
+                let pem = std::fs::read_to_string(path)
+                    .with_context(|| format!("Read RVPS trusted key {:?} failed.", path))?;
+                VerifyingKey::from_public_key_pem(&pem)
+                    .with_context(|| format!("Parse RVPS trusted key {:?} failed.", path))
+            })
+            .collect()
+    }
+
+    /// Verify the provenance message and store its reference values, which replace the stored
+    /// ones of the same TEE claims. Returns the names of the registered claims.
+    pub async fn register(&self, message: &str) -> Result<Vec<String>> {
+        let message =
+            serde_json::from_str::<Message>(message).context("Deserialize Message failed.")?;
+        if message.version != MESSAGE_VERSION {
+            return Err(anyhow!(
+                "Unsupported message version {}, {} is expected",
+                message.version,
+                MESSAGE_VERSION
+            ));
+        }
+        let now = now()?;
+        if message
+            .expiration
+            .is_some_and(|expiration| expiration <= now)
+        {
+            return Err(anyhow!("The message is expired"));
+        }
+
+        let provenance =
+            base64::decode(&message.payload).context("Base64 decode payload failed.")?;
+        let values = self
+            .extractors
+            .get(&message.provenance_type)?
+            .verify_and_extract(&provenance)
+            .context("Extract reference values failed.")?;
+
+        let mut names = Vec::new();
+        for (name, values) in values {
+            let reference_value = ReferenceValue {
+                version: message.version.clone(),
+                tee: message.tee.to_ascii_lowercase(),
+                name: name.clone(),
+                values,
+                expiration: message.expiration,
+            };
+            debug!("Register reference value: {:?}", &reference_value);
+            self.store
+                .set(reference_value.key(), reference_value)
+                .await?;
+            names.push(name);
+        }
+        Ok(names)
+    }
+
+    /// The unexpired reference values of TEE `tee`.
+    pub async fn reference_values(&self, tee: &str) -> Result<Vec<ReferenceValue>> {
+        let now = now()?;
+        let mut values: Vec<ReferenceValue> = self
+            .store
+            .values()
+            .await?
+            .into_iter()
+            .filter(|value| value.tee.eq_ignore_ascii_case(tee) && !value.expired(now))
+            .collect();
+        values.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(values)
+    }
+
+    /// Assemble the `data.reference` document of TEE `tee`.
+    pub async fn reference(&self, tee: &str) -> Result<Map<String, Value>> {
+        let reference = self
+            .reference_values(tee)
+            .await?
+            .into_iter()
+            .map(|value| (value.name, Value::Array(value.values)))
+            .collect();
+        Ok(reference)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn sample_message(tee: &str, expiration: Option<u64>) -> String {
+        let provenance = json!({ "svn": [1, 2], "cpusvn": [1] }).to_string();
+        serde_json::to_string(&Message {
+            version: MESSAGE_VERSION.to_string(),
+            provenance_type: "sample".to_string(),
+            tee: tee.to_string(),
+            payload: base64::encode(provenance),
+            expiration,
+        })
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_register() {
+        let rvps = Rvps::new(Box::new(MemoryStore::default()), Vec::new()).allow_unsigned_sample();
+        let names = rvps.register(&sample_message("SAMPLE", None)).await;
+        assert!(names.is_ok(), "Register should success: {:?}", names);
+
+        let reference = rvps.reference("sample").await.unwrap();
+        assert_eq!(
+            Value::Object(reference),
+            json!({ "svn": [1, 2], "cpusvn": [1] })
+        );
+        assert!(rvps.reference("tdx").await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_register_unsigned() {
+        // The unsigned provenances are rejected unless they're allowed explicitly.
+        let rvps = Rvps::new(Box::new(MemoryStore::default()), Vec::new());
+        let res = rvps.register(&sample_message("sample", None)).await;
+        assert!(res.is_err(), "Unsigned provenance should fail");
+        assert!(rvps.reference_values("sample").await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_register_expired() {
+        let rvps = Rvps::new(Box::new(MemoryStore::default()), Vec::new()).allow_unsigned_sample();
+        let res = rvps.register(&sample_message("sample", Some(1))).await;
+        assert!(res.is_err(), "Expired message should fail");
+
+        // Reference values expire after being registered.
+        let expiration = now().unwrap() + 1;
+        rvps.register(&sample_message("sample", Some(expiration)))
+            .await
+            .unwrap();
+        assert_eq!(rvps.reference_values("sample").await.unwrap().len(), 2);
+        tokio::time::sleep(std::time::Duration::from_millis(2100)).await;
+        assert!(rvps.reference_values("sample").await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_register_illegal() {
+        let rvps = Rvps::new(Box::new(MemoryStore::default()), Vec::new()).allow_unsigned_sample();
+        assert!(rvps.register("{}").await.is_err());
+
+        let mut message: Message = serde_json::from_str(&sample_message("sample", None)).unwrap();
+        message.provenance_type = "unknown".to_string();
+        let res = rvps
+            .register(&serde_json::to_string(&message).unwrap())
+            .await;
+        assert!(res.is_err(), "Unknown provenance type should fail");
+
+        message.provenance_type = "sample".to_string();
+        message.version = "0.0.1".to_string();
+        let res = rvps
+            .register(&serde_json::to_string(&message).unwrap())
+            .await;
+        assert!(res.is_err(), "Unsupported version should fail");
+    }
+}
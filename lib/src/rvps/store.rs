@@ -0,0 +1,153 @@
+use super::ReferenceValue;
+use crate::Error;
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use tokio::sync::{Mutex, RwLock};
+
+/// The store of the reference values, keyed by `ReferenceValue::key`.
+#[async_trait]
+pub trait Store: Send + Sync {
+    /// Store the reference value, returns the replaced one.
+    async fn set(&self, key: String, value: ReferenceValue) -> Result<Option<ReferenceValue>>;
+
+    async fn get(&self, key: &str) -> Result<Option<ReferenceValue>>;
+
+    async fn values(&self) -> Result<Vec<ReferenceValue>>;
+}
+
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    values: RwLock<HashMap<String, ReferenceValue>>,
+}
+
+#[async_trait]
+impl Store for MemoryStore {
+    async fn set(&self, key: String, value: ReferenceValue) -> Result<Option<ReferenceValue>> {
+        Ok(self.values.write().await.insert(key, value))
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<ReferenceValue>> {
+        Ok(self.values.read().await.get(key).cloned())
+    }
+
+    async fn values(&self) -> Result<Vec<ReferenceValue>> {
+        Ok(self.values.read().await.values().cloned().collect())
+    }
+}
+
+/// Persist the reference values in a local JSON file.
+#[derive(Debug)]
+pub struct LocalJsonStore {
+    file: PathBuf,
+    // Serialize the read-modify-write of the file.
+    lock: Mutex<()>,
+}
+
+impl LocalJsonStore {
+    pub fn new(file: PathBuf) -> Self {
+        Self {
+            file,
+            lock: Mutex::new(()),
+        }
+    }
+
+    async fn load(&self) -> Result<HashMap<String, ReferenceValue>> {
+        if !self.file.exists() {
+            return Ok(HashMap::new());
+        }
+        let content = tokio::fs::read(&self.file).await.with_context(|| {
+            Error::Storage(format!("Read reference values {:?} failed.", self.file))
+        })?;
+        serde_json::from_slice(&content).with_context(|| {
+            Error::Storage(format!(
+                "Deserialize reference values {:?} failed.",
+                self.file
+            ))
+        })
+    }
+
+    // Write to a temporary file then rename it, the file is never partially written.
+    async fn save(&self, values: &HashMap<String, ReferenceValue>) -> Result<()> {
+        let error = || Error::Storage(format!("Write reference values {:?} failed.", self.file));
+        if let Some(dir) = self.file.parent() {
+            tokio::fs::create_dir_all(dir).await.with_context(error)?;
+        }
+        let tmp = self.file.with_extension("tmp");
+        tokio::fs::write(&tmp, serde_json::to_vec_pretty(values)?)
+            .await
+            .with_context(error)?;
+        tokio::fs::rename(&tmp, &self.file)
+            .await
+            .with_context(error)?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Store for LocalJsonStore {
+    async fn set(&self, key: String, value: ReferenceValue) -> Result<Option<ReferenceValue>> {
+        let _guard = self.lock.lock().await;
+        let mut values = self.load().await?;
+        let old = values.insert(key, value);
+        self.save(&values).await?;
+        Ok(old)
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<ReferenceValue>> {
+        let _guard = self.lock.lock().await;
+        Ok(self.load().await?.remove(key))
+    }
+
+    async fn values(&self) -> Result<Vec<ReferenceValue>> {
+        let _guard = self.lock.lock().await;
+        Ok(self.load().await?.into_values().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn reference_value(name: &str, value: u64) -> ReferenceValue {
+        ReferenceValue {
+            version: super::super::MESSAGE_VERSION.to_string(),
+            tee: "sample".to_string(),
+            name: name.to_string(),
+            values: vec![json!(value)],
+            expiration: None,
+        }
+    }
+
+    async fn check_store(store: &dyn Store) {
+        let svn = reference_value("svn", 1);
+        assert!(store.set(svn.key(), svn.clone()).await.unwrap().is_none());
+        assert_eq!(store.get("sample/svn").await.unwrap(), Some(svn.clone()));
+
+        let new_svn = reference_value("svn", 2);
+        let old = store.set(new_svn.key(), new_svn.clone()).await.unwrap();
+        assert_eq!(old, Some(svn));
+        assert_eq!(store.values().await.unwrap(), vec![new_svn]);
+        assert!(store.get("sample/cpusvn").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_memory_store() {
+        check_store(&MemoryStore::default()).await;
+    }
+
+    #[tokio::test]
+    async fn test_local_json_store() {
+        let dir = std::env::temp_dir().join(format!("rvps-store-{}", std::process::id()));
+        let file = dir.join("reference_values.json");
+        check_store(&LocalJsonStore::new(file.clone())).await;
+
+        // Reload from the file.
+        let store = LocalJsonStore::new(file);
+        let values = store.values().await;
+        std::fs::remove_dir_all(dir).unwrap();
+        assert_eq!(values.unwrap(), vec![reference_value("svn", 2)]);
+    }
+}
@@ -0,0 +1,353 @@
+//! Signed attestation token (JWT/EAT) issued for the attestation results.
+
+use crate::core::{AttestationResults, Evidence};
+use anyhow::{anyhow, Context, Result};
+use p256::pkcs8::DecodePrivateKey;
+use rand_core::OsRng;
+use rsa::pkcs1::DecodeRsaPrivateKey;
+use rsa::signature::{SignatureEncoding, Signer};
+use rsa::traits::PublicKeyParts;
+use rsa::RsaPrivateKey;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The default `iss` claim of the issued tokens.
+pub const DEFAULT_TOKEN_ISSUER: &str = "attestation-service";
+/// The default lifetime of the issued tokens.
+pub const DEFAULT_TOKEN_LIFETIME: Duration = Duration::from_secs(5 * 60);
+
+const RSA_KEY_BITS: usize = 2048;
+
+/// The supported token signing algorithms.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumString, AsRefStr)]
+#[strum(ascii_case_insensitive)]
+pub enum TokenAlgorithm {
+    ES256,
+    RS256,
+}
+
+enum SigningKey {
+    Es256(p256::ecdsa::SigningKey),
+    Rs256(Box<rsa::pkcs1v15::SigningKey<Sha256>>),
+}
+
+/// Sign the attestation results as a JWT, the verification key is published as a JWKS.
+pub struct TokenSigner {
+    key: SigningKey,
+    kid: String,
+    issuer: String,
+    lifetime: Duration,
+}
+
+impl std::fmt::Debug for TokenSigner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TokenSigner")
+            .field("algorithm", &self.algorithm())
+            .field("kid", &self.kid)
+            .field("issuer", &self.issuer)
+            .field("lifetime", &self.lifetime)
+            .finish()
+    }
+}
+
+fn base64_url(data: impl AsRef<[u8]>) -> String {
+    base64::encode_config(data, base64::URL_SAFE_NO_PAD)
+}
+
+impl TokenSigner {
+    fn new(key: SigningKey) -> Self {
+        let mut signer = Self {
+            key,
+            kid: String::default(),
+            issuer: DEFAULT_TOKEN_ISSUER.to_string(),
+            lifetime: DEFAULT_TOKEN_LIFETIME,
+        };
+        signer.kid = signer.thumbprint();
+        signer
+    }
+
+    /// Generate an ephemeral signing key.
+    pub fn generate(algorithm: TokenAlgorithm) -> Result<Self> {
+        let key = match algorithm {
+            TokenAlgorithm::ES256 => SigningKey::Es256(p256::ecdsa::SigningKey::random(&mut OsRng)),
+            TokenAlgorithm::RS256 => {
+                let key = RsaPrivateKey::new(&mut OsRng, RSA_KEY_BITS)
+                    .context("Generate RSA key failed.")?;
+                rsa_key(key)
+            }
+        };
+        Ok(Self::new(key))
+    }
+
+    /// Load the signing key from a PEM encoded PKCS#8, SEC1 (P-256) or PKCS#1 (RSA) private key.
+    pub fn from_pem(pem: &str) -> Result<Self> {
+        let key = if let Ok(key) = p256::ecdsa::SigningKey::from_pkcs8_pem(pem) {
+            SigningKey::Es256(key)
+        } else if let Ok(key) = p256::SecretKey::from_sec1_pem(pem) {
+            SigningKey::Es256(key.into())
+        } else if let Ok(key) = RsaPrivateKey::from_pkcs8_pem(pem) {
+            rsa_key(key)
+        } else if let Ok(key) = RsaPrivateKey::from_pkcs1_pem(pem) {
+            rsa_key(key)
+        } else {
+            return Err(anyhow!(
+                "Unsupported token signing key, ES256 or RS256 is expected"
+            ));
+        };
+        Ok(Self::new(key))
+    }
+
+    pub fn from_pem_file(path: &Path) -> Result<Self> {
+        let pem = std::fs::read_to_string(path)
+            .with_context(|| format!("Read token signing key {:?} failed.", path))?;
+        Self::from_pem(&pem)
+    }
+
+    pub fn with_issuer(mut self, issuer: &str) -> Self {
+        self.issuer = issuer.to_string();
+        self
+    }
+
+    pub fn with_lifetime(mut self, lifetime: Duration) -> Self {
+        self.lifetime = lifetime;
+        self
+    }
+
+    pub fn algorithm(&self) -> TokenAlgorithm {
+        match self.key {
+            SigningKey::Es256(_) => TokenAlgorithm::ES256,
+            SigningKey::Rs256(_) => TokenAlgorithm::RS256,
+        }
+    }
+
+    /// The key ID, i.e. the JWK thumbprint (RFC 7638) of the verification key.
+    pub fn kid(&self) -> &str {
+        &self.kid
+    }
+
+    // The required members of the public JWK, in lexicographic order.
+    fn public_jwk(&self) -> Value {
+        match &self.key {
+            SigningKey::Es256(key) => {
+                let point = key.verifying_key().to_encoded_point(false);
+                json!({
+                    "crv": "P-256",
+                    "kty": "EC",
+                    "x": base64_url(point.x().unwrap()),
+                    "y": base64_url(point.y().unwrap()),
+                })
+            }
+            SigningKey::Rs256(key) => {
+                let key: &RsaPrivateKey = key.as_ref().as_ref();
+                json!({
+                    "e": base64_url(key.e().to_bytes_be()),
+                    "kty": "RSA",
+                    "n": base64_url(key.n().to_bytes_be()),
+                })
+            }
+        }
+    }
+
+    fn thumbprint(&self) -> String {
+        base64_url(Sha256::digest(self.public_jwk().to_string()))
+    }
+
+    /// The JSON Web Key Set containing the verification key.
+    pub fn jwks(&self) -> Value {
+        let mut jwk = self.public_jwk();
+        jwk["kid"] = json!(self.kid);
+        jwk["alg"] = json!(self.algorithm().as_ref());
+        jwk["use"] = json!("sig");
+        json!({ "keys": [jwk] })
+    }
+
+    /// Sign the claims as a compact JWS.
+    pub fn sign(&self, claims: &Value) -> Result<String> {
+        let header = json!({
+            "alg": self.algorithm().as_ref(),
+            "typ": "JWT",
+            "kid": self.kid,
+        });
+        let signing_input = format!(
+            "{}.{}",
+            base64_url(header.to_string()),
+            base64_url(claims.to_string())
+        );
+        let signature = match &self.key {
+            SigningKey::Es256(key) => {
+                let signature: p256::ecdsa::Signature = key.sign(signing_input.as_bytes());
+                signature.to_vec()
+            }
+            SigningKey::Rs256(key) => key.sign(signing_input.as_bytes()).to_vec(),
+        };
+        Ok(format!("{}.{}", signing_input, base64_url(signature)))
+    }
+
+    /// Issue the token of the attestation results:
+    /// - iss, iat, exp: the issuer, issued at and expiration time.
+    /// - eat_nonce: the echo of `Evidence.nonce`.
+    /// - tee-pubkey-hash: the base64url encoded SHA-256 of `Evidence.tee-pubkey`.
+    /// - tee, tcb: the TEE and its TCB claims.
+    /// - allow, violations, verifier_output, policy_engine_output: the policy decision.
+    pub fn issue(&self, evidence: &Evidence, results: &AttestationResults) -> Result<String> {
+        let iat = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        let tcb = match results.tcb.is_empty() {
+            true => Value::Null,
+            false => serde_json::from_str(&results.tcb)?,
+        };
+        let claims = json!({
+            "iss": self.issuer,
+            "iat": iat,
+            "exp": iat + self.lifetime.as_secs(),
+            "eat_nonce": evidence.nonce,
+            "tee-pubkey-hash": base64_url(Sha256::digest(&evidence.tee_pubkey)),
+            "tee": results.tee,
+            "tcb": tcb,
+            "allow": results.allow,
+            "violations": results.violations,
+            "verifier_output": results.verifier_output,
+            "policy_engine_output": results.policy_engine_output,
+        });
+        self.sign(&claims)
+    }
+}
+
+fn rsa_key(key: RsaPrivateKey) -> SigningKey {
+    SigningKey::Rs256(Box::new(rsa::pkcs1v15::SigningKey::<Sha256>::new(key)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use p256::ecdsa::signature::Verifier as _;
+    use rsa::{BigUint, RsaPublicKey};
+
+    fn test_key(name: &str) -> std::path::PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("test_data/token")
+            .join(name)
+    }
+
+    fn base64_url_decode(data: &str) -> Vec<u8> {
+        base64::decode_config(data, base64::URL_SAFE_NO_PAD).unwrap()
+    }
+
+    // Verify the token with the JWKS, returns the claims.
+    fn verify(token: &str, jwks: &Value) -> Result<Value> {
+        let parts: Vec<&str> = token.split('.').collect();
+        assert_eq!(parts.len(), 3, "Compact JWS has 3 parts");
+        let header: Value = serde_json::from_slice(&base64_url_decode(parts[0]))?;
+        let jwk = &jwks["keys"][0];
+        if header["kid"] != jwk["kid"] || header["alg"] != jwk["alg"] {
+            return Err(anyhow!("Unknown key"));
+        }
+
+        let signing_input = format!("{}.{}", parts[0], parts[1]);
+        let signature = base64_url_decode(parts[2]);
+        let param = |name: &str| base64_url_decode(jwk[name].as_str().unwrap());
+        match jwk["kty"].as_str() {
+            Some("EC") => {
+                let mut point = vec![0x04];
+                point.extend(param("x"));
+                point.extend(param("y"));
+                let key = p256::ecdsa::VerifyingKey::from_sec1_bytes(&point)?;
+                let signature = p256::ecdsa::Signature::from_slice(&signature)?;
+                key.verify(signing_input.as_bytes(), &signature)?;
+            }
+            Some("RSA") => {
+                let key = RsaPublicKey::new(
+                    BigUint::from_bytes_be(&param("n")),
+                    BigUint::from_bytes_be(&param("e")),
+                )?;
+                let key = rsa::pkcs1v15::VerifyingKey::<Sha256>::new(key);
+                let signature = rsa::pkcs1v15::Signature::try_from(signature.as_slice())?;
+                key.verify(signing_input.as_bytes(), &signature)?;
+            }
+            _ => return Err(anyhow!("Unknown key type")),
+        }
+        Ok(serde_json::from_slice(&base64_url_decode(parts[1]))?)
+    }
+
+    fn evidence() -> Evidence {
+        Evidence {
+            nonce: "1234567890".to_string(),
+            tee: "sample".to_string(),
+            tee_pubkey: "pubkey".to_string(),
+            tee_evidence: String::default(),
+        }
+    }
+
+    fn results() -> AttestationResults {
+        AttestationResults {
+            tee: "sample".to_string(),
+            allow: true,
+            violations: Vec::new(),
+            verifier_output: String::default(),
+            verifier_error_reason: None,
+            policy_engine_output: json!({ "allow": true }).to_string(),
+            tcb: json!({ "svn": 1 }).to_string(),
+        }
+    }
+
+    #[test]
+    fn test_issue_es256() {
+        let signer = TokenSigner::generate(TokenAlgorithm::ES256)
+            .unwrap()
+            .with_issuer("test-issuer")
+            .with_lifetime(Duration::from_secs(60));
+        let token = signer.issue(&evidence(), &results()).unwrap();
+        let claims = verify(&token, &signer.jwks()).unwrap();
+        assert_eq!(claims["iss"], json!("test-issuer"));
+        assert_eq!(
+            claims["exp"].as_u64().unwrap() - claims["iat"].as_u64().unwrap(),
+            60
+        );
+        assert_eq!(claims["eat_nonce"], json!("1234567890"));
+        assert_eq!(
+            claims["tee-pubkey-hash"],
+            json!(base64_url(Sha256::digest("pubkey")))
+        );
+        assert_eq!(claims["tcb"], json!({ "svn": 1 }));
+        assert_eq!(claims["allow"], json!(true));
+    }
+
+    #[test]
+    fn test_issue_rs256() {
+        let signer = TokenSigner::from_pem_file(&test_key("rs256.pem")).unwrap();
+        assert_eq!(signer.algorithm(), TokenAlgorithm::RS256);
+        let token = signer.issue(&evidence(), &results()).unwrap();
+        let claims = verify(&token, &signer.jwks()).unwrap();
+        assert_eq!(claims["iss"], json!(DEFAULT_TOKEN_ISSUER));
+    }
+
+    #[test]
+    fn test_from_pem() {
+        for name in ["es256.pem", "es256_pkcs8.pem"] {
+            let signer = TokenSigner::from_pem_file(&test_key(name)).unwrap();
+            assert_eq!(signer.algorithm(), TokenAlgorithm::ES256);
+        }
+        // Both encodings hold the same key.
+        let sec1 = TokenSigner::from_pem_file(&test_key("es256.pem")).unwrap();
+        let pkcs8 = TokenSigner::from_pem_file(&test_key("es256_pkcs8.pem")).unwrap();
+        assert_eq!(sec1.kid(), pkcs8.kid());
+
+        assert!(TokenSigner::from_pem("not a key").is_err());
+    }
+
+    #[test]
+    fn test_tampered_token() {
+        let signer = TokenSigner::generate(TokenAlgorithm::ES256).unwrap();
+        let token = signer.issue(&evidence(), &results()).unwrap();
+        let mut parts: Vec<String> = token.split('.').map(|s| s.to_string()).collect();
+        let mut claims: Value = serde_json::from_slice(&base64_url_decode(&parts[1])).unwrap();
+        claims["allow"] = json!(false);
+        parts[1] = base64_url(claims.to_string());
+        assert!(verify(&parts.join("."), &signer.jwks()).is_err());
+
+        // Signed by another key.
+        let other = TokenSigner::generate(TokenAlgorithm::ES256).unwrap();
+        assert!(verify(&token, &other.jwks()).is_err());
+    }
+}
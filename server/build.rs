@@ -1,8 +1,34 @@
+use std::env;
+use std::path::PathBuf;
 use std::process::exit;
 
 fn real_main() -> Result<(), String> {
-    tonic_build::compile_protos("proto/management.proto").map_err(|e| format!("{}", e))?;
-    tonic_build::compile_protos("proto/attestation.proto").map_err(|e| format!("{}", e))?;
+    // The descriptors of the services and their dependencies are served by the reflection.
+    let out_dir = PathBuf::from(env::var("OUT_DIR").map_err(|e| format!("{}", e))?);
+    tonic_build::configure()
+        .file_descriptor_set_path(out_dir.join("descriptor.bin"))
+        .compile(
+            &[
+                "proto/management.proto",
+                "proto/attestation.proto",
+                "proto/reference.proto",
+                "proto/grpc/health/v1/health.proto",
+                "proto/grpc/reflection/v1alpha/reflection.proto",
+            ],
+            &["proto"],
+        )
+        .map_err(|e| format!("{}", e))?;
+    tonic_build::configure()
+        .build_server(false)
+        .build_client(false)
+        .compile(
+            &[
+                "proto/google/rpc/status.proto",
+                "proto/google/rpc/error_details.proto",
+            ],
+            &["proto"],
+        )
+        .map_err(|e| format!("{}", e))?;
 
     Ok(())
 }
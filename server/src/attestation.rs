@@ -1,34 +1,153 @@
-use anyhow::Result;
-use std::path::PathBuf;
+use anyhow::{anyhow, Result};
+use std::net::SocketAddr;
 use std::sync::Arc;
-use tokio::sync::RwLock;
-use tonic::{Request, Response, Status};
+use std::time::Duration;
+use tokio::sync::{mpsc, RwLock};
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::{Stream, StreamExt};
+use tonic::{Code, Request, Response, Status, Streaming};
 extern crate serde;
+use crate::auth;
+use crate::common;
+use crate::error;
+use crate::google::rpc;
+use crate::health::Health;
+use crate::metrics::GrpcMetricsLayer;
+use crate::reflection;
+use crate::tls;
 use crate::user;
-use crate::ATTESTATION_SERVICE;
-use tonic::transport::Server;
+use attestation_service::audit::{self, AuditLog, Event as AuditEvent};
+use attestation_service::nonce::Challenge;
+use attestation_service::{Evidence, TeePolicies, TokenSigner, TypedAttestationResults};
+use prost_types::value::Kind;
+use prost_types::{ListValue, Struct};
+use serde_json::Value;
+use tonic::transport::{NamedService, ServerTlsConfig};
 
 use crate::attestation_api::attestation_service_server::{
     AttestationService, AttestationServiceServer,
 };
-use crate::attestation_api::{AttestationRequest, AttestationResponse};
+use crate::attestation_api::attestation_stream_response::Event;
+use crate::attestation_api::{
+    batch_attestation_item, AttestationRequest, AttestationResponse, AttestationResults,
+    AttestationStreamRequest, AttestationStreamResponse, BatchAttestationItem,
+    BatchAttestationRequest, BatchAttestationResponse, ChallengeRequest, ChallengeResponse,
+    GetJwksRequest, GetJwksResponse, PolicyDecision, TokenFormat, Violation,
+};
 
-const DEFAULT_ATTESTATION_SOCK: &str = "127.0.0.1:3000";
+// The events of an `AttestationStream` buffered before the client reads them.
+const STREAM_BUFFER: usize = 16;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Service {
-    // The default user
-    user: Arc<RwLock<user::User>>,
-    workdir: PathBuf,
+    users: Arc<user::Users>,
+    attestation_service: Arc<attestation_service::Service>,
+    // Sign the JWT attestation results.
+    signer: Arc<TokenSigner>,
+    // The evidences of a batch attested concurrently.
+    batch_parallelism: usize,
+    // The interval of pushing the nonces and re-evaluating the evidences of the streams.
+    stream_interval: Duration,
+    // Record the decisions.
+    audit: Option<Arc<AuditLog>>,
 }
 
 impl Service {
-    pub fn new(usr: Arc<RwLock<user::User>>, dir: PathBuf) -> Self {
+    pub fn new(
+        users: Arc<user::Users>,
+        attestation_service: Arc<attestation_service::Service>,
+        signer: Arc<TokenSigner>,
+        batch_parallelism: usize,
+        stream_interval: Duration,
+        audit: Option<Arc<AuditLog>>,
+    ) -> Self {
         Self {
-            user: usr,
-            workdir: dir,
+            users,
+            attestation_service,
+            signer,
+            batch_parallelism,
+            stream_interval,
+            audit,
         }
     }
+
+    // Load the request's user's policies and reference data.
+    async fn policies(&self, user: Option<&common::User>) -> Result<TeePolicies, Status> {
+        self.user(user)
+            .await?
+            .read()
+            .await
+            .policies()
+            .await
+            .map_err(|e| error::status("Get policy", e, Code::Aborted))
+    }
+
+    // Issue the attestation results in the requested format, along with the typed ones.
+    fn response(
+        &self,
+        format: TokenFormat,
+        evidence: &Evidence,
+        results: &attestation_service::AttestationResults,
+    ) -> Result<AttestationResponse> {
+        let attestation_results = match format {
+            TokenFormat::Json => serde_json::to_string(results)?,
+            TokenFormat::Jwt => self.signer.issue(evidence, results)?,
+        };
+        let typed_results = TypedAttestationResults::try_from(results)?;
+
+        debug!("Attestation Results: {}", &attestation_results);
+
+        #[allow(deprecated)]
+        Ok(AttestationResponse {
+            attestation_results: attestation_results.into_bytes(),
+            results: Some(proto_results(typed_results)),
+        })
+    }
+
+    // Record the decision in the audit log, along with the hashes of the claims and the policy
+    // which made it.
+    async fn audit(
+        &self,
+        user: Option<&common::User>,
+        policies: &TeePolicies,
+        evidence: &Evidence,
+        results: &attestation_service::AttestationResults,
+    ) -> Result<()> {
+        let audit = match &self.audit {
+            Some(audit) => audit,
+            None => return Ok(()),
+        };
+        let policy = match policies.select(&evidence.tee).policy {
+            Some(policy) => policy,
+            None => {
+                self.attestation_service
+                    .tee_default_policy(&evidence.tee)
+                    .await?
+            }
+        };
+        let claims_sha256 = match results.tcb.is_empty() {
+            true => String::new(),
+            false => audit::sha256(results.tcb.as_bytes()),
+        };
+        let event = AuditEvent::Attestation {
+            tenant: auth::tenant(user).to_string(),
+            tee: evidence.tee.clone(),
+            nonce: evidence.nonce.clone(),
+            claims_sha256,
+            policy_sha256: audit::sha256(policy.as_bytes()),
+            allow: results.allow,
+        };
+        audit.append(event).await?;
+        Ok(())
+    }
+
+    // Resolve the request's user.
+    async fn user(&self, user: Option<&common::User>) -> Result<Arc<RwLock<user::User>>, Status> {
+        self.users
+            .get(user)
+            .await
+            .map_err(|e| error::lookup_status("User", e, Code::InvalidArgument))
+    }
 }
 
 #[tonic::async_trait]
@@ -38,57 +157,369 @@ impl AttestationService for Service {
         request: Request<AttestationRequest>,
     ) -> Result<Response<AttestationResponse>, Status> {
         let request: AttestationRequest = request.into_inner();
+        let format = TokenFormat::from_i32(request.format)
+            .ok_or_else(|| Status::invalid_argument("Unsupported token format"))?;
         let evidence = std::str::from_utf8(&request.evidence)
             .map_err(|e| Status::invalid_argument(format!("Parse evidence: {}", e)))?
             .to_string();
         debug!("Evidence: {}", evidence);
 
-        let user: &Arc<RwLock<user::User>> = request.user.map_or_else(
-            || Ok(&self.user),
-            |_user| Err(Status::invalid_argument("Multiple user is not supported")),
-        )?;
+        let policies = self.policies(request.user.as_ref()).await?;
 
-        let policy = user
-            .read()
+        let (evidence, results) = self
+            .attestation_service
+            .attestation_results_with_policies(&evidence, &policies)
             .await
-            .policy(self.workdir.as_path())
+            .map_err(|e| error::status("Attestation", e, Code::Aborted))?;
+        self.audit(request.user.as_ref(), &policies, &evidence, &results)
             .await
-            .map_err(|e| Status::invalid_argument(format!("Get policy: {}", e)))?;
-        let reference_data = user
-            .read()
-            .await
-            .reference_data(self.workdir.as_path())
+            .map_err(|e| error::status("Audit", e, Code::Internal))?;
+        let res = self
+            .response(format, &evidence, &results)
+            .map_err(|e| error::status("Attestation", e, Code::Aborted))?;
+
+        Ok(Response::new(res))
+    }
+
+    async fn batch_attestation(
+        &self,
+        request: Request<BatchAttestationRequest>,
+    ) -> Result<Response<BatchAttestationResponse>, Status> {
+        let request: BatchAttestationRequest = request.into_inner();
+        let format = TokenFormat::from_i32(request.format)
+            .ok_or_else(|| Status::invalid_argument("Unsupported token format"))?;
+        // The evidence which isn't UTF-8 fails to be deserialized as a malformed one.
+        let evidences: Vec<String> = request
+            .evidences
+            .iter()
+            .map(|evidence| String::from_utf8_lossy(evidence).into_owned())
+            .collect();
+        debug!("Batch of {} evidences", evidences.len());
+
+        // Load the policies once for all the evidences.
+        let policies = self.policies(request.user.as_ref()).await?;
+
+        let attested = self
+            .attestation_service
+            .attestation_batch(&evidences, &policies, self.batch_parallelism)
+            .await;
+        let mut items = Vec::with_capacity(attested.len());
+        for res in attested {
+            let response = match res {
+                Ok((evidence, results)) => self
+                    .audit(request.user.as_ref(), &policies, &evidence, &results)
+                    .await
+                    .and_then(|_| self.response(format, &evidence, &results)),
+                Err(e) => Err(e),
+            };
+            let result = response
+                .map(batch_attestation_item::Result::Response)
+                .unwrap_or_else(|e| {
+                    batch_attestation_item::Result::Error(error::rpc_status(
+                        "Attestation",
+                        &e,
+                        Code::Aborted,
+                    ))
+                });
+            items.push(BatchAttestationItem {
+                result: Some(result),
+            });
+        }
+
+        Ok(Response::new(BatchAttestationResponse { items }))
+    }
+
+    type AttestationStreamStream = ReceiverStream<Result<AttestationStreamResponse, Status>>;
+
+    async fn attestation_stream(
+        &self,
+        request: Request<Streaming<AttestationStreamRequest>>,
+    ) -> Result<Response<Self::AttestationStreamStream>, Status> {
+        // The stream is pointless without the nonces.
+        let challenge = self
+            .attestation_service
+            .challenge()
             .await
-            .map_err(|e| Status::invalid_argument(format!("Get reference data: {}", e)))?;
+            .map_err(|e| error::status("Challenge", e, Code::FailedPrecondition))?;
 
-        let attestation_service = Arc::clone(&ATTESTATION_SERVICE);
+        let (tx, rx) = mpsc::channel(STREAM_BUFFER);
+        let service = self.clone();
+        tokio::spawn(service.stream_session(request.into_inner(), challenge, tx));
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
 
-        let attestation_results = attestation_service
-            .attestation(&evidence, policy, reference_data)
+    async fn challenge(
+        &self,
+        _request: Request<ChallengeRequest>,
+    ) -> Result<Response<ChallengeResponse>, Status> {
+        let challenge = self
+            .attestation_service
+            .challenge()
             .await
-            .map_err(|e| Status::aborted(format!("Attestation: {}", e)))?;
+            .map_err(|e| error::status("Challenge", e, Code::FailedPrecondition))?;
+        Ok(Response::new(ChallengeResponse {
+            nonce: challenge.nonce,
+            expiration: challenge.expiration,
+        }))
+    }
 
-        debug!("Attestation Results: {}", &attestation_results);
+    async fn get_jwks(
+        &self,
+        _request: Request<GetJwksRequest>,
+    ) -> Result<Response<GetJwksResponse>, Status> {
+        let res = GetJwksResponse {
+            content: self.signer.jwks().to_string().into_bytes(),
+        };
+        Ok(Response::new(res))
+    }
+}
 
-        let res = AttestationResponse {
-            attestation_results: attestation_results.into_bytes(),
+// The last evidence attested in an `AttestationStream`, whose claims are re-evaluated
+// periodically.
+struct StreamSession {
+    user: Option<common::User>,
+    format: TokenFormat,
+    evidence: Evidence,
+    results: attestation_service::AttestationResults,
+}
+
+impl Service {
+    // Serve an `AttestationStream`: push the nonces and re-evaluate the last evidence every
+    // `stream_interval`, and attest the evidences as they are received.
+    async fn stream_session<S>(
+        self,
+        mut requests: S,
+        challenge: Challenge,
+        tx: mpsc::Sender<Result<AttestationStreamResponse, Status>>,
+    ) where
+        S: Stream<Item = Result<AttestationStreamRequest, Status>> + Unpin,
+    {
+        let mut interval = tokio::time::interval_at(
+            tokio::time::Instant::now() + self.stream_interval,
+            self.stream_interval,
+        );
+        let mut session = None;
+        let mut events = vec![stream_challenge(challenge)];
+        loop {
+            for event in events.drain(..) {
+                let response = AttestationStreamResponse { event: Some(event) };
+                if tx.send(Ok(response)).await.is_err() {
+                    debug!("Attestation stream closed by the client");
+                    return;
+                }
+            }
+            tokio::select! {
+                _ = interval.tick() => {
+                    events.extend(self.stream_reevaluate(&mut session).await);
+                    events.push(match self.attestation_service.challenge().await {
+                        Ok(challenge) => stream_challenge(challenge),
+                        Err(e) => {
+                            let status = error::rpc_status("Challenge", &e, Code::FailedPrecondition);
+                            Event::Error(status)
+                        }
+                    });
+                }
+                request = requests.next() => match request {
+                    Some(Ok(request)) => {
+                        events.push(self.stream_attestation(request, &mut session).await)
+                    }
+                    Some(Err(status)) => {
+                        debug!("Attestation stream: {}", status);
+                        return;
+                    }
+                    None => return,
+                },
+            }
+        }
+    }
+
+    // Attest the received evidence, it replaces the last one of the session if it's attested.
+    async fn stream_attestation(
+        &self,
+        request: AttestationStreamRequest,
+        session: &mut Option<StreamSession>,
+    ) -> Event {
+        let format = match TokenFormat::from_i32(request.format) {
+            Some(format) => format,
+            None => {
+                let status = Status::invalid_argument("Unsupported token format");
+                return Event::Error(rpc::Status {
+                    code: status.code() as i32,
+                    message: status.message().to_string(),
+                    details: Vec::new(),
+                });
+            }
+        };
+        let evidence = String::from_utf8_lossy(&request.evidence).into_owned();
+        let attested = async {
+            let policies = self
+                .policies(request.user.as_ref())
+                .await
+                .map_err(|status| anyhow!(status.message().to_string()))?;
+            let (evidence, results) = self
+                .attestation_service
+                .attestation_results_with_policies(&evidence, &policies)
+                .await?;
+            self.audit(request.user.as_ref(), &policies, &evidence, &results)
+                .await?;
+            let response = self.response(format, &evidence, &results)?;
+            Ok::<_, anyhow::Error>((evidence, results, response))
         };
+        match attested.await {
+            Ok((evidence, results, response)) => {
+                *session = Some(StreamSession {
+                    user: request.user,
+                    format,
+                    evidence,
+                    results,
+                });
+                Event::Results(response)
+            }
+            Err(e) => Event::Error(error::rpc_status("Attestation", &e, Code::Aborted)),
+        }
+    }
 
-        Ok(Response::new(res))
+    // Re-evaluate the claims of the session's evidence with the current policies, the updated
+    // results are pushed if the decision changes, as a revocation if the evidence stops passing.
+    async fn stream_reevaluate(&self, session: &mut Option<StreamSession>) -> Option<Event> {
+        // The evidence which failed to be verified has no claims.
+        let session = session
+            .as_mut()
+            .filter(|session| !session.results.tcb.is_empty())?;
+        let reevaluated = async {
+            let policies = self
+                .policies(session.user.as_ref())
+                .await
+                .map_err(|status| anyhow!(status.message().to_string()))?;
+            let claims: Value = serde_json::from_str(&session.results.tcb)?;
+            let results = self
+                .attestation_service
+                .evaluate_claims(&session.evidence.tee, &claims, &policies)
+                .await?;
+            Ok::<_, anyhow::Error>((policies, results))
+        };
+        let (policies, results) = match reevaluated.await {
+            Ok(reevaluated) => reevaluated,
+            Err(e) => {
+                return Some(Event::Error(error::rpc_status(
+                    "Re-evaluation",
+                    &e,
+                    Code::Aborted,
+                )))
+            }
+        };
+        if results.allow == session.results.allow
+            && results.violations == session.results.violations
+        {
+            return None;
+        }
+
+        let revoked = session.results.allow && !results.allow;
+        session.results = results;
+        // Only the changed decisions are pushed, hence audited.
+        let audited = self
+            .audit(
+                session.user.as_ref(),
+                &policies,
+                &session.evidence,
+                &session.results,
+            )
+            .await;
+        if let Err(e) = audited {
+            return Some(Event::Error(error::rpc_status("Audit", &e, Code::Internal)));
+        }
+        let response = match self.response(session.format, &session.evidence, &session.results) {
+            Ok(response) => response,
+            Err(e) => {
+                return Some(Event::Error(error::rpc_status(
+                    "Re-evaluation",
+                    &e,
+                    Code::Aborted,
+                )))
+            }
+        };
+        match revoked {
+            true => {
+                info!(
+                    "Attestation stream: the {} TCB is revoked",
+                    session.evidence.tee
+                );
+                Some(Event::Revocation(response))
+            }
+            false => Some(Event::Results(response)),
+        }
     }
 }
 
+fn stream_challenge(challenge: Challenge) -> Event {
+    Event::Challenge(ChallengeResponse {
+        nonce: challenge.nonce,
+        expiration: challenge.expiration,
+    })
+}
+
+// Convert the typed attestation results to their protobuf message.
+fn proto_results(results: TypedAttestationResults) -> AttestationResults {
+    AttestationResults {
+        version: results.version,
+        tee: results.tee,
+        allow: results.allow,
+        claims: results
+            .claims
+            .into_iter()
+            .map(|(claim, value)| (claim, proto_value(value)))
+            .collect(),
+        decision: results.decision.map(|decision| PolicyDecision {
+            allow: decision.allow,
+            violations: decision
+                .violations
+                .into_iter()
+                .map(|violation| Violation {
+                    claim: violation.claim,
+                    observed: Some(proto_value(violation.observed)),
+                    expected: Some(proto_value(violation.expected)),
+                })
+                .collect(),
+        }),
+        verifier_error: results.verifier_error.unwrap_or_default(),
+        verifier_error_reason: results.verifier_error_reason.unwrap_or_default(),
+    }
+}
+
+fn proto_value(value: Value) -> prost_types::Value {
+    let kind = match value {
+        Value::Null => Kind::NullValue(0),
+        Value::Bool(value) => Kind::BoolValue(value),
+        Value::Number(value) => Kind::NumberValue(value.as_f64().unwrap_or_default()),
+        Value::String(value) => Kind::StringValue(value),
+        Value::Array(values) => Kind::ListValue(ListValue {
+            values: values.into_iter().map(proto_value).collect(),
+        }),
+        Value::Object(fields) => Kind::StructValue(Struct {
+            fields: fields
+                .into_iter()
+                .map(|(key, value)| (key, proto_value(value)))
+                .collect(),
+        }),
+    };
+    prost_types::Value { kind: Some(kind) }
+}
+
 pub async fn start_service(
-    socket: Option<&str>,
-    usr: Arc<RwLock<user::User>>,
-    dir: PathBuf,
+    socket: SocketAddr,
+    service: Service,
+    tls: Option<ServerTlsConfig>,
+    metrics: GrpcMetricsLayer,
+    health: Health,
 ) -> Result<()> {
-    let socket = socket.unwrap_or(DEFAULT_ATTESTATION_SOCK).parse()?;
     debug!("Attestation listen socket: {}", &socket);
-    let service = Service::new(usr, dir);
-    Server::builder()
+    let services = [<AttestationServiceServer<Service> as NamedService>::NAME];
+    tls::server(tls)?
+        .layer(metrics)
+        .add_service(health.service(&services))
+        .add_service(reflection::server(&services)?)
         .add_service(AttestationServiceServer::new(service))
-        .serve(socket)
+        .serve_with_shutdown(socket, health.stopped())
         .await?;
     Ok(())
 }
@@ -99,9 +530,11 @@ mod tests {
     use crate::attestation_api::attestation_service_server::AttestationService;
     use crate::attestation_api::{AttestationRequest, AttestationResponse};
     use crate::common;
+    use crate::store;
+    use attestation_service::nonce::{MemoryStore, Nonces, DEFAULT_NONCE_TTL};
+    use attestation_service::{TokenAlgorithm, DEFAULT_BATCH_PARALLELISM};
     use serde_json::{json, Value};
     use sha2::{Digest, Sha384};
-    use std::path::Path;
     use std::sync::Arc;
     use tonic::Request;
     use uuid::Uuid;
@@ -111,13 +544,19 @@ mod tests {
 
     fn reference(ver: u64) -> String {
         json!({
-            "cpusvn": ver,
-            "svn": ver
+            "reference": {
+                "cpusvn": ver,
+                "svn": ver
+            }
         })
         .to_string()
     }
 
     fn evidence() -> String {
+        evidence_with_nonce(NONCE)
+    }
+
+    fn evidence_with_nonce(nonce: &str) -> String {
         let pubkey = json!({
             "algorithm": "".to_string(),
             "pubkey-length": "".to_string(),
@@ -125,7 +564,7 @@ mod tests {
         })
         .to_string();
         let mut hasher = Sha384::new();
-        hasher.update(NONCE);
+        hasher.update(nonce);
         hasher.update(&pubkey);
         let hash = hasher.finalize();
         let tee_evidence = json!({
@@ -136,7 +575,7 @@ mod tests {
         })
         .to_string();
         json!({
-            "nonce": NONCE.to_owned(),
+            "nonce": nonce,
             "tee": "sample".to_string(),
             "tee-pubkey": pubkey,
             "tee-evidence": tee_evidence
@@ -144,111 +583,389 @@ mod tests {
         .to_string()
     }
 
-    async fn attestation(service: &Service) -> Value {
+    async fn attestation(service: &Service, user: Option<common::User>) -> Value {
         let attestaion_request = AttestationRequest {
             evidence: evidence().into_bytes(),
-            user: None,
+            user,
+            format: TokenFormat::Json as i32,
         };
         let request = Request::new(attestaion_request);
         let response = service.attestation(request).await;
         assert!(response.is_ok(), "attestation should success");
         let attestation_response: AttestationResponse = response.unwrap().into_inner();
+        #[allow(deprecated)]
         let attestation_results = std::str::from_utf8(&attestation_response.attestation_results);
         assert!(
             attestation_results.is_ok(),
             "attestation results should success"
         );
-        serde_json::from_str(&attestation_results.unwrap()).unwrap()
+        serde_json::from_str(attestation_results.unwrap()).unwrap()
     }
 
-    fn create_service(id: Option<&str>) -> (PathBuf, Service) {
-        let workdir = Path::new("./").to_owned();
-        let user = id.map_or_else(
-            || Arc::new(RwLock::new(user::User::default())),
-            |id| Arc::new(RwLock::new(user::User::from_str(id).unwrap())),
+    // Create the service along with the user `id`.
+    async fn create_service(id: Option<&str>) -> (Service, Option<common::User>) {
+        let users = Arc::new(user::Users::new(
+            Arc::new(store::MemoryStore::default()),
+            false,
+        ));
+        let user = match id {
+            Some(id) => {
+                users.create(id).await.unwrap();
+                Some(common::User { id: id.to_owned() })
+            }
+            None => None,
+        };
+        let signer = Arc::new(TokenSigner::generate(TokenAlgorithm::ES256).unwrap());
+        let service = Service::new(
+            users,
+            Arc::new(attestation_service::Service::new()),
+            signer,
+            DEFAULT_BATCH_PARALLELISM,
+            Duration::from_secs(60),
+            None,
         );
-        let service = Service::new(user.clone(), workdir.clone());
-        (workdir, service)
+        (service, user)
+    }
+
+    async fn set_reference_data(service: &Service, user: Option<&common::User>, ver: u64) {
+        let res = service
+            .user(user)
+            .await
+            .unwrap()
+            .write()
+            .await
+            .update(
+                None,
+                user::Kind::ReferenceData,
+                "test",
+                Some(reference(ver)),
+            )
+            .await;
+        assert!(res.is_ok(), "Set reference should success");
     }
 
     #[tokio::test]
     async fn test_attestation_default_xxx_allow() {
-        let (workdir, service) = create_service(None);
+        let (service, _) = create_service(None).await;
 
         // Default allow
-        let res = attestation(&service).await;
+        let res = attestation(&service, None).await;
         assert_eq!(res["allow"], true);
 
         // Default not allow
-        let res = service
-            .user
-            .write()
-            .await
-            .set_reference_data(service.workdir.as_path(), reference(5))
-            .await;
-        assert!(res.is_ok(), "Set reference should success");
-        let res = attestation(&service).await;
+        set_reference_data(&service, None, 5).await;
+        let res = attestation(&service, None).await;
         assert_eq!(res["allow"], false);
-
-        let dir = workdir.join("users").join("default");
-        if dir.exists() {
-            // delete the temporary user folder
-            std::fs::remove_dir_all(dir).unwrap();
-        }
     }
 
     #[tokio::test]
     async fn test_attestation_allow() {
         let uuid = Uuid::new_v4().to_string();
-        let (workdir, service) = create_service(Some(&uuid));
-        let res = attestation(&service).await;
+        let (service, user) = create_service(Some(&uuid)).await;
+        let res = attestation(&service, user).await;
         assert_eq!(res["allow"], true);
+    }
 
-        let dir = workdir.join("users").join(uuid);
-        if dir.exists() {
-            // delete the temporary user folder
-            std::fs::remove_dir_all(dir).unwrap();
+    #[tokio::test]
+    async fn test_attestation_not_allow() {
+        let uuid = Uuid::new_v4().to_string();
+        let (service, user) = create_service(Some(&uuid)).await;
+        set_reference_data(&service, user.as_ref(), 5).await;
+        let res = attestation(&service, user).await;
+        assert_eq!(res["allow"], false);
+
+        // The other users aren't affected.
+        let other = Uuid::new_v4().to_string();
+        service.users.create(&other).await.unwrap();
+        let res = attestation(&service, Some(common::User { id: other })).await;
+        assert_eq!(res["allow"], true);
+    }
+
+    #[tokio::test]
+    async fn test_attestation_audit() {
+        let uuid = Uuid::new_v4().to_string();
+        let (service, user) = create_service(Some(&uuid)).await;
+        let file = std::env::temp_dir().join(format!("audit-{}.log", uuid));
+        let service = Service {
+            audit: Some(Arc::new(AuditLog::open(file.clone()).await.unwrap())),
+            ..service
+        };
+        let res = attestation(&service, user.clone()).await;
+        assert_eq!(res["allow"], true);
+        set_reference_data(&service, user.as_ref(), 5).await;
+        let res = attestation(&service, user).await;
+        assert_eq!(res["allow"], false);
+
+        let content = std::fs::read_to_string(&file).unwrap();
+        let decisions: Vec<_> = content
+            .lines()
+            .map(|line| serde_json::from_str::<audit::Record>(line).unwrap().event)
+            .collect();
+        let policy = std::include_str!("../../lib/src/core/policy_engine/default_policy.rego");
+        for (event, allow) in decisions.into_iter().zip([true, false]) {
+            assert_eq!(
+                event,
+                AuditEvent::Attestation {
+                    tenant: uuid.clone(),
+                    tee: "sample".to_string(),
+                    nonce: NONCE.to_string(),
+                    claims_sha256: audit::sha256(res["tcb"].as_str().unwrap().as_bytes()),
+                    policy_sha256: audit::sha256(policy.as_bytes()),
+                    allow,
+                }
+            );
         }
+        assert_eq!(audit::verify(&content).unwrap(), 2);
+
+        std::fs::remove_file(file).unwrap();
     }
 
     #[tokio::test]
-    async fn test_attestation_not_allow() {
+    async fn test_attestation_typed_results() {
         let uuid = Uuid::new_v4().to_string();
-        let (workdir, service) = create_service(Some(&uuid));
-        let res = service
-            .user
-            .write()
-            .await
-            .set_reference_data(service.workdir.as_path(), reference(5))
+        let (service, user) = create_service(Some(&uuid)).await;
+        set_reference_data(&service, user.as_ref(), 5).await;
+        let attestaion_request = AttestationRequest {
+            evidence: evidence().into_bytes(),
+            user,
+            format: TokenFormat::Jwt as i32,
+        };
+        let response = service.attestation(Request::new(attestaion_request)).await;
+        let results = response.unwrap().into_inner().results.unwrap();
+        assert_eq!(
+            results.version,
+            attestation_service::ATTESTATION_RESULTS_VERSION
+        );
+        assert_eq!(results.tee, "sample");
+        assert!(!results.allow);
+        assert_eq!(results.claims["svn"], proto_value(json!(1)));
+        assert!(results.verifier_error.is_empty());
+        let violations = results.decision.unwrap().violations;
+        assert_eq!(violations.len(), 2);
+        assert_eq!(violations[0].claim, "cpusvn");
+        assert_eq!(violations[0].observed, Some(proto_value(json!(1))));
+        assert_eq!(violations[0].expected, Some(proto_value(json!(5))));
+    }
+
+    #[tokio::test]
+    async fn test_attestation_challenge() {
+        let uuid = Uuid::new_v4().to_string();
+        let (mut service, user) = create_service(Some(&uuid)).await;
+        let response = service.challenge(Request::new(ChallengeRequest {})).await;
+        let status = response.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::FailedPrecondition);
+
+        let nonces = Nonces::new(Box::new(MemoryStore::default()), DEFAULT_NONCE_TTL);
+        service.attestation_service = Arc::new(
+            attestation_service::Service::builder()
+                .nonces(Arc::new(nonces))
+                .build(),
+        );
+        let response = service.challenge(Request::new(ChallengeRequest {})).await;
+        let nonce = response.unwrap().into_inner().nonce;
+        let attestaion_request = AttestationRequest {
+            evidence: evidence_with_nonce(&nonce).into_bytes(),
+            user,
+            format: TokenFormat::Json as i32,
+        };
+        let response = service
+            .attestation(Request::new(attestaion_request.clone()))
             .await;
-        assert!(res.is_ok(), "Set reference should success");
-        let res = attestation(&service).await;
-        assert_eq!(res["allow"], false);
+        assert!(response.unwrap().into_inner().results.unwrap().allow);
 
-        let dir = workdir.join("users").join(uuid);
-        if dir.exists() {
-            // delete the temporary user folder
-            std::fs::remove_dir_all(dir).unwrap();
+        // The replayed evidence is rejected.
+        let response = service.attestation(Request::new(attestaion_request)).await;
+        assert!(response.is_err(), "attestation should failed");
+    }
+
+    async fn next_event(
+        events: &mut mpsc::Receiver<Result<AttestationStreamResponse, Status>>,
+    ) -> Event {
+        events.recv().await.unwrap().unwrap().event.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_attestation_stream() {
+        let uuid = Uuid::new_v4().to_string();
+        let (mut service, user) = create_service(Some(&uuid)).await;
+        let nonces = Nonces::new(Box::new(MemoryStore::default()), DEFAULT_NONCE_TTL);
+        service.attestation_service = Arc::new(
+            attestation_service::Service::builder()
+                .nonces(Arc::new(nonces))
+                .build(),
+        );
+        service.stream_interval = Duration::from_millis(200);
+
+        let (requests, rx) = mpsc::channel(1);
+        let (tx, mut events) = mpsc::channel(STREAM_BUFFER);
+        let challenge = service.attestation_service.challenge().await.unwrap();
+        tokio::spawn(
+            service
+                .clone()
+                .stream_session(ReceiverStream::new(rx), challenge, tx),
+        );
+
+        let nonce = match next_event(&mut events).await {
+            Event::Challenge(challenge) => challenge.nonce,
+            event => panic!("Unexpected event: {:?}", event),
+        };
+        let request = AttestationStreamRequest {
+            evidence: evidence_with_nonce(&nonce).into_bytes(),
+            user: user.clone(),
+            format: TokenFormat::Json as i32,
+        };
+        requests.send(Ok(request.clone())).await.unwrap();
+        match next_event(&mut events).await {
+            Event::Results(response) => assert!(response.results.unwrap().allow),
+            event => panic!("Unexpected event: {:?}", event),
         }
+
+        // The replayed evidence is rejected.
+        requests.send(Ok(request)).await.unwrap();
+        match next_event(&mut events).await {
+            Event::Error(status) => {
+                let info = error::rpc_error_info(&status).unwrap();
+                assert_eq!(info.reason, "NONCE_INVALID");
+            }
+            event => panic!("Unexpected event: {:?}", event),
+        }
+
+        // The evidence is revoked once the reference data is updated.
+        set_reference_data(&service, user.as_ref(), 5).await;
+        loop {
+            match next_event(&mut events).await {
+                Event::Challenge(_) => continue,
+                Event::Revocation(response) => {
+                    assert!(!response.results.unwrap().allow);
+                    break;
+                }
+                event => panic!("Unexpected event: {:?}", event),
+            }
+        }
+        drop(requests);
     }
 
     #[tokio::test]
-    async fn test_attestation_multiple_user_failed() {
-        let (_, service) = create_service(None);
+    async fn test_attestation_unknown_user_failed() {
+        let (service, _) = create_service(None).await;
         let user = common::User {
-            id: "testing user".to_string(),
+            id: Uuid::new_v4().to_string(),
         };
         let attestaion_request = AttestationRequest {
             evidence: evidence().into_bytes(),
             user: Some(user),
+            format: TokenFormat::Json as i32,
         };
         let request = Request::new(attestaion_request);
         let response = service.attestation(request).await;
         assert!(response.is_err(), "attestation should failed");
         let status: Status = response.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::NotFound);
         assert!(
-            status.message().contains("Multiple user is not supported"),
-            "Should contains multiple user is not supported error"
+            status.message().contains("does not exist"),
+            "Should contains user does not exist error"
         );
     }
+
+    #[tokio::test]
+    async fn test_attestation_jwt() {
+        let (service, _) = create_service(None).await;
+        let attestaion_request = AttestationRequest {
+            evidence: evidence().into_bytes(),
+            user: None,
+            format: TokenFormat::Jwt as i32,
+        };
+        let response = service.attestation(Request::new(attestaion_request)).await;
+        assert!(response.is_ok(), "attestation should success");
+        #[allow(deprecated)]
+        let token = String::from_utf8(response.unwrap().into_inner().attestation_results).unwrap();
+        let parts: Vec<&str> = token.split('.').collect();
+        assert_eq!(parts.len(), 3, "JWT should have 3 parts");
+        let header: Value = serde_json::from_slice(
+            &base64::decode_config(parts[0], base64::URL_SAFE_NO_PAD).unwrap(),
+        )
+        .unwrap();
+        let claims: Value = serde_json::from_slice(
+            &base64::decode_config(parts[1], base64::URL_SAFE_NO_PAD).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(claims["eat_nonce"], NONCE);
+
+        let response = service.get_jwks(Request::new(GetJwksRequest {})).await;
+        assert!(response.is_ok(), "get jwks should success");
+        let jwks: Value = serde_json::from_slice(&response.unwrap().into_inner().content).unwrap();
+        assert_eq!(jwks["keys"][0]["kid"], header["kid"]);
+    }
+
+    #[tokio::test]
+    async fn test_attestation_error_details() {
+        let (service, _) = create_service(None).await;
+        let cases = [
+            (
+                "{}".to_string(),
+                tonic::Code::InvalidArgument,
+                "MALFORMED_EVIDENCE",
+            ),
+            (
+                evidence().replace("\"sample\"", "\"unknown\""),
+                tonic::Code::Unimplemented,
+                "UNSUPPORTED_TEE",
+            ),
+        ];
+        for (evidence, code, reason) in cases {
+            let attestaion_request = AttestationRequest {
+                evidence: evidence.into_bytes(),
+                user: None,
+                format: TokenFormat::Json as i32,
+            };
+            let response = service.attestation(Request::new(attestaion_request)).await;
+            let status: Status = response.unwrap_err();
+            assert_eq!(status.code(), code);
+            let info = error::error_info(&status).unwrap();
+            assert_eq!(info.reason, reason);
+            assert_eq!(info.domain, error::ERROR_DOMAIN);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_batch_attestation() {
+        let (service, _) = create_service(None).await;
+        let request = BatchAttestationRequest {
+            evidences: vec![evidence().into_bytes(), b"{}".to_vec(), vec![0xff]],
+            user: None,
+            format: TokenFormat::Json as i32,
+        };
+        let response = service.batch_attestation(Request::new(request)).await;
+        let items = response.unwrap().into_inner().items;
+        assert_eq!(items.len(), 3);
+
+        match &items[0].result {
+            Some(batch_attestation_item::Result::Response(response)) => {
+                assert!(response.results.as_ref().unwrap().allow)
+            }
+            result => panic!("Unexpected result: {:?}", result),
+        }
+        for item in &items[1..] {
+            match &item.result {
+                Some(batch_attestation_item::Result::Error(status)) => {
+                    assert_eq!(status.code, tonic::Code::InvalidArgument as i32);
+                    let info = error::rpc_error_info(status).unwrap();
+                    assert_eq!(info.reason, "MALFORMED_EVIDENCE");
+                }
+                result => panic!("Unexpected result: {:?}", result),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_attestation_unsupported_format() {
+        let (service, _) = create_service(None).await;
+        let attestaion_request = AttestationRequest {
+            evidence: evidence().into_bytes(),
+            user: None,
+            format: 100,
+        };
+        let response = service.attestation(Request::new(attestaion_request)).await;
+        assert!(response.is_err(), "attestation should failed");
+    }
 }
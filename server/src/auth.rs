@@ -0,0 +1,285 @@
+//! Authentication and authorization of the management API.
+//!
+//! The management clients authenticate with a bearer token or their TLS client certificate,
+//! which are matched against the credentials file. The interceptor attaches the authenticated
+//! [`Principal`] to the request, and each endpoint authorizes it by its role of the tenant.
+
+use crate::common;
+use crate::user::DEFAULT_USER_ID;
+use anyhow::{anyhow, bail, Context, Result};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+use tonic::{Request, Status};
+
+/// The tenant of the roles which apply to all the tenants, and the tenant-agnostic endpoints,
+/// e.g. creating users and registering reference values.
+pub const ALL_TENANTS: &str = "*";
+
+// The principal of the requests which aren't authenticated.
+const ANONYMOUS: &str = "anonymous";
+
+/// The roles of a principal, each of them includes the permissions of the ones before it.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// Read the policies, reference data and users.
+    Auditor,
+    /// Change the policies, reference data and users as well.
+    Admin,
+}
+
+/// An entry of the credentials file.
+#[derive(Deserialize, Debug)]
+struct Credential {
+    name: String,
+    /// Hex encoded SHA-256 of the bearer token.
+    #[serde(default)]
+    token_sha256: Option<String>,
+    /// Hex encoded SHA-256 of the DER client certificate.
+    #[serde(default)]
+    cert_sha256: Option<String>,
+    /// The roles keyed by the tenant (user) id, or `*` for all the tenants.
+    roles: HashMap<String, Role>,
+}
+
+/// The authenticated management client.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Principal {
+    pub name: String,
+    roles: HashMap<String, Role>,
+}
+
+impl Principal {
+    fn role(&self, tenant: &str) -> Option<Role> {
+        let all = self.roles.get(ALL_TENANTS).copied();
+        match tenant {
+            ALL_TENANTS => all,
+            _ => self.roles.get(tenant).copied().max(all),
+        }
+    }
+
+    /// Check the principal has `role` of `tenant`.
+    pub fn authorize(&self, tenant: &str, role: Role) -> Result<(), Status> {
+        match self.role(tenant) {
+            Some(granted) if granted >= role => Ok(()),
+            _ => Err(Status::permission_denied(format!(
+                "{} isn't {:?} of tenant {}",
+                self.name, role, tenant
+            ))),
+        }
+    }
+}
+
+fn digest(hex_digest: &str) -> Result<[u8; 32]> {
+    hex::decode(hex_digest)?
+        .try_into()
+        .map_err(|_| anyhow!("{} isn't a SHA-256 digest", hex_digest))
+}
+
+// The principals keyed by the digests of their tokens and client certificates.
+#[derive(Debug, Default)]
+struct Principals {
+    tokens: HashMap<[u8; 32], Arc<Principal>>,
+    certs: HashMap<[u8; 32], Arc<Principal>>,
+}
+
+/// The credentials of the management clients, which can be replaced while serving.
+#[derive(Debug, Default)]
+pub struct Credentials {
+    principals: RwLock<Principals>,
+}
+
+impl Credentials {
+    /// Parse the JSON array of the credentials.
+    pub fn from_json(json: &str) -> Result<Self> {
+        let entries: Vec<Credential> = serde_json::from_str(json)?;
+        let mut principals = Principals::default();
+        for entry in entries {
+            if entry.token_sha256.is_none() && entry.cert_sha256.is_none() {
+                bail!("{} has neither token_sha256 nor cert_sha256", entry.name);
+            }
+            let principal = Arc::new(Principal {
+                name: entry.name,
+                roles: entry.roles,
+            });
+            if let Some(token) = &entry.token_sha256 {
+                principals.tokens.insert(digest(token)?, principal.clone());
+            }
+            if let Some(cert) = &entry.cert_sha256 {
+                principals.certs.insert(digest(cert)?, principal);
+            }
+        }
+        Ok(Self {
+            principals: RwLock::new(principals),
+        })
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let json = std::fs::read_to_string(path)
+            .with_context(|| format!("Read credentials {:?} failed.", path))?;
+        Self::from_json(&json).with_context(|| format!("Parse credentials {:?} failed.", path))
+    }
+
+    /// Replace the credentials by the reloaded ones, the authenticated requests are unaffected.
+    pub fn replace(&self, credentials: Credentials) {
+        let principals = credentials
+            .principals
+            .into_inner()
+            .unwrap_or_else(|e| e.into_inner());
+        *self.principals.write().unwrap_or_else(|e| e.into_inner()) = principals;
+    }
+
+    /// Authenticate the request by its bearer token, or its TLS client certificate.
+    pub fn authenticate<T>(&self, request: &Request<T>) -> Result<Arc<Principal>, Status> {
+        let principals = self.principals.read().unwrap_or_else(|e| e.into_inner());
+        if let Some(authorization) = request.metadata().get("authorization") {
+            let token = authorization
+                .to_str()
+                .ok()
+                .and_then(|value| value.strip_prefix("Bearer "))
+                .ok_or_else(|| Status::unauthenticated("Malformed authorization"))?;
+            let digest: [u8; 32] = Sha256::digest(token.trim().as_bytes()).into();
+            return principals
+                .tokens
+                .get(&digest)
+                .cloned()
+                .ok_or_else(|| Status::unauthenticated("Unknown token"));
+        }
+
+        let certs = request
+            .peer_certs()
+            .ok_or_else(|| Status::unauthenticated("Neither token nor client certificate"))?;
+        certs
+            .first()
+            .and_then(|cert| {
+                let digest: [u8; 32] = Sha256::digest(cert.get_ref()).into();
+                principals.certs.get(&digest).cloned()
+            })
+            .ok_or_else(|| Status::unauthenticated("Unknown client certificate"))
+    }
+}
+
+/// The tonic interceptor of the management services, which authenticates the requests and
+/// attaches their [`Principal`]. The requests aren't authenticated without `credentials`, which
+/// is only allowed by `management.insecure_no_auth`.
+pub fn interceptor(
+    credentials: Option<Arc<Credentials>>,
+) -> impl FnMut(Request<()>) -> Result<Request<()>, Status> + Clone {
+    move |mut request: Request<()>| {
+        if let Some(credentials) = &credentials {
+            let principal = credentials.authenticate(&request)?;
+            debug!("Management principal: {}", principal.name);
+            request.extensions_mut().insert(principal);
+        }
+        Ok(request)
+    }
+}
+
+/// Check the request's principal has `role` of `tenant`, every request is allowed if the
+/// authentication isn't `enabled`.
+pub fn authorize<T>(
+    request: &Request<T>,
+    enabled: bool,
+    tenant: &str,
+    role: Role,
+) -> Result<(), Status> {
+    if !enabled {
+        return Ok(());
+    }
+    request
+        .extensions()
+        .get::<Arc<Principal>>()
+        .ok_or_else(|| Status::unauthenticated("The request isn't authenticated"))?
+        .authorize(tenant, role)
+}
+
+/// The name of the request's principal, `anonymous` if it isn't authenticated.
+pub fn principal<T>(request: &Request<T>) -> String {
+    request
+        .extensions()
+        .get::<Arc<Principal>>()
+        .map_or_else(|| ANONYMOUS.to_string(), |principal| principal.name.clone())
+}
+
+/// The tenant of the request's user, the default user if absent.
+pub fn tenant(user: Option<&common::User>) -> &str {
+    user.map_or(DEFAULT_USER_ID, |user| user.id.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn sha256(token: &str) -> String {
+        hex::encode(Sha256::digest(token.as_bytes()))
+    }
+
+    fn credentials() -> Credentials {
+        let json = json!([
+            { "name": "admin", "token_sha256": sha256("admin-token"), "roles": { "*": "admin" } },
+            {
+                "name": "auditor",
+                "token_sha256": sha256("auditor-token"),
+                "roles": { "*": "auditor", "team-a": "admin" }
+            }
+        ]);
+        Credentials::from_json(&json.to_string()).unwrap()
+    }
+
+    fn request(token: &str) -> Request<()> {
+        let mut request = Request::new(());
+        request.metadata_mut().insert(
+            "authorization",
+            format!("Bearer {}", token).parse().unwrap(),
+        );
+        request
+    }
+
+    #[test]
+    fn test_authenticate() {
+        let credentials = credentials();
+        let principal = credentials.authenticate(&request("admin-token")).unwrap();
+        assert_eq!(principal.name, "admin");
+
+        let status = credentials.authenticate(&request("guess")).unwrap_err();
+        assert_eq!(status.code(), tonic::Code::Unauthenticated);
+        let status = credentials.authenticate(&Request::new(())).unwrap_err();
+        assert_eq!(status.code(), tonic::Code::Unauthenticated);
+
+        let json = json!([{ "name": "nobody", "roles": {} }]);
+        assert!(Credentials::from_json(&json.to_string()).is_err());
+
+        // The replaced credentials revoke the previous tokens.
+        let json = json!([
+            { "name": "rotated", "token_sha256": sha256("rotated-token"), "roles": {} }
+        ]);
+        credentials.replace(Credentials::from_json(&json.to_string()).unwrap());
+        assert!(credentials.authenticate(&request("admin-token")).is_err());
+        let principal = credentials.authenticate(&request("rotated-token")).unwrap();
+        assert_eq!(principal.name, "rotated");
+    }
+
+    #[test]
+    fn test_authorize() {
+        let credentials = credentials();
+        let mut intercept = interceptor(Some(Arc::new(credentials)));
+        let admin = intercept(request("admin-token")).unwrap();
+        assert!(authorize(&admin, true, "team-b", Role::Admin).is_ok());
+        assert!(authorize(&admin, true, ALL_TENANTS, Role::Admin).is_ok());
+
+        let auditor = intercept(request("auditor-token")).unwrap();
+        assert!(authorize(&auditor, true, "team-b", Role::Auditor).is_ok());
+        assert!(authorize(&auditor, true, "team-a", Role::Admin).is_ok());
+        let status = authorize(&auditor, true, "team-b", Role::Admin).unwrap_err();
+        assert_eq!(status.code(), tonic::Code::PermissionDenied);
+        assert!(authorize(&auditor, true, ALL_TENANTS, Role::Admin).is_err());
+
+        assert!(intercept(Request::new(())).is_err());
+        assert!(authorize(&Request::new(()), true, "team-a", Role::Auditor).is_err());
+        assert!(authorize(&Request::new(()), false, "team-a", Role::Admin).is_ok());
+    }
+}
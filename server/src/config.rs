@@ -0,0 +1,556 @@
+//! Configuration of the attestation server.
+//!
+//! The settings are layered: the defaults, the TOML or JSON config file, the `AS_*` environment
+//! variables, then the command line flags. The environment variable of a setting is its path in
+//! uppercase joined by `_`, e.g. `AS_TLS_CERT` for `cert` of `[tls]`, and the lists are comma
+//! separated.
+
+use crate::store::StoreType;
+use anyhow::{anyhow, bail, Context, Result};
+use attestation_service::{
+    PolicyEngineType, TokenAlgorithm, VerifierRegistry, DEFAULT_BATCH_PARALLELISM,
+    DEFAULT_POLICY_CACHE_SIZE,
+};
+use log::LevelFilter;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+const DEFAULT_WORKDIR: &str = "/opt/attestation-server";
+const DEFAULT_ATTESTATION_SOCK: &str = "127.0.0.1:3000";
+const DEFAULT_MANAGEMENT_SOCK: &str = "127.0.0.1:3001";
+const ENV_PREFIX: &str = "AS";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// The directory of the users' policies, reference values, nonces and audit log.
+    pub workdir: PathBuf,
+    /// `off`, `error`, `warn`, `info`, `debug` or `trace`, `RUST_LOG` takes precedence.
+    pub log_level: String,
+    pub attestation_sock: String,
+    pub management_sock: String,
+    /// The socket of the HTTP listener which serves the Prometheus metrics at `/metrics`, which
+    /// is disabled if absent.
+    pub metrics_sock: Option<String>,
+    /// The TEEs whose built-in verifiers are enabled.
+    pub verifiers: Vec<String>,
+    /// Whether the policies edited in the `fs` policy store and the config file are reloaded
+    /// once they change.
+    pub watch: bool,
+    /// Seconds the listeners report NOT_SERVING on SIGTERM before they stop, which lets the load
+    /// balancers drain them.
+    pub shutdown_delay: u64,
+    // The tables follow the values, which TOML requires.
+    pub tls: TlsConfig,
+    pub token: TokenConfig,
+    pub policy: PolicyConfig,
+    pub nonce: NonceConfig,
+    pub attestation: AttestationConfig,
+    pub management: ManagementConfig,
+    pub rvps: RvpsConfig,
+}
+
+/// Both sockets are plaintext without `cert` and `key`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct TlsConfig {
+    /// PEM file of the certificate chain.
+    pub cert: Option<PathBuf>,
+    /// PEM file of the private key of `cert`.
+    pub key: Option<PathBuf>,
+    /// PEM file of the CA certificate which issues the management clients' certificates.
+    pub client_ca: Option<PathBuf>,
+    /// Whether the management socket requires the client certificates issued by `client_ca`.
+    pub management_mtls: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct TokenConfig {
+    /// PEM file of the ES256/RS256 signing key, a key of `alg` is generated at startup if absent.
+    pub key: Option<PathBuf>,
+    pub alg: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct PolicyConfig {
+    pub engine: String,
+    /// `fs`, `sqlite` or `memory`.
+    pub store: String,
+    /// The compiled policies cached by the engine, 0 disables the cache.
+    pub cache_size: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct NonceConfig {
+    /// `memory` or `file`, the nonces aren't checked if absent.
+    pub store: Option<String>,
+    /// Seconds before the issued nonces expire.
+    pub ttl: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct AttestationConfig {
+    pub batch_parallelism: usize,
+    /// Seconds between the nonces pushed to the `AttestationStream` clients.
+    pub stream_interval: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct ManagementConfig {
+    /// JSON file of the management clients' credentials, which requires TLS.
+    pub credentials: Option<PathBuf>,
+    /// Whether the management requests are allowed without credentials, which is only to
+    /// test/demo.
+    pub insecure_no_auth: bool,
+    /// `audit/audit.log` of the workdir if absent.
+    pub audit_log: Option<PathBuf>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct RvpsConfig {
+    /// PEM files of the P-256 public keys which verify the registered provenances.
+    pub trusted_keys: Vec<PathBuf>,
+    /// Whether the unsigned `sample` provenances are accepted, which is only to test/demo.
+    pub allow_unsigned_sample: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            workdir: PathBuf::from(DEFAULT_WORKDIR),
+            log_level: "info".to_owned(),
+            attestation_sock: DEFAULT_ATTESTATION_SOCK.to_owned(),
+            management_sock: DEFAULT_MANAGEMENT_SOCK.to_owned(),
+            metrics_sock: None,
+            verifiers: VerifierRegistry::builtin().tees(),
+            watch: true,
+            shutdown_delay: 5,
+            tls: TlsConfig::default(),
+            token: TokenConfig::default(),
+            policy: PolicyConfig::default(),
+            nonce: NonceConfig::default(),
+            attestation: AttestationConfig::default(),
+            management: ManagementConfig::default(),
+            rvps: RvpsConfig::default(),
+        }
+    }
+}
+
+impl Default for TlsConfig {
+    fn default() -> Self {
+        Self {
+            cert: None,
+            key: None,
+            client_ca: None,
+            management_mtls: true,
+        }
+    }
+}
+
+impl Default for TokenConfig {
+    fn default() -> Self {
+        Self {
+            key: None,
+            alg: TokenAlgorithm::ES256.as_ref().to_owned(),
+        }
+    }
+}
+
+impl Default for PolicyConfig {
+    fn default() -> Self {
+        Self {
+            engine: PolicyEngineType::default().as_ref().to_owned(),
+            store: "fs".to_owned(),
+            cache_size: DEFAULT_POLICY_CACHE_SIZE,
+        }
+    }
+}
+
+impl Default for NonceConfig {
+    fn default() -> Self {
+        Self {
+            store: None,
+            ttl: 300,
+        }
+    }
+}
+
+impl Default for AttestationConfig {
+    fn default() -> Self {
+        Self {
+            batch_parallelism: DEFAULT_BATCH_PARALLELISM,
+            stream_interval: 60,
+        }
+    }
+}
+
+// Override the leaves of `value` by the environment variables named after their paths.
+fn override_env(value: &mut Value, name: &str, env: &dyn Fn(&str) -> Option<String>) -> Result<()> {
+    if let Value::Object(object) = value {
+        for (key, child) in object.iter_mut() {
+            override_env(child, &format!("{}_{}", name, key.to_uppercase()), env)?;
+        }
+        return Ok(());
+    }
+    let var = match env(name) {
+        Some(var) => var,
+        None => return Ok(()),
+    };
+    let error = || format!("Illegal environment variable {}={:?}", name, var);
+    *value = match value {
+        Value::Bool(_) => Value::Bool(var.parse().with_context(error)?),
+        Value::Number(_) => Value::Number(var.parse::<u64>().with_context(error)?.into()),
+        Value::Array(_) => Value::Array(
+            var.split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(|item| Value::String(item.to_owned()))
+                .collect(),
+        ),
+        _ => Value::String(var),
+    };
+    Ok(())
+}
+
+fn check_file(name: &str, file: &Option<PathBuf>) -> Result<()> {
+    match file {
+        Some(file) if !file.is_file() => bail!("{} {:?} does not exist", name, file),
+        _ => Ok(()),
+    }
+}
+
+impl Config {
+    /// Parse the config file, which is JSON if its extension is `.json`, otherwise TOML.
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Read config {:?} failed.", path))?;
+        let config = match path.extension().and_then(|extension| extension.to_str()) {
+            Some("json") => serde_json::from_str(&content).map_err(anyhow::Error::from),
+            _ => toml::from_str(&content).map_err(anyhow::Error::from),
+        };
+        config.with_context(|| format!("Parse config {:?} failed.", path))
+    }
+
+    /// Override the settings by the `AS_*` variables looked up by `env`.
+    pub fn override_env(self, env: &dyn Fn(&str) -> Option<String>) -> Result<Self> {
+        let mut value = serde_json::to_value(self)?;
+        override_env(&mut value, ENV_PREFIX, env)?;
+        Ok(serde_json::from_value(value)?)
+    }
+
+    /// The default config in TOML.
+    pub fn default_toml() -> Result<String> {
+        Ok(toml::to_string_pretty(&Self::default())?)
+    }
+
+    pub fn attestation_addr(&self) -> Result<SocketAddr> {
+        self.attestation_sock.parse().map_err(|e| {
+            anyhow!(
+                "Illegal attestation_sock {:?}: {}",
+                self.attestation_sock,
+                e
+            )
+        })
+    }
+
+    pub fn management_addr(&self) -> Result<SocketAddr> {
+        self.management_sock
+            .parse()
+            .map_err(|e| anyhow!("Illegal management_sock {:?}: {}", self.management_sock, e))
+    }
+
+    pub fn metrics_addr(&self) -> Result<Option<SocketAddr>> {
+        match &self.metrics_sock {
+            Some(sock) => sock
+                .parse()
+                .map(Some)
+                .map_err(|e| anyhow!("Illegal metrics_sock {:?}: {}", sock, e)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn log_level(&self) -> Result<LevelFilter> {
+        LevelFilter::from_str(&self.log_level)
+            .map_err(|_| anyhow!("Illegal log_level {:?}", self.log_level))
+    }
+
+    pub fn policy_engine(&self) -> Result<PolicyEngineType> {
+        let engine = PolicyEngineType::from_str(&self.policy.engine)
+            .map_err(|_| anyhow!("Unknown policy engine {:?}", self.policy.engine))?;
+        if !PolicyEngineType::enabled().contains(&engine) {
+            bail!(
+                "Policy engine {} isn't enabled by its cargo feature",
+                engine.as_ref()
+            );
+        }
+        Ok(engine)
+    }
+
+    /// Check the settings, so the server fails at startup instead of on the first request.
+    pub fn validate(&self) -> Result<()> {
+        self.attestation_addr()?;
+        self.management_addr()?;
+        self.metrics_addr()?;
+        self.log_level()?;
+
+        let builtin = VerifierRegistry::builtin().tees();
+        if let Some(tee) = self
+            .verifiers
+            .iter()
+            .find(|tee| !builtin.contains(&tee.to_ascii_lowercase()))
+        {
+            bail!(
+                "Verifier {:?} isn't built in, the built-in ones: {}",
+                tee,
+                builtin.join(", ")
+            );
+        }
+
+        let tls = &self.tls;
+        if tls.cert.is_some() != tls.key.is_some() {
+            bail!("tls.cert and tls.key must be specified together");
+        }
+        if tls.cert.is_none() && tls.client_ca.is_some() {
+            bail!("tls.client_ca requires tls.cert");
+        }
+        if tls.cert.is_some() && tls.client_ca.is_none() && tls.management_mtls {
+            bail!("The management socket requires mTLS, specify tls.client_ca, or set tls.management_mtls = false to disable it");
+        }
+        check_file("tls.cert", &tls.cert)?;
+        check_file("tls.key", &tls.key)?;
+        check_file("tls.client_ca", &tls.client_ca)?;
+
+        check_file("token.key", &self.token.key)?;
+        TokenAlgorithm::from_str(&self.token.alg)
+            .map_err(|_| anyhow!("Unknown token.alg {:?}", self.token.alg))?;
+
+        self.policy_engine()?;
+        StoreType::from_str(&self.policy.store)?;
+
+        match self.nonce.store.as_deref() {
+            None | Some("memory") | Some("file") => (),
+            Some(store) => bail!("Unknown nonce.store {:?}", store),
+        }
+        if self.nonce.ttl == 0 {
+            bail!("nonce.ttl must be positive");
+        }
+        if self.attestation.batch_parallelism == 0 {
+            bail!("attestation.batch_parallelism must be positive");
+        }
+        if self.attestation.stream_interval == 0 {
+            bail!("attestation.stream_interval must be positive");
+        }
+
+        let management = &self.management;
+        match (&management.credentials, management.insecure_no_auth) {
+            (None, false) => bail!("The management requests require authentication, specify management.credentials, or set management.insecure_no_auth = true to disable it"),
+            (Some(_), true) => bail!("management.credentials conflicts with management.insecure_no_auth"),
+            // The bearer tokens aren't sent in plaintext, and the client certificates require TLS.
+            (Some(_), false) if tls.cert.is_none() => bail!("management.credentials requires tls.cert"),
+            _ => (),
+        }
+        check_file("management.credentials", &management.credentials)?;
+        for key in &self.rvps.trusted_keys {
+            check_file("rvps.trusted_keys", &Some(key.clone()))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn env(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        move |name| vars.get(name).cloned()
+    }
+
+    #[test]
+    fn test_default_config() {
+        let config = Config::default();
+        let toml = Config::default_toml().unwrap();
+        assert_eq!(toml::from_str::<Config>(&toml).unwrap(), config);
+
+        // The management requests aren't allowed without authentication by default.
+        assert!(config.validate().is_err());
+        let config = Config {
+            management: ManagementConfig {
+                insecure_no_auth: true,
+                ..ManagementConfig::default()
+            },
+            ..Config::default()
+        };
+        config.validate().unwrap();
+    }
+
+    #[test]
+    fn test_config_file() {
+        let dir = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let file = dir.join("config.toml");
+        let toml = r#"
+workdir = "/var/lib/attestation-server"
+verifiers = ["sample"]
+
+[policy]
+store = "sqlite"
+cache_size = 8
+"#;
+        std::fs::write(&file, toml).unwrap();
+        let config = Config::from_file(&file).unwrap();
+        assert_eq!(config.workdir, PathBuf::from("/var/lib/attestation-server"));
+        assert_eq!(config.verifiers, ["sample"]);
+        assert_eq!(config.policy.store, "sqlite");
+        assert_eq!(config.policy.cache_size, 8);
+        assert_eq!(config.policy.engine, PolicyConfig::default().engine);
+        assert_eq!(config.attestation_sock, DEFAULT_ATTESTATION_SOCK);
+
+        let file = dir.join("config.json");
+        std::fs::write(&file, r#"{ "nonce": { "store": "memory", "ttl": 60 } }"#).unwrap();
+        let config = Config::from_file(&file).unwrap();
+        assert_eq!(config.nonce.store, Some("memory".to_owned()));
+        assert_eq!(config.nonce.ttl, 60);
+
+        // The typos aren't ignored.
+        std::fs::write(&file, r#"{ "nonce": { "tll": 60 } }"#).unwrap();
+        assert!(Config::from_file(&file).is_err());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_override_env() {
+        let config = Config::default()
+            .override_env(&env(&[
+                ("AS_MANAGEMENT_SOCK", "0.0.0.0:4001"),
+                ("AS_METRICS_SOCK", "0.0.0.0:9090"),
+                ("AS_TLS_MANAGEMENT_MTLS", "false"),
+                ("AS_NONCE_STORE", "file"),
+                ("AS_POLICY_CACHE_SIZE", "0"),
+                ("AS_SHUTDOWN_DELAY", "0"),
+                ("AS_VERIFIERS", "sample, tdx"),
+            ]))
+            .unwrap();
+        assert_eq!(config.management_sock, "0.0.0.0:4001");
+        assert_eq!(
+            config.metrics_addr().unwrap(),
+            Some(([0, 0, 0, 0], 9090).into())
+        );
+        assert!(!config.tls.management_mtls);
+        assert_eq!(config.nonce.store, Some("file".to_owned()));
+        assert_eq!(config.policy.cache_size, 0);
+        assert_eq!(config.shutdown_delay, 0);
+        assert_eq!(config.verifiers, ["sample", "tdx"]);
+
+        let e = Config::default()
+            .override_env(&env(&[("AS_NONCE_TTL", "soon")]))
+            .unwrap_err();
+        assert!(e.to_string().contains("AS_NONCE_TTL"), "{}", e);
+    }
+
+    #[test]
+    fn test_validate() {
+        let insecure = || Config {
+            management: ManagementConfig {
+                insecure_no_auth: true,
+                ..ManagementConfig::default()
+            },
+            ..Config::default()
+        };
+        // Only the existence of the files is checked.
+        let file = Some(Path::new(env!("CARGO_MANIFEST_DIR")).join("Cargo.toml"));
+        let secure = || Config {
+            tls: TlsConfig {
+                cert: file.clone(),
+                key: file.clone(),
+                client_ca: file.clone(),
+                ..TlsConfig::default()
+            },
+            management: ManagementConfig {
+                credentials: file.clone(),
+                ..ManagementConfig::default()
+            },
+            ..Config::default()
+        };
+        insecure().validate().unwrap();
+        secure().validate().unwrap();
+
+        let invalid = [
+            Config {
+                attestation_sock: "localhost".to_owned(),
+                ..insecure()
+            },
+            Config {
+                metrics_sock: Some("9090".to_owned()),
+                ..insecure()
+            },
+            Config {
+                log_level: "verbose".to_owned(),
+                ..insecure()
+            },
+            Config {
+                verifiers: vec!["unknown".to_owned()],
+                ..insecure()
+            },
+            Config {
+                tls: TlsConfig {
+                    cert: Some(PathBuf::from("server.pem")),
+                    ..TlsConfig::default()
+                },
+                ..insecure()
+            },
+            Config {
+                policy: PolicyConfig {
+                    store: "etcd".to_owned(),
+                    ..PolicyConfig::default()
+                },
+                ..insecure()
+            },
+            Config {
+                nonce: NonceConfig {
+                    ttl: 0,
+                    ..NonceConfig::default()
+                },
+                ..insecure()
+            },
+            Config {
+                management: ManagementConfig {
+                    credentials: Some(PathBuf::from("/nonexistent/credentials.json")),
+                    ..ManagementConfig::default()
+                },
+                ..secure()
+            },
+            // The bearer tokens would be sent in plaintext.
+            Config {
+                tls: TlsConfig::default(),
+                ..secure()
+            },
+            Config {
+                management: ManagementConfig {
+                    credentials: file.clone(),
+                    insecure_no_auth: true,
+                    ..ManagementConfig::default()
+                },
+                ..secure()
+            },
+        ];
+        for config in invalid {
+            assert!(config.validate().is_err(), "{:?} should be invalid", config);
+        }
+    }
+}
@@ -0,0 +1,151 @@
+//! Map the errors of the attestation service to the gRPC status.
+//!
+//! The status code is derived from the typed [`attestation_service::Error`], and its reason is
+//! attached as a `google.rpc.ErrorInfo` in the `google.rpc.Status` details, so the clients can
+//! react to the failures without parsing the messages.
+
+use crate::google::rpc;
+use crate::user::NotFound;
+use attestation_service::Error;
+use prost::Message;
+use tonic::{Code, Status};
+
+/// The `google.rpc.ErrorInfo.domain` of the errors.
+pub const ERROR_DOMAIN: &str = "attestation-service";
+
+const ERROR_INFO_TYPE_URL: &str = "type.googleapis.com/google.rpc.ErrorInfo";
+
+/// The status code of the typed error.
+pub fn code(error: &Error) -> Code {
+    match error {
+        Error::MalformedEvidence(_) => Code::InvalidArgument,
+        Error::UnsupportedTee(_) => Code::Unimplemented,
+        Error::SignatureInvalid(_) | Error::ReportDataMismatch(_) | Error::NonceInvalid(_) => {
+            Code::Unauthenticated
+        }
+        Error::PolicyCompile(_) => Code::FailedPrecondition,
+        Error::PolicyEval(_) => Code::Internal,
+        Error::Storage(_) => Code::Unavailable,
+    }
+}
+
+/// Convert `e` to the `google.rpc.Status` whose message is prefixed with `context`. The
+/// unclassified errors keep the `fallback` code and carry no details.
+pub fn rpc_status(context: &str, e: &anyhow::Error, fallback: Code) -> rpc::Status {
+    let message = format!("{}: {}", context, e);
+    let error = match Error::find(e) {
+        Some(error) => error,
+        None => {
+            return rpc::Status {
+                code: fallback as i32,
+                message,
+                details: Vec::new(),
+            }
+        }
+    };
+
+    let info = rpc::ErrorInfo {
+        reason: error.reason().to_string(),
+        domain: ERROR_DOMAIN.to_string(),
+        metadata: Default::default(),
+    };
+    rpc::Status {
+        code: code(error) as i32,
+        message,
+        details: vec![prost_types::Any {
+            type_url: ERROR_INFO_TYPE_URL.to_string(),
+            value: info.encode_to_vec(),
+        }],
+    }
+}
+
+/// Convert `e` to the status like [`rpc_status`], which is carried in the status details if it
+/// has any.
+pub fn status(context: &str, e: anyhow::Error, fallback: Code) -> Status {
+    let status = rpc_status(context, &e, fallback);
+    let code = Code::from_i32(status.code);
+    match status.details.is_empty() {
+        true => Status::new(code, status.message),
+        false => Status::with_details(code, status.message.clone(), status.encode_to_vec().into()),
+    }
+}
+
+/// Convert the failed lookup `e` to NOT_FOUND if the user or version doesn't exist, otherwise
+/// like [`status`].
+pub fn lookup_status(context: &str, e: anyhow::Error, fallback: Code) -> Status {
+    match e.downcast_ref::<NotFound>() {
+        Some(_) => Status::not_found(format!("{}: {}", context, e)),
+        None => status(context, e, fallback),
+    }
+}
+
+/// The `google.rpc.ErrorInfo` in the details of `status`, if any.
+#[cfg(test)]
+pub fn error_info(status: &Status) -> Option<rpc::ErrorInfo> {
+    let details = rpc::Status::decode(status.details()).ok()?;
+    rpc_error_info(&details)
+}
+
+/// The `google.rpc.ErrorInfo` in the details of the `google.rpc.Status`, if any.
+#[cfg(test)]
+pub fn rpc_error_info(status: &rpc::Status) -> Option<rpc::ErrorInfo> {
+    status
+        .details
+        .iter()
+        .find(|any| any.type_url == ERROR_INFO_TYPE_URL)
+        .and_then(|any| rpc::ErrorInfo::decode(any.value.as_slice()).ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status() {
+        let e = anyhow::Error::from(Error::ReportDataMismatch("mismatch".to_string()))
+            .context("Verify evidence failed.");
+        let status = status("Attestation", e, Code::Aborted);
+        assert_eq!(status.code(), Code::Unauthenticated);
+        assert_eq!(status.message(), "Attestation: Verify evidence failed.");
+        let info = error_info(&status).unwrap();
+        assert_eq!(info.reason, "REPORT_DATA_MISMATCH");
+        assert_eq!(info.domain, ERROR_DOMAIN);
+
+        let status = super::status(
+            "Attestation",
+            anyhow::anyhow!("unclassified"),
+            Code::Aborted,
+        );
+        assert_eq!(status.code(), Code::Aborted);
+        assert!(error_info(&status).is_none());
+    }
+
+    #[test]
+    fn test_code() {
+        let codes = [
+            (
+                Error::MalformedEvidence(String::new()),
+                Code::InvalidArgument,
+            ),
+            (Error::UnsupportedTee(String::new()), Code::Unimplemented),
+            (
+                Error::SignatureInvalid(String::new()),
+                Code::Unauthenticated,
+            ),
+            (
+                Error::ReportDataMismatch(String::new()),
+                Code::Unauthenticated,
+            ),
+            (Error::NonceInvalid(String::new()), Code::Unauthenticated),
+            (
+                Error::PolicyCompile(String::new()),
+                Code::FailedPrecondition,
+            ),
+            (Error::PolicyEval(String::new()), Code::Internal),
+            (Error::Storage(String::new()), Code::Unavailable),
+        ];
+        for (error, expected) in codes {
+            assert_eq!(code(&error), expected, "{}", error.reason());
+        }
+    }
+}
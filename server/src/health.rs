@@ -0,0 +1,242 @@
+//! The lifecycle of the server, reported by the standard `grpc.health.v1.Health` service of the
+//! listeners.
+//!
+//! The listeners are NOT_SERVING until the startup self-test passes, and again once the server
+//! begins to shut down, which lets the load balancers drain them before they stop.
+
+use crate::grpc::health::v1::health_check_response::ServingStatus;
+use crate::grpc::health::v1::health_server::{self, HealthServer};
+use crate::grpc::health::v1::{HealthCheckRequest, HealthCheckResponse};
+use std::sync::Arc;
+use tokio::sync::{mpsc, watch};
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{Request, Response, Status};
+
+// The buffer of the status changes pushed to a `Watch` client.
+const WATCH_BUFFER: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Starting,
+    Serving,
+    Draining,
+    Stopped,
+}
+
+/// The lifecycle shared by the listeners.
+#[derive(Debug, Clone)]
+pub struct Health {
+    state: Arc<watch::Sender<State>>,
+}
+
+impl Default for Health {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Health {
+    pub fn new() -> Self {
+        let (state, _) = watch::channel(State::Starting);
+        Self {
+            state: Arc::new(state),
+        }
+    }
+
+    /// Report SERVING, unless the server is shutting down.
+    pub fn serving(&self) {
+        self.state.send_if_modified(|state| match state {
+            State::Starting => {
+                *state = State::Serving;
+                true
+            }
+            _ => false,
+        });
+    }
+
+    /// Report NOT_SERVING until the server stops.
+    pub fn drain(&self) {
+        self.state.send_if_modified(|state| match state {
+            State::Starting | State::Serving => {
+                *state = State::Draining;
+                true
+            }
+            _ => false,
+        });
+    }
+
+    /// Stop the listeners gracefully.
+    pub fn stop(&self) {
+        self.state.send_replace(State::Stopped);
+    }
+
+    /// Resolve once the listeners should stop.
+    pub async fn stopped(&self) {
+        let mut state = self.state.subscribe();
+        // The sender is owned by `self`, so it isn't dropped meanwhile.
+        let _ = state.wait_for(|state| *state == State::Stopped).await;
+    }
+
+    /// The health service of a listener serving the gRPC services `services`.
+    pub fn service(&self, services: &[&str]) -> HealthServer<Service> {
+        HealthServer::new(self.listener(services))
+    }
+
+    fn listener(&self, services: &[&str]) -> Service {
+        Service {
+            services: services.iter().map(|name| name.to_string()).collect(),
+            state: self.state.subscribe(),
+        }
+    }
+}
+
+pub struct Service {
+    services: Vec<String>,
+    state: watch::Receiver<State>,
+}
+
+// The status of the gRPC service `service` of the listener, the empty one is the whole listener.
+fn status(services: &[String], service: &str, state: State) -> ServingStatus {
+    if !service.is_empty() && !services.iter().any(|name| name == service) {
+        return ServingStatus::ServiceUnknown;
+    }
+    match state {
+        State::Serving => ServingStatus::Serving,
+        _ => ServingStatus::NotServing,
+    }
+}
+
+fn response(status: ServingStatus) -> HealthCheckResponse {
+    HealthCheckResponse {
+        status: status as i32,
+    }
+}
+
+#[tonic::async_trait]
+impl health_server::Health for Service {
+    async fn check(
+        &self,
+        request: Request<HealthCheckRequest>,
+    ) -> Result<Response<HealthCheckResponse>, Status> {
+        let service = request.into_inner().service;
+        match status(&self.services, &service, *self.state.borrow()) {
+            ServingStatus::ServiceUnknown => {
+                Err(Status::not_found(format!("Unknown service {}", service)))
+            }
+            status => Ok(Response::new(response(status))),
+        }
+    }
+
+    type WatchStream = ReceiverStream<Result<HealthCheckResponse, Status>>;
+
+    async fn watch(
+        &self,
+        request: Request<HealthCheckRequest>,
+    ) -> Result<Response<Self::WatchStream>, Status> {
+        let service = request.into_inner().service;
+        let services = self.services.clone();
+        let mut states = self.state.clone();
+        let (tx, rx) = mpsc::channel(WATCH_BUFFER);
+        tokio::spawn(async move {
+            let mut last = None;
+            loop {
+                let state = *states.borrow_and_update();
+                let status = status(&services, &service, state);
+                if last != Some(status) {
+                    if tx.send(Ok(response(status))).await.is_err() {
+                        return;
+                    }
+                    last = Some(status);
+                }
+                // The unknown services stay unknown, and the stream ends once the server stops
+                // rather than holding it.
+                if status == ServingStatus::ServiceUnknown
+                    || state == State::Stopped
+                    || states.changed().await.is_err()
+                {
+                    return;
+                }
+            }
+        });
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grpc::health::v1::health_server::Health as _;
+    use std::time::Duration;
+    use tokio_stream::StreamExt;
+
+    const SERVICE: &str = "attestation.AttestationService";
+
+    async fn check(service: &Service, name: &str) -> Result<ServingStatus, Status> {
+        let request = Request::new(HealthCheckRequest {
+            service: name.to_owned(),
+        });
+        let response = service.check(request).await?.into_inner();
+        Ok(ServingStatus::from_i32(response.status).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_check() {
+        let health = Health::new();
+        let service = health.listener(&[SERVICE]);
+        for name in ["", SERVICE] {
+            assert_eq!(
+                check(&service, name).await.unwrap(),
+                ServingStatus::NotServing
+            );
+        }
+        health.serving();
+        for name in ["", SERVICE] {
+            assert_eq!(check(&service, name).await.unwrap(), ServingStatus::Serving);
+        }
+        let res = check(&service, "management.ManagementService").await;
+        assert_eq!(res.unwrap_err().code(), tonic::Code::NotFound);
+
+        // The shutdown isn't reverted by a late self-test.
+        health.drain();
+        health.serving();
+        assert_eq!(
+            check(&service, "").await.unwrap(),
+            ServingStatus::NotServing
+        );
+    }
+
+    async fn next(
+        stream: &mut ReceiverStream<Result<HealthCheckResponse, Status>>,
+    ) -> Option<ServingStatus> {
+        let response = stream.next().await?.unwrap();
+        ServingStatus::from_i32(response.status)
+    }
+
+    #[tokio::test]
+    async fn test_watch() {
+        let health = Health::new();
+        let service = health.listener(&[SERVICE]);
+        let request = Request::new(HealthCheckRequest {
+            service: SERVICE.to_owned(),
+        });
+        let mut stream = service.watch(request).await.unwrap().into_inner();
+        assert_eq!(next(&mut stream).await, Some(ServingStatus::NotServing));
+        health.serving();
+        assert_eq!(next(&mut stream).await, Some(ServingStatus::Serving));
+        health.drain();
+        assert_eq!(next(&mut stream).await, Some(ServingStatus::NotServing));
+        // The stream ends once the server stops.
+        health.stop();
+        assert_eq!(next(&mut stream).await, None);
+        tokio::time::timeout(Duration::from_secs(1), health.stopped())
+            .await
+            .unwrap();
+
+        let request = Request::new(HealthCheckRequest {
+            service: "unknown.Service".to_owned(),
+        });
+        let mut stream = service.watch(request).await.unwrap().into_inner();
+        assert_eq!(next(&mut stream).await, Some(ServingStatus::ServiceUnknown));
+        assert_eq!(next(&mut stream).await, None);
+    }
+}
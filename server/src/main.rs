@@ -1,13 +1,20 @@
-use anyhow::Result;
+// `shadow-rs` generated code and `tonic::Status` results trip these lints.
+#![allow(clippy::empty_line_after_doc_comments, clippy::result_large_err)]
+
+use anyhow::{Context, Result};
+use attestation_service::audit::AuditLog;
+use attestation_service::nonce::{self, Nonces};
+use attestation_service::rvps::{LocalJsonStore, Rvps};
 use attestation_service::Service as AttestationService;
-use clap::{App, Arg};
+use attestation_service::{TokenAlgorithm, TokenSigner};
+use clap::{App, Arg, ArgMatches};
+use config::Config;
 use shadow_rs::shadow;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 use std::sync::Arc;
-use tokio::sync::RwLock;
-
-#[macro_use]
-extern crate lazy_static;
+use std::time::Duration;
+use tokio::signal::unix::{signal, SignalKind};
 
 pub mod management_api {
     tonic::include_proto!("management");
@@ -18,10 +25,25 @@ pub mod attestation_api {
 pub mod common {
     tonic::include_proto!("common");
 }
-
-lazy_static! {
-    pub static ref ATTESTATION_SERVICE: Arc<AttestationService> =
-        Arc::new(AttestationService::new());
+pub mod reference_api {
+    tonic::include_proto!("reference");
+}
+pub mod grpc {
+    pub mod health {
+        pub mod v1 {
+            tonic::include_proto!("grpc.health.v1");
+        }
+    }
+    pub mod reflection {
+        pub mod v1alpha {
+            tonic::include_proto!("grpc.reflection.v1alpha");
+        }
+    }
+}
+pub mod google {
+    pub mod rpc {
+        tonic::include_proto!("google.rpc");
+    }
 }
 
 #[macro_use]
@@ -29,15 +51,28 @@ extern crate log;
 shadow!(build);
 
 mod attestation;
+mod auth;
+mod config;
+mod error;
+mod health;
 mod management;
+mod metrics;
+mod reflection;
+mod reload;
+mod rvps;
+mod store;
+mod tls;
 mod user;
 
-const ATTESTATION_SERVER_WORKDIR: &str = "/opt/attestation-server";
+const RVPS_STORE_NAME: &str = "reference_values.json";
+const NONCE_STORE_NAME: &str = "nonces.json";
+const AUDIT_LOG_NAME: &str = "audit.log";
+// The connections still open once the listeners stop, e.g. the AttestationStream sessions, are
+// closed after the timeout.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    env_logger::init();
-
     let version = format!(
         "\nv{}\ncommit: {}\nbuildtime: {}",
         build::PKG_VERSION,
@@ -49,6 +84,18 @@ async fn main() -> Result<()> {
         .version(version.as_str())
         .long_version(version.as_str())
         .author("Confidential-Containers Team")
+        .arg(
+            Arg::with_name("config")
+                .long("config")
+                .value_name("CONFIG")
+                .help("TOML config file, or JSON if its extension is `.json`. The `AS_*` environment variables override its settings, and the flags override both.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("print-default-config")
+                .long("print-default-config")
+                .help("Print the default config in TOML and exit."),
+        )
         .arg(
             Arg::with_name("attestation-sock")
                 .long("attestation-sock")
@@ -63,23 +110,429 @@ async fn main() -> Result<()> {
                 .help("Socket that the server will listen on to accept management requests.")
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("metrics-sock")
+                .long("metrics-sock")
+                .value_name("METRICS_SOCK")
+                .help("Socket that the server will serve the Prometheus metrics on at `/metrics`, they aren't served if absent.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("token-key")
+                .long("token-key")
+                .value_name("TOKEN_KEY")
+                .help("PEM file of the ES256/RS256 private key which signs the JWT attestation results, a key is generated at startup if absent.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("token-alg")
+                .long("token-alg")
+                .value_name("TOKEN_ALG")
+                .help("Algorithm of the generated token signing key, ES256 by default.")
+                .possible_values(["ES256", "RS256"])
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("rvps-trusted-key")
+                .long("rvps-trusted-key")
+                .value_name("RVPS_TRUSTED_KEY")
+                .help("PEM file of the P-256 public key which verifies the provenances registered to RVPS, can be specified multiple times.")
+                .takes_value(true)
+                .multiple_occurrences(true),
+        )
+        .arg(
+            Arg::with_name("policy-engine")
+                .long("policy-engine")
+                .value_name("POLICY_ENGINE")
+                .help("Policy engine which evaluates the TCB claims, OPA if it's enabled by default.")
+                .possible_values(["opa", "regorus"])
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("policy-store")
+                .long("policy-store")
+                .value_name("POLICY_STORE")
+                .help("Store of the users' policies, reference data and their versions, `fs` by default. The replicas sharing a workdir must use `fs` or `sqlite`, `memory` loses them on exit.")
+                .possible_values(["fs", "sqlite", "memory"])
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("nonce-store")
+                .long("nonce-store")
+                .value_name("NONCE_STORE")
+                .help("Store of the nonces issued by the Challenge endpoint, the evidence whose nonce wasn't issued, has expired or was already consumed is rejected. The nonces aren't checked if absent.")
+                .possible_values(["memory", "file"])
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("nonce-ttl")
+                .long("nonce-ttl")
+                .value_name("NONCE_TTL")
+                .help("Seconds before the issued nonces expire, 300 by default.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("batch-parallelism")
+                .long("batch-parallelism")
+                .value_name("BATCH_PARALLELISM")
+                .help("Number of the evidences of a BatchAttestation request which are attested concurrently, 16 by default.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("stream-interval")
+                .long("stream-interval")
+                .value_name("STREAM_INTERVAL")
+                .help("Seconds between the nonces pushed to the AttestationStream clients, their last evidences are re-evaluated meanwhile, 60 by default.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("tls-cert")
+                .long("tls-cert")
+                .value_name("TLS_CERT")
+                .help("PEM file of the certificate chain which the attestation and management sockets serve TLS with, they're plaintext if absent.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("tls-key")
+                .long("tls-key")
+                .value_name("TLS_KEY")
+                .help("PEM file of the private key of --tls-cert.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("client-ca")
+                .long("client-ca")
+                .value_name("CLIENT_CA")
+                .help("PEM file of the CA certificate which issues the management clients' certificates, the management socket requires it (mTLS) unless --no-management-mtls.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("no-management-mtls")
+                .long("no-management-mtls")
+                .help("Serve the management socket over TLS without verifying the client certificates.")
+                .conflicts_with("client-ca"),
+        )
+        .arg(
+            Arg::with_name("management-credentials")
+                .long("management-credentials")
+                .value_name("MANAGEMENT_CREDENTIALS")
+                .help("JSON file of the management clients' bearer token or client certificate digests and their per-tenant roles, which requires --tls-cert.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("insecure-no-auth")
+                .long("insecure-no-auth")
+                .help("Allow the management requests without --management-credentials, which is only to test/demo.")
+                .conflicts_with("management-credentials"),
+        )
+        .arg(
+            Arg::with_name("audit-log")
+                .long("audit-log")
+                .value_name("AUDIT_LOG")
+                .help("JSON lines file of the hash-chained audit log of the management changes and the attestation decisions, `audit/audit.log` of the workdir if absent.")
+                .takes_value(true),
+        )
         .get_matches();
 
-    // Create a default User.
-    let user = Arc::new(RwLock::new(user::User::default()));
-    let workdir = Path::new(ATTESTATION_SERVER_WORKDIR).to_owned();
+    if matches.is_present("print-default-config") {
+        print!("{}", Config::default_toml()?);
+        return Ok(());
+    }
+    let config_file = match matches.value_of("config") {
+        Some(path) => Some(std::env::current_dir()?.join(path)),
+        None => None,
+    };
+    let load_config: reload::ConfigLoader = {
+        let config_file = config_file.clone();
+        Box::new(move || {
+            let config = match &config_file {
+                Some(path) => Config::from_file(path)?,
+                None => Config::default(),
+            };
+            let mut config = config.override_env(&|name| std::env::var(name).ok())?;
+            override_args(&mut config, &matches)?;
+            config.validate()?;
+            Ok(config)
+        })
+    };
+    let config = load_config()?;
+
+    // The dependencies are filtered at the configured log level, the server's own logs are
+    // filtered by the max level, which the reloaded config can raise.
+    let filter = format!(
+        "{},attestation_server=trace,attestation_service=trace",
+        config.log_level
+    );
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or(filter)).init();
+    reload::apply_log_level(&config)?;
+
+    let signer = match &config.token.key {
+        Some(path) => TokenSigner::from_pem_file(path)?,
+        None => TokenSigner::generate(TokenAlgorithm::from_str(&config.token.alg)?)?,
+    };
+    info!(
+        "Token signing key: {} ({})",
+        signer.kid(),
+        signer.algorithm().as_ref()
+    );
+
+    let tls = &config.tls;
+    let (attestation_tls, management_tls) = match (&tls.cert, &tls.key) {
+        (Some(cert), Some(key)) => (
+            Some(tls::server_config(cert, key, None)?),
+            Some(tls::server_config(cert, key, tls.client_ca.as_deref())?),
+        ),
+        _ => {
+            warn!("TLS is disabled, specify the TLS certificate and key to enable it");
+            (None, None)
+        }
+    };
+
+    let credentials = match &config.management.credentials {
+        Some(path) => Some(Arc::new(auth::Credentials::from_file(path)?)),
+        None => {
+            warn!("The management requests aren't authenticated, which is only to test/demo");
+            None
+        }
+    };
+
+    let workdir = std::env::current_dir()?.join(&config.workdir);
+    info!("Workdir: {:?}", workdir);
+    let policy_store = store::StoreType::from_str(&config.policy.store)?;
+    info!("Policy store: {:?}", policy_store);
+    // Only the `fs` store is watched, the policies of the others aren't cached.
+    let watch = config.watch && matches!(policy_store, store::StoreType::Fs);
+    let users = Arc::new(user::Users::new(policy_store.open(&workdir)?, watch));
 
+    let audit_log = match &config.management.audit_log {
+        Some(path) => path.clone(),
+        None => workdir.join("audit").join(AUDIT_LOG_NAME),
+    };
+    info!("Audit log: {:?}", audit_log);
+    let audit = Arc::new(AuditLog::open(audit_log).await?);
+
+    let trusted_keys: Vec<&Path> = config
+        .rvps
+        .trusted_keys
+        .iter()
+        .map(PathBuf::as_path)
+        .collect();
+    let mut rvps = Rvps::new(
+        Box::new(LocalJsonStore::new(
+            workdir.join("rvps").join(RVPS_STORE_NAME),
+        )),
+        Rvps::load_trusted_keys(&trusted_keys)?,
+    );
+    if config.rvps.allow_unsigned_sample {
+        warn!("RVPS accepts the unsigned sample provenances, which is only to test/demo");
+        rvps = rvps.allow_unsigned_sample();
+    }
+    let rvps = Arc::new(rvps);
+    let policy_engine = config.policy_engine()?;
+    info!(
+        "Policy engine: {} (cache size {})",
+        policy_engine.as_ref(),
+        config.policy.cache_size
+    );
+    info!("Verifiers: {}", config.verifiers.join(", "));
+    let metrics = Arc::new(metrics::Metrics::new()?);
+    let mut builder = AttestationService::builder()
+        .enable_verifiers(&config.verifiers)?
+        .metrics(metrics.attestation.clone())
+        .rvps(rvps.clone())
+        .policy_engine(policy_engine.to_engine_with_cache_size(config.policy.cache_size)?);
+    if let Some(store) = &config.nonce.store {
+        let store: Box<dyn nonce::Store> = match store.as_str() {
+            "file" => Box::new(nonce::LocalJsonStore::new(
+                workdir.join("nonces").join(NONCE_STORE_NAME),
+            )),
+            _ => Box::new(nonce::MemoryStore::default()),
+        };
+        let ttl = Duration::from_secs(config.nonce.ttl);
+        info!("Nonce TTL: {}s", ttl.as_secs());
+        builder = builder.nonces(Arc::new(Nonces::new(store, ttl)));
+    }
+    let attestation_service = Arc::new(builder.build());
+
+    let reloader = Arc::new(reload::Reloader::new(
+        users.clone(),
+        attestation_service.clone(),
+        watch.then(|| workdir.join(store::USERS_DIR)),
+        config_file.filter(|_| config.watch),
+        load_config,
+        config.clone(),
+        credentials.clone(),
+    ));
+    // Validate the stored policies, the invalid ones are logged.
+    reloader.reload_users().await;
+
+    // The listeners report NOT_SERVING until the self-test passes.
+    let health = health::Health::new();
     let attestation_server = attestation::start_service(
-        matches.value_of("attestation-sock"),
-        user.clone(),
-        workdir.clone(),
+        config.attestation_addr()?,
+        attestation::Service::new(
+            users.clone(),
+            attestation_service.clone(),
+            Arc::new(signer),
+            config.attestation.batch_parallelism,
+            Duration::from_secs(config.attestation.stream_interval),
+            Some(audit.clone()),
+        ),
+        attestation_tls,
+        metrics.attestation_requests.clone(),
+        health.clone(),
     );
     let management_server = management::start_service(
-        matches.value_of("management-sock"),
-        user.clone(),
-        workdir.clone(),
+        config.management_addr()?,
+        management::Service::new(
+            users.clone(),
+            attestation_service.clone(),
+            credentials,
+            Some(audit),
+        ),
+        rvps,
+        management_tls,
+        metrics.management_operations.clone(),
+        health.clone(),
     );
-    tokio::try_join!(attestation_server, management_server)?;
+    let metrics_addr = config.metrics_addr()?;
+    let metrics_server = async {
+        match metrics_addr {
+            Some(socket) => {
+                metrics::start_service(socket, metrics.clone(), users.clone(), health.stopped())
+                    .await
+            }
+            None => Ok(()),
+        }
+    };
+    let reload = async {
+        tokio::select! {
+            res = reloader.run() => res,
+            _ = health.stopped() => Ok(()),
+        }
+    };
+    let ready = async {
+        match attestation_service.self_test().await {
+            Ok(()) => {
+                info!("Self-test passed, serving");
+                health.serving();
+            }
+            Err(e) => error!(
+                "Self-test failed, the listeners report NOT_SERVING: {:#}",
+                e
+            ),
+        }
+        Ok(())
+    };
+    let shutdown = async {
+        let mut terminate = signal(SignalKind::terminate())?;
+        tokio::select! {
+            _ = terminate.recv() => (),
+            _ = tokio::signal::ctrl_c() => (),
+        }
+        info!(
+            "Shutting down, the listeners report NOT_SERVING for {}s before they stop",
+            config.shutdown_delay
+        );
+        health.drain();
+        tokio::time::sleep(Duration::from_secs(config.shutdown_delay)).await;
+        health.stop();
+        Ok(())
+    };
+    let servers = async {
+        tokio::try_join!(
+            attestation_server,
+            management_server,
+            metrics_server,
+            reload,
+            ready,
+            shutdown
+        )
+    };
+    tokio::select! {
+        res = servers => {
+            res?;
+        }
+        _ = async {
+            health.stopped().await;
+            tokio::time::sleep(SHUTDOWN_TIMEOUT).await
+        } => warn!("Closed the connections still open after {}s", SHUTDOWN_TIMEOUT.as_secs()),
+    }
+    info!("Stopped");
+
+    Ok(())
+}
 
+// Override the config by the flags which are present.
+fn override_args(config: &mut Config, matches: &ArgMatches) -> Result<()> {
+    let value = |name| matches.value_of(name).map(str::to_owned);
+    let path = |name| matches.value_of(name).map(PathBuf::from);
+    let number = |name| -> Result<Option<u64>> {
+        match matches.value_of(name) {
+            Some(value) => {
+                Ok(Some(value.parse().with_context(|| {
+                    format!("Illegal --{} {:?}", name, value)
+                })?))
+            }
+            None => Ok(None),
+        }
+    };
+    if let Some(sock) = value("attestation-sock") {
+        config.attestation_sock = sock;
+    }
+    if let Some(sock) = value("management-sock") {
+        config.management_sock = sock;
+    }
+    if let Some(sock) = value("metrics-sock") {
+        config.metrics_sock = Some(sock);
+    }
+    if let Some(key) = path("token-key") {
+        config.token.key = Some(key);
+    }
+    if let Some(alg) = value("token-alg") {
+        config.token.alg = alg;
+    }
+    if let Some(keys) = matches.values_of("rvps-trusted-key") {
+        config.rvps.trusted_keys = keys.map(PathBuf::from).collect();
+    }
+    if let Some(engine) = value("policy-engine") {
+        config.policy.engine = engine;
+    }
+    if let Some(store) = value("policy-store") {
+        config.policy.store = store;
+    }
+    if let Some(store) = value("nonce-store") {
+        config.nonce.store = Some(store);
+    }
+    if let Some(ttl) = number("nonce-ttl")? {
+        config.nonce.ttl = ttl;
+    }
+    if let Some(parallelism) = number("batch-parallelism")? {
+        config.attestation.batch_parallelism = parallelism as usize;
+    }
+    if let Some(interval) = number("stream-interval")? {
+        config.attestation.stream_interval = interval;
+    }
+    if let Some(cert) = path("tls-cert") {
+        config.tls.cert = Some(cert);
+    }
+    if let Some(key) = path("tls-key") {
+        config.tls.key = Some(key);
+    }
+    if let Some(client_ca) = path("client-ca") {
+        config.tls.client_ca = Some(client_ca);
+    }
+    if matches.is_present("no-management-mtls") {
+        config.tls.management_mtls = false;
+    }
+    if let Some(credentials) = path("management-credentials") {
+        config.management.credentials = Some(credentials);
+    }
+    if matches.is_present("insecure-no-auth") {
+        config.management.insecure_no_auth = true;
+    }
+    if let Some(audit_log) = path("audit-log") {
+        config.management.audit_log = Some(audit_log);
+    }
     Ok(())
 }
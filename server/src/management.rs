@@ -1,37 +1,150 @@
+use crate::auth::{self, Credentials, Role, ALL_TENANTS};
+use crate::common;
+use crate::error;
+use crate::health::Health;
+use crate::metrics::GrpcMetricsLayer;
+use crate::reference_api::reference_value_provider_service_server::ReferenceValueProviderServiceServer;
+use crate::reflection;
+use crate::rvps;
+use crate::tls;
 use crate::user;
-use crate::ATTESTATION_SERVICE;
 use anyhow::Result;
-use std::path::PathBuf;
+use attestation_service::audit::{self, AuditLog, Event};
+use attestation_service::rvps::Rvps;
+use attestation_service::Service as AttestationService;
+use attestation_service::TEE;
+use std::net::SocketAddr;
 use std::sync::Arc;
 use tokio::sync::RwLock;
-use tonic::transport::Server;
-use tonic::{Request, Response, Status};
+use tonic::transport::{NamedService, ServerTlsConfig};
+use tonic::{Code, Request, Response, Status};
 
 use crate::management_api::management_service_server::{
     ManagementService, ManagementServiceServer,
 };
 use crate::management_api::{
-    GetPolicyRequest, GetPolicyResponse, GetReferenceDataRequest, GetReferenceDataResponse,
-    RestoreDefaultPolicyRequest, RestoreDefaultPolicyResponse, RestoreDefaultReferenceDataRequest,
-    RestoreDefaultReferenceDataResponse, SetPolicyRequest, SetPolicyResponse,
-    SetReferenceDataRequest, SetReferenceDataResponse,
+    CreateUserRequest, CreateUserResponse, DeleteUserRequest, DeleteUserResponse,
+    DiffVersionsRequest, DiffVersionsResponse, GetPolicyRequest, GetPolicyResponse,
+    GetReferenceDataRequest, GetReferenceDataResponse, ListUsersRequest, ListUsersResponse,
+    ListVersionsRequest, ListVersionsResponse, RestoreDefaultPolicyRequest,
+    RestoreDefaultPolicyResponse, RestoreDefaultReferenceDataRequest,
+    RestoreDefaultReferenceDataResponse, RollbackRequest, RollbackResponse, SetPolicyRequest,
+    SetPolicyResponse, SetReferenceDataRequest, SetReferenceDataResponse, Version, VersionKind,
 };
 
-const DEFAULT_MANAGEMENT_SOCK: &str = "127.0.0.1:3001";
-
 #[derive(Debug)]
 pub struct Service {
-    user: Arc<RwLock<user::User>>,
-    workdir: PathBuf,
+    users: Arc<user::Users>,
+    attestation_service: Arc<AttestationService>,
+    // Authenticate the requests by the interceptor, which must be authorized then.
+    credentials: Option<Arc<Credentials>>,
+    // Record the changes.
+    audit: Option<Arc<AuditLog>>,
 }
 
 impl Service {
-    pub fn new(usr: Arc<RwLock<user::User>>, dir: PathBuf) -> Self {
+    pub fn new(
+        users: Arc<user::Users>,
+        attestation_service: Arc<AttestationService>,
+        credentials: Option<Arc<Credentials>>,
+        audit: Option<Arc<AuditLog>>,
+    ) -> Self {
         Self {
-            user: usr,
-            workdir: dir,
+            users,
+            attestation_service,
+            credentials,
+            audit,
         }
     }
+
+    // Record the change of `principal` in the audit log, along with the hashes of the replaced
+    // and the new contents.
+    async fn audit(
+        &self,
+        principal: String,
+        action: &str,
+        user: Option<&common::User>,
+        tee: Option<&str>,
+        old: Option<&str>,
+        new: Option<&str>,
+    ) -> Result<(), Status> {
+        let audit = match &self.audit {
+            Some(audit) => audit,
+            None => return Ok(()),
+        };
+        let event = Event::Management {
+            principal,
+            action: action.to_string(),
+            tenant: auth::tenant(user).to_string(),
+            tee: tee.map(str::to_string),
+            old_sha256: old.map(|old| audit::sha256(old.as_bytes())),
+            new_sha256: new.map(|new| audit::sha256(new.as_bytes())),
+        };
+        audit
+            .append(event)
+            .await
+            .map(|_| ())
+            .map_err(|e| error::status("Audit", e, Code::Internal))
+    }
+
+    // Authorize the request's principal as `role` of the tenant `user`.
+    fn authorize<T>(
+        &self,
+        request: &Request<T>,
+        user: Option<&common::User>,
+        role: Role,
+    ) -> Result<(), Status> {
+        auth::authorize(
+            request,
+            self.credentials.is_some(),
+            auth::tenant(user),
+            role,
+        )
+    }
+
+    // Resolve the request's user.
+    async fn user(&self, user: Option<&common::User>) -> Result<Arc<RwLock<user::User>>, Status> {
+        self.users
+            .get(user)
+            .await
+            .map_err(|e| error::lookup_status("User", e, Code::InvalidArgument))
+    }
+}
+
+// The name of the request's TEE, `None` designates the TEE-agnostic policy and reference data.
+fn tee_name(tee: Option<i32>) -> Result<Option<String>, Status> {
+    let tee = match tee {
+        Some(tee) => {
+            common::Tee::from_i32(tee).ok_or_else(|| Status::invalid_argument("Unsupported TEE"))?
+        }
+        None => return Ok(None),
+    };
+    let tee = match tee {
+        common::Tee::Sgx => TEE::SGX,
+        common::Tee::Tdx => TEE::TDX,
+        common::Tee::SevSnp => TEE::SEVSNP,
+        common::Tee::Sample => TEE::SAMPLE,
+    };
+    Ok(Some(tee.as_ref().to_owned()))
+}
+
+// The versioned content of the request.
+fn version_kind(kind: i32) -> Result<user::Kind, Status> {
+    match VersionKind::from_i32(kind) {
+        Some(VersionKind::Policy) => Ok(user::Kind::Policy),
+        Some(VersionKind::ReferenceData) => Ok(user::Kind::ReferenceData),
+        None => Err(Status::invalid_argument("Unsupported version kind")),
+    }
+}
+
+// The version's message, without its content.
+fn proto_version(version: &user::Version) -> Version {
+    Version {
+        id: version.id,
+        time: version.time,
+        author: version.author.clone(),
+        sha256: version.sha256.clone(),
+    }
 }
 
 #[tonic::async_trait]
@@ -40,29 +153,47 @@ impl ManagementService for Service {
         &self,
         request: Request<SetPolicyRequest>,
     ) -> Result<Response<SetPolicyResponse>, Status> {
+        self.authorize(&request, request.get_ref().user.as_ref(), Role::Admin)?;
+        let principal = auth::principal(&request);
         let request: SetPolicyRequest = request.into_inner();
+        let tee = tee_name(request.tee)?;
         let policy = std::str::from_utf8(&request.content)
             .map_err(|e| Status::invalid_argument(format!("Parse content: {}", e)))?
             .to_owned();
         debug!("Policy: {}", &policy);
 
-        let attestation_service = Arc::clone(&ATTESTATION_SERVICE);
-
         // Check new policy's syntax
-        attestation_service
-            .opa_test(policy.clone(), "{}".to_string(), "{}".to_string())
-            .map_err(|e| Status::aborted(format!("Syntax: {}", e)))?;
+        self.attestation_service
+            .check_policy(&policy)
+            .map_err(|e| error::status("Syntax", e, Code::Aborted))?;
 
-        let user: &Arc<RwLock<user::User>> = request.user.map_or_else(
-            || Ok(&self.user),
-            |_user| Err(Status::invalid_argument("Multiple user is not supported")),
-        )?;
+        let user = self.user(request.user.as_ref()).await?;
+        let user = user.write().await;
 
-        user.write()
-            .await
-            .set_policy(self.workdir.as_path(), policy)
+        let replaced = user
+            .policy(tee.as_deref())
             .await
-            .map_err(|e| Status::aborted(format!("Failure: {}", e)))?;
+            .map_err(|e| error::status("Failure", e, Code::Aborted))?;
+        user.update(
+            tee.as_deref(),
+            user::Kind::Policy,
+            &principal,
+            Some(policy.clone()),
+        )
+        .await
+        .map_err(|e| error::status("Failure", e, Code::Aborted))?;
+        if let Some(replaced) = &replaced {
+            self.attestation_service.invalidate_policy(replaced);
+        }
+        self.audit(
+            principal,
+            "set_policy",
+            request.user.as_ref(),
+            tee.as_deref(),
+            replaced.as_deref(),
+            Some(&policy),
+        )
+        .await?;
 
         Ok(Response::new(SetPolicyResponse {}))
     }
@@ -71,21 +202,38 @@ impl ManagementService for Service {
         &self,
         request: Request<SetReferenceDataRequest>,
     ) -> Result<Response<SetReferenceDataResponse>, Status> {
+        self.authorize(&request, request.get_ref().user.as_ref(), Role::Admin)?;
+        let principal = auth::principal(&request);
         let request: SetReferenceDataRequest = request.into_inner();
+        let tee = tee_name(request.tee)?;
         let reference_data = std::str::from_utf8(&request.content)
             .map_err(|e| Status::invalid_argument(format!("Parse content: {}", e)))?;
         debug!("Reference data:\n{}", reference_data);
 
-        let user: &Arc<RwLock<user::User>> = request.user.map_or_else(
-            || Ok(&self.user),
-            |_user| Err(Status::invalid_argument("Multiple user is not supported")),
-        )?;
+        let user = self.user(request.user.as_ref()).await?;
+        let user = user.write().await;
 
-        user.write()
-            .await
-            .set_reference_data(self.workdir.as_path(), reference_data.to_owned())
+        let replaced = user
+            .reference_data(tee.as_deref())
             .await
-            .map_err(|e| Status::aborted(format!("Failure: {}", e)))?;
+            .map_err(|e| error::status("Failure", e, Code::Aborted))?;
+        user.update(
+            tee.as_deref(),
+            user::Kind::ReferenceData,
+            &principal,
+            Some(reference_data.to_owned()),
+        )
+        .await
+        .map_err(|e| error::status("Failure", e, Code::Aborted))?;
+        self.audit(
+            principal,
+            "set_reference_data",
+            request.user.as_ref(),
+            tee.as_deref(),
+            replaced.as_deref(),
+            Some(reference_data),
+        )
+        .await?;
 
         Ok(Response::new(SetReferenceDataResponse {}))
     }
@@ -94,27 +242,30 @@ impl ManagementService for Service {
         &self,
         request: Request<GetPolicyRequest>,
     ) -> Result<Response<GetPolicyResponse>, Status> {
+        self.authorize(&request, request.get_ref().user.as_ref(), Role::Auditor)?;
         let request: GetPolicyRequest = request.into_inner();
+        let tee = tee_name(request.tee)?;
 
-        let user: &Arc<RwLock<user::User>> = request.user.map_or_else(
-            || Ok(&self.user),
-            |_user| Err(Status::invalid_argument("Multiple user is not supported")),
-        )?;
+        let user = self.user(request.user.as_ref()).await?;
+        let user = user.read().await;
 
-        let policy = user
-            .read()
+        // The TEE's policy falls back to the TEE-agnostic one, then its built-in default one.
+        let mut policy = user
+            .policy(tee.as_deref())
             .await
-            .policy(self.workdir.as_path())
-            .await
-            .map_err(|e| Status::invalid_argument(format!("Get policy: {}", e)))?;
-        let policy = match policy {
-            Some(policy) => Ok(policy),
-            None => {
-                let attestation_service = Arc::clone(&ATTESTATION_SERVICE);
-                attestation_service.default_policy().await
-            }
+            .map_err(|e| error::status("Get policy", e, Code::Aborted))?;
+        if policy.is_none() && tee.is_some() {
+            policy = user
+                .policy(None)
+                .await
+                .map_err(|e| error::status("Get policy", e, Code::Aborted))?;
+        }
+        let policy = match (policy, tee) {
+            (Some(policy), _) => Ok(policy),
+            (None, Some(tee)) => self.attestation_service.tee_default_policy(&tee).await,
+            (None, None) => self.attestation_service.default_policy().await,
         }
-        .map_err(|e| Status::aborted(format!("Get policy: {}", e)))?;
+        .map_err(|e| error::status("Get policy", e, Code::Aborted))?;
         debug!("Policy: {}", policy);
         let res = GetPolicyResponse {
             content: policy.into_bytes(),
@@ -127,19 +278,24 @@ impl ManagementService for Service {
         &self,
         request: Request<GetReferenceDataRequest>,
     ) -> Result<Response<GetReferenceDataResponse>, Status> {
+        self.authorize(&request, request.get_ref().user.as_ref(), Role::Auditor)?;
         let request: GetReferenceDataRequest = request.into_inner();
+        let tee = tee_name(request.tee)?;
 
-        let user: &Arc<RwLock<user::User>> = request.user.map_or_else(
-            || Ok(&self.user),
-            |_user| Err(Status::invalid_argument("Multiple user is not supported")),
-        )?;
+        let user = self.user(request.user.as_ref()).await?;
+        let user = user.read().await;
 
-        let reference_data = user
-            .read()
-            .await
-            .reference_data(self.workdir.as_path())
+        // The TEE's reference data falls back to the TEE-agnostic one.
+        let mut reference_data = user
+            .reference_data(tee.as_deref())
             .await
-            .map_err(|e| Status::invalid_argument(format!("Get reference data: {}", e)))?;
+            .map_err(|e| error::status("Get reference data", e, Code::Aborted))?;
+        if reference_data.is_none() && tee.is_some() {
+            reference_data = user
+                .reference_data(None)
+                .await
+                .map_err(|e| error::status("Get reference data", e, Code::Aborted))?;
+        }
 
         let reference_data = reference_data.unwrap_or_else(|| "".to_string());
         debug!("Reference: {}", reference_data);
@@ -155,18 +311,33 @@ impl ManagementService for Service {
         &self,
         request: Request<RestoreDefaultPolicyRequest>,
     ) -> Result<Response<RestoreDefaultPolicyResponse>, Status> {
+        self.authorize(&request, request.get_ref().user.as_ref(), Role::Admin)?;
+        let principal = auth::principal(&request);
         let request: RestoreDefaultPolicyRequest = request.into_inner();
+        let tee = tee_name(request.tee)?;
 
-        let user: &Arc<RwLock<user::User>> = request.user.map_or_else(
-            || Ok(&self.user),
-            |_user| Err(Status::invalid_argument("Multiple user is not supported")),
-        )?;
+        let user = self.user(request.user.as_ref()).await?;
+        let user = user.write().await;
 
-        user.write()
+        let deleted = user
+            .policy(tee.as_deref())
             .await
-            .delete_policy(self.workdir.as_path())
+            .map_err(|e| error::status("Failure", e, Code::Aborted))?;
+        user.update(tee.as_deref(), user::Kind::Policy, &principal, None)
             .await
-            .map_err(|e| Status::aborted(format!("Failure: {}", e)))?;
+            .map_err(|e| error::status("Failure", e, Code::Aborted))?;
+        if let Some(deleted) = &deleted {
+            self.attestation_service.invalidate_policy(deleted);
+        }
+        self.audit(
+            principal,
+            "restore_default_policy",
+            request.user.as_ref(),
+            tee.as_deref(),
+            deleted.as_deref(),
+            None,
+        )
+        .await?;
 
         Ok(Response::new(RestoreDefaultPolicyResponse {}))
     }
@@ -175,34 +346,237 @@ impl ManagementService for Service {
         &self,
         request: Request<RestoreDefaultReferenceDataRequest>,
     ) -> Result<Response<RestoreDefaultReferenceDataResponse>, Status> {
+        self.authorize(&request, request.get_ref().user.as_ref(), Role::Admin)?;
+        let principal = auth::principal(&request);
         let request: RestoreDefaultReferenceDataRequest = request.into_inner();
+        let tee = tee_name(request.tee)?;
 
-        let user: &Arc<RwLock<user::User>> = request.user.map_or_else(
-            || Ok(&self.user),
-            |_user| Err(Status::invalid_argument("Multiple user is not supported")),
-        )?;
+        let user = self.user(request.user.as_ref()).await?;
+        let user = user.write().await;
 
-        user.write()
+        let deleted = user
+            .reference_data(tee.as_deref())
             .await
-            .delete_reference_data(self.workdir.as_path())
+            .map_err(|e| error::status("Failure", e, Code::Aborted))?;
+        user.update(tee.as_deref(), user::Kind::ReferenceData, &principal, None)
             .await
-            .map_err(|e| Status::aborted(format!("Failure: {}", e)))?;
+            .map_err(|e| error::status("Failure", e, Code::Aborted))?;
+        self.audit(
+            principal,
+            "restore_default_reference_data",
+            request.user.as_ref(),
+            tee.as_deref(),
+            deleted.as_deref(),
+            None,
+        )
+        .await?;
 
         Ok(Response::new(RestoreDefaultReferenceDataResponse {}))
     }
+
+    async fn create_user(
+        &self,
+        request: Request<CreateUserRequest>,
+    ) -> Result<Response<CreateUserResponse>, Status> {
+        auth::authorize(
+            &request,
+            self.credentials.is_some(),
+            ALL_TENANTS,
+            Role::Admin,
+        )?;
+        let principal = auth::principal(&request);
+        let request: CreateUserRequest = request.into_inner();
+        let user = request
+            .user
+            .ok_or_else(|| Status::invalid_argument("User is required"))?;
+
+        self.users
+            .create(&user.id)
+            .await
+            .map_err(|e| error::status("Create user", e, Code::AlreadyExists))?;
+        self.audit(principal, "create_user", Some(&user), None, None, None)
+            .await?;
+
+        Ok(Response::new(CreateUserResponse {}))
+    }
+
+    async fn delete_user(
+        &self,
+        request: Request<DeleteUserRequest>,
+    ) -> Result<Response<DeleteUserResponse>, Status> {
+        auth::authorize(
+            &request,
+            self.credentials.is_some(),
+            ALL_TENANTS,
+            Role::Admin,
+        )?;
+        let principal = auth::principal(&request);
+        let request: DeleteUserRequest = request.into_inner();
+        let user = request
+            .user
+            .ok_or_else(|| Status::invalid_argument("User is required"))?;
+
+        self.users
+            .delete(&user.id)
+            .await
+            .map_err(|e| error::status("Delete user", e, Code::FailedPrecondition))?;
+        self.audit(principal, "delete_user", Some(&user), None, None, None)
+            .await?;
+
+        Ok(Response::new(DeleteUserResponse {}))
+    }
+
+    async fn list_users(
+        &self,
+        request: Request<ListUsersRequest>,
+    ) -> Result<Response<ListUsersResponse>, Status> {
+        auth::authorize(
+            &request,
+            self.credentials.is_some(),
+            ALL_TENANTS,
+            Role::Auditor,
+        )?;
+        let users = self
+            .users
+            .list()
+            .await
+            .map_err(|e| error::status("List users", e, Code::Aborted))?
+            .into_iter()
+            .map(|id| common::User { id })
+            .collect();
+
+        Ok(Response::new(ListUsersResponse { users }))
+    }
+
+    async fn list_versions(
+        &self,
+        request: Request<ListVersionsRequest>,
+    ) -> Result<Response<ListVersionsResponse>, Status> {
+        self.authorize(&request, request.get_ref().user.as_ref(), Role::Auditor)?;
+        let request: ListVersionsRequest = request.into_inner();
+        let tee = tee_name(request.tee)?;
+        let kind = version_kind(request.kind)?;
+
+        let user = self.user(request.user.as_ref()).await?;
+        let versions = user
+            .read()
+            .await
+            .versions(tee.as_deref(), kind)
+            .await
+            .map_err(|e| error::status("List versions", e, Code::Aborted))?
+            .iter()
+            .map(proto_version)
+            .collect();
+
+        Ok(Response::new(ListVersionsResponse { versions }))
+    }
+
+    async fn diff_versions(
+        &self,
+        request: Request<DiffVersionsRequest>,
+    ) -> Result<Response<DiffVersionsResponse>, Status> {
+        self.authorize(&request, request.get_ref().user.as_ref(), Role::Auditor)?;
+        let request: DiffVersionsRequest = request.into_inner();
+        let tee = tee_name(request.tee)?;
+        let kind = version_kind(request.kind)?;
+
+        let user = self.user(request.user.as_ref()).await?;
+        let user = user.read().await;
+        let mut versions = Vec::new();
+        for id in [request.from, request.to] {
+            let version = user
+                .version(tee.as_deref(), kind, id)
+                .await
+                .map_err(|e| error::lookup_status("Get version", e, Code::Aborted))?;
+            versions.push(version);
+        }
+        let (from, to) = (&versions[0].content, &versions[1].content);
+        let diff = similar::TextDiff::from_lines(
+            from.as_deref().unwrap_or(""),
+            to.as_deref().unwrap_or(""),
+        )
+        .unified_diff()
+        .header(
+            &format!("{} version {}", kind.name(), request.from),
+            &format!("{} version {}", kind.name(), request.to),
+        )
+        .to_string();
+
+        Ok(Response::new(DiffVersionsResponse { diff }))
+    }
+
+    async fn rollback(
+        &self,
+        request: Request<RollbackRequest>,
+    ) -> Result<Response<RollbackResponse>, Status> {
+        self.authorize(&request, request.get_ref().user.as_ref(), Role::Admin)?;
+        let principal = auth::principal(&request);
+        let request: RollbackRequest = request.into_inner();
+        let tee = tee_name(request.tee)?;
+        let kind = version_kind(request.kind)?;
+
+        let user = self.user(request.user.as_ref()).await?;
+        let user = user.write().await;
+        user.version(tee.as_deref(), kind, request.version)
+            .await
+            .map_err(|e| error::lookup_status("Get version", e, Code::Aborted))?;
+        let (version, replaced) = user
+            .rollback(tee.as_deref(), kind, request.version, &principal)
+            .await
+            .map_err(|e| error::status("Rollback", e, Code::Aborted))?;
+        if let (user::Kind::Policy, Some(replaced)) = (kind, &replaced) {
+            self.attestation_service.invalidate_policy(replaced);
+        }
+        self.audit(
+            principal,
+            &format!("rollback_{}", kind.name()),
+            request.user.as_ref(),
+            tee.as_deref(),
+            replaced.as_deref(),
+            version.content.as_deref(),
+        )
+        .await?;
+
+        Ok(Response::new(RollbackResponse {
+            version: Some(proto_version(&version)),
+        }))
+    }
 }
 
+/// Serve the management endpoint along with the RVPS one, which share the service's
+/// credentials and audit log.
 pub async fn start_service(
-    socket: Option<&str>,
-    usr: Arc<RwLock<user::User>>,
-    dir: PathBuf,
+    socket: SocketAddr,
+    service: Service,
+    rvps: Arc<Rvps>,
+    tls: Option<ServerTlsConfig>,
+    metrics: GrpcMetricsLayer,
+    health: Health,
 ) -> Result<()> {
-    let socket = socket.unwrap_or(DEFAULT_MANAGEMENT_SOCK).parse()?;
     debug!("Management listen socket: {}", &socket);
-    let service = Service::new(usr, dir);
-    Server::builder()
-        .add_service(ManagementServiceServer::new(service))
-        .serve(socket)
+    let services = [
+        <ManagementServiceServer<Service> as NamedService>::NAME,
+        <ReferenceValueProviderServiceServer<rvps::Service> as NamedService>::NAME,
+    ];
+    let interceptor = auth::interceptor(service.credentials.clone());
+    let rvps = rvps::server(
+        rvps,
+        service.credentials.is_some(),
+        service.audit.clone(),
+        interceptor.clone(),
+    );
+    // The health and reflection services aren't authenticated, so the probes and tools can
+    // reach them.
+    tls::server(tls)?
+        .layer(metrics)
+        .add_service(health.service(&services))
+        .add_service(reflection::server(&services)?)
+        .add_service(ManagementServiceServer::with_interceptor(
+            service,
+            interceptor,
+        ))
+        .add_service(rvps)
+        .serve_with_shutdown(socket, health.stopped())
         .await?;
     Ok(())
 }
@@ -211,10 +585,10 @@ pub async fn start_service(
 mod tests {
     use super::*;
     use crate::management_api::management_service_server::ManagementService;
+    use crate::store;
     use serde_json::{json, Value};
-    use std::path::Path;
+    use sha2::{Digest, Sha256};
     use std::sync::Arc;
-    use tokio::sync::RwLock;
     use uuid::Uuid;
 
     fn reference(ver: u64) -> String {
@@ -249,18 +623,25 @@ svn {
         policy.to_string()
     }
 
-    fn create_service(id: Option<&str>) -> (PathBuf, Service) {
-        let workdir = Path::new("./").to_owned();
-        let user = id.map_or_else(
-            || Arc::new(RwLock::new(user::User::default())),
-            |id| Arc::new(RwLock::new(user::User::from_str(id).unwrap())),
-        );
-        let service = Service::new(user.clone(), workdir.clone());
-        (workdir, service)
+    // Create the service along with the user `id`.
+    async fn create_service(id: Option<&str>) -> (Service, Option<common::User>) {
+        let users = Arc::new(user::Users::new(
+            Arc::new(store::MemoryStore::default()),
+            false,
+        ));
+        let user = match id {
+            Some(id) => {
+                users.create(id).await.unwrap();
+                Some(common::User { id: id.to_owned() })
+            }
+            None => None,
+        };
+        let service = Service::new(users, Arc::new(AttestationService::new()), None, None);
+        (service, user)
     }
 
-    async fn get_policy(service: &Service) -> GetPolicyResponse {
-        let request = GetPolicyRequest { user: None };
+    async fn get_policy(service: &Service, user: Option<common::User>) -> GetPolicyResponse {
+        let request = GetPolicyRequest { user, tee: None };
         let request = Request::new(request);
         let response = service.get_policy(request).await;
         assert!(response.is_ok(), "Get policy should success");
@@ -269,18 +650,20 @@ svn {
 
     async fn set_policy(
         service: &Service,
+        user: Option<common::User>,
         policy: String,
     ) -> Result<Response<SetPolicyResponse>, Status> {
         let request = SetPolicyRequest {
-            user: None,
+            user,
             content: policy.into_bytes(),
+            tee: None,
         };
         let request = Request::new(request);
         service.set_policy(request).await
     }
 
-    async fn restore_default_policy(service: &Service) {
-        let request = RestoreDefaultPolicyRequest { user: None };
+    async fn restore_default_policy(service: &Service, user: Option<common::User>) {
+        let request = RestoreDefaultPolicyRequest { user, tee: None };
         let request = Request::new(request);
         let response = service.restore_default_policy(request).await;
         assert!(response.is_ok(), "Reset policy should success");
@@ -289,38 +672,32 @@ svn {
     #[tokio::test]
     async fn test_xxx_policy() {
         let uuid = Uuid::new_v4().to_string();
-        let (workdir, service) = create_service(Some(&uuid));
+        let (service, user) = create_service(Some(&uuid)).await;
 
         // Set customized policy
-        let res = set_policy(&service, policy()).await;
+        let res = set_policy(&service, user.clone(), policy()).await;
         assert!(res.is_ok(), "Set policy should success");
 
         // Get the customized policy file.
-        let response = get_policy(&service).await;
+        let response = get_policy(&service, user.clone()).await;
         let content = std::str::from_utf8(&response.content);
         assert!(content.is_ok(), "Policy content should OK");
         assert!(
             content.unwrap() == policy(),
             "The customized policy should equal."
         );
-
-        let userdir = workdir.join("users").join(uuid);
-        if userdir.exists() {
-            // Delete the temporary user folder
-            std::fs::remove_dir_all(userdir).unwrap();
-        }
     }
 
     #[tokio::test]
     async fn test_restore_default_policy() {
         let uuid = Uuid::new_v4().to_string();
-        let (workdir, service) = create_service(Some(&uuid));
+        let (service, user) = create_service(Some(&uuid)).await;
 
         // Set customized policy
-        let res = set_policy(&service, policy()).await;
+        let res = set_policy(&service, user.clone(), policy()).await;
         assert!(res.is_ok(), "Set policy should success");
         // Get the customized policy file.
-        let response = get_policy(&service).await;
+        let response = get_policy(&service, user.clone()).await;
         let content = std::str::from_utf8(&response.content);
         assert!(content.is_ok(), "Policy content should OK");
         assert!(
@@ -329,22 +706,16 @@ svn {
         );
 
         // Restore the customized policy.
-        restore_default_policy(&service).await;
+        restore_default_policy(&service, user.clone()).await;
 
         // Get default policy
-        let response: GetPolicyResponse = get_policy(&service).await;
+        let response: GetPolicyResponse = get_policy(&service, user.clone()).await;
         let content = std::str::from_utf8(&response.content);
         assert!(content.is_ok(), "Policy content should OK");
         assert!(
-            content.unwrap().to_string() == default_policy(),
+            content.unwrap() == default_policy(),
             "The default policy should equal."
         );
-
-        let userdir = workdir.join("users").join(uuid);
-        if userdir.exists() {
-            // Delete the temporary user folder
-            std::fs::remove_dir_all(userdir).unwrap();
-        }
     }
 
     #[tokio::test]
@@ -355,21 +726,77 @@ default allow = false
 allow {
     cpusvn
 }"#;
-        let (_, service) = create_service(None);
+        let (service, user) = create_service(None).await;
 
-        let response = set_policy(&service, policy_illegal.to_string()).await;
+        let response = set_policy(&service, user.clone(), policy_illegal.to_string()).await;
         assert!(response.is_err(), "Set policy should not success");
         let status: Status = response.unwrap_err();
+        let error = match attestation_service::PolicyEngineType::default() {
+            attestation_service::PolicyEngineType::OPA => {
+                "policy.rego:5: rego_unsafe_var_error: var cpusvn is unsafe"
+            }
+            attestation_service::PolicyEngineType::REGORUS => "`cpusvn` is unsafe",
+        };
         assert!(
-            status
-                .message()
-                .contains("policy.rego:5: rego_unsafe_var_error: var cpusvn is unsafe"),
+            status.message().contains(error),
             "Should contains .rego error"
         );
+        assert_eq!(status.code(), tonic::Code::FailedPrecondition);
+        let info = error::error_info(&status).unwrap();
+        assert_eq!(info.reason, "POLICY_COMPILE");
+    }
+
+    #[tokio::test]
+    async fn test_storage_failure() {
+        // The store's directory is under a regular file, so its reads and writes fail.
+        let file = std::env::temp_dir().join(Uuid::new_v4().to_string());
+        std::fs::write(&file, "").unwrap();
+        let store = Arc::new(store::FsStore::new(file.join("users")));
+        let users = Arc::new(user::Users::new(store, false));
+        let service = Service::new(users, Arc::new(AttestationService::new()), None, None);
+
+        let team_a = common::User {
+            id: "team-a".to_owned(),
+        };
+        let statuses = [
+            set_policy(&service, None, policy()).await.unwrap_err(),
+            service
+                .get_policy(Request::new(GetPolicyRequest {
+                    user: None,
+                    tee: None,
+                }))
+                .await
+                .unwrap_err(),
+            service
+                .get_reference_data(Request::new(GetReferenceDataRequest {
+                    user: None,
+                    tee: None,
+                }))
+                .await
+                .unwrap_err(),
+            // The failing store isn't taken as the unknown tenant.
+            service
+                .get_policy(Request::new(GetPolicyRequest {
+                    user: Some(team_a),
+                    tee: None,
+                }))
+                .await
+                .unwrap_err(),
+        ];
+        for status in statuses {
+            assert_eq!(status.code(), tonic::Code::Unavailable, "{:?}", status);
+            let info = error::error_info(&status).unwrap();
+            assert_eq!(info.reason, "STORAGE");
+        }
+
+        std::fs::remove_file(file).unwrap();
     }
 
-    async fn get_reference_data(service: &Service) -> GetReferenceDataResponse {
-        let request = GetReferenceDataRequest { user: None };
+    async fn get_reference_data(
+        service: &Service,
+        user: Option<common::User>,
+    ) -> GetReferenceDataResponse {
+        let request = GetReferenceDataRequest { user, tee: None };
         let request = Request::new(request);
         let response = service.get_reference_data(request).await;
         assert!(response.is_ok(), "Get reference data should success");
@@ -378,18 +805,20 @@ allow {
 
     async fn set_reference_data(
         service: &Service,
+        user: Option<common::User>,
         reference_data: String,
     ) -> Result<Response<SetReferenceDataResponse>, Status> {
         let request = SetReferenceDataRequest {
-            user: None,
+            user,
             content: reference_data.into_bytes(),
+            tee: None,
         };
         let request = Request::new(request);
         service.set_reference_data(request).await
     }
 
-    async fn restore_default_reference_data(service: &Service) {
-        let request = RestoreDefaultReferenceDataRequest { user: None };
+    async fn restore_default_reference_data(service: &Service, user: Option<common::User>) {
+        let request = RestoreDefaultReferenceDataRequest { user, tee: None };
         let request = Request::new(request);
         let response = service.restore_default_reference_data(request).await;
         assert!(response.is_ok(), "Reset reference data should success");
@@ -398,43 +827,37 @@ allow {
     #[tokio::test]
     async fn test_xxx_reference_data() {
         let uuid = Uuid::new_v4().to_string();
-        let (workdir, service) = create_service(Some(&uuid));
+        let (service, user) = create_service(Some(&uuid)).await;
 
         // Set the customized reference data
-        let res = set_reference_data(&service, reference(5)).await;
+        let res = set_reference_data(&service, user.clone(), reference(5)).await;
         assert!(res.is_ok(), "Set reference data should success");
 
         // Get the customized reference data
-        let response = get_reference_data(&service).await;
+        let response = get_reference_data(&service, user.clone()).await;
         let content = std::str::from_utf8(&response.content);
         assert!(content.is_ok(), "Reference data content should OK");
-        let v: Value = serde_json::from_str(&content.unwrap()).unwrap();
+        let v: Value = serde_json::from_str(content.unwrap()).unwrap();
         assert!(v["svn"].as_u64().unwrap() == 5, "The customized svn == 0.");
         assert!(
             v["cpusvn"].as_u64().unwrap() == 5,
             "The customized cpusvn == 0."
         );
-
-        let userdir = workdir.join("users").join(uuid);
-        if userdir.exists() {
-            // Delete the temporary user folder
-            std::fs::remove_dir_all(userdir).unwrap();
-        }
     }
 
     #[tokio::test]
     async fn test_restore_default_reference_data() {
         let uuid = Uuid::new_v4().to_string();
-        let (workdir, service) = create_service(Some(&uuid));
+        let (service, user) = create_service(Some(&uuid)).await;
 
         // Set the customized reference data
-        let res = set_reference_data(&service, reference(5)).await;
+        let res = set_reference_data(&service, user.clone(), reference(5)).await;
         assert!(res.is_ok(), "Set reference data should success");
         // Get the customized reference data
-        let response = get_reference_data(&service).await;
+        let response = get_reference_data(&service, user.clone()).await;
         let content = std::str::from_utf8(&response.content);
         assert!(content.is_ok(), "Reference data content should OK");
-        let v: Value = serde_json::from_str(&content.unwrap()).unwrap();
+        let v: Value = serde_json::from_str(content.unwrap()).unwrap();
         assert!(v["svn"].as_u64().unwrap() == 5, "The customized svn == 0.");
         assert!(
             v["cpusvn"].as_u64().unwrap() == 5,
@@ -442,18 +865,372 @@ allow {
         );
 
         // Restore the reference data.
-        restore_default_reference_data(&service).await;
+        restore_default_reference_data(&service, user.clone()).await;
 
         // Get the default reference data
-        let response = get_reference_data(&service).await;
+        let response = get_reference_data(&service, user.clone()).await;
         let content = std::str::from_utf8(&response.content);
         assert!(content.is_ok(), "Reference data content should OK");
         assert!(content.unwrap().to_string().is_empty());
+    }
+
+    async fn get_tee_reference_data(service: &Service, tee: Option<common::Tee>) -> String {
+        let request = GetReferenceDataRequest {
+            user: None,
+            tee: tee.map(|tee| tee as i32),
+        };
+        let response = service.get_reference_data(Request::new(request)).await;
+        assert!(response.is_ok(), "Get reference data should success");
+        String::from_utf8(response.unwrap().into_inner().content).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_tee_reference_data() {
+        let service = Service::new(
+            Arc::new(user::Users::new(
+                Arc::new(store::MemoryStore::default()),
+                false,
+            )),
+            Arc::new(AttestationService::new()),
+            None,
+            None,
+        );
+
+        // The TEE-agnostic reference data applies to the TEEs without their own.
+        let res = set_reference_data(&service, None, reference(1)).await;
+        assert!(res.is_ok(), "Set reference data should success");
+        let request = SetReferenceDataRequest {
+            user: None,
+            content: reference(5).into_bytes(),
+            tee: Some(common::Tee::Tdx as i32),
+        };
+        let res = service.set_reference_data(Request::new(request)).await;
+        assert!(res.is_ok(), "Set TDX reference data should success");
+
+        let tdx = get_tee_reference_data(&service, Some(common::Tee::Tdx)).await;
+        assert_eq!(tdx, reference(5));
+        let sgx = get_tee_reference_data(&service, Some(common::Tee::Sgx)).await;
+        assert_eq!(sgx, reference(1));
+        assert_eq!(get_tee_reference_data(&service, None).await, reference(1));
+
+        // Restore the TDX reference data.
+        let request = RestoreDefaultReferenceDataRequest {
+            user: None,
+            tee: Some(common::Tee::Tdx as i32),
+        };
+        let res = service
+            .restore_default_reference_data(Request::new(request))
+            .await;
+        assert!(res.is_ok(), "Reset TDX reference data should success");
+        let tdx = get_tee_reference_data(&service, Some(common::Tee::Tdx)).await;
+        assert_eq!(tdx, reference(1));
+
+        // Unknown TEE
+        let request = GetReferenceDataRequest {
+            user: None,
+            tee: Some(100),
+        };
+        let response = service.get_reference_data(Request::new(request)).await;
+        assert_eq!(response.unwrap_err().code(), tonic::Code::InvalidArgument);
+    }
+
+    #[tokio::test]
+    async fn test_get_tee_default_policy() {
+        let (service, _) = create_service(None).await;
+        let request = GetPolicyRequest {
+            user: None,
+            tee: Some(common::Tee::SevSnp as i32),
+        };
+        let response = service.get_policy(Request::new(request)).await;
+        assert!(response.is_ok(), "Get policy should success");
+        assert_eq!(
+            String::from_utf8(response.unwrap().into_inner().content).unwrap(),
+            std::include_str!("../../lib/src/core/policy_engine/sevsnp_default_policy.rego")
+        );
+    }
+
+    async fn list_users(service: &Service) -> Vec<String> {
+        let response = service.list_users(Request::new(ListUsersRequest {})).await;
+        assert!(response.is_ok(), "List users should success");
+        response
+            .unwrap()
+            .into_inner()
+            .users
+            .into_iter()
+            .map(|user| user.id)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_xxx_user() {
+        let (service, _) = create_service(None).await;
+        let uuid = Uuid::new_v4().to_string();
+        let user = Some(common::User { id: uuid.clone() });
+
+        // Unknown user
+        let response = service
+            .get_policy(Request::new(GetPolicyRequest {
+                user: user.clone(),
+                tee: None,
+            }))
+            .await;
+        assert_eq!(response.unwrap_err().code(), tonic::Code::NotFound);
+
+        // Create user
+        let request = CreateUserRequest { user: user.clone() };
+        let response = service.create_user(Request::new(request)).await;
+        assert!(response.is_ok(), "Create user should success");
+        let request = CreateUserRequest { user: user.clone() };
+        let response = service.create_user(Request::new(request)).await;
+        assert_eq!(response.unwrap_err().code(), tonic::Code::AlreadyExists);
+        assert!(list_users(&service).await.contains(&uuid));
+
+        // The new user has the default policy.
+        let response = get_policy(&service, user.clone()).await;
+        assert_eq!(
+            std::str::from_utf8(&response.content).unwrap(),
+            default_policy()
+        );
+
+        // Delete user
+        let request = DeleteUserRequest { user: user.clone() };
+        let response = service.delete_user(Request::new(request)).await;
+        assert!(response.is_ok(), "Delete user should success");
+        assert!(!list_users(&service).await.contains(&uuid));
+        let request = DeleteUserRequest { user: user.clone() };
+        let response = service.delete_user(Request::new(request)).await;
+        assert!(response.is_err(), "Delete user should failed");
+    }
 
-        let userdir = workdir.join("users").join(uuid);
-        if userdir.exists() {
-            // Delete the temporary user folder
-            std::fs::remove_dir_all(userdir).unwrap();
+    #[tokio::test]
+    async fn test_create_user_illegal() {
+        let (service, _) = create_service(None).await;
+        for id in ["../default", ""] {
+            let request = CreateUserRequest {
+                user: Some(common::User { id: id.to_owned() }),
+            };
+            let response = service.create_user(Request::new(request)).await;
+            assert!(response.is_err(), "Create user {:?} should failed", id);
+        }
+        let response = service
+            .create_user(Request::new(CreateUserRequest { user: None }))
+            .await;
+        assert_eq!(response.unwrap_err().code(), tonic::Code::InvalidArgument);
+
+        let request = DeleteUserRequest {
+            user: Some(common::User {
+                id: user::DEFAULT_USER_ID.to_owned(),
+            }),
+        };
+        let response = service.delete_user(Request::new(request)).await;
+        assert!(response.is_err(), "Delete the default user should failed");
+    }
+
+    // The request authenticated by `token` like the management server's interceptor.
+    fn authenticated<T>(credentials: &Credentials, token: &str, message: T) -> Request<T> {
+        let mut request = Request::new(message);
+        request.metadata_mut().insert(
+            "authorization",
+            format!("Bearer {}", token).parse().unwrap(),
+        );
+        let principal = credentials.authenticate(&request).unwrap();
+        request.extensions_mut().insert(principal);
+        request
+    }
+
+    #[tokio::test]
+    async fn test_authorization() {
+        let credentials = json!([
+            {
+                "name": "auditor",
+                "token_sha256": hex::encode(Sha256::digest(b"auditor-token")),
+                "roles": { "*": "auditor" }
+            },
+            {
+                "name": "admin",
+                "token_sha256": hex::encode(Sha256::digest(b"admin-token")),
+                "roles": { "default": "admin" }
+            }
+        ]);
+        let credentials = Arc::new(Credentials::from_json(&credentials.to_string()).unwrap());
+        let uuid = Uuid::new_v4().to_string();
+        let service = Service::new(
+            Arc::new(user::Users::new(
+                Arc::new(store::MemoryStore::default()),
+                false,
+            )),
+            Arc::new(AttestationService::new()),
+            Some(credentials.clone()),
+            None,
+        );
+        let set_request = || SetReferenceDataRequest {
+            user: None,
+            content: reference(1).into_bytes(),
+            tee: None,
+        };
+
+        // The auditor can read but not change the reference data.
+        let request = authenticated(&credentials, "auditor-token", set_request());
+        let response = service.set_reference_data(request).await;
+        assert_eq!(response.unwrap_err().code(), tonic::Code::PermissionDenied);
+        let request = authenticated(&credentials, "admin-token", set_request());
+        let response = service.set_reference_data(request).await;
+        assert!(response.is_ok(), "Admin should set the reference data");
+        let request = GetReferenceDataRequest {
+            user: None,
+            tee: None,
+        };
+        let response = service
+            .get_reference_data(authenticated(&credentials, "auditor-token", request))
+            .await;
+        assert_eq!(
+            response.unwrap().into_inner().content,
+            reference(1).into_bytes()
+        );
+
+        // The tenant admin can't create users.
+        let request = CreateUserRequest {
+            user: Some(common::User { id: uuid.clone() }),
+        };
+        let response = service
+            .create_user(authenticated(&credentials, "admin-token", request))
+            .await;
+        assert_eq!(response.unwrap_err().code(), tonic::Code::PermissionDenied);
+
+        // The unauthenticated requests are rejected.
+        let response = service.list_users(Request::new(ListUsersRequest {})).await;
+        assert_eq!(response.unwrap_err().code(), tonic::Code::Unauthenticated);
+    }
+
+    #[tokio::test]
+    async fn test_audit() {
+        let uuid = Uuid::new_v4().to_string();
+        let file = std::env::temp_dir().join(format!("audit-{}.log", uuid));
+        let audit = Arc::new(AuditLog::open(file.clone()).await.unwrap());
+        let service = Service::new(
+            Arc::new(user::Users::new(
+                Arc::new(store::MemoryStore::default()),
+                false,
+            )),
+            Arc::new(AttestationService::new()),
+            None,
+            Some(audit),
+        );
+
+        for ver in [1, 2] {
+            let res = set_reference_data(&service, None, reference(ver)).await;
+            assert!(res.is_ok(), "Set reference data should success");
+        }
+        restore_default_reference_data(&service, None).await;
+
+        let hash = |ver| Some(audit::sha256(reference(ver).as_bytes()));
+        let changes: Vec<_> = std::fs::read_to_string(&file)
+            .unwrap()
+            .lines()
+            .map(
+                |line| match serde_json::from_str::<audit::Record>(line).unwrap().event {
+                    Event::Management {
+                        principal,
+                        action,
+                        tenant,
+                        old_sha256,
+                        new_sha256,
+                        ..
+                    } => {
+                        assert_eq!(principal, "anonymous");
+                        assert_eq!(tenant, user::DEFAULT_USER_ID);
+                        (action, old_sha256, new_sha256)
+                    }
+                    event => panic!("Unexpected event {:?}", event),
+                },
+            )
+            .collect();
+        assert_eq!(
+            changes,
+            vec![
+                ("set_reference_data".to_string(), None, hash(1)),
+                ("set_reference_data".to_string(), hash(1), hash(2)),
+                ("restore_default_reference_data".to_string(), hash(2), None),
+            ]
+        );
+        assert_eq!(audit::verify_file(&file).unwrap(), 3);
+
+        std::fs::remove_file(file).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_versions() {
+        let service = Service::new(
+            Arc::new(user::Users::new(
+                Arc::new(store::MemoryStore::default()),
+                false,
+            )),
+            Arc::new(AttestationService::new()),
+            None,
+            None,
+        );
+        for ver in [1, 2] {
+            let res = set_reference_data(&service, None, reference(ver)).await;
+            assert!(res.is_ok(), "Set reference data should success");
         }
+        let kind = VersionKind::ReferenceData as i32;
+
+        let request = ListVersionsRequest {
+            user: None,
+            tee: None,
+            kind,
+        };
+        let versions = service
+            .list_versions(Request::new(request))
+            .await
+            .unwrap()
+            .into_inner()
+            .versions;
+        assert_eq!(versions.iter().map(|v| v.id).collect::<Vec<_>>(), [1, 2]);
+        assert_eq!(versions[1].author, "anonymous");
+        assert_eq!(
+            versions[1].sha256,
+            Some(audit::sha256(reference(2).as_bytes()))
+        );
+
+        let request = DiffVersionsRequest {
+            user: None,
+            tee: None,
+            kind,
+            from: 1,
+            to: 2,
+        };
+        let diff = service
+            .diff_versions(Request::new(request))
+            .await
+            .unwrap()
+            .into_inner()
+            .diff;
+        assert!(diff.contains(&format!("-{}", reference(1))), "{}", diff);
+        assert!(diff.contains(&format!("+{}", reference(2))), "{}", diff);
+
+        // Roll back to the first version.
+        let request = RollbackRequest {
+            user: None,
+            tee: None,
+            kind,
+            version: 1,
+        };
+        let response = service.rollback(Request::new(request)).await.unwrap();
+        let version = response.into_inner().version.unwrap();
+        assert_eq!(version.id, 3);
+        assert_eq!(version.sha256, versions[0].sha256);
+        let response = get_reference_data(&service, None).await;
+        assert_eq!(response.content, reference(1).into_bytes());
+
+        // Unknown version
+        let request = RollbackRequest {
+            user: None,
+            tee: None,
+            kind,
+            version: 10,
+        };
+        let response = service.rollback(Request::new(request)).await;
+        assert_eq!(response.unwrap_err().code(), tonic::Code::NotFound);
     }
 }
@@ -0,0 +1,271 @@
+//! The Prometheus metrics of the server, served by the `/metrics` HTTP listener.
+
+use crate::user::Users;
+use anyhow::Result;
+use attestation_service::metrics::Metrics as AttestationMetrics;
+use hyper::header::CONTENT_TYPE;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Request, Response, StatusCode};
+use prometheus::{HistogramOpts, HistogramVec, IntCounterVec, IntGauge, Opts, TEXT_FORMAT};
+use std::convert::Infallible;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Instant;
+use tonic::Code;
+use tower::{Layer, Service};
+
+/// The `method` label of the requests to the unimplemented methods, which keeps the labels
+/// bounded.
+const UNKNOWN_METHOD: &str = "unknown";
+
+/// The attestations' metrics along with the gRPC requests' and the users' cache.
+#[derive(Debug, Clone)]
+pub struct Metrics {
+    pub attestation: Arc<AttestationMetrics>,
+    /// The requests of the attestation service.
+    pub attestation_requests: GrpcMetricsLayer,
+    /// The operations of the management service.
+    pub management_operations: GrpcMetricsLayer,
+    cached_users: IntGauge,
+}
+
+impl Metrics {
+    pub fn new() -> Result<Self> {
+        let attestation = Arc::new(AttestationMetrics::new()?);
+        let attestation_requests = GrpcMetricsLayer::new(
+            &attestation,
+            "attestation_grpc_requests_total",
+            "attestation_grpc_request_duration_seconds",
+            "the requests of the attestation service",
+            "method",
+        )?;
+        let management_operations = GrpcMetricsLayer::new(
+            &attestation,
+            "attestation_management_operations_total",
+            "attestation_management_operation_duration_seconds",
+            "the operations of the management service",
+            "operation",
+        )?;
+        let cached_users = IntGauge::new(
+            "attestation_policy_cached_users",
+            "The users whose policies are cached.",
+        )?;
+        attestation
+            .registry()
+            .register(Box::new(cached_users.clone()))?;
+        Ok(Self {
+            attestation,
+            attestation_requests,
+            management_operations,
+            cached_users,
+        })
+    }
+}
+
+/// Count the gRPC requests by method and status code, along with their latency until the
+/// response headers.
+#[derive(Debug, Clone)]
+pub struct GrpcMetricsLayer {
+    requests: IntCounterVec,
+    duration: HistogramVec,
+}
+
+impl GrpcMetricsLayer {
+    fn new(
+        metrics: &AttestationMetrics,
+        requests: &str,
+        duration: &str,
+        help: &str,
+        label: &str,
+    ) -> Result<Self> {
+        let requests = IntCounterVec::new(
+            Opts::new(requests, format!("The count of {}.", help)),
+            &[label, "code"],
+        )?;
+        let duration = HistogramVec::new(
+            HistogramOpts::new(duration, format!("The latency of {}.", help)),
+            &[label],
+        )?;
+        metrics.registry().register(Box::new(requests.clone()))?;
+        metrics.registry().register(Box::new(duration.clone()))?;
+        Ok(Self { requests, duration })
+    }
+
+    fn record(&self, method: &str, code: Code, start: Instant) {
+        // The path of the unimplemented methods is arbitrary.
+        let method = match code {
+            Code::Unimplemented => UNKNOWN_METHOD,
+            _ => method,
+        };
+        self.requests
+            .with_label_values(&[method, &format!("{:?}", code)])
+            .inc();
+        self.duration
+            .with_label_values(&[method])
+            .observe(start.elapsed().as_secs_f64());
+    }
+}
+
+impl<S> Layer<S> for GrpcMetricsLayer {
+    type Service = GrpcMetrics<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        GrpcMetrics {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct GrpcMetrics<S> {
+    inner: S,
+    layer: GrpcMetricsLayer,
+}
+
+impl<S, B, ResBody> Service<Request<B>> for GrpcMetrics<S>
+where
+    S: Service<Request<B>, Response = Response<ResBody>>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<B>) -> Self::Future {
+        let start = Instant::now();
+        // The path is `/<package>.<service>/<method>`.
+        let method = request
+            .uri()
+            .path()
+            .rsplit('/')
+            .next()
+            .unwrap_or_default()
+            .to_owned();
+        let layer = self.layer.clone();
+        let response = self.inner.call(request);
+        Box::pin(async move {
+            let response = response.await?;
+            // The failed requests carry the status in the headers, the succeeded ones in the
+            // trailers.
+            let code = response
+                .headers()
+                .get("grpc-status")
+                .map_or(Code::Ok, |status| Code::from_bytes(status.as_bytes()));
+            layer.record(&method, code, start);
+            Ok(response)
+        })
+    }
+}
+
+async fn handle(
+    request: Request<Body>,
+    metrics: Arc<Metrics>,
+    users: Arc<Users>,
+) -> Result<Response<Body>, Infallible> {
+    let mut response = Response::default();
+    if request.uri().path() != "/metrics" {
+        *response.status_mut() = StatusCode::NOT_FOUND;
+        return Ok(response);
+    }
+    metrics.cached_users.set(users.cached().await as i64);
+    match metrics.attestation.encode() {
+        Ok(text) => {
+            response
+                .headers_mut()
+                .insert(CONTENT_TYPE, TEXT_FORMAT.parse().unwrap());
+            *response.body_mut() = Body::from(text);
+        }
+        Err(e) => {
+            error!("Encode metrics failed: {:#}", e);
+            *response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+        }
+    }
+    Ok(response)
+}
+
+/// Serve the metrics in the Prometheus text format at `/metrics` until `shutdown` resolves.
+pub async fn start_service(
+    socket: SocketAddr,
+    metrics: Arc<Metrics>,
+    users: Arc<Users>,
+    shutdown: impl Future<Output = ()>,
+) -> Result<()> {
+    debug!("Metrics listen socket: {}", &socket);
+    let make_service = make_service_fn(move |_| {
+        let metrics = metrics.clone();
+        let users = users.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                handle(request, metrics.clone(), users.clone())
+            }))
+        }
+    });
+    hyper::Server::try_bind(&socket)?
+        .serve(make_service)
+        .with_graceful_shutdown(shutdown)
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::MemoryStore;
+
+    #[tokio::test]
+    async fn test_grpc_metrics() {
+        let metrics = Metrics::new().unwrap();
+        let mut service =
+            metrics
+                .management_operations
+                .layer(service_fn(|request: Request<Body>| async move {
+                    let mut response = Response::new(Body::empty());
+                    if request.uri().path() != "/management.ManagementService/GetPolicy" {
+                        response
+                            .headers_mut()
+                            .insert("grpc-status", "12".parse().unwrap());
+                    }
+                    Ok::<_, Infallible>(response)
+                }));
+        for path in [
+            "/management.ManagementService/GetPolicy",
+            "/management.ManagementService/Arbitrary",
+        ] {
+            let request = Request::builder().uri(path).body(Body::empty()).unwrap();
+            service.call(request).await.unwrap();
+        }
+
+        let metrics = Arc::new(metrics);
+        let users = Arc::new(Users::new(Arc::new(MemoryStore::default()), false));
+        let request = Request::builder()
+            .uri("/metrics")
+            .body(Body::empty())
+            .unwrap();
+        let response = handle(request, metrics.clone(), users.clone())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let text = std::str::from_utf8(&body).unwrap();
+        for line in [
+            r#"attestation_management_operations_total{code="Ok",operation="GetPolicy"} 1"#,
+            r#"attestation_management_operations_total{code="Unimplemented",operation="unknown"} 1"#,
+            r#"attestation_management_operation_duration_seconds_count{operation="GetPolicy"} 1"#,
+            "attestation_policy_cached_users 0",
+        ] {
+            assert!(text.contains(line), "{} should be exported", line);
+        }
+
+        let request = Request::builder().uri("/").body(Body::empty()).unwrap();
+        let response = handle(request, metrics, users).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
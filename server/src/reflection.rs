@@ -0,0 +1,314 @@
+//! The gRPC server reflection (`grpc.reflection.v1alpha.ServerReflection`) of the listeners, which
+//! lets the generic tools, e.g. grpcurl, call the services without their `.proto` files.
+//!
+//! Only the descriptors of a listener's own services and their dependencies are served.
+
+use crate::grpc::health::v1::health_server::HealthServer;
+use crate::grpc::reflection::v1alpha::server_reflection_request::MessageRequest;
+use crate::grpc::reflection::v1alpha::server_reflection_response::MessageResponse;
+use crate::grpc::reflection::v1alpha::server_reflection_server::{
+    ServerReflection, ServerReflectionServer,
+};
+use crate::grpc::reflection::v1alpha::{
+    ErrorResponse, ExtensionNumberResponse, FileDescriptorResponse, ListServiceResponse,
+    ServerReflectionRequest, ServerReflectionResponse, ServiceResponse,
+};
+use crate::health;
+use anyhow::{anyhow, Context, Result};
+use prost::Message;
+use prost_types::{DescriptorProto, FileDescriptorProto, FileDescriptorSet};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::StreamExt;
+use tonic::transport::NamedService;
+use tonic::{Code, Request, Response, Status, Streaming};
+
+// The descriptors of the compiled `.proto` files along with their imports.
+const FILE_DESCRIPTOR_SET: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/descriptor.bin"));
+
+// The buffer of the responses to a reflection client.
+const REFLECTION_BUFFER: usize = 4;
+
+/// The reflection service of a listener serving the gRPC services `services`, the health and
+/// reflection services are added.
+pub fn server(services: &[&str]) -> Result<ServerReflectionServer<Service>> {
+    Ok(ServerReflectionServer::new(Service {
+        descriptors: Arc::new(Descriptors::new(services)?),
+    }))
+}
+
+pub struct Service {
+    descriptors: Arc<Descriptors>,
+}
+
+struct Descriptors {
+    services: Vec<String>,
+    // The served files by name.
+    files: HashMap<String, FileDescriptorProto>,
+    // The files defining the fully-qualified symbols, i.e. the messages, enums, services and
+    // methods.
+    symbols: HashMap<String, String>,
+}
+
+impl Descriptors {
+    fn new(services: &[&str]) -> Result<Self> {
+        let set = FileDescriptorSet::decode(FILE_DESCRIPTOR_SET)
+            .context("Decode the file descriptor set failed.")?;
+        let all: HashMap<String, FileDescriptorProto> = set
+            .file
+            .into_iter()
+            .map(|file| (file.name().to_string(), file))
+            .collect();
+        let mut services: Vec<String> = services.iter().map(|name| name.to_string()).collect();
+        services.push(<HealthServer<health::Service> as NamedService>::NAME.to_string());
+        services.push(<ServerReflectionServer<Service> as NamedService>::NAME.to_string());
+
+        let mut served = Self {
+            services: Vec::new(),
+            files: HashMap::new(),
+            symbols: HashMap::new(),
+        };
+        for name in &services {
+            let file = all
+                .values()
+                .find(|file| {
+                    file.service
+                        .iter()
+                        .any(|service| &qualified(file.package(), service.name()) == name)
+                })
+                .ok_or_else(|| anyhow!("The descriptor of service {} is missing.", name))?;
+            served.add(file, &all);
+        }
+        served.services = services;
+        Ok(served)
+    }
+
+    // Serve the file along with its dependencies.
+    fn add(&mut self, file: &FileDescriptorProto, all: &HashMap<String, FileDescriptorProto>) {
+        if self.files.contains_key(file.name()) {
+            return;
+        }
+        let package = file.package();
+        let mut symbols = Vec::new();
+        for message in &file.message_type {
+            messages(package, message, &mut symbols);
+        }
+        for enumeration in &file.enum_type {
+            symbols.push(qualified(package, enumeration.name()));
+        }
+        for service in &file.service {
+            let service_name = qualified(package, service.name());
+            for method in &service.method {
+                symbols.push(qualified(&service_name, method.name()));
+            }
+            symbols.push(service_name);
+        }
+        for symbol in symbols {
+            self.symbols.insert(symbol, file.name().to_string());
+        }
+        self.files.insert(file.name().to_string(), file.clone());
+        for dependency in &file.dependency {
+            if let Some(dependency) = all.get(dependency) {
+                self.add(dependency, all);
+            }
+        }
+    }
+
+    // The encoded file `name` followed by its transitive dependencies.
+    fn file(&self, name: &str) -> Option<Vec<Vec<u8>>> {
+        let mut names = vec![name];
+        let mut visited = HashSet::new();
+        let mut encoded = Vec::new();
+        while let Some(name) = names.pop() {
+            if !visited.insert(name) {
+                continue;
+            }
+            let file = self.files.get(name)?;
+            encoded.push(file.encode_to_vec());
+            names.extend(file.dependency.iter().map(String::as_str));
+        }
+        Some(encoded)
+    }
+
+    fn respond(&self, request: &MessageRequest) -> MessageResponse {
+        let not_found = |message: String| {
+            MessageResponse::ErrorResponse(ErrorResponse {
+                error_code: Code::NotFound as i32,
+                error_message: message,
+            })
+        };
+        let file = |name: &str| match self.file(name) {
+            Some(file_descriptor_proto) => {
+                MessageResponse::FileDescriptorResponse(FileDescriptorResponse {
+                    file_descriptor_proto,
+                })
+            }
+            None => not_found(format!("Unknown file {}", name)),
+        };
+        match request {
+            MessageRequest::FileByFilename(name) => file(name),
+            MessageRequest::FileContainingSymbol(symbol) => match self.symbols.get(symbol) {
+                Some(name) => file(name),
+                None => not_found(format!("Unknown symbol {}", symbol)),
+            },
+            // The proto3 files don't define the extensions.
+            MessageRequest::FileContainingExtension(extension) => not_found(format!(
+                "Unknown extension {} of {}",
+                extension.extension_number, extension.containing_type
+            )),
+            MessageRequest::AllExtensionNumbersOfType(name) => match self.symbols.get(name) {
+                Some(_) => MessageResponse::AllExtensionNumbersResponse(ExtensionNumberResponse {
+                    base_type_name: name.clone(),
+                    extension_number: Vec::new(),
+                }),
+                None => not_found(format!("Unknown type {}", name)),
+            },
+            MessageRequest::ListServices(_) => {
+                MessageResponse::ListServicesResponse(ListServiceResponse {
+                    service: self
+                        .services
+                        .iter()
+                        .map(|name| ServiceResponse { name: name.clone() })
+                        .collect(),
+                })
+            }
+        }
+    }
+}
+
+fn qualified(scope: &str, name: &str) -> String {
+    match scope {
+        "" => name.to_string(),
+        _ => format!("{}.{}", scope, name),
+    }
+}
+
+// The fully-qualified names of the message and its nested messages and enums.
+fn messages(scope: &str, message: &DescriptorProto, symbols: &mut Vec<String>) {
+    let name = qualified(scope, message.name());
+    for nested in &message.nested_type {
+        messages(&name, nested, symbols);
+    }
+    for enumeration in &message.enum_type {
+        symbols.push(qualified(&name, enumeration.name()));
+    }
+    symbols.push(name);
+}
+
+#[tonic::async_trait]
+impl ServerReflection for Service {
+    type ServerReflectionInfoStream = ReceiverStream<Result<ServerReflectionResponse, Status>>;
+
+    async fn server_reflection_info(
+        &self,
+        request: Request<Streaming<ServerReflectionRequest>>,
+    ) -> Result<Response<Self::ServerReflectionInfoStream>, Status> {
+        let mut requests = request.into_inner();
+        let descriptors = self.descriptors.clone();
+        let (tx, rx) = mpsc::channel(REFLECTION_BUFFER);
+        tokio::spawn(async move {
+            while let Some(request) = requests.next().await {
+                let response = request.and_then(|request| {
+                    let message_request = request
+                        .message_request
+                        .as_ref()
+                        .ok_or_else(|| Status::invalid_argument("Missing message_request"))?;
+                    Ok(ServerReflectionResponse {
+                        valid_host: request.host.clone(),
+                        message_response: Some(descriptors.respond(message_request)),
+                        original_request: Some(request),
+                    })
+                });
+                if tx.send(response).await.is_err() {
+                    return;
+                }
+            }
+        });
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SERVICE: &str = "attestation.AttestationService";
+
+    fn respond(descriptors: &Descriptors, request: MessageRequest) -> MessageResponse {
+        descriptors.respond(&request)
+    }
+
+    fn files(response: MessageResponse) -> Vec<String> {
+        match response {
+            MessageResponse::FileDescriptorResponse(response) => response
+                .file_descriptor_proto
+                .iter()
+                .map(|file| {
+                    FileDescriptorProto::decode(file.as_slice())
+                        .unwrap()
+                        .name()
+                        .to_string()
+                })
+                .collect(),
+            response => panic!("Unexpected response {:?}", response),
+        }
+    }
+
+    fn is_not_found(response: MessageResponse) -> bool {
+        matches!(
+            response,
+            MessageResponse::ErrorResponse(ErrorResponse { error_code, .. })
+                if error_code == Code::NotFound as i32
+        )
+    }
+
+    #[test]
+    fn test_list_services() {
+        let descriptors = Descriptors::new(&[SERVICE]).unwrap();
+        match respond(&descriptors, MessageRequest::ListServices(String::new())) {
+            MessageResponse::ListServicesResponse(response) => assert_eq!(
+                response
+                    .service
+                    .into_iter()
+                    .map(|service| service.name)
+                    .collect::<Vec<_>>(),
+                [
+                    SERVICE,
+                    "grpc.health.v1.Health",
+                    "grpc.reflection.v1alpha.ServerReflection"
+                ]
+            ),
+            response => panic!("Unexpected response {:?}", response),
+        }
+        assert!(Descriptors::new(&["unknown.Service"]).is_err());
+    }
+
+    #[test]
+    fn test_file_containing_symbol() {
+        let descriptors = Descriptors::new(&[SERVICE]).unwrap();
+        for symbol in [
+            SERVICE,
+            "attestation.AttestationService.Attestation",
+            "attestation.AttestationRequest",
+        ] {
+            let request = MessageRequest::FileContainingSymbol(symbol.to_owned());
+            let files = files(respond(&descriptors, request));
+            assert_eq!(files[0], "attestation.proto");
+            // The dependencies are included.
+            assert!(files.iter().any(|file| file == "common.proto"));
+        }
+        let request = MessageRequest::FileByFilename("common.proto".to_owned());
+        assert_eq!(files(respond(&descriptors, request)), ["common.proto"]);
+
+        // The other listeners' services aren't served.
+        for request in [
+            MessageRequest::FileContainingSymbol("management.ManagementService".to_owned()),
+            MessageRequest::FileByFilename("management.proto".to_owned()),
+            MessageRequest::AllExtensionNumbersOfType("unknown.Message".to_owned()),
+        ] {
+            assert!(is_not_found(respond(&descriptors, request)));
+        }
+    }
+}
@@ -0,0 +1,396 @@
+//! Hot reload of the users' policies and the config.
+//!
+//! The watcher reloads the policies edited in place in the filesystem store, which replace the
+//! cached ones only if they pass the syntax check of `SetPolicy`, and the reloadable settings once
+//! the config file changes. SIGHUP reloads all of them.
+
+use crate::auth::Credentials;
+use crate::common;
+use crate::config::Config;
+use crate::user::Users;
+use anyhow::{Context, Result};
+use attestation_service::{Service as AttestationService, TeePolicies};
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use serde_json::Value;
+use std::collections::{BTreeSet, HashSet};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::mpsc;
+
+// Collect the events of a burst, e.g. an editor's writes and renames, before reloading.
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// Load and validate the config, along with its environment and command line overrides.
+pub type ConfigLoader = Box<dyn Fn() -> Result<Config> + Send + Sync>;
+
+// The changes of a burst of events.
+#[derive(Debug, Default, PartialEq)]
+struct Changes {
+    users: BTreeSet<String>,
+    config: bool,
+}
+
+/// Apply the config's log level, unless `RUST_LOG` takes precedence.
+pub fn apply_log_level(config: &Config) -> Result<()> {
+    if std::env::var_os("RUST_LOG").is_none() {
+        log::set_max_level(config.log_level()?);
+    }
+    Ok(())
+}
+
+// The `.` separated paths of the settings which differ between `current` and `config`.
+fn changed_settings(current: &Value, config: &Value, path: &str, changed: &mut Vec<String>) {
+    match (current, config) {
+        (Value::Object(current), Value::Object(config)) => {
+            let keys: BTreeSet<&String> = current.keys().chain(config.keys()).collect();
+            for key in keys {
+                let child = match path {
+                    "" => key.clone(),
+                    _ => format!("{}.{}", path, key),
+                };
+                changed_settings(
+                    current.get(key).unwrap_or(&Value::Null),
+                    config.get(key).unwrap_or(&Value::Null),
+                    &child,
+                    changed,
+                );
+            }
+        }
+        _ if current != config => changed.push(path.to_owned()),
+        _ => (),
+    }
+}
+
+fn policies(policies: &TeePolicies) -> HashSet<&str> {
+    std::iter::once(&policies.common)
+        .chain(policies.tees.values())
+        .filter_map(|data| data.policy.as_deref())
+        .collect()
+}
+
+pub struct Reloader {
+    users: Arc<Users>,
+    attestation_service: Arc<AttestationService>,
+    // The directory of the filesystem store's users, which is watched if present.
+    users_dir: Option<PathBuf>,
+    config_file: Option<PathBuf>,
+    load_config: ConfigLoader,
+    config: Mutex<Config>,
+    // The management credentials, which are replaced by the reloaded ones.
+    credentials: Option<Arc<Credentials>>,
+}
+
+impl Reloader {
+    pub fn new(
+        users: Arc<Users>,
+        attestation_service: Arc<AttestationService>,
+        users_dir: Option<PathBuf>,
+        config_file: Option<PathBuf>,
+        load_config: ConfigLoader,
+        config: Config,
+        credentials: Option<Arc<Credentials>>,
+    ) -> Self {
+        Self {
+            users,
+            attestation_service,
+            users_dir,
+            config_file,
+            load_config,
+            config: Mutex::new(config),
+            credentials,
+        }
+    }
+
+    /// Reload the policies of the user `id`, the invalid ones are rejected and logged.
+    pub async fn reload_user(&self, id: &str) {
+        let service = &self.attestation_service;
+        match self
+            .users
+            .reload(id, |policy| service.check_policy(policy))
+            .await
+        {
+            Ok(Some(replaced)) => {
+                info!("Reloaded the policies of user {}", id);
+                // Drop the replaced policies from the policy engine's cache.
+                let user = common::User { id: id.to_owned() };
+                let current = match self.users.get(Some(&user)).await {
+                    Ok(user) => user.read().await.policies().await,
+                    Err(e) => Err(e),
+                };
+                let current = match current {
+                    Ok(current) => current,
+                    Err(e) => return error!("Reload user {} failed: {:#}", id, e),
+                };
+                let current = policies(&current);
+                for policy in policies(&replaced).difference(&current) {
+                    service.invalidate_policy(policy);
+                }
+            }
+            Ok(None) => debug!("Reloaded user {}", id),
+            Err(e) => error!("Rejected the change of user {}: {:#}", id, e),
+        }
+    }
+
+    /// Reload the config, the invalid one is rejected and logged. The log level and the
+    /// management credentials are applied, the other changed settings are logged and take effect
+    /// after restart.
+    pub fn reload_config(&self) {
+        let config = match (self.load_config)() {
+            Ok(config) => config,
+            Err(e) => return error!("Rejected the config change: {:#}", e),
+        };
+        if let Err(e) = apply_log_level(&config) {
+            error!("Apply the log level failed: {:#}", e);
+        }
+        let mut current = self.config.lock().unwrap_or_else(|e| e.into_inner());
+        current.log_level = config.log_level.clone();
+        if let (Some(credentials), Some(path)) = (&self.credentials, &config.management.credentials)
+        {
+            match Credentials::from_file(path) {
+                Ok(reloaded) => {
+                    credentials.replace(reloaded);
+                    current.management.credentials = Some(path.clone());
+                    info!("Reloaded the management credentials {:?}", path);
+                }
+                Err(e) => error!("Rejected the management credentials: {:#}", e),
+            }
+        }
+
+        // The settings which aren't applied keep their current values until restart.
+        let mut restart = Vec::new();
+        match (
+            serde_json::to_value(&*current),
+            serde_json::to_value(&config),
+        ) {
+            (Ok(current), Ok(config)) => changed_settings(&current, &config, "", &mut restart),
+            (Err(e), _) | (_, Err(e)) => error!("Compare the config failed: {}", e),
+        }
+        if !restart.is_empty() {
+            warn!(
+                "The changed settings take effect after restart: {}",
+                restart.join(", ")
+            );
+        }
+        info!("Reloaded the config");
+    }
+
+    /// Reload the policies of all the users.
+    pub async fn reload_users(&self) {
+        match self.users.list().await {
+            Ok(ids) => {
+                for id in ids {
+                    self.reload_user(&id).await;
+                }
+            }
+            Err(e) => error!("List users failed: {:#}", e),
+        }
+    }
+
+    /// Reload the config and the policies of all the users.
+    pub async fn reload_all(&self) {
+        self.reload_config();
+        self.reload_users().await;
+    }
+
+    // The users and the config changed by the paths.
+    fn changes(&self, paths: &[PathBuf], changes: &mut Changes) {
+        for path in paths {
+            if self.config_file.as_deref() == Some(path.as_path()) {
+                changes.config = true;
+            }
+            let relative = match &self.users_dir {
+                Some(dir) => match path.strip_prefix(dir) {
+                    Ok(relative) => relative,
+                    Err(_) => continue,
+                },
+                None => continue,
+            };
+            // Skip the temporary files of the store's writes and the versions.
+            let hidden = relative.components().any(|component| match component {
+                Component::Normal(name) => name.to_string_lossy().starts_with('.'),
+                _ => true,
+            });
+            if hidden {
+                continue;
+            }
+            if let Some(Component::Normal(id)) = relative.components().next() {
+                changes.users.insert(id.to_string_lossy().to_string());
+            }
+        }
+    }
+
+    async fn apply(&self, changes: Changes) {
+        if changes.config {
+            self.reload_config();
+        }
+        for id in &changes.users {
+            self.reload_user(id).await;
+        }
+    }
+
+    /// Watch the users' directory and the config file, and reload their changes until the
+    /// server stops.
+    pub async fn run(self: Arc<Self>) -> Result<()> {
+        let (tx, mut events) = mpsc::unbounded_channel();
+        let mut watcher =
+            notify::recommended_watcher(move |event: notify::Result<Event>| match event {
+                Ok(event) => {
+                    let _ = tx.send(event);
+                }
+                Err(e) => error!("Watch failed: {}", e),
+            })?;
+        if let Some(dir) = &self.users_dir {
+            std::fs::create_dir_all(dir)?;
+            watcher
+                .watch(dir, RecursiveMode::Recursive)
+                .with_context(|| format!("Watch {:?} failed.", dir))?;
+            info!("Watching the policies under {:?}", dir);
+        }
+        // Watch the directory, as the editors replace the file by renaming.
+        if let Some(dir) = self.config_file.as_deref().and_then(Path::parent) {
+            watcher
+                .watch(dir, RecursiveMode::NonRecursive)
+                .with_context(|| format!("Watch {:?} failed.", dir))?;
+        }
+        let mut hangup = signal(SignalKind::hangup())?;
+
+        loop {
+            tokio::select! {
+                event = events.recv() => {
+                    let mut event = match event {
+                        Some(event) => event,
+                        None => return Ok(()),
+                    };
+                    let mut changes = Changes::default();
+                    loop {
+                        if !matches!(event.kind, EventKind::Access(_)) {
+                            self.changes(&event.paths, &mut changes);
+                        }
+                        event = match tokio::time::timeout(DEBOUNCE, events.recv()).await {
+                            Ok(Some(event)) => event,
+                            _ => break,
+                        };
+                    }
+                    self.apply(changes).await;
+                }
+                _ = hangup.recv() => {
+                    info!("SIGHUP received, reloading");
+                    self.reload_all().await;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::{FsStore, USERS_DIR};
+    use uuid::Uuid;
+
+    const POLICY: &str = "package policy\n\ndefault allow = true\n";
+
+    fn reloader(workdir: &Path, config: Config) -> Reloader {
+        let users_dir = workdir.join(USERS_DIR);
+        let users = Users::new(Arc::new(FsStore::new(users_dir.clone())), true);
+        let load = config.clone();
+        Reloader::new(
+            Arc::new(users),
+            Arc::new(AttestationService::new()),
+            Some(users_dir),
+            Some(workdir.join("config.toml")),
+            Box::new(move || Ok(load.clone())),
+            config,
+            None,
+        )
+    }
+
+    async fn policy(reloader: &Reloader) -> Option<String> {
+        let user = reloader.users.get(None).await.unwrap();
+        let policies = user.read().await.policies().await.unwrap();
+        policies.common.policy
+    }
+
+    #[tokio::test]
+    async fn test_reload_user() {
+        let workdir = std::env::temp_dir().join(Uuid::new_v4().to_string());
+        let reloader = reloader(&workdir, Config::default());
+        let file = workdir.join(USERS_DIR).join("default").join("policy.rego");
+        std::fs::create_dir_all(file.parent().unwrap()).unwrap();
+        std::fs::write(&file, POLICY).unwrap();
+        reloader.reload_user("default").await;
+        assert_eq!(policy(&reloader).await, Some(POLICY.to_owned()));
+
+        // The invalid edit is rejected, the cached policy keeps serving.
+        std::fs::write(&file, "package policy\nallow {\n").unwrap();
+        reloader.reload_user("default").await;
+        assert_eq!(policy(&reloader).await, Some(POLICY.to_owned()));
+
+        let edited = POLICY.replace("true", "false");
+        std::fs::write(&file, &edited).unwrap();
+        reloader.reload_user("default").await;
+        assert_eq!(policy(&reloader).await, Some(edited));
+
+        // The deleted users are dropped.
+        reloader.users.create("team-a").await.unwrap();
+        std::fs::remove_dir_all(workdir.join(USERS_DIR).join("team-a")).unwrap();
+        reloader.reload_user("team-a").await;
+        let user = common::User {
+            id: "team-a".to_owned(),
+        };
+        assert!(reloader.users.get(Some(&user)).await.is_err());
+
+        std::fs::remove_dir_all(workdir).unwrap();
+    }
+
+    #[test]
+    fn test_changes() {
+        let workdir = PathBuf::from("/opt/attestation-server");
+        let reloader = reloader(&workdir, Config::default());
+        let users = workdir.join(USERS_DIR);
+        let mut changes = Changes::default();
+        reloader.changes(
+            &[
+                users.join("default").join("tdx").join("policy.rego"),
+                users.join("team-a").join("reference_data.json"),
+                users
+                    .join("team-b")
+                    .join(".versions")
+                    .join("policy")
+                    .join("1.json"),
+                users.join("team-b").join(".0a1b.tmp"),
+                workdir.join("rvps").join("reference_values.json"),
+            ],
+            &mut changes,
+        );
+        assert_eq!(
+            changes.users.into_iter().collect::<Vec<_>>(),
+            ["default", "team-a"]
+        );
+        assert!(!changes.config);
+
+        let mut changes = Changes::default();
+        reloader.changes(&[workdir.join("config.toml")], &mut changes);
+        assert!(changes.config);
+    }
+
+    #[test]
+    fn test_changed_settings() {
+        let current = Config::default();
+        let mut config = current.clone();
+        config.log_level = "debug".to_owned();
+        config.nonce.ttl = 60;
+        config.tls.cert = Some(PathBuf::from("server.pem"));
+        config.verifiers.push("unknown".to_owned());
+        let mut changed = Vec::new();
+        changed_settings(
+            &serde_json::to_value(&current).unwrap(),
+            &serde_json::to_value(&config).unwrap(),
+            "",
+            &mut changed,
+        );
+        assert_eq!(changed, ["log_level", "nonce.ttl", "tls.cert", "verifiers"]);
+    }
+}
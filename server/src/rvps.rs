@@ -0,0 +1,170 @@
+use crate::auth::{self, Role, ALL_TENANTS};
+use crate::error;
+use anyhow::Result;
+use attestation_service::audit::{self, AuditLog, Event};
+use attestation_service::rvps::Rvps;
+use std::sync::Arc;
+use tonic::codegen::InterceptedService;
+use tonic::service::Interceptor;
+use tonic::{Code, Request, Response, Status};
+
+use crate::reference_api::reference_value_provider_service_server::{
+    ReferenceValueProviderService, ReferenceValueProviderServiceServer,
+};
+use crate::reference_api::{
+    GetReferenceValueRequest, GetReferenceValueResponse, RegisterReferenceValueRequest,
+    RegisterReferenceValueResponse,
+};
+
+#[derive(Debug)]
+pub struct Service {
+    rvps: Arc<Rvps>,
+    // Whether the requests are authenticated by the interceptor, hence must be authorized.
+    authentication: bool,
+    // Record the registrations.
+    audit: Option<Arc<AuditLog>>,
+}
+
+impl Service {
+    pub fn new(rvps: Arc<Rvps>, authentication: bool, audit: Option<Arc<AuditLog>>) -> Self {
+        Self {
+            rvps,
+            authentication,
+            audit,
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl ReferenceValueProviderService for Service {
+    async fn register_reference_value(
+        &self,
+        request: Request<RegisterReferenceValueRequest>,
+    ) -> Result<Response<RegisterReferenceValueResponse>, Status> {
+        auth::authorize(&request, self.authentication, ALL_TENANTS, Role::Admin)?;
+        let principal = auth::principal(&request);
+        let request: RegisterReferenceValueRequest = request.into_inner();
+        let message = std::str::from_utf8(&request.message)
+            .map_err(|e| Status::invalid_argument(format!("Parse message: {}", e)))?;
+        debug!("Provenance message: {}", message);
+
+        let names = self
+            .rvps
+            .register(message)
+            .await
+            .map_err(|e| Status::invalid_argument(format!("Register: {:?}", e)))?;
+        if let Some(audit) = &self.audit {
+            let event = Event::Management {
+                principal,
+                action: "register_reference_value".to_string(),
+                tenant: ALL_TENANTS.to_string(),
+                tee: None,
+                old_sha256: None,
+                new_sha256: Some(audit::sha256(&request.message)),
+            };
+            audit
+                .append(event)
+                .await
+                .map_err(|e| error::status("Audit", e, Code::Internal))?;
+        }
+
+        Ok(Response::new(RegisterReferenceValueResponse { names }))
+    }
+
+    async fn get_reference_value(
+        &self,
+        request: Request<GetReferenceValueRequest>,
+    ) -> Result<Response<GetReferenceValueResponse>, Status> {
+        auth::authorize(&request, self.authentication, ALL_TENANTS, Role::Auditor)?;
+        let request: GetReferenceValueRequest = request.into_inner();
+
+        let values = self
+            .rvps
+            .reference_values(&request.tee)
+            .await
+            .map_err(|e| error::status("Get reference value", e, Code::Aborted))?;
+        let content = serde_json::to_vec(&values)
+            .map_err(|e| error::status("Serialize reference value", e.into(), Code::Aborted))?;
+
+        Ok(Response::new(GetReferenceValueResponse { content }))
+    }
+}
+
+/// The RVPS registration endpoint, which is served next to the management endpoint and shares
+/// its authentication.
+pub fn server<F: Interceptor>(
+    rvps: Arc<Rvps>,
+    authentication: bool,
+    audit: Option<Arc<AuditLog>>,
+    interceptor: F,
+) -> InterceptedService<ReferenceValueProviderServiceServer<Service>, F> {
+    ReferenceValueProviderServiceServer::with_interceptor(
+        Service::new(rvps, authentication, audit),
+        interceptor,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use attestation_service::rvps::{MemoryStore, Message, MESSAGE_VERSION};
+    use serde_json::{json, Value};
+
+    fn message(payload: &Value) -> Vec<u8> {
+        serde_json::to_vec(&Message {
+            version: MESSAGE_VERSION.to_string(),
+            provenance_type: "sample".to_string(),
+            tee: "sample".to_string(),
+            payload: base64::encode(payload.to_string()),
+            expiration: None,
+        })
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_register_reference_value() {
+        let service = Service::new(
+            Arc::new(
+                Rvps::new(Box::new(MemoryStore::default()), Vec::new()).allow_unsigned_sample(),
+            ),
+            false,
+            None,
+        );
+        let request = RegisterReferenceValueRequest {
+            message: message(&json!({ "svn": [2] })),
+        };
+        let response = service
+            .register_reference_value(Request::new(request))
+            .await;
+        assert!(response.is_ok(), "register should success");
+        assert_eq!(response.unwrap().into_inner().names, vec!["svn"]);
+
+        let request = GetReferenceValueRequest {
+            tee: "sample".to_string(),
+        };
+        let response = service.get_reference_value(Request::new(request)).await;
+        assert!(response.is_ok(), "get reference value should success");
+        let values: Value =
+            serde_json::from_slice(&response.unwrap().into_inner().content).unwrap();
+        assert_eq!(values[0]["name"], "svn");
+        assert_eq!(values[0]["values"], json!([2]));
+    }
+
+    #[tokio::test]
+    async fn test_register_reference_value_illegal() {
+        let service = Service::new(
+            Arc::new(
+                Rvps::new(Box::new(MemoryStore::default()), Vec::new()).allow_unsigned_sample(),
+            ),
+            false,
+            None,
+        );
+        let request = RegisterReferenceValueRequest {
+            message: b"illegal".to_vec(),
+        };
+        let response = service
+            .register_reference_value(Request::new(request))
+            .await;
+        assert!(response.is_err(), "register should failed");
+    }
+}
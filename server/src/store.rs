@@ -0,0 +1,820 @@
+//! Storage of the users' policies, reference data and their versions.
+//!
+//! The entries of a user are addressed by `/` separated paths, e.g. `tdx/policy.rego`. The
+//! replicas of the server may share a store, so the writes are atomic and the creations of the
+//! users and the immutable entries (the versions) are exclusive. A version is committed along with
+//! the content it records, so neither of them is written without the other.
+
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use attestation_service::{audit, Error};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::fs;
+use tokio::sync::RwLock;
+
+pub const USERS_DIR: &str = "users";
+const SQLITE_STORE_NAME: &str = "policies.db";
+// The hidden directory of the filesystem store's in-flight commits.
+const JOURNAL_DIR: &str = ".journal";
+// Wait for the other replicas' transactions before failing with SQLITE_BUSY.
+const SQLITE_BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The backends of the [`PolicyStore`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StoreType {
+    /// The files under `<workdir>/users`.
+    Fs,
+    /// The SQLite database `<workdir>/policies.db`.
+    Sqlite,
+    /// The process memory, which is lost on exit.
+    Memory,
+}
+
+impl FromStr for StoreType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "fs" => Ok(StoreType::Fs),
+            "sqlite" => Ok(StoreType::Sqlite),
+            "memory" => Ok(StoreType::Memory),
+            _ => Err(anyhow!("Unknown policy store: {}", s)),
+        }
+    }
+}
+
+impl StoreType {
+    /// Open the store under the working directory.
+    pub fn open(&self, workdir: &Path) -> Result<Arc<dyn PolicyStore>> {
+        let store: Arc<dyn PolicyStore> = match self {
+            StoreType::Fs => Arc::new(FsStore::new(workdir.join(USERS_DIR))),
+            StoreType::Sqlite => Arc::new(SqliteStore::open(&workdir.join(SQLITE_STORE_NAME))?),
+            StoreType::Memory => Arc::new(MemoryStore::default()),
+        };
+        Ok(store)
+    }
+}
+
+#[async_trait]
+pub trait PolicyStore: Send + Sync + std::fmt::Debug {
+    async fn get(&self, user: &str, path: &str) -> Result<Option<String>>;
+
+    /// Atomically replace the entry, the readers see either the old or the new content.
+    async fn put(&self, user: &str, path: &str, content: &str) -> Result<()>;
+
+    /// Create the entry unless it exists, returns whether it's created.
+    async fn create(&self, user: &str, path: &str, content: &str) -> Result<bool>;
+
+    async fn delete(&self, user: &str, path: &str) -> Result<()>;
+
+    /// Atomically create the immutable entry `path` unless it exists, along with replacing the
+    /// entry `target` by `content`, or deleting it if `None`. Returns whether they're written.
+    async fn commit(
+        &self,
+        user: &str,
+        path: &str,
+        record: &str,
+        target: &str,
+        content: Option<&str>,
+    ) -> Result<bool>;
+
+    /// Complete or drop the commits interrupted by a crash, which only the filesystem store
+    /// needs.
+    async fn recover(&self, _user: &str) -> Result<()> {
+        Ok(())
+    }
+
+    /// The names of the entries directly under `dir`, `""` for the user's top level.
+    async fn list(&self, user: &str, dir: &str) -> Result<Vec<String>>;
+
+    /// The names of the directories directly under `dir`.
+    async fn dirs(&self, user: &str, dir: &str) -> Result<Vec<String>>;
+
+    /// Create the user unless it exists, returns whether it's created.
+    async fn create_user(&self, user: &str) -> Result<bool>;
+
+    /// Delete the user along with its entries, returns whether it existed.
+    async fn delete_user(&self, user: &str) -> Result<bool>;
+
+    async fn user_exists(&self, user: &str) -> Result<bool>;
+
+    async fn users(&self) -> Result<Vec<String>>;
+}
+
+// Split the direct children of `dir` among `paths` into the entries and the directories.
+fn children<'a>(
+    paths: impl Iterator<Item = &'a str>,
+    dir: &str,
+) -> (BTreeSet<String>, BTreeSet<String>) {
+    let (mut entries, mut dirs) = (BTreeSet::new(), BTreeSet::new());
+    for path in paths {
+        let rest = match dir {
+            "" => path,
+            _ => match path
+                .strip_prefix(dir)
+                .and_then(|rest| rest.strip_prefix('/'))
+            {
+                Some(rest) => rest,
+                None => continue,
+            },
+        };
+        match rest.split_once('/') {
+            Some((child, _)) => dirs.insert(child.to_owned()),
+            None => entries.insert(rest.to_owned()),
+        };
+    }
+    (entries, dirs)
+}
+
+// The intent of a commit of the filesystem store, which is written before the commit, and replayed
+// by `recover` if the commit is interrupted.
+#[derive(Serialize, Deserialize, Debug)]
+struct Journal {
+    path: String,
+    record: String,
+    target: String,
+    // The SHA-256 of the replaced content, the replay is dropped if the target has changed since.
+    previous_sha256: Option<String>,
+    content: Option<String>,
+}
+
+/// Store the entries as the files under `<dir>/<user>`.
+#[derive(Debug)]
+pub struct FsStore {
+    dir: PathBuf,
+}
+
+impl FsStore {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    fn file(&self, user: &str, path: &str) -> PathBuf {
+        self.dir.join(user).join(path)
+    }
+
+    // The names under the directory whose file type matches `dir`.
+    async fn read_dir(&self, path: &Path, dir: bool) -> Result<Vec<String>> {
+        let error = || Error::Storage(format!("Read {:?} failed.", path));
+        let mut entries = match fs::read_dir(path).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e).with_context(error),
+        };
+        let mut names = Vec::new();
+        while let Some(entry) = entries.next_entry().await.with_context(error)? {
+            let name = entry.file_name().to_string_lossy().to_string();
+            // Skip the temporary files of the in-flight writes.
+            let file_type = entry.file_type().await.with_context(error)?;
+            if file_type.is_dir() == dir && !name.ends_with(".tmp") {
+                names.push(name);
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+
+    // Write and sync a temporary file next to `file`, which is unique among the replicas.
+    async fn write_tmp(&self, file: &Path, content: &str) -> Result<PathBuf> {
+        let dir = file.parent().context("Entry without directory")?;
+        fs::create_dir_all(dir).await?;
+        let tmp = dir.join(format!(".{}.tmp", uuid::Uuid::new_v4()));
+        let mut tmp_file = fs::File::create(&tmp).await?;
+        tokio::io::AsyncWriteExt::write_all(&mut tmp_file, content.as_bytes()).await?;
+        tmp_file.sync_all().await?;
+        Ok(tmp)
+    }
+}
+
+// Sync the directory, so the renames and links in it are durable.
+async fn sync_dir(file: &Path) -> Result<()> {
+    if let Some(dir) = file.parent() {
+        fs::File::open(dir).await?.sync_all().await?;
+    }
+    Ok(())
+}
+
+#[async_trait]
+impl PolicyStore for FsStore {
+    async fn get(&self, user: &str, path: &str) -> Result<Option<String>> {
+        let file = self.file(user, path);
+        match fs::read_to_string(&file).await {
+            Ok(content) => Ok(Some(content)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).with_context(|| Error::Storage(format!("Read {:?} failed.", file))),
+        }
+    }
+
+    async fn put(&self, user: &str, path: &str, content: &str) -> Result<()> {
+        let file = self.file(user, path);
+        let error = || Error::Storage(format!("Write {:?} failed.", file));
+        let tmp = self.write_tmp(&file, content).await.with_context(error)?;
+        if let Err(e) = fs::rename(&tmp, &file).await {
+            let _ = fs::remove_file(&tmp).await;
+            return Err(e).with_context(error);
+        }
+        sync_dir(&file).await.with_context(error)
+    }
+
+    async fn create(&self, user: &str, path: &str, content: &str) -> Result<bool> {
+        let file = self.file(user, path);
+        let error = || Error::Storage(format!("Create {:?} failed.", file));
+        let tmp = self.write_tmp(&file, content).await.with_context(error)?;
+        // Linking fails if the file exists, unlike renaming.
+        let linked = fs::hard_link(&tmp, &file).await;
+        fs::remove_file(&tmp).await.with_context(error)?;
+        match linked {
+            Ok(()) => {
+                sync_dir(&file).await.with_context(error)?;
+                Ok(true)
+            }
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => Ok(false),
+            Err(e) => Err(e).with_context(error),
+        }
+    }
+
+    async fn delete(&self, user: &str, path: &str) -> Result<()> {
+        let file = self.file(user, path);
+        match fs::remove_file(&file).await {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e).with_context(|| Error::Storage(format!("Delete {:?} failed.", file))),
+        }
+    }
+
+    async fn commit(
+        &self,
+        user: &str,
+        path: &str,
+        record: &str,
+        target: &str,
+        content: Option<&str>,
+    ) -> Result<bool> {
+        let journal = Journal {
+            path: path.to_owned(),
+            record: record.to_owned(),
+            target: target.to_owned(),
+            previous_sha256: self
+                .get(user, target)
+                .await?
+                .map(|previous| audit::sha256(previous.as_bytes())),
+            content: content.map(str::to_owned),
+        };
+        let entry = format!("{}/{}.json", JOURNAL_DIR, uuid::Uuid::new_v4());
+        self.put(user, &entry, &serde_json::to_string(&journal)?)
+            .await?;
+        // Creating the record is the commit point, the target is replayed once it's created.
+        let created = self.create(user, path, record).await?;
+        if created {
+            match content {
+                Some(content) => self.put(user, target, content).await?,
+                None => self.delete(user, target).await?,
+            }
+        }
+        self.delete(user, &entry).await?;
+        Ok(created)
+    }
+
+    async fn recover(&self, user: &str) -> Result<()> {
+        for name in self.list(user, JOURNAL_DIR).await? {
+            let entry = format!("{}/{}", JOURNAL_DIR, name);
+            let journal = match self.get(user, &entry).await? {
+                Some(journal) => journal,
+                None => continue,
+            };
+            let journal: Journal = serde_json::from_str(&journal)
+                .with_context(|| Error::Storage(format!("Parse journal {} failed.", entry)))?;
+            let recorded = self.get(user, &journal.path).await? == Some(journal.record);
+            let current = self
+                .get(user, &journal.target)
+                .await?
+                .map(|current| audit::sha256(current.as_bytes()));
+            if recorded && current == journal.previous_sha256 {
+                info!("Replay the interrupted commit of {}", journal.path);
+                match &journal.content {
+                    Some(content) => self.put(user, &journal.target, content).await?,
+                    None => self.delete(user, &journal.target).await?,
+                }
+            }
+            self.delete(user, &entry).await?;
+        }
+        Ok(())
+    }
+
+    async fn list(&self, user: &str, dir: &str) -> Result<Vec<String>> {
+        self.read_dir(&self.file(user, dir), false).await
+    }
+
+    async fn dirs(&self, user: &str, dir: &str) -> Result<Vec<String>> {
+        self.read_dir(&self.file(user, dir), true).await
+    }
+
+    async fn create_user(&self, user: &str) -> Result<bool> {
+        let error = || Error::Storage(format!("Create user {} failed.", user));
+        fs::create_dir_all(&self.dir).await.with_context(error)?;
+        match fs::create_dir(self.dir.join(user)).await {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => Ok(false),
+            Err(e) => Err(e).with_context(error),
+        }
+    }
+
+    async fn delete_user(&self, user: &str) -> Result<bool> {
+        match fs::remove_dir_all(self.dir.join(user)).await {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(false),
+            Err(e) => {
+                Err(e).with_context(|| Error::Storage(format!("Delete user {} failed.", user)))
+            }
+        }
+    }
+
+    async fn user_exists(&self, user: &str) -> Result<bool> {
+        match fs::metadata(self.dir.join(user)).await {
+            Ok(metadata) => Ok(metadata.is_dir()),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e).with_context(|| Error::Storage(format!("Read user {} failed.", user))),
+        }
+    }
+
+    async fn users(&self) -> Result<Vec<String>> {
+        self.read_dir(&self.dir, true).await
+    }
+}
+
+/// Store the entries in a SQLite database, whose locking serializes the replicas' writes.
+#[derive(Debug)]
+pub struct SqliteStore {
+    connection: Arc<Mutex<Connection>>,
+}
+
+impl SqliteStore {
+    pub fn open(file: &Path) -> Result<Self> {
+        let error = || Error::Storage(format!("Open policy store {:?} failed.", file));
+        if let Some(dir) = file.parent() {
+            std::fs::create_dir_all(dir).with_context(error)?;
+        }
+        let connection = Connection::open(file).with_context(error)?;
+        Self::init(connection).with_context(error)
+    }
+
+    fn init(connection: Connection) -> Result<Self> {
+        connection.busy_timeout(SQLITE_BUSY_TIMEOUT)?;
+        // WAL lets the readers proceed during the writes.
+        connection.pragma_update(None, "journal_mode", "WAL")?;
+        connection.execute_batch(
+            "CREATE TABLE IF NOT EXISTS users (id TEXT PRIMARY KEY);
+             CREATE TABLE IF NOT EXISTS entries (
+                 user TEXT NOT NULL,
+                 path TEXT NOT NULL,
+                 content TEXT NOT NULL,
+                 PRIMARY KEY (user, path)
+             );",
+        )?;
+        Ok(Self {
+            connection: Arc::new(Mutex::new(connection)),
+        })
+    }
+
+    // Run `f` with the connection off the async runtime.
+    async fn call<T, F>(&self, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Connection) -> rusqlite::Result<T> + Send + 'static,
+    {
+        let connection = self.connection.clone();
+        let error = || Error::Storage("Policy store query failed.".to_owned());
+        tokio::task::spawn_blocking(move || -> Result<T> {
+            let mut connection = connection
+                .lock()
+                .map_err(|_| anyhow!("Policy store poisoned"))?;
+            Ok(f(&mut connection)?)
+        })
+        .await
+        .with_context(error)?
+        .with_context(error)
+    }
+
+    async fn paths(&self, user: &str, dir: &str) -> Result<Vec<String>> {
+        let user = user.to_owned();
+        let prefix = match dir {
+            "" => String::new(),
+            _ => format!("{}/", dir),
+        };
+        self.call(move |connection| {
+            let mut statement = connection.prepare(
+                "SELECT path FROM entries WHERE user = ?1 AND substr(path, 1, length(?2)) = ?2",
+            )?;
+            let paths = statement
+                .query_map(params![user, prefix], |row| row.get(0))?
+                .collect::<rusqlite::Result<Vec<String>>>();
+            paths
+        })
+        .await
+    }
+}
+
+#[async_trait]
+impl PolicyStore for SqliteStore {
+    async fn get(&self, user: &str, path: &str) -> Result<Option<String>> {
+        let (user, path) = (user.to_owned(), path.to_owned());
+        self.call(move |connection| {
+            connection
+                .query_row(
+                    "SELECT content FROM entries WHERE user = ?1 AND path = ?2",
+                    params![user, path],
+                    |row| row.get(0),
+                )
+                .optional()
+        })
+        .await
+    }
+
+    async fn put(&self, user: &str, path: &str, content: &str) -> Result<()> {
+        let (user, path, content) = (user.to_owned(), path.to_owned(), content.to_owned());
+        self.call(move |connection| {
+            connection.execute(
+                "INSERT INTO entries (user, path, content) VALUES (?1, ?2, ?3)
+                 ON CONFLICT (user, path) DO UPDATE SET content = excluded.content",
+                params![user, path, content],
+            )
+        })
+        .await?;
+        Ok(())
+    }
+
+    async fn create(&self, user: &str, path: &str, content: &str) -> Result<bool> {
+        let (user, path, content) = (user.to_owned(), path.to_owned(), content.to_owned());
+        let created = self
+            .call(move |connection| {
+                connection.execute(
+                    "INSERT OR IGNORE INTO entries (user, path, content) VALUES (?1, ?2, ?3)",
+                    params![user, path, content],
+                )
+            })
+            .await?;
+        Ok(created == 1)
+    }
+
+    async fn delete(&self, user: &str, path: &str) -> Result<()> {
+        let (user, path) = (user.to_owned(), path.to_owned());
+        self.call(move |connection| {
+            connection.execute(
+                "DELETE FROM entries WHERE user = ?1 AND path = ?2",
+                params![user, path],
+            )
+        })
+        .await?;
+        Ok(())
+    }
+
+    async fn commit(
+        &self,
+        user: &str,
+        path: &str,
+        record: &str,
+        target: &str,
+        content: Option<&str>,
+    ) -> Result<bool> {
+        let (user, path, record) = (user.to_owned(), path.to_owned(), record.to_owned());
+        let (target, content) = (target.to_owned(), content.map(str::to_owned));
+        self.call(move |connection| {
+            let transaction = connection.transaction()?;
+            let created = transaction.execute(
+                "INSERT OR IGNORE INTO entries (user, path, content) VALUES (?1, ?2, ?3)",
+                params![user, path, record],
+            )? == 1;
+            if created {
+                match content {
+                    Some(content) => transaction.execute(
+                        "INSERT INTO entries (user, path, content) VALUES (?1, ?2, ?3)
+                         ON CONFLICT (user, path) DO UPDATE SET content = excluded.content",
+                        params![user, target, content],
+                    )?,
+                    None => transaction.execute(
+                        "DELETE FROM entries WHERE user = ?1 AND path = ?2",
+                        params![user, target],
+                    )?,
+                };
+            }
+            transaction.commit()?;
+            Ok(created)
+        })
+        .await
+    }
+
+    async fn list(&self, user: &str, dir: &str) -> Result<Vec<String>> {
+        let paths = self.paths(user, dir).await?;
+        Ok(children(paths.iter().map(String::as_str), dir)
+            .0
+            .into_iter()
+            .collect())
+    }
+
+    async fn dirs(&self, user: &str, dir: &str) -> Result<Vec<String>> {
+        let paths = self.paths(user, dir).await?;
+        Ok(children(paths.iter().map(String::as_str), dir)
+            .1
+            .into_iter()
+            .collect())
+    }
+
+    async fn create_user(&self, user: &str) -> Result<bool> {
+        let user = user.to_owned();
+        let created = self
+            .call(move |connection| {
+                connection.execute("INSERT OR IGNORE INTO users (id) VALUES (?1)", [user])
+            })
+            .await?;
+        Ok(created == 1)
+    }
+
+    async fn delete_user(&self, user: &str) -> Result<bool> {
+        let user = user.to_owned();
+        self.call(move |connection| {
+            let transaction = connection.transaction()?;
+            let deleted = transaction.execute("DELETE FROM users WHERE id = ?1", [&user])?;
+            transaction.execute("DELETE FROM entries WHERE user = ?1", [&user])?;
+            transaction.commit()?;
+            Ok(deleted == 1)
+        })
+        .await
+    }
+
+    async fn user_exists(&self, user: &str) -> Result<bool> {
+        let user = user.to_owned();
+        self.call(move |connection| {
+            connection
+                .query_row("SELECT 1 FROM users WHERE id = ?1", [user], |_| Ok(()))
+                .optional()
+                .map(|row| row.is_some())
+        })
+        .await
+    }
+
+    async fn users(&self) -> Result<Vec<String>> {
+        self.call(|connection| {
+            let mut statement = connection.prepare("SELECT id FROM users ORDER BY id")?;
+            let users = statement
+                .query_map([], |row| row.get(0))?
+                .collect::<rusqlite::Result<Vec<String>>>();
+            users
+        })
+        .await
+    }
+}
+
+/// Store the entries in memory, e.g. for the tests.
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    users: RwLock<HashSet<String>>,
+    // The contents keyed by the user and the path.
+    entries: RwLock<HashMap<(String, String), String>>,
+}
+
+impl MemoryStore {
+    async fn paths(&self, user: &str) -> Vec<String> {
+        self.entries
+            .read()
+            .await
+            .keys()
+            .filter(|(owner, _)| owner == user)
+            .map(|(_, path)| path.clone())
+            .collect()
+    }
+}
+
+#[async_trait]
+impl PolicyStore for MemoryStore {
+    async fn get(&self, user: &str, path: &str) -> Result<Option<String>> {
+        let key = (user.to_owned(), path.to_owned());
+        Ok(self.entries.read().await.get(&key).cloned())
+    }
+
+    async fn put(&self, user: &str, path: &str, content: &str) -> Result<()> {
+        let key = (user.to_owned(), path.to_owned());
+        self.entries.write().await.insert(key, content.to_owned());
+        Ok(())
+    }
+
+    async fn create(&self, user: &str, path: &str, content: &str) -> Result<bool> {
+        let key = (user.to_owned(), path.to_owned());
+        let mut entries = self.entries.write().await;
+        if entries.contains_key(&key) {
+            return Ok(false);
+        }
+        entries.insert(key, content.to_owned());
+        Ok(true)
+    }
+
+    async fn delete(&self, user: &str, path: &str) -> Result<()> {
+        let key = (user.to_owned(), path.to_owned());
+        self.entries.write().await.remove(&key);
+        Ok(())
+    }
+
+    async fn commit(
+        &self,
+        user: &str,
+        path: &str,
+        record: &str,
+        target: &str,
+        content: Option<&str>,
+    ) -> Result<bool> {
+        let key = (user.to_owned(), path.to_owned());
+        let mut entries = self.entries.write().await;
+        if entries.contains_key(&key) {
+            return Ok(false);
+        }
+        entries.insert(key, record.to_owned());
+        let target = (user.to_owned(), target.to_owned());
+        match content {
+            Some(content) => entries.insert(target, content.to_owned()),
+            None => entries.remove(&target),
+        };
+        Ok(true)
+    }
+
+    async fn list(&self, user: &str, dir: &str) -> Result<Vec<String>> {
+        let paths = self.paths(user).await;
+        Ok(children(paths.iter().map(String::as_str), dir)
+            .0
+            .into_iter()
+            .collect())
+    }
+
+    async fn dirs(&self, user: &str, dir: &str) -> Result<Vec<String>> {
+        let paths = self.paths(user).await;
+        Ok(children(paths.iter().map(String::as_str), dir)
+            .1
+            .into_iter()
+            .collect())
+    }
+
+    async fn create_user(&self, user: &str) -> Result<bool> {
+        Ok(self.users.write().await.insert(user.to_owned()))
+    }
+
+    async fn delete_user(&self, user: &str) -> Result<bool> {
+        let mut users = self.users.write().await;
+        self.entries
+            .write()
+            .await
+            .retain(|(owner, _), _| owner != user);
+        Ok(users.remove(user))
+    }
+
+    async fn user_exists(&self, user: &str) -> Result<bool> {
+        Ok(self.users.read().await.contains(user))
+    }
+
+    async fn users(&self) -> Result<Vec<String>> {
+        let mut users: Vec<String> = self.users.read().await.iter().cloned().collect();
+        users.sort();
+        Ok(users)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    async fn check_store(store: &dyn PolicyStore) {
+        assert!(store.create_user("team-a").await.unwrap());
+        assert!(!store.create_user("team-a").await.unwrap());
+        assert!(store.create_user("team-b").await.unwrap());
+        assert!(store.user_exists("team-a").await.unwrap());
+        assert!(!store.user_exists("team-c").await.unwrap());
+        assert_eq!(store.users().await.unwrap(), ["team-a", "team-b"]);
+
+        assert_eq!(store.get("team-a", "policy.rego").await.unwrap(), None);
+        store.put("team-a", "policy.rego", "v1").await.unwrap();
+        store.put("team-a", "policy.rego", "v2").await.unwrap();
+        store.put("team-a", "tdx/policy.rego", "tdx").await.unwrap();
+        assert!(store.create("team-a", ".v/p/1", "1").await.unwrap());
+        assert!(!store.create("team-a", ".v/p/1", "2").await.unwrap());
+        assert!(store.create("team-a", ".v/p/2", "2").await.unwrap());
+        assert_eq!(
+            store.get("team-a", "policy.rego").await.unwrap(),
+            Some("v2".to_owned())
+        );
+        assert_eq!(
+            store.get("team-a", ".v/p/1").await.unwrap(),
+            Some("1".to_owned())
+        );
+        assert_eq!(store.get("team-b", "policy.rego").await.unwrap(), None);
+
+        assert_eq!(store.list("team-a", "").await.unwrap(), ["policy.rego"]);
+        assert_eq!(store.dirs("team-a", "").await.unwrap(), [".v", "tdx"]);
+        assert_eq!(store.list("team-a", ".v/p").await.unwrap(), ["1", "2"]);
+        assert!(store.list("team-a", "sgx").await.unwrap().is_empty());
+
+        store.delete("team-a", "tdx/policy.rego").await.unwrap();
+        store.delete("team-a", "tdx/policy.rego").await.unwrap();
+        assert_eq!(store.get("team-a", "tdx/policy.rego").await.unwrap(), None);
+
+        // The record and the target are written together, or neither of them.
+        let commit = |path, content| store.commit("team-a", path, "r", "data.json", content);
+        assert!(commit(".v/d/1", Some("d1")).await.unwrap());
+        assert!(!commit(".v/d/1", Some("d2")).await.unwrap());
+        assert_eq!(
+            store.get("team-a", "data.json").await.unwrap(),
+            Some("d1".to_owned())
+        );
+        assert!(commit(".v/d/2", None).await.unwrap());
+        assert_eq!(store.get("team-a", "data.json").await.unwrap(), None);
+        assert_eq!(store.list("team-a", ".v/d").await.unwrap(), ["1", "2"]);
+        store.recover("team-a").await.unwrap();
+        assert_eq!(store.list("team-a", "").await.unwrap(), ["policy.rego"]);
+
+        assert!(store.delete_user("team-a").await.unwrap());
+        assert!(!store.delete_user("team-a").await.unwrap());
+        assert_eq!(store.get("team-a", "policy.rego").await.unwrap(), None);
+        assert_eq!(store.users().await.unwrap(), ["team-b"]);
+    }
+
+    #[tokio::test]
+    async fn test_fs_store() {
+        let dir = std::env::temp_dir().join(Uuid::new_v4().to_string());
+        check_store(&FsStore::new(dir.clone())).await;
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_fs_recover() {
+        let dir = std::env::temp_dir().join(Uuid::new_v4().to_string());
+        let store = FsStore::new(dir.clone());
+        store.put("default", "policy.rego", "v1").await.unwrap();
+        // A crash after the record is created, but before the target is written.
+        let journal = |path: &str, previous: &str| Journal {
+            path: path.to_owned(),
+            record: "r".to_owned(),
+            target: "policy.rego".to_owned(),
+            previous_sha256: Some(audit::sha256(previous.as_bytes())),
+            content: Some("v2".to_owned()),
+        };
+        let interrupt = |journal: Journal| {
+            let store = &store;
+            async move {
+                let entry = format!("{}/{}.json", JOURNAL_DIR, Uuid::new_v4());
+                let json = serde_json::to_string(&journal).unwrap();
+                store.put("default", &entry, &json).await.unwrap();
+                store.create("default", &journal.path, "r").await.unwrap();
+            }
+        };
+        interrupt(journal(".v/1", "v1")).await;
+        store.recover("default").await.unwrap();
+        assert_eq!(
+            store.get("default", "policy.rego").await.unwrap(),
+            Some("v2".to_owned())
+        );
+        assert!(store.list("default", JOURNAL_DIR).await.unwrap().is_empty());
+
+        // The replay is dropped once the target has changed since.
+        interrupt(journal(".v/2", "v1")).await;
+        store.recover("default").await.unwrap();
+        store.put("default", "policy.rego", "v3").await.unwrap();
+        interrupt(journal(".v/3", "v1")).await;
+        store.recover("default").await.unwrap();
+        assert_eq!(
+            store.get("default", "policy.rego").await.unwrap(),
+            Some("v3".to_owned())
+        );
+        assert!(store.list("default", JOURNAL_DIR).await.unwrap().is_empty());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_store() {
+        let dir = std::env::temp_dir().join(Uuid::new_v4().to_string());
+        let file = dir.join(SQLITE_STORE_NAME);
+        check_store(&SqliteStore::open(&file).unwrap()).await;
+
+        // The replicas sharing the database see each other's changes.
+        let (a, b) = (
+            SqliteStore::open(&file).unwrap(),
+            SqliteStore::open(&file).unwrap(),
+        );
+        a.put("default", "policy.rego", "policy").await.unwrap();
+        assert_eq!(
+            b.get("default", "policy.rego").await.unwrap(),
+            Some("policy".to_owned())
+        );
+        assert!(a.create_user("team-a").await.unwrap());
+        assert!(!b.create_user("team-a").await.unwrap());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_memory_store() {
+        check_store(&MemoryStore::default()).await;
+    }
+}
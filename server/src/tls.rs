@@ -0,0 +1,165 @@
+//! TLS of the gRPC listeners.
+
+use anyhow::{Context, Result};
+use std::path::Path;
+use tonic::transport::{Certificate, Identity, Server, ServerTlsConfig};
+
+fn read(path: &Path) -> Result<Vec<u8>> {
+    std::fs::read(path).with_context(|| format!("Read {:?} failed.", path))
+}
+
+/// Load the TLS configuration of a listener from the PEM files of its certificate chain and
+/// private key. The clients must present a certificate issued by `client_ca` if it's specified.
+pub fn server_config(cert: &Path, key: &Path, client_ca: Option<&Path>) -> Result<ServerTlsConfig> {
+    let mut config = ServerTlsConfig::new().identity(Identity::from_pem(read(cert)?, read(key)?));
+    if let Some(ca) = client_ca {
+        config = config.client_ca_root(Certificate::from_pem(read(ca)?));
+    }
+    Ok(config)
+}
+
+/// The builder of a listener, which serves TLS if `tls` is specified.
+pub fn server(tls: Option<ServerTlsConfig>) -> Result<Server> {
+    let server = Server::builder();
+    match tls {
+        Some(tls) => Ok(server.tls_config(tls)?),
+        None => Ok(server),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::attestation;
+    use crate::attestation_api::attestation_service_client::AttestationServiceClient;
+    use crate::attestation_api::attestation_service_server::AttestationServiceServer;
+    use crate::attestation_api::GetJwksRequest;
+    use crate::store;
+    use crate::user;
+    use attestation_service::{TokenAlgorithm, TokenSigner};
+    use rcgen::{BasicConstraints, CertificateParams, IsCa};
+    use std::path::PathBuf;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::net::TcpListener;
+    use tokio_stream::wrappers::TcpListenerStream;
+    use tonic::transport::{Channel, ClientTlsConfig};
+
+    // The self-signed CA, the server's and the client's certificates issued by it.
+    struct Pki {
+        dir: PathBuf,
+        ca: String,
+        client_cert: String,
+        client_key: String,
+    }
+
+    impl Pki {
+        fn generate() -> Self {
+            let mut params = CertificateParams::new(Vec::new());
+            params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+            let ca = rcgen::Certificate::from_params(params).unwrap();
+            let issue = |name: &str| {
+                let cert = rcgen::generate_simple_self_signed(vec![name.to_string()]).unwrap();
+                (
+                    cert.serialize_pem_with_signer(&ca).unwrap(),
+                    cert.serialize_private_key_pem(),
+                )
+            };
+            let (server_cert, server_key) = issue("localhost");
+            let (client_cert, client_key) = issue("client");
+
+            let dir = std::env::temp_dir().join(format!("tls-{}", uuid::Uuid::new_v4()));
+            std::fs::create_dir_all(&dir).unwrap();
+            std::fs::write(dir.join("server.pem"), server_cert).unwrap();
+            std::fs::write(dir.join("server.key"), server_key).unwrap();
+            std::fs::write(dir.join("ca.pem"), ca.serialize_pem().unwrap()).unwrap();
+            Self {
+                dir,
+                ca: ca.serialize_pem().unwrap(),
+                client_cert,
+                client_key,
+            }
+        }
+
+        fn server_config(&self, client_auth: bool) -> ServerTlsConfig {
+            let ca = self.dir.join("ca.pem");
+            server_config(
+                &self.dir.join("server.pem"),
+                &self.dir.join("server.key"),
+                client_auth.then_some(ca.as_path()),
+            )
+            .unwrap()
+        }
+
+        fn client_config(&self, identity: bool) -> ClientTlsConfig {
+            let config = ClientTlsConfig::new()
+                .domain_name("localhost")
+                .ca_certificate(Certificate::from_pem(&self.ca));
+            match identity {
+                true => config.identity(Identity::from_pem(&self.client_cert, &self.client_key)),
+                false => config,
+            }
+        }
+    }
+
+    impl Drop for Pki {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    // Serve the attestation service on a random port, returns its address.
+    async fn serve(tls: ServerTlsConfig) -> String {
+        let service = attestation::Service::new(
+            Arc::new(user::Users::new(
+                Arc::new(store::MemoryStore::default()),
+                false,
+            )),
+            Arc::new(attestation_service::Service::new()),
+            Arc::new(TokenSigner::generate(TokenAlgorithm::ES256).unwrap()),
+            1,
+            Duration::from_secs(60),
+            None,
+        );
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let router = server(Some(tls))
+            .unwrap()
+            .add_service(AttestationServiceServer::new(service));
+        tokio::spawn(router.serve_with_incoming(TcpListenerStream::new(listener)));
+        format!("https://{}", addr)
+    }
+
+    async fn get_jwks(addr: &str, tls: ClientTlsConfig) -> Result<()> {
+        let channel = Channel::from_shared(addr.to_string())?
+            .tls_config(tls)?
+            .connect()
+            .await?;
+        AttestationServiceClient::new(channel)
+            .get_jwks(GetJwksRequest {})
+            .await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_tls() {
+        let pki = Pki::generate();
+        let addr = serve(pki.server_config(false)).await;
+        assert!(get_jwks(&addr, pki.client_config(false)).await.is_ok());
+
+        // The server isn't trusted without the CA.
+        let tls = ClientTlsConfig::new().domain_name("localhost");
+        assert!(get_jwks(&addr, tls).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_mutual_tls() {
+        let pki = Pki::generate();
+        let addr = serve(pki.server_config(true)).await;
+        assert!(get_jwks(&addr, pki.client_config(true)).await.is_ok());
+        assert!(
+            get_jwks(&addr, pki.client_config(false)).await.is_err(),
+            "The client without certificate should be rejected"
+        );
+    }
+}
@@ -1,84 +1,639 @@
-use anyhow::Result;
-use std::path::{Path, PathBuf};
-use tokio::fs;
+use crate::common;
+use crate::store::PolicyStore;
+use anyhow::{anyhow, Context, Result};
+use attestation_service::audit;
+use attestation_service::{PolicyData, TeePolicies};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::RwLock;
 
-const DEFAULT_USER_ID: &str = "default";
+pub const DEFAULT_USER_ID: &str = "default";
+const MAX_USER_ID_LEN: usize = 64;
 const POLICY_NAME: &str = "policy.rego";
 const REFERENCE_DATA_NAME: &str = "reference_data.json";
+// The hidden directory of the versions, which isn't taken as a TEE's directory.
+const VERSIONS_DIR: &str = ".versions";
 
-#[derive(Debug, Default)]
+/// The user or version which doesn't exist, unlike the store's failures.
+#[derive(Debug)]
+pub struct NotFound(String);
+
+impl std::fmt::Display for NotFound {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} does not exist", self.0)
+    }
+}
+
+impl std::error::Error for NotFound {}
+
+/// The versioned contents.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Policy,
+    ReferenceData,
+}
+
+impl Kind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Kind::Policy => "policy",
+            Kind::ReferenceData => "reference_data",
+        }
+    }
+
+    fn file_name(&self) -> &'static str {
+        match self {
+            Kind::Policy => POLICY_NAME,
+            Kind::ReferenceData => REFERENCE_DATA_NAME,
+        }
+    }
+}
+
+/// An immutable version of the policy or reference data.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Version {
+    pub id: u64,
+    /// The time (seconds since UNIX epoch).
+    pub time: u64,
+    pub author: String,
+    /// The hex encoded SHA-256 of `content`.
+    pub sha256: Option<String>,
+    /// `None` is the built-in default, which the restored default ones record.
+    pub content: Option<String>,
+}
+
+// The path of the user's entry, or the TEE's one.
+fn path(tee: Option<&str>, name: &str) -> String {
+    match tee {
+        Some(tee) => format!("{}/{}", tee, name),
+        None => name.to_owned(),
+    }
+}
+
+#[derive(Debug)]
 pub struct User {
     id: String,
+    store: Arc<dyn PolicyStore>,
+    // The policies served to the attestations if they're cached, `None` until they're loaded or
+    // once they're changed.
+    cache: Option<Mutex<Option<TeePolicies>>>,
 }
 
 impl User {
-    pub fn default() -> Self {
+    fn new(id: &str, store: Arc<dyn PolicyStore>, cache: bool) -> Self {
         Self {
-            id: DEFAULT_USER_ID.to_owned(),
+            id: id.to_owned(),
+            store,
+            cache: cache.then(|| Mutex::new(None)),
         }
     }
 
-    #[allow(dead_code)]
-    pub fn from_str(id: &str) -> Result<Self> {
-        Ok(Self { id: id.to_owned() })
+    // Drop the cached policies, which are reloaded from the store on demand.
+    fn invalidate(&self) {
+        if let Some(cache) = &self.cache {
+            *cache.lock().unwrap_or_else(|e| e.into_inner()) = None;
+        }
+    }
+
+    pub async fn policy(&self, tee: Option<&str>) -> Result<Option<String>> {
+        self.store.get(&self.id, &path(tee, POLICY_NAME)).await
+    }
+
+    pub async fn reference_data(&self, tee: Option<&str>) -> Result<Option<String>> {
+        self.store
+            .get(&self.id, &path(tee, REFERENCE_DATA_NAME))
+            .await
+    }
+
+    fn versions_dir(tee: Option<&str>, kind: Kind) -> String {
+        path(tee, &format!("{}/{}", VERSIONS_DIR, kind.name()))
     }
 
-    // Fetch the users working directory.
-    fn workdir(&self, dir: &Path) -> PathBuf {
-        dir.join("users").join(&self.id)
+    /// The versions of the policy or reference data in the order of their ids.
+    pub async fn versions(&self, tee: Option<&str>, kind: Kind) -> Result<Vec<Version>> {
+        let dir = Self::versions_dir(tee, kind);
+        let mut versions = Vec::new();
+        for name in self.store.list(&self.id, &dir).await? {
+            let entry = format!("{}/{}", dir, name);
+            let content = match self.store.get(&self.id, &entry).await? {
+                Some(content) => content,
+                None => continue,
+            };
+            let version: Version = serde_json::from_str(&content)
+                .with_context(|| format!("Parse version {} failed.", entry))?;
+            versions.push(version);
+        }
+        versions.sort_by_key(|version| version.id);
+        Ok(versions)
     }
 
-    pub async fn policy(&self, dir: &Path) -> Result<Option<String>> {
-        let file = self.workdir(dir).join(POLICY_NAME);
-        let policy = match file.exists() {
-            true => Some(fs::read_to_string(file).await?),
-            false => None,
+    pub async fn version(&self, tee: Option<&str>, kind: Kind, id: u64) -> Result<Version> {
+        let entry = format!("{}/{}.json", Self::versions_dir(tee, kind), id);
+        match self.store.get(&self.id, &entry).await? {
+            Some(content) => Ok(serde_json::from_str(&content)?),
+            None => Err(NotFound(format!("Version {}", id)).into()),
+        }
+    }
+
+    /// Set the policy or reference data to `content`, or restore the default one if `None`,
+    /// which is recorded as a new version by `author`. The version and the content are committed
+    /// together, so a change is never left unversioned.
+    pub async fn update(
+        &self,
+        tee: Option<&str>,
+        kind: Kind,
+        author: &str,
+        content: Option<String>,
+    ) -> Result<Version> {
+        self.store.recover(&self.id).await?;
+        let mut version = Version {
+            id: match self.versions(tee, kind).await?.last() {
+                Some(last) => last.id + 1,
+                None => 1,
+            },
+            time: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+            author: author.to_owned(),
+            sha256: content
+                .as_ref()
+                .map(|content| audit::sha256(content.as_bytes())),
+            content,
         };
-        Ok(policy)
+        let target = path(tee, kind.file_name());
+        // Another replica sharing the store may have taken the id.
+        loop {
+            let entry = format!("{}/{}.json", Self::versions_dir(tee, kind), version.id);
+            let json = serde_json::to_string(&version)?;
+            let content = version.content.as_deref();
+            if self
+                .store
+                .commit(&self.id, &entry, &json, &target, content)
+                .await?
+            {
+                break;
+            }
+            version.id += 1;
+        }
+        self.invalidate();
+        Ok(version)
     }
 
-    pub async fn reference_data(&self, dir: &Path) -> Result<Option<String>> {
-        let file = self.workdir(dir).join(REFERENCE_DATA_NAME);
-        let reference_data = match file.exists() {
-            true => Some(fs::read_to_string(file).await?),
-            false => None,
+    /// Restore the policy or reference data to the content of version `id`, which is recorded
+    /// as a new version by `author`. Returns the new version along with the replaced content.
+    pub async fn rollback(
+        &self,
+        tee: Option<&str>,
+        kind: Kind,
+        id: u64,
+        author: &str,
+    ) -> Result<(Version, Option<String>)> {
+        let target = self.version(tee, kind, id).await?;
+        let replaced = self
+            .store
+            .get(&self.id, &path(tee, kind.file_name()))
+            .await?;
+        let version = self.update(tee, kind, author, target.content).await?;
+        Ok((version, replaced))
+    }
+
+    // Whether the policies are cached.
+    fn cached(&self) -> bool {
+        self.cache
+            .as_ref()
+            .is_some_and(|cache| cache.lock().unwrap_or_else(|e| e.into_inner()).is_some())
+    }
+
+    /// The TEE-agnostic policy and reference data along with the ones of each TEE, which are
+    /// cached until they're changed or reloaded.
+    pub async fn policies(&self) -> Result<TeePolicies> {
+        let cache = match &self.cache {
+            Some(cache) => cache,
+            None => return self.load().await,
         };
-        Ok(reference_data)
+        if let Some(policies) = cache.lock().unwrap_or_else(|e| e.into_inner()).as_ref() {
+            return Ok(policies.clone());
+        }
+        let policies = self.load().await?;
+        *cache.lock().unwrap_or_else(|e| e.into_inner()) = Some(policies.clone());
+        Ok(policies)
     }
 
-    pub async fn set_policy(&self, dir: &Path, content: String) -> Result<()> {
-        let path = self.workdir(dir);
-        if !path.exists() {
-            fs::create_dir_all(path.as_path()).await?;
+    /// Reload the policies from the store, which replace the cached ones only if `check` accepts
+    /// each of them, and each reference data is JSON. Returns the replaced policies.
+    pub async fn reload(&self, check: impl Fn(&str) -> Result<()>) -> Result<Option<TeePolicies>> {
+        let policies = self.load().await?;
+        let all = std::iter::once((None, &policies.common))
+            .chain(policies.tees.iter().map(|(tee, data)| (Some(tee), data)));
+        for (tee, data) in all {
+            if let Some(policy) = &data.policy {
+                check(policy).with_context(|| match tee {
+                    Some(tee) => format!("Illegal {} policy of user {}", tee, self.id),
+                    None => format!("Illegal policy of user {}", self.id),
+                })?;
+            }
+            if let Some(reference_data) = &data.reference_data {
+                serde_json::from_str::<serde_json::Value>(reference_data).with_context(
+                    || match tee {
+                        Some(tee) => format!("Illegal {} reference data of user {}", tee, self.id),
+                        None => format!("Illegal reference data of user {}", self.id),
+                    },
+                )?;
+            }
         }
-        let file = path.join(POLICY_NAME);
-        fs::write(file.as_path(), &content).await?;
-        Ok(())
+        Ok(match &self.cache {
+            Some(cache) => cache
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .replace(policies),
+            None => None,
+        })
     }
 
-    pub async fn set_reference_data(&self, dir: &Path, content: String) -> Result<()> {
-        let path = self.workdir(dir);
-        if !path.exists() {
-            fs::create_dir_all(path.as_path()).await?;
+    async fn load(&self) -> Result<TeePolicies> {
+        self.store.recover(&self.id).await?;
+        let mut policies = TeePolicies::from(PolicyData {
+            policy: self.policy(None).await?,
+            reference_data: self.reference_data(None).await?,
+        });
+        for tee in self.store.dirs(&self.id, "").await? {
+            if tee.starts_with('.') {
+                continue;
+            }
+            let data = PolicyData {
+                policy: self.policy(Some(&tee)).await?,
+                reference_data: self.reference_data(Some(&tee)).await?,
+            };
+            policies.tees.insert(tee, data);
         }
-        let file = path.join(REFERENCE_DATA_NAME);
-        fs::write(file.as_path(), &content).await?;
-        Ok(())
+        Ok(policies)
+    }
+}
+
+// The user id names the user's directory of the filesystem store, so it's restricted to a safe
+// file name.
+fn check_id(id: &str) -> Result<()> {
+    let valid = !id.is_empty()
+        && id.len() <= MAX_USER_ID_LEN
+        && !id.starts_with('.')
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.');
+    if !valid {
+        return Err(anyhow!("Illegal user id: {:?}", id));
+    }
+    Ok(())
+}
+
+/// The registry of the tenants, each of them has isolated policy and reference data in the
+/// policy store. The users are loaded from the store on demand.
+#[derive(Debug)]
+pub struct Users {
+    store: Arc<dyn PolicyStore>,
+    // Whether the users' policies are cached, which requires the external changes of the store
+    // to be reloaded.
+    cache: bool,
+    users: RwLock<HashMap<String, Arc<RwLock<User>>>>,
+}
+
+impl Users {
+    /// The users' policies are cached if `cache`, see [`Users::reload`].
+    pub fn new(store: Arc<dyn PolicyStore>, cache: bool) -> Self {
+        let mut users = HashMap::new();
+        users.insert(
+            DEFAULT_USER_ID.to_owned(),
+            Arc::new(RwLock::new(User::new(
+                DEFAULT_USER_ID,
+                store.clone(),
+                cache,
+            ))),
+        );
+        Self {
+            store,
+            cache,
+            users: RwLock::new(users),
+        }
+    }
+
+    fn user(&self, id: &str) -> Arc<RwLock<User>> {
+        Arc::new(RwLock::new(User::new(id, self.store.clone(), self.cache)))
     }
 
-    pub async fn delete_policy(&self, dir: &Path) -> Result<()> {
-        let file = self.workdir(dir).join(POLICY_NAME);
-        if file.exists() {
-            fs::remove_file(file.as_path()).await?;
+    /// Resolve the request's user, the default user if absent.
+    pub async fn get(&self, user: Option<&common::User>) -> Result<Arc<RwLock<User>>> {
+        let id = user.map_or(DEFAULT_USER_ID, |user| user.id.as_str());
+        if let Some(user) = self.users.read().await.get(id) {
+            return Ok(user.clone());
         }
+
+        check_id(id)?;
+        if !self.store.user_exists(id).await? {
+            return Err(NotFound(format!("User {}", id)).into());
+        }
+        let mut users = self.users.write().await;
+        let user = users.entry(id.to_owned()).or_insert_with(|| self.user(id));
+        Ok(user.clone())
+    }
+
+    pub async fn create(&self, id: &str) -> Result<()> {
+        check_id(id)?;
+        let mut users = self.users.write().await;
+        if id == DEFAULT_USER_ID || !self.store.create_user(id).await? {
+            return Err(anyhow!("User {} already exists", id));
+        }
+        users.insert(id.to_owned(), self.user(id));
         Ok(())
     }
 
-    pub async fn delete_reference_data(&self, dir: &Path) -> Result<()> {
-        let file = self.workdir(dir).join(REFERENCE_DATA_NAME);
-        if file.exists() {
-            fs::remove_file(file.as_path()).await?;
+    /// Delete the user along with its policy and reference data.
+    pub async fn delete(&self, id: &str) -> Result<()> {
+        check_id(id)?;
+        if id == DEFAULT_USER_ID {
+            return Err(anyhow!("The default user can't be deleted"));
+        }
+        let mut users = self.users.write().await;
+        // Wait for the in-flight requests of the user.
+        let _user = match users.remove(id) {
+            Some(user) => Some(user.write_owned().await),
+            None => None,
+        };
+        if !self.store.delete_user(id).await? {
+            return Err(NotFound(format!("User {}", id)).into());
         }
         Ok(())
     }
+
+    /// Reload the policies of the user `id` changed in the store, e.g. edited in place. They're
+    /// swapped in only if `check` accepts each of them, otherwise the cached ones are kept.
+    /// Returns the replaced policies.
+    pub async fn reload(
+        &self,
+        id: &str,
+        check: impl Fn(&str) -> Result<()>,
+    ) -> Result<Option<TeePolicies>> {
+        check_id(id)?;
+        if id != DEFAULT_USER_ID && !self.store.user_exists(id).await? {
+            self.users.write().await.remove(id);
+            return Ok(None);
+        }
+        let user = self
+            .users
+            .write()
+            .await
+            .entry(id.to_owned())
+            .or_insert_with(|| self.user(id))
+            .clone();
+        let user = user.read().await;
+        user.reload(check).await
+    }
+
+    /// The number of the users whose policies are cached.
+    pub async fn cached(&self) -> usize {
+        let users: Vec<_> = self.users.read().await.values().cloned().collect();
+        let mut cached = 0;
+        for user in users {
+            if user.read().await.cached() {
+                cached += 1;
+            }
+        }
+        cached
+    }
+
+    /// The ids of all the users, including the default user.
+    pub async fn list(&self) -> Result<Vec<String>> {
+        let _users = self.users.read().await;
+        let mut ids = vec![DEFAULT_USER_ID.to_owned()];
+        for id in self.store.users().await? {
+            if id != DEFAULT_USER_ID && check_id(&id).is_ok() {
+                ids.push(id);
+            }
+        }
+        ids[1..].sort();
+        Ok(ids)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::{FsStore, MemoryStore};
+    use uuid::Uuid;
+
+    fn user(id: &str) -> common::User {
+        common::User { id: id.to_owned() }
+    }
+
+    #[tokio::test]
+    async fn test_users() {
+        let workdir = std::env::temp_dir().join(Uuid::new_v4().to_string());
+        let store = || Arc::new(FsStore::new(workdir.clone()));
+        let users = Users::new(store(), false);
+
+        assert_eq!(users.list().await.unwrap(), vec![DEFAULT_USER_ID]);
+        assert!(users.get(None).await.is_ok(), "Default user should exist");
+        assert!(users.get(Some(&user("team-a"))).await.is_err());
+
+        users.create("team-b").await.unwrap();
+        users.create("team-a").await.unwrap();
+        assert!(users.create("team-a").await.is_err(), "User already exists");
+        assert!(users.create(DEFAULT_USER_ID).await.is_err());
+        assert_eq!(
+            users.list().await.unwrap(),
+            vec![DEFAULT_USER_ID, "team-a", "team-b"]
+        );
+
+        // Policies are isolated.
+        let team_a = users.get(Some(&user("team-a"))).await.unwrap();
+        team_a
+            .write()
+            .await
+            .update(
+                None,
+                Kind::Policy,
+                "alice",
+                Some("policy of team-a".to_owned()),
+            )
+            .await
+            .unwrap();
+        let team_b = users.get(Some(&user("team-b"))).await.unwrap();
+        assert!(team_b.read().await.policy(None).await.unwrap().is_none());
+
+        // The users are loaded from the store on demand.
+        let reloaded = Users::new(store(), false);
+        let team_a = reloaded.get(Some(&user("team-a"))).await.unwrap();
+        assert_eq!(
+            team_a.read().await.policy(None).await.unwrap(),
+            Some("policy of team-a".to_owned())
+        );
+
+        users.delete("team-a").await.unwrap();
+        assert!(users.get(Some(&user("team-a"))).await.is_err());
+        assert!(users.delete("team-a").await.is_err(), "User does not exist");
+        assert!(users.delete(DEFAULT_USER_ID).await.is_err());
+
+        std::fs::remove_dir_all(workdir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_tee_policies() {
+        let users = Users::new(Arc::new(MemoryStore::default()), false);
+        let user = users.get(None).await.unwrap();
+        let user = user.write().await;
+
+        for (tee, kind, content) in [
+            (None, Kind::Policy, "common policy"),
+            (Some("tdx"), Kind::Policy, "tdx policy"),
+            (Some("sgx"), Kind::ReferenceData, "sgx reference data"),
+        ] {
+            user.update(tee, kind, "alice", Some(content.to_owned()))
+                .await
+                .unwrap();
+        }
+        assert_eq!(
+            user.policy(Some("tdx")).await.unwrap(),
+            Some("tdx policy".to_owned())
+        );
+        assert!(user.policy(Some("sgx")).await.unwrap().is_none());
+
+        let policies = user.policies().await.unwrap();
+        assert_eq!(policies.select("tdx").policy, Some("tdx policy".to_owned()));
+        let sgx = policies.select("sgx");
+        assert_eq!(sgx.policy, Some("common policy".to_owned()));
+        assert_eq!(sgx.reference_data, Some("sgx reference data".to_owned()));
+
+        user.update(Some("tdx"), Kind::Policy, "alice", None)
+            .await
+            .unwrap();
+        let policies = user.policies().await.unwrap();
+        assert_eq!(
+            policies.select("tdx").policy,
+            Some("common policy".to_owned())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_versions() {
+        let users = Users::new(Arc::new(MemoryStore::default()), false);
+        let user = users.get(None).await.unwrap();
+        let user = user.write().await;
+
+        for (author, policy) in [("alice", "policy 1"), ("bob", "policy 2")] {
+            user.update(None, Kind::Policy, author, Some(policy.to_owned()))
+                .await
+                .unwrap();
+        }
+        let versions = user.versions(None, Kind::Policy).await.unwrap();
+        assert_eq!(versions.len(), 2);
+        assert_eq!((versions[0].id, versions[0].author.as_str()), (1, "alice"));
+        assert_eq!(versions[1].sha256, Some(audit::sha256(b"policy 2")));
+        // The versions of the other contents and TEEs are separate.
+        assert!(user
+            .versions(Some("tdx"), Kind::Policy)
+            .await
+            .unwrap()
+            .is_empty());
+        assert!(user
+            .versions(None, Kind::ReferenceData)
+            .await
+            .unwrap()
+            .is_empty());
+        // The versions directory isn't taken as a TEE.
+        assert!(user.policies().await.unwrap().tees.is_empty());
+
+        let (version, replaced) = user.rollback(None, Kind::Policy, 1, "carol").await.unwrap();
+        assert_eq!(replaced, Some("policy 2".to_owned()));
+        assert_eq!(
+            (version.id, version.content.as_deref()),
+            (3, Some("policy 1"))
+        );
+        assert_eq!(
+            user.policy(None).await.unwrap(),
+            Some("policy 1".to_owned())
+        );
+        assert!(user
+            .rollback(None, Kind::Policy, 10, "carol")
+            .await
+            .is_err());
+
+        // The restored default is versioned, and rolled back to by deleting the content.
+        let version = user.update(None, Kind::Policy, "dave", None).await.unwrap();
+        assert_eq!((version.id, version.content), (4, None));
+        assert_eq!(user.policy(None).await.unwrap(), None);
+        user.rollback(None, Kind::Policy, 3, "erin").await.unwrap();
+        let (_, replaced) = user.rollback(None, Kind::Policy, 4, "erin").await.unwrap();
+        assert_eq!(replaced, Some("policy 1".to_owned()));
+        assert_eq!(user.policy(None).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_illegal_user_id() {
+        let users = Users::new(Arc::new(MemoryStore::default()), false);
+        for id in ["", "..", "../etc", "a/b", ".hidden", &"x".repeat(65)] {
+            assert!(
+                users.create(id).await.is_err(),
+                "{:?} should be illegal",
+                id
+            );
+            assert!(users.get(Some(&user(id))).await.is_err());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cache() {
+        let store = Arc::new(MemoryStore::default());
+        let users = Users::new(store.clone(), true);
+        let user = users.get(None).await.unwrap();
+        let user = user.read().await;
+        user.update(None, Kind::Policy, "alice", Some("v1".to_owned()))
+            .await
+            .unwrap();
+        assert_eq!(user.policies().await.unwrap().common.policy.unwrap(), "v1");
+
+        // The external changes are served once they're reloaded.
+        store.put(DEFAULT_USER_ID, POLICY_NAME, "v2").await.unwrap();
+        assert_eq!(user.policies().await.unwrap().common.policy.unwrap(), "v1");
+        assert_eq!(users.cached().await, 1);
+        let check = |policy: &str| match policy {
+            "invalid" => Err(anyhow!("Syntax error")),
+            _ => Ok(()),
+        };
+        let replaced = users.reload(DEFAULT_USER_ID, check).await.unwrap();
+        assert_eq!(replaced.unwrap().common.policy.unwrap(), "v1");
+        assert_eq!(user.policies().await.unwrap().common.policy.unwrap(), "v2");
+
+        // The invalid ones are rejected and the cached ones keep serving.
+        store
+            .put(DEFAULT_USER_ID, &path(Some("tdx"), POLICY_NAME), "invalid")
+            .await
+            .unwrap();
+        assert!(users.reload(DEFAULT_USER_ID, check).await.is_err());
+        let policies = user.policies().await.unwrap();
+        assert_eq!(policies.common.policy.unwrap(), "v2");
+        assert!(policies.tees.is_empty());
+
+        // So is the reference data which isn't JSON.
+        store
+            .delete(DEFAULT_USER_ID, &path(Some("tdx"), POLICY_NAME))
+            .await
+            .unwrap();
+        store
+            .put(DEFAULT_USER_ID, REFERENCE_DATA_NAME, "{\"svn\": ")
+            .await
+            .unwrap();
+        assert!(users.reload(DEFAULT_USER_ID, check).await.is_err());
+        assert!(user
+            .policies()
+            .await
+            .unwrap()
+            .common
+            .reference_data
+            .is_none());
+        store
+            .put(DEFAULT_USER_ID, REFERENCE_DATA_NAME, "{\"svn\": 1}")
+            .await
+            .unwrap();
+        users.reload(DEFAULT_USER_ID, check).await.unwrap();
+        let policies = user.policies().await.unwrap();
+        assert_eq!(policies.common.reference_data.unwrap(), "{\"svn\": 1}");
+    }
 }
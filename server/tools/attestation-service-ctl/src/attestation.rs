@@ -1,27 +1,203 @@
 use anyhow::{anyhow, Result};
 use log::Level;
+use prost::Message;
+use prost_types::value::Kind;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
 use std::fs;
 use std::path::Path;
+use tonic::transport::Channel;
+use tonic::Status;
 
 use crate::attestation_api::attestation_service_client::AttestationServiceClient;
-use crate::attestation_api::{AttestationRequest, AttestationResponse};
+use crate::attestation_api::{
+    batch_attestation_item, AttestationRequest, AttestationResponse, AttestationResults,
+    BatchAttestationRequest, BatchAttestationResponse, ChallengeRequest, ChallengeResponse,
+    GetJwksRequest, GetJwksResponse, TokenFormat,
+};
+use crate::google::rpc;
 
-pub const DEFAULT_ATTESTATION_ADDR: &str = "https://127.0.0.1:3000";
+pub const DEFAULT_ATTESTATION_ADDR: &str = "http://127.0.0.1:3000";
 
-pub async fn attestation_cmd(evidence_path: &Path, address: &str) -> Result<()> {
+pub async fn attestation_cmd(
+    evidence_path: &Path,
+    jwt: bool,
+    user_id: Option<&str>,
+    channel: Channel,
+) -> Result<()> {
     let evidence =
         fs::read_to_string(evidence_path).map_err(|e| anyhow!("Read evidence error: {:?}", e))?;
 
     let request = AttestationRequest {
         evidence: evidence.into_bytes(),
-        user: None,
+        user: crate::management::user(user_id),
+        format: match jwt {
+            true => TokenFormat::Jwt as i32,
+            false => TokenFormat::Json as i32,
+        },
     };
 
     // This can connect to Attestation-Server deployed locally or remotely.
-    let mut client = AttestationServiceClient::connect(address.to_string()).await?;
-    let response: AttestationResponse = client.attestation(request).await?.into_inner();
-    let results = String::from_utf8(response.attestation_results)?;
-    log!(Level::Info, "{}", results);
+    let mut client = AttestationServiceClient::new(channel);
+    let response: AttestationResponse = client
+        .attestation(request)
+        .await
+        .map_err(status_error)?
+        .into_inner();
+    log!(Level::Info, "{}", render_response(response, jwt)?);
 
     Ok(())
 }
+
+// The machine-readable reason in the `google.rpc.ErrorInfo` details, if any.
+fn reason(details: &rpc::Status) -> Option<String> {
+    details
+        .details
+        .iter()
+        .find(|any| any.type_url.ends_with("google.rpc.ErrorInfo"))
+        .and_then(|any| rpc::ErrorInfo::decode(any.value.as_slice()).ok())
+        .map(|info| info.reason)
+}
+
+// Append the machine-readable reason of the status details to the error.
+fn status_error(status: Status) -> anyhow::Error {
+    let reason = rpc::Status::decode(status.details())
+        .ok()
+        .as_ref()
+        .and_then(reason);
+    match reason {
+        Some(reason) => anyhow!("{} ({})", status, reason),
+        None => status.into(),
+    }
+}
+
+// Render the attestation results in the response like the `attest` command.
+fn render_response(response: AttestationResponse, jwt: bool) -> Result<String> {
+    #[allow(deprecated)]
+    let attestation_results = String::from_utf8(response.attestation_results)?;
+    Ok(match (jwt, response.results) {
+        (false, Some(results)) => render_results(&results),
+        // The token, or the JSON results of the server without the typed ones.
+        _ => attestation_results,
+    })
+}
+
+pub async fn batch_attestation_cmd(
+    evidence_paths: &[String],
+    jwt: bool,
+    user_id: Option<&str>,
+    channel: Channel,
+) -> Result<()> {
+    let evidences = evidence_paths
+        .iter()
+        .map(|path| fs::read(path).map_err(|e| anyhow!("Read evidence {} error: {:?}", path, e)))
+        .collect::<Result<Vec<_>>>()?;
+
+    let request = BatchAttestationRequest {
+        evidences,
+        user: crate::management::user(user_id),
+        format: match jwt {
+            true => TokenFormat::Jwt as i32,
+            false => TokenFormat::Json as i32,
+        },
+    };
+
+    let mut client = AttestationServiceClient::new(channel);
+    let response: BatchAttestationResponse = client
+        .batch_attestation(request)
+        .await
+        .map_err(status_error)?
+        .into_inner();
+    for (path, item) in evidence_paths.iter().zip(response.items) {
+        match item.result {
+            Some(batch_attestation_item::Result::Response(response)) => {
+                log!(Level::Info, "{}: {}", path, render_response(response, jwt)?)
+            }
+            Some(batch_attestation_item::Result::Error(details)) => match reason(&details) {
+                Some(reason) => log!(Level::Error, "{}: {} ({})", path, details.message, reason),
+                None => log!(Level::Error, "{}: {}", path, details.message),
+            },
+            None => log!(Level::Error, "{}: No result", path),
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn challenge_cmd(channel: Channel) -> Result<()> {
+    let mut client = AttestationServiceClient::new(channel);
+    let response: ChallengeResponse = client.challenge(ChallengeRequest {}).await?.into_inner();
+    log!(
+        Level::Info,
+        "Nonce: {} (expires at {})",
+        response.nonce,
+        response.expiration
+    );
+
+    Ok(())
+}
+
+pub async fn get_jwks_cmd(channel: Channel) -> Result<()> {
+    let mut client = AttestationServiceClient::new(channel);
+    let response: GetJwksResponse = client.get_jwks(GetJwksRequest {}).await?.into_inner();
+    let jwks = String::from_utf8(response.content)?;
+    log!(Level::Info, "{}", jwks);
+
+    Ok(())
+}
+
+// Render the typed Attestation Results, with a line per violation and TCB claim.
+fn render_results(results: &AttestationResults) -> String {
+    let mut lines = vec![
+        format!("TEE: {}", results.tee),
+        format!("Allow: {}", results.allow),
+    ];
+    if !results.verifier_error.is_empty() {
+        lines.push(format!(
+            "Verifier: {} ({})",
+            results.verifier_error, results.verifier_error_reason
+        ));
+    }
+    let violations = results
+        .decision
+        .as_ref()
+        .map_or(&[][..], |decision| &decision.violations[..]);
+    if !violations.is_empty() {
+        lines.push("Violations:".to_string());
+        for violation in violations {
+            lines.push(format!(
+                "  {}: observed {}, expected {}",
+                violation.claim,
+                json_value(violation.observed.as_ref()),
+                json_value(violation.expected.as_ref())
+            ));
+        }
+    }
+    if !results.claims.is_empty() {
+        let claims: BTreeMap<_, _> = results.claims.iter().collect();
+        lines.push("TCB claims:".to_string());
+        for (claim, value) in claims {
+            lines.push(format!("  {}: {}", claim, json_value(Some(value))));
+        }
+    }
+    lines.join("\n")
+}
+
+fn json_value(value: Option<&prost_types::Value>) -> Value {
+    match value.and_then(|value| value.kind.as_ref()) {
+        None | Some(Kind::NullValue(_)) => Value::Null,
+        Some(Kind::BoolValue(value)) => json!(value),
+        Some(Kind::NumberValue(value)) => json!(value),
+        Some(Kind::StringValue(value)) => json!(value),
+        Some(Kind::ListValue(list)) => {
+            Value::Array(list.values.iter().map(|v| json_value(Some(v))).collect())
+        }
+        Some(Kind::StructValue(fields)) => Value::Object(
+            fields
+                .fields
+                .iter()
+                .map(|(key, value)| (key.clone(), json_value(Some(value))))
+                .collect(),
+        ),
+    }
+}
@@ -0,0 +1,14 @@
+use anyhow::Result;
+use attestation_service::audit;
+use log::Level;
+use std::path::Path;
+
+pub fn verify_cmd(file: &Path) -> Result<()> {
+    let records = audit::verify_file(file)?;
+    log!(
+        Level::Info,
+        "The hash chain of {} records is intact",
+        records
+    );
+    Ok(())
+}
@@ -0,0 +1,60 @@
+use anyhow::{Context, Result};
+use clap::Args;
+use std::fs;
+use std::path::PathBuf;
+use tonic::codegen::InterceptedService;
+use tonic::metadata::{Ascii, MetadataValue};
+use tonic::service::Interceptor;
+use tonic::transport::Channel;
+use tonic::{Request, Status};
+
+/// The connection to the `management-sock`, which carries the bearer token if any.
+pub type Connection = InterceptedService<Channel, Bearer>;
+
+/// The credentials of the management requests, the client certificate of `--tls-cert`
+/// authenticates them if neither token is specified.
+#[derive(Args)]
+pub struct AuthArgs {
+    /// The bearer token which authenticates the management requests.
+    #[clap(long, value_parser, conflicts_with = "token-file")]
+    token: Option<String>,
+
+    /// The file of the bearer token, which keeps it out of the command line.
+    #[clap(long, value_parser)]
+    token_file: Option<PathBuf>,
+}
+
+impl AuthArgs {
+    fn bearer(&self) -> Result<Bearer> {
+        let token = match (&self.token, &self.token_file) {
+            (Some(token), _) => token.clone(),
+            (None, Some(path)) => fs::read_to_string(path)
+                .with_context(|| format!("Read token {:?} failed.", path))?,
+            (None, None) => return Ok(Bearer(None)),
+        };
+        let value = format!("Bearer {}", token.trim())
+            .parse()
+            .context("Illegal token")?;
+        Ok(Bearer(Some(value)))
+    }
+
+    /// Authenticate the requests over `channel`.
+    pub fn authenticate(&self, channel: Channel) -> Result<Connection> {
+        Ok(InterceptedService::new(channel, self.bearer()?))
+    }
+}
+
+/// The client interceptor which sets the `authorization` header of the requests.
+#[derive(Clone)]
+pub struct Bearer(Option<MetadataValue<Ascii>>);
+
+impl Interceptor for Bearer {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        if let Some(value) = &self.0 {
+            request
+                .metadata_mut()
+                .insert("authorization", value.clone());
+        }
+        Ok(request)
+    }
+}
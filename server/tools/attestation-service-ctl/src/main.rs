@@ -1,12 +1,20 @@
+// `shadow-rs` generated code and `tonic::Status` results trip these lints.
+#![allow(clippy::empty_line_after_doc_comments, clippy::result_large_err)]
+
 use anyhow::{anyhow, Result};
 use attestation::DEFAULT_ATTESTATION_ADDR;
 use clap::{Args, Parser, Subcommand};
 use management::DEFAULT_MANAGEMENT_ADDR;
+use management_api::VersionKind;
 use shadow_rs::shadow;
 use std::path::Path;
 
 mod attestation;
+mod audit;
+mod auth;
 mod management;
+mod rvps;
+mod tls;
 
 pub mod management_api {
     tonic::include_proto!("management");
@@ -17,6 +25,14 @@ pub mod attestation_api {
 pub mod common {
     tonic::include_proto!("common");
 }
+pub mod reference_api {
+    tonic::include_proto!("reference");
+}
+pub mod google {
+    pub mod rpc {
+        tonic::include_proto!("google.rpc");
+    }
+}
 
 #[macro_use]
 extern crate log;
@@ -32,6 +48,22 @@ struct Cli {
     /// The Attestation Server's `management-sock` address.
     #[clap(long, value_parser, default_value_t = String::from(DEFAULT_MANAGEMENT_ADDR))]
     addr: String,
+
+    /// The user (tenant) whose policy, reference data and attestation are requested, the
+    /// server's default user if absent.
+    #[clap(long, value_parser)]
+    user: Option<String>,
+
+    /// The TEE whose policy or reference data is requested, the TEE-agnostic one which applies
+    /// to the TEEs without their own if absent.
+    #[clap(long, value_parser = ["sgx", "tdx", "sevsnp", "sample"])]
+    tee: Option<String>,
+
+    #[clap(flatten)]
+    tls: tls::TlsArgs,
+
+    #[clap(flatten)]
+    auth: auth::AuthArgs,
 }
 
 #[derive(Subcommand)]
@@ -44,6 +76,18 @@ enum Commands {
     #[clap(arg_required_else_help = true)]
     ReferenceData(ReferenceData),
 
+    /// Create, Delete or List the users (tenants) of Attestation Server.
+    #[clap(arg_required_else_help = true)]
+    User(User),
+
+    /// Register or Get the reference values of the Reference Value Provider Service (RVPS).
+    #[clap(arg_required_else_help = true)]
+    Rvps(Rvps),
+
+    /// Verify the hash chain of Attestation Server's audit log.
+    #[clap(arg_required_else_help = true)]
+    Audit(Audit),
+
     /// Test Attestation Server's `attestation` function with the input evidence file.
     #[clap(arg_required_else_help = true)]
     Attest {
@@ -51,6 +95,41 @@ enum Commands {
         #[clap(long, value_parser)]
         evidence: String,
 
+        /// Request the Attestation Results as a signed JWT.
+        #[clap(long, action)]
+        jwt: bool,
+
+        /// The Attestation Server's `attestation-sock` address.
+        #[clap(long, value_parser, default_value_t = String::from(DEFAULT_ATTESTATION_ADDR))]
+        attest_addr: String,
+    },
+
+    /// Attest many evidence files at once by Attestation Server's `BatchAttestation` function.
+    #[clap(arg_required_else_help = true)]
+    BatchAttest {
+        /// The evidence file paths which are evaluated by Attestation Server.
+        #[clap(long, value_parser, multiple_values = true, required = true)]
+        evidence: Vec<String>,
+
+        /// Request the Attestation Results as signed JWTs.
+        #[clap(long, action)]
+        jwt: bool,
+
+        /// The Attestation Server's `attestation-sock` address.
+        #[clap(long, value_parser, default_value_t = String::from(DEFAULT_ATTESTATION_ADDR))]
+        attest_addr: String,
+    },
+
+    /// Get a nonce from Attestation Server as the challenge of the attestation, which the
+    /// evidence should carry as its `nonce`.
+    Challenge {
+        /// The Attestation Server's `attestation-sock` address.
+        #[clap(long, value_parser, default_value_t = String::from(DEFAULT_ATTESTATION_ADDR))]
+        attest_addr: String,
+    },
+
+    /// Get the JSON Web Key Set which verifies the JWT Attestation Results.
+    Jwks {
         /// The Attestation Server's `attestation-sock` address.
         #[clap(long, value_parser, default_value_t = String::from(DEFAULT_ATTESTATION_ADDR))]
         attest_addr: String,
@@ -82,6 +161,29 @@ enum PolicyCommands {
 
     /// Restore the Attestation Server's `Policy(.rego)` to default.
     Restore,
+
+    /// List the versions of the `Policy(.rego)`, which are created by each set or rollback.
+    Versions,
+
+    /// Diff two versions of the `Policy(.rego)`.
+    #[clap(arg_required_else_help = true)]
+    Diff {
+        /// The id of the old version.
+        #[clap(long, value_parser)]
+        from: u64,
+
+        /// The id of the new version.
+        #[clap(long, value_parser)]
+        to: u64,
+    },
+
+    /// Atomically restore the `Policy(.rego)` to the content of a version.
+    #[clap(arg_required_else_help = true)]
+    Rollback {
+        /// The id of the version.
+        #[clap(long, value_parser)]
+        version: u64,
+    },
 }
 
 #[derive(Args)]
@@ -109,6 +211,100 @@ enum ReferenceDataCommands {
 
     /// Restore the Attestation Server's `Reference Data(.json)` to default.
     Restore,
+
+    /// List the versions of the `Reference Data(.json)`, which are created by each set or rollback.
+    Versions,
+
+    /// Diff two versions of the `Reference Data(.json)`.
+    #[clap(arg_required_else_help = true)]
+    Diff {
+        /// The id of the old version.
+        #[clap(long, value_parser)]
+        from: u64,
+
+        /// The id of the new version.
+        #[clap(long, value_parser)]
+        to: u64,
+    },
+
+    /// Atomically restore the `Reference Data(.json)` to the content of a version.
+    #[clap(arg_required_else_help = true)]
+    Rollback {
+        /// The id of the version.
+        #[clap(long, value_parser)]
+        version: u64,
+    },
+}
+
+#[derive(Args)]
+struct User {
+    #[clap(subcommand)]
+    command: Option<UserCommands>,
+}
+
+#[derive(Subcommand)]
+enum UserCommands {
+    /// Create the user with the default policy and reference data.
+    #[clap(arg_required_else_help = true)]
+    Create {
+        /// The user id, which consists of ASCII alphanumerics, `-`, `_` and `.`.
+        #[clap(long, value_parser)]
+        id: String,
+    },
+
+    /// Delete the user along with its policy and reference data.
+    #[clap(arg_required_else_help = true)]
+    Delete {
+        /// The user id.
+        #[clap(long, value_parser)]
+        id: String,
+    },
+
+    /// List the users.
+    List,
+}
+
+#[derive(Args)]
+struct Rvps {
+    #[clap(subcommand)]
+    command: Option<RvpsCommands>,
+}
+
+#[derive(Subcommand)]
+enum RvpsCommands {
+    /// Register the provenance message's reference values to RVPS.
+    #[clap(arg_required_else_help = true)]
+    Register {
+        /// The path of local provenance message(.json).
+        #[clap(long, value_parser)]
+        message: String,
+    },
+
+    /// Get the TEE's unexpired reference values from RVPS.
+    #[clap(arg_required_else_help = true)]
+    Get {
+        /// The TEE type, e.g. `tdx`.
+        #[clap(long, value_parser)]
+        tee: String,
+    },
+}
+
+#[derive(Args)]
+struct Audit {
+    #[clap(subcommand)]
+    command: Option<AuditCommands>,
+}
+
+#[derive(Subcommand)]
+enum AuditCommands {
+    /// Verify the audit log wasn't tampered, i.e. none of its records was edited, dropped or
+    /// reordered.
+    #[clap(arg_required_else_help = true)]
+    Verify {
+        /// The path of the audit log, e.g. `/opt/attestation-server/audit/audit.log`.
+        #[clap(long, value_parser)]
+        log: String,
+    },
 }
 
 #[tokio::main]
@@ -118,19 +314,58 @@ async fn main() -> Result<()> {
         .init();
 
     let args = Cli::parse();
+    let user = args.user.as_deref();
+    let tee = args.tee.as_deref();
+    let management_channel = || async {
+        let channel = args.tls.connect(&args.addr).await?;
+        args.auth.authenticate(channel)
+    };
 
     match args.command {
         Commands::Policy(policy) => match policy.command {
             Some(PolicyCommands::Get { output }) => {
                 let output_path = Path::new(&output);
-                management::get_policy_cmd(output_path, &args.addr).await?;
+                management::get_policy_cmd(output_path, user, tee, management_channel().await?)
+                    .await?;
             }
             Some(PolicyCommands::Set { policy }) => {
                 let policy_path = Path::new(&policy);
-                management::set_policy_cmd(policy_path, &args.addr).await?;
+                management::set_policy_cmd(policy_path, user, tee, management_channel().await?)
+                    .await?;
             }
             Some(PolicyCommands::Restore) => {
-                management::restore_default_policy_cmd(&args.addr).await?;
+                management::restore_default_policy_cmd(user, tee, management_channel().await?)
+                    .await?;
+            }
+            Some(PolicyCommands::Versions) => {
+                management::list_versions_cmd(
+                    VersionKind::Policy,
+                    user,
+                    tee,
+                    management_channel().await?,
+                )
+                .await?;
+            }
+            Some(PolicyCommands::Diff { from, to }) => {
+                management::diff_versions_cmd(
+                    VersionKind::Policy,
+                    from,
+                    to,
+                    user,
+                    tee,
+                    management_channel().await?,
+                )
+                .await?;
+            }
+            Some(PolicyCommands::Rollback { version }) => {
+                management::rollback_cmd(
+                    VersionKind::Policy,
+                    version,
+                    user,
+                    tee,
+                    management_channel().await?,
+                )
+                .await?;
             }
             _ => {
                 return Err(anyhow!("Unsupported command, use --help for information"));
@@ -139,14 +374,94 @@ async fn main() -> Result<()> {
         Commands::ReferenceData(ref_data) => match ref_data.command {
             Some(ReferenceDataCommands::Get { output }) => {
                 let output_path = Path::new(&output);
-                management::get_reference_data_cmd(output_path, &args.addr).await?;
+                management::get_reference_data_cmd(
+                    output_path,
+                    user,
+                    tee,
+                    management_channel().await?,
+                )
+                .await?;
             }
             Some(ReferenceDataCommands::Set { reference_data }) => {
                 let reference_data_path = Path::new(&reference_data);
-                management::set_reference_data_cmd(reference_data_path, &args.addr).await?;
+                management::set_reference_data_cmd(
+                    reference_data_path,
+                    user,
+                    tee,
+                    management_channel().await?,
+                )
+                .await?;
             }
             Some(ReferenceDataCommands::Restore) => {
-                management::restore_default_reference_data_cmd(&args.addr).await?;
+                management::restore_default_reference_data_cmd(
+                    user,
+                    tee,
+                    management_channel().await?,
+                )
+                .await?;
+            }
+            Some(ReferenceDataCommands::Versions) => {
+                management::list_versions_cmd(
+                    VersionKind::ReferenceData,
+                    user,
+                    tee,
+                    management_channel().await?,
+                )
+                .await?;
+            }
+            Some(ReferenceDataCommands::Diff { from, to }) => {
+                management::diff_versions_cmd(
+                    VersionKind::ReferenceData,
+                    from,
+                    to,
+                    user,
+                    tee,
+                    management_channel().await?,
+                )
+                .await?;
+            }
+            Some(ReferenceDataCommands::Rollback { version }) => {
+                management::rollback_cmd(
+                    VersionKind::ReferenceData,
+                    version,
+                    user,
+                    tee,
+                    management_channel().await?,
+                )
+                .await?;
+            }
+            _ => {
+                return Err(anyhow!("Unsupported command, use --help for information"));
+            }
+        },
+        Commands::User(user) => match user.command {
+            Some(UserCommands::Create { id }) => {
+                management::create_user_cmd(&id, management_channel().await?).await?;
+            }
+            Some(UserCommands::Delete { id }) => {
+                management::delete_user_cmd(&id, management_channel().await?).await?;
+            }
+            Some(UserCommands::List) => {
+                management::list_users_cmd(management_channel().await?).await?;
+            }
+            _ => {
+                return Err(anyhow!("Unsupported command, use --help for information"));
+            }
+        },
+        Commands::Rvps(rvps) => match rvps.command {
+            Some(RvpsCommands::Register { message }) => {
+                rvps::register_cmd(Path::new(&message), management_channel().await?).await?;
+            }
+            Some(RvpsCommands::Get { tee }) => {
+                rvps::get_cmd(&tee, management_channel().await?).await?;
+            }
+            _ => {
+                return Err(anyhow!("Unsupported command, use --help for information"));
+            }
+        },
+        Commands::Audit(audit) => match audit.command {
+            Some(AuditCommands::Verify { log }) => {
+                audit::verify_cmd(Path::new(&log))?;
             }
             _ => {
                 return Err(anyhow!("Unsupported command, use --help for information"));
@@ -154,12 +469,49 @@ async fn main() -> Result<()> {
         },
         Commands::Attest {
             evidence,
+            jwt,
             attest_addr,
         } => {
             let evidence_path = Path::new(&evidence);
-            attestation::attestation_cmd(evidence_path, &attest_addr).await?;
+            attestation::attestation_cmd(
+                evidence_path,
+                jwt,
+                user,
+                args.tls.connect(&attest_addr).await?,
+            )
+            .await?;
+        }
+        Commands::BatchAttest {
+            evidence,
+            jwt,
+            attest_addr,
+        } => {
+            attestation::batch_attestation_cmd(
+                &evidence,
+                jwt,
+                user,
+                args.tls.connect(&attest_addr).await?,
+            )
+            .await?;
+        }
+        Commands::Challenge { attest_addr } => {
+            attestation::challenge_cmd(args.tls.connect(&attest_addr).await?).await?;
+        }
+        Commands::Jwks { attest_addr } => {
+            attestation::get_jwks_cmd(args.tls.connect(&attest_addr).await?).await?;
         }
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn test_cli() {
+        Cli::command().debug_assert();
+    }
+}
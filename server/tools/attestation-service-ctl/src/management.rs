@@ -4,45 +4,86 @@ use std::fs;
 use std::io::prelude::*;
 use std::path::Path;
 
+use crate::auth::Connection;
+use crate::common::{Tee, User};
 use crate::management_api::management_service_client::ManagementServiceClient;
 use crate::management_api::{
-    GetPolicyRequest, GetPolicyResponse, GetReferenceDataRequest, GetReferenceDataResponse,
-    RestoreDefaultPolicyRequest, RestoreDefaultReferenceDataRequest, SetPolicyRequest,
-    SetReferenceDataRequest,
+    CreateUserRequest, DeleteUserRequest, DiffVersionsRequest, GetPolicyRequest, GetPolicyResponse,
+    GetReferenceDataRequest, GetReferenceDataResponse, ListUsersRequest, ListUsersResponse,
+    ListVersionsRequest, RestoreDefaultPolicyRequest, RestoreDefaultReferenceDataRequest,
+    RollbackRequest, SetPolicyRequest, SetReferenceDataRequest, Version, VersionKind,
 };
 
-pub const DEFAULT_MANAGEMENT_ADDR: &str = "https://127.0.0.1:3001";
+pub const DEFAULT_MANAGEMENT_ADDR: &str = "http://127.0.0.1:3001";
 
-pub async fn set_policy_cmd(file: &Path, address: &str) -> Result<()> {
+/// The request's user, the server's default user if `id` is absent.
+pub fn user(id: Option<&str>) -> Option<User> {
+    id.map(|id| User { id: id.to_string() })
+}
+
+/// The request's TEE, the TEE-agnostic one if `tee` is absent.
+fn tee(tee: Option<&str>) -> Result<Option<i32>> {
+    let tee = match tee {
+        Some("sgx") => Tee::Sgx,
+        Some("tdx") => Tee::Tdx,
+        Some("sevsnp") => Tee::SevSnp,
+        Some("sample") => Tee::Sample,
+        Some(tee) => return Err(anyhow!("Unsupported TEE: {}", tee)),
+        None => return Ok(None),
+    };
+    Ok(Some(tee as i32))
+}
+
+pub async fn set_policy_cmd(
+    file: &Path,
+    user_id: Option<&str>,
+    tee_name: Option<&str>,
+    channel: Connection,
+) -> Result<()> {
     let policy = fs::read_to_string(file).context(anyhow!("Read policy error"))?;
 
     let request = SetPolicyRequest {
-        user: None,
+        user: user(user_id),
         content: policy.into_bytes(),
+        tee: tee(tee_name)?,
     };
 
-    let mut client = ManagementServiceClient::connect(address.to_string()).await?;
+    let mut client = ManagementServiceClient::new(channel);
     client.set_policy(request).await?;
     Ok(())
 }
 
-pub async fn set_reference_data_cmd(file: &Path, address: &str) -> Result<()> {
+pub async fn set_reference_data_cmd(
+    file: &Path,
+    user_id: Option<&str>,
+    tee_name: Option<&str>,
+    channel: Connection,
+) -> Result<()> {
     let reference_data = fs::read_to_string(file).context(anyhow!("Read reference data error"))?;
 
     let request = SetReferenceDataRequest {
-        user: None,
+        user: user(user_id),
         content: reference_data.into_bytes(),
+        tee: tee(tee_name)?,
     };
 
-    let mut client = ManagementServiceClient::connect(address.to_string()).await?;
+    let mut client = ManagementServiceClient::new(channel);
     client.set_reference_data(request).await?;
     Ok(())
 }
 
-pub async fn get_policy_cmd(output_path: &Path, address: &str) -> Result<()> {
-    let request = GetPolicyRequest { user: None };
+pub async fn get_policy_cmd(
+    output_path: &Path,
+    user_id: Option<&str>,
+    tee_name: Option<&str>,
+    channel: Connection,
+) -> Result<()> {
+    let request = GetPolicyRequest {
+        user: user(user_id),
+        tee: tee(tee_name)?,
+    };
 
-    let mut client = ManagementServiceClient::connect(address.to_string()).await?;
+    let mut client = ManagementServiceClient::new(channel);
     let response: GetPolicyResponse = client.get_policy(request).await?.into_inner();
     let policy = String::from_utf8(response.content).unwrap();
     log!(Level::Info, "{}", &policy);
@@ -55,10 +96,18 @@ pub async fn get_policy_cmd(output_path: &Path, address: &str) -> Result<()> {
     Ok(())
 }
 
-pub async fn get_reference_data_cmd(output_path: &Path, address: &str) -> Result<()> {
-    let request = GetReferenceDataRequest { user: None };
+pub async fn get_reference_data_cmd(
+    output_path: &Path,
+    user_id: Option<&str>,
+    tee_name: Option<&str>,
+    channel: Connection,
+) -> Result<()> {
+    let request = GetReferenceDataRequest {
+        user: user(user_id),
+        tee: tee(tee_name)?,
+    };
 
-    let mut client = ManagementServiceClient::connect(address.to_string()).await?;
+    let mut client = ManagementServiceClient::new(channel);
     let response: GetReferenceDataResponse = client.get_reference_data(request).await?.into_inner();
     let reference_data = String::from_utf8(response.content).unwrap();
     log!(Level::Info, "{}", &reference_data);
@@ -71,18 +120,139 @@ pub async fn get_reference_data_cmd(output_path: &Path, address: &str) -> Result
     Ok(())
 }
 
-pub async fn restore_default_policy_cmd(address: &str) -> Result<()> {
-    let request = RestoreDefaultPolicyRequest { user: None };
+pub async fn restore_default_policy_cmd(
+    user_id: Option<&str>,
+    tee_name: Option<&str>,
+    channel: Connection,
+) -> Result<()> {
+    let request = RestoreDefaultPolicyRequest {
+        user: user(user_id),
+        tee: tee(tee_name)?,
+    };
 
-    let mut client = ManagementServiceClient::connect(address.to_string()).await?;
+    let mut client = ManagementServiceClient::new(channel);
     client.restore_default_policy(request).await?;
     Ok(())
 }
 
-pub async fn restore_default_reference_data_cmd(address: &str) -> Result<()> {
-    let request = RestoreDefaultReferenceDataRequest { user: None };
+pub async fn restore_default_reference_data_cmd(
+    user_id: Option<&str>,
+    tee_name: Option<&str>,
+    channel: Connection,
+) -> Result<()> {
+    let request = RestoreDefaultReferenceDataRequest {
+        user: user(user_id),
+        tee: tee(tee_name)?,
+    };
 
-    let mut client = ManagementServiceClient::connect(address.to_string()).await?;
+    let mut client = ManagementServiceClient::new(channel);
     client.restore_default_reference_data(request).await?;
     Ok(())
 }
+
+pub async fn create_user_cmd(id: &str, channel: Connection) -> Result<()> {
+    let request = CreateUserRequest {
+        user: user(Some(id)),
+    };
+
+    let mut client = ManagementServiceClient::new(channel);
+    client.create_user(request).await?;
+    Ok(())
+}
+
+pub async fn delete_user_cmd(id: &str, channel: Connection) -> Result<()> {
+    let request = DeleteUserRequest {
+        user: user(Some(id)),
+    };
+
+    let mut client = ManagementServiceClient::new(channel);
+    client.delete_user(request).await?;
+    Ok(())
+}
+
+pub async fn list_users_cmd(channel: Connection) -> Result<()> {
+    let mut client = ManagementServiceClient::new(channel);
+    let response: ListUsersResponse = client.list_users(ListUsersRequest {}).await?.into_inner();
+    for user in response.users {
+        log!(Level::Info, "{}", user.id);
+    }
+    Ok(())
+}
+
+fn render_version(version: &Version) -> String {
+    format!(
+        "{}\t{}\t{}\t{}",
+        version.id,
+        version.time,
+        version.author,
+        version.sha256.as_deref().unwrap_or("default")
+    )
+}
+
+pub async fn list_versions_cmd(
+    kind: VersionKind,
+    user_id: Option<&str>,
+    tee_name: Option<&str>,
+    channel: Connection,
+) -> Result<()> {
+    let request = ListVersionsRequest {
+        user: user(user_id),
+        tee: tee(tee_name)?,
+        kind: kind as i32,
+    };
+
+    let mut client = ManagementServiceClient::new(channel);
+    let response = client.list_versions(request).await?.into_inner();
+    for version in response.versions {
+        log!(Level::Info, "{}", render_version(&version));
+    }
+    Ok(())
+}
+
+pub async fn diff_versions_cmd(
+    kind: VersionKind,
+    from: u64,
+    to: u64,
+    user_id: Option<&str>,
+    tee_name: Option<&str>,
+    channel: Connection,
+) -> Result<()> {
+    let request = DiffVersionsRequest {
+        user: user(user_id),
+        tee: tee(tee_name)?,
+        kind: kind as i32,
+        from,
+        to,
+    };
+
+    let mut client = ManagementServiceClient::new(channel);
+    let response = client.diff_versions(request).await?.into_inner();
+    log!(Level::Info, "\n{}", response.diff);
+    Ok(())
+}
+
+pub async fn rollback_cmd(
+    kind: VersionKind,
+    version: u64,
+    user_id: Option<&str>,
+    tee_name: Option<&str>,
+    channel: Connection,
+) -> Result<()> {
+    let request = RollbackRequest {
+        user: user(user_id),
+        tee: tee(tee_name)?,
+        kind: kind as i32,
+        version,
+    };
+
+    let mut client = ManagementServiceClient::new(channel);
+    let response = client.rollback(request).await?.into_inner();
+    if let Some(version) = response.version {
+        log!(
+            Level::Info,
+            "Rolled back as version {}",
+            render_version(&version)
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,38 @@
+use anyhow::{anyhow, Context, Result};
+use log::Level;
+use std::fs;
+use std::path::Path;
+
+use crate::auth::Connection;
+use crate::reference_api::reference_value_provider_service_client::ReferenceValueProviderServiceClient;
+use crate::reference_api::{
+    GetReferenceValueRequest, GetReferenceValueResponse, RegisterReferenceValueRequest,
+    RegisterReferenceValueResponse,
+};
+
+pub async fn register_cmd(file: &Path, channel: Connection) -> Result<()> {
+    let message = fs::read_to_string(file).context(anyhow!("Read provenance message error"))?;
+
+    let request = RegisterReferenceValueRequest {
+        message: message.into_bytes(),
+    };
+
+    let mut client = ReferenceValueProviderServiceClient::new(channel);
+    let response: RegisterReferenceValueResponse =
+        client.register_reference_value(request).await?.into_inner();
+    log!(Level::Info, "Registered: {}", response.names.join(", "));
+    Ok(())
+}
+
+pub async fn get_cmd(tee: &str, channel: Connection) -> Result<()> {
+    let request = GetReferenceValueRequest {
+        tee: tee.to_string(),
+    };
+
+    let mut client = ReferenceValueProviderServiceClient::new(channel);
+    let response: GetReferenceValueResponse =
+        client.get_reference_value(request).await?.into_inner();
+    let values = String::from_utf8(response.content)?;
+    log!(Level::Info, "{}", values);
+    Ok(())
+}
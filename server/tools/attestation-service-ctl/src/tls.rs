@@ -0,0 +1,56 @@
+use anyhow::{Context, Result};
+use clap::Args;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tonic::transport::{Certificate, Channel, ClientTlsConfig, Identity};
+
+/// The TLS options of the connections to the `https://` addresses.
+#[derive(Args)]
+pub struct TlsArgs {
+    /// PEM file of the CA certificate which verifies the server certificate, the system's root
+    /// certificates if absent.
+    #[clap(long, value_parser)]
+    tls_ca: Option<PathBuf>,
+
+    /// PEM file of the client certificate, which the `management-sock` requires for mTLS.
+    #[clap(long, value_parser, requires = "tls-key")]
+    tls_cert: Option<PathBuf>,
+
+    /// PEM file of the private key of `--tls-cert`.
+    #[clap(long, value_parser, requires = "tls-cert")]
+    tls_key: Option<PathBuf>,
+
+    /// The server name verified against the server certificate, the host of the address if
+    /// absent.
+    #[clap(long, value_parser)]
+    tls_domain: Option<String>,
+}
+
+fn read(path: &Path) -> Result<Vec<u8>> {
+    fs::read(path).with_context(|| format!("Read {:?} failed.", path))
+}
+
+impl TlsArgs {
+    fn client_config(&self) -> Result<ClientTlsConfig> {
+        let mut config = ClientTlsConfig::new();
+        if let Some(ca) = &self.tls_ca {
+            config = config.ca_certificate(Certificate::from_pem(read(ca)?));
+        }
+        if let (Some(cert), Some(key)) = (&self.tls_cert, &self.tls_key) {
+            config = config.identity(Identity::from_pem(read(cert)?, read(key)?));
+        }
+        if let Some(domain) = &self.tls_domain {
+            config = config.domain_name(domain);
+        }
+        Ok(config)
+    }
+
+    /// Connect to `address`, over TLS if it's an `https://` address.
+    pub async fn connect(&self, address: &str) -> Result<Channel> {
+        let mut endpoint = Channel::from_shared(address.to_string())?;
+        if address.starts_with("https://") {
+            endpoint = endpoint.tls_config(self.client_config()?)?;
+        }
+        Ok(endpoint.connect().await?)
+    }
+}